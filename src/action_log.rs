@@ -0,0 +1,233 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::i18n::log_tr;
+use crate::{read_archive_entries_with_password, ExtractStats, FailedEntry, Outcome};
+
+/// Appended to the archive's file stem to name its action log, see
+/// `action_log_path`.
+pub const ACTION_LOG_SUFFIX: &str = ".extract.json";
+
+/// What was done with one archive, written next to the output when
+/// `write_action_log` is set, for auditing without going through the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionLog {
+    pub archive: PathBuf,
+    pub dest: PathBuf,
+    pub smart_mode: SmartModeDecision,
+    /// `extracted`, `failed`, `not_found`, `timed_out` or `cancelled`.
+    pub outcome: String,
+    /// Where the password that worked is in the list tried, counting from
+    /// 0. `None` when none worked, or it came from a wordlist or the file
+    /// name rather than the list.
+    pub password_index: Option<usize>,
+    pub files: Vec<ActionLogFile>,
+    /// Entries left out by `max_file_size` or the extension filters.
+    pub skipped: Vec<String>,
+    /// Entries `continue_on_entry_error` got past.
+    pub failed: Vec<ActionLogFailure>,
+    /// In RFC 3339 and local time, as the two below.
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SmartModeDecision {
+    pub enabled: bool,
+    /// Whether the files were put in a folder of their own rather than
+    /// straight into the destination.
+    pub own_folder: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionLogFile {
+    /// Relative to `dest`.
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionLogFailure {
+    pub name: String,
+    pub error: String,
+}
+
+impl From<&FailedEntry> for ActionLogFailure {
+    fn from(failed: &FailedEntry) -> Self {
+        ActionLogFailure {
+            name: failed.name.clone(),
+            error: failed.error.clone(),
+        }
+    }
+}
+
+/// `<stem>.extract.json` in `base_dest`, the folder holding the output, so
+/// with smart mode it sits next to the archive's own folder.
+pub fn action_log_path(archive: &Path, base_dest: &Path) -> PathBuf {
+    let stem = archive.file_stem().unwrap_or(archive.as_os_str());
+    let mut name = stem.to_os_string();
+    name.push(ACTION_LOG_SUFFIX);
+    base_dest.join(name)
+}
+
+impl ActionLog {
+    /// Starts the log of extracting `archive` to `dest`, which smart mode
+    /// picked inside `base_dest`.
+    pub fn start(archive: &Path, base_dest: &Path, dest: &Path, smart_mode: bool) -> Self {
+        ActionLog {
+            archive: archive.to_path_buf(),
+            dest: dest.to_path_buf(),
+            smart_mode: SmartModeDecision {
+                enabled: smart_mode,
+                own_folder: dest != base_dest,
+            },
+            outcome: String::new(),
+            password_index: None,
+            files: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+            started_at: Local::now().to_rfc3339(),
+            finished_at: String::new(),
+        }
+    }
+
+    /// Takes the files from an extraction made with `record_written_files`
+    /// set to `PathStyle::Relative`.
+    pub fn record_stats(&mut self, stats: &ExtractStats) {
+        self.files = stats
+            .written_files
+            .iter()
+            .map(|file| ActionLogFile {
+                name: file.to_string_lossy().into_owned(),
+                size: fs::metadata(self.dest.join(file)).map_or(0, |m| m.len()),
+            })
+            .collect();
+        self.skipped = stats
+            .too_large
+            .iter()
+            .chain(&stats.filtered)
+            .cloned()
+            .collect();
+        self.failed = stats.failed_entries.iter().map(Into::into).collect();
+    }
+
+    /// Takes the files from the listing of a 7z archive, for the cracking
+    /// path, which extracts every entry and keeps no stats.
+    pub fn record_listing(&mut self, password: &str) {
+        self.files = read_archive_entries_with_password(&self.archive, password)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| !entry.is_directory())
+            .map(|entry| ActionLogFile {
+                size: entry.size(),
+                name: entry.name,
+            })
+            .collect();
+    }
+
+    /// Sets `outcome`, and `password_index` when the password found is one
+    /// of `passwords`.
+    pub fn record_outcome(&mut self, outcome: &Outcome, passwords: &[Arc<String>]) {
+        self.outcome = match outcome {
+            Outcome::Found(password) => {
+                self.password_index = passwords.iter().position(|p| **p == *password);
+                "extracted"
+            }
+            Outcome::NotFound { .. } => "not_found",
+            Outcome::TimedOut { .. } => "timed_out",
+            Outcome::Cancelled { .. } => "cancelled",
+            Outcome::ExtractFailed { .. } => "extract_failed",
+            Outcome::Skipped { .. } => "skipped",
+        }
+        .to_string();
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("an action log always serializes")
+    }
+
+    /// Sets `finished_at` and writes the log to `action_log_path`.
+    pub fn finish(mut self, base_dest: &Path) -> io::Result<PathBuf> {
+        self.finished_at = Local::now().to_rfc3339();
+        let path = action_log_path(&self.archive, base_dest);
+        fs::create_dir_all(base_dest)?;
+        fs::write(&path, self.to_json())?;
+        Ok(path)
+    }
+}
+
+/// `ActionLog::finish`, only logging a failure: the files are extracted
+/// either way.
+pub(crate) fn write_action_log(log: ActionLog, base_dest: &Path) {
+    let archive = log.archive.clone();
+    if let Err(e) = log.finish(base_dest) {
+        log_tr!(
+            warn,
+            "无法写入操作记录 {}: {}",
+            "cannot write the action log of {}: {}",
+            archive.display(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use crate::{extract_with_options, ExtractOptions, PathStyle};
+
+    #[test]
+    fn test_action_log_path() {
+        assert_eq!(
+            action_log_path(Path::new("in/photos.7z"), Path::new("out")),
+            Path::new("out/photos.extract.json")
+        );
+    }
+
+    #[test]
+    fn test_action_log() {
+        let base = test_dir("action_log");
+        let dest = base.join("7ziplogo_p");
+        let mut log = ActionLog::start(Path::new("tests/7ziplogo_p.7z"), &base, &dest, true);
+        log.record_listing("1151");
+        let passwords = ["", "0000", "1151"].map(|p| Arc::new(p.to_string()));
+        log.record_outcome(&Outcome::Found("1151".to_string()), &passwords);
+        let path = log.finish(&base).unwrap();
+        assert_eq!(path, base.join("7ziplogo_p.extract.json"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json["outcome"], "extracted");
+        assert_eq!(json["password_index"], 2);
+        assert_eq!(json["smart_mode"]["own_folder"], true);
+        assert_eq!(json["files"][0]["name"], "7ziplogo.png");
+        assert_eq!(
+            json["files"][0]["size"],
+            fs::metadata("tests/7ziplogo.png").unwrap().len()
+        );
+        assert!(json["finished_at"].as_str().unwrap() >= json["started_at"].as_str().unwrap());
+    }
+
+    #[test]
+    fn test_action_log_record_stats() {
+        let dest = test_dir("action_log_record_stats");
+        let options = ExtractOptions {
+            record_written_files: Some(PathStyle::Relative),
+            max_file_size: Some(1),
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        let mut log = ActionLog::start(Path::new("tests/sample.7z"), &dest, &dest, false);
+        log.record_stats(&stats);
+        assert!(!log.smart_mode.own_folder);
+        assert_eq!(log.skipped, stats.too_large);
+        assert_eq!(log.files.len(), stats.written_files.len());
+        assert!(log.files.iter().all(|file| file.size <= 1));
+    }
+}
@@ -0,0 +1,197 @@
+#[cfg(feature = "async")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use crate::{i18n::log_tr, start_extraction, CascadeError, CrackSuccess};
+
+/// How many candidates are handed to `start_extraction` at a time.
+pub const BRUTE_FORCE_BATCH_SIZE: usize = 1024;
+
+/// Every string over `charset` with a length between `min_len` and `max_len`,
+/// shortest first. Candidates are addressed by index, so a crashed run can
+/// continue with `candidates_from(last_index)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BruteForceSpec {
+    pub charset: Vec<char>,
+    pub min_len: usize,
+    pub max_len: usize,
+}
+
+impl BruteForceSpec {
+    /// Duplicate characters in `charset` are dropped.
+    pub fn new(charset: &str, min_len: usize, max_len: usize) -> Self {
+        let mut chars: Vec<char> = Vec::new();
+        for c in charset.chars() {
+            if !chars.contains(&c) {
+                chars.push(c);
+            }
+        }
+        BruteForceSpec {
+            charset: chars,
+            min_len,
+            max_len,
+        }
+    }
+
+    /// Numeric PINs such as `"1151"`.
+    pub fn digits(min_len: usize, max_len: usize) -> Self {
+        Self::new("0123456789", min_len, max_len)
+    }
+
+    /// Total number of candidates, or `None` if it doesn't fit in a `u64`.
+    pub fn total(&self) -> Option<u64> {
+        let base = self.charset.len() as u64;
+        (self.min_len..=self.max_len).try_fold(0u64, |sum, len| {
+            sum.checked_add(base.checked_pow(u32::try_from(len).ok()?)?)
+        })
+    }
+
+    pub fn candidate(&self, index: u64) -> Option<String> {
+        if self.charset.is_empty() {
+            return None;
+        }
+        let base = self.charset.len() as u64;
+        let mut index = index;
+
+        for len in self.min_len..=self.max_len {
+            let count = u32::try_from(len)
+                .ok()
+                .and_then(|len| base.checked_pow(len))
+                .unwrap_or(u64::MAX);
+            if index < count {
+                let mut chars = vec![self.charset[0]; len];
+                for slot in chars.iter_mut().rev() {
+                    *slot = self.charset[(index % base) as usize];
+                    index /= base;
+                }
+                return Some(chars.into_iter().collect());
+            }
+            index -= count;
+        }
+
+        None
+    }
+
+    pub fn candidates(&self) -> BruteForceIter<'_> {
+        self.candidates_from(0)
+    }
+
+    pub fn candidates_from(&self, start: u64) -> BruteForceIter<'_> {
+        BruteForceIter {
+            spec: self,
+            next: start,
+        }
+    }
+}
+
+pub struct BruteForceIter<'a> {
+    spec: &'a BruteForceSpec,
+    next: u64,
+}
+
+impl BruteForceIter<'_> {
+    /// Index of the candidate the next call to `next` will return.
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+}
+
+impl Iterator for BruteForceIter<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let candidate = self.spec.candidate(self.next)?;
+        self.next += 1;
+        Some(candidate)
+    }
+}
+
+/// Feeds the spec's candidates to `start_extraction` in batches, starting at
+/// `start_index`. Returns the index and value of the password that worked.
+#[cfg(feature = "async")]
+pub async fn start_brute_force(
+    paths: Arc<[PathBuf]>,
+    spec: &BruteForceSpec,
+    dest: &Path,
+    max_threads: usize,
+    start_index: u64,
+) -> Result<Option<(u64, String)>, CascadeError> {
+    let total = spec
+        .total()
+        .ok_or_else(|| CascadeError::InvalidBruteForceSpec("too many candidates".to_string()))?;
+    let mut candidates = spec.candidates_from(start_index);
+
+    loop {
+        let batch_start = candidates.position();
+        let batch: Vec<Arc<String>> = candidates
+            .by_ref()
+            .take(BRUTE_FORCE_BATCH_SIZE)
+            .map(Arc::new)
+            .collect();
+        if batch.is_empty() {
+            return Ok(None);
+        }
+
+        let found = start_extraction(
+            paths.clone(),
+            batch.clone(),
+            dest.to_path_buf(),
+            max_threads,
+        )
+        .await;
+        if let Some(CrackSuccess { password, .. }) = found {
+            let offset = batch.iter().position(|p| **p == password).unwrap_or(0);
+            return Ok(Some((batch_start + offset as u64, password)));
+        }
+
+        log_tr!(
+            info,
+            "暴力破解进度: {}/{}",
+            "brute force progress: {}/{}",
+            candidates.position(),
+            total
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "async")]
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_brute_force_candidates() {
+        let spec = BruteForceSpec::digits(1, 2);
+        assert_eq!(spec.total(), Some(110));
+        assert_eq!(spec.candidate(0).as_deref(), Some("0"));
+        assert_eq!(spec.candidate(9).as_deref(), Some("9"));
+        assert_eq!(spec.candidate(10).as_deref(), Some("00"));
+        assert_eq!(spec.candidate(109).as_deref(), Some("99"));
+        assert_eq!(spec.candidate(110), None);
+
+        let mut resumed = spec.candidates_from(105);
+        assert_eq!(resumed.next().as_deref(), Some("95"));
+        assert_eq!(resumed.position(), 106);
+        assert_eq!(resumed.count(), 4);
+    }
+
+    #[test]
+    fn test_brute_force_total_overflow() {
+        assert_eq!(BruteForceSpec::digits(1, 40).total(), None);
+        assert_eq!(BruteForceSpec::new("aab", 2, 2).total(), Some(4));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_brute_force() {
+        let dest = test_dir("brute_force");
+        let spec = BruteForceSpec::new("15", 4, 4);
+        let paths: Arc<[PathBuf]> = Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]);
+        let found = start_brute_force(paths, &spec, &dest, 4, 0).await.unwrap();
+        // "1111", "1115", "1151", ...
+        assert_eq!(found, Some((2, "1151".to_string())));
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::i18n::log_tr;
+use crate::{
+    extract_any, planned_dest, read_archive_entries_with_password, CascadeError, ConfigSettings,
+    ExtractOptions, PathStyle, SmartModeOptions,
+};
+
+/// Which limit of `extract_cascading` was hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CascadeLimit {
+    /// An archive nested deeper than `max_cascade_depth`.
+    Depth { max: usize },
+    /// Everything extracted so far plus the next archive comes to more than
+    /// `max_total_extracted_bytes`.
+    TotalBytes { max: u64 },
+}
+
+impl fmt::Display for CascadeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CascadeLimit::Depth { max } => write!(f, "archives nested more than {} deep", max),
+            CascadeLimit::TotalBytes { max } => write!(f, "more than {} bytes extracted", max),
+        }
+    }
+}
+
+/// What `extract_cascading` did across all levels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CascadeStats {
+    /// The outer archive and every nested one that was extracted.
+    pub archives: usize,
+    /// How deep the nesting went, 1 for an archive without nested ones.
+    pub depth: usize,
+    pub files: usize,
+    /// Counted over every level, the nested archives themselves included.
+    pub bytes_written: u64,
+}
+
+/// Extracts the archive to `dest`, then every supported archive that came
+/// out of it into its own `planned_dest` next to it, and so on, with the
+/// same password. A nested archive is deleted once it is extracted, so only
+/// the innermost contents are left. With `cascade` off only the outer
+/// archive is extracted and the ones inside stay as they are.
+///
+/// Nested archives are the classic way to make a decompression bomb, so
+/// this gives up with `CascadeLimitExceeded` on an archive nested more
+/// than `max_cascade_depth` deep, the outer one being at depth 1, or once
+/// the bytes written over all levels would pass
+/// `max_total_extracted_bytes`. Whatever was extracted until then is left
+/// in place.
+pub fn extract_cascading<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    config: &ConfigSettings,
+) -> Result<CascadeStats, CascadeError> {
+    let mut options = ExtractOptions {
+        record_written_files: Some(PathStyle::Absolute),
+        ..ExtractOptions::from_config(config)
+    };
+    let mut stats = CascadeStats::default();
+    let mut queue: Vec<(PathBuf, PathBuf, usize)> =
+        vec![(path.as_ref().to_path_buf(), dest.to_path_buf(), 1)];
+
+    while let Some((archive, dest, depth)) = queue.pop() {
+        if depth > config.max_cascade_depth {
+            return Err(CascadeError::CascadeLimitExceeded {
+                limit: CascadeLimit::Depth {
+                    max: config.max_cascade_depth,
+                },
+            });
+        }
+        if let Some(max) = config.max_total_extracted_bytes {
+            // only 7z archives list their sizes up front, the others are
+            // held to what is left of the budget as they are extracted
+            let planned = read_archive_entries_with_password(&archive, password)
+                .map(|entries| entries.iter().map(|entry| entry.size()).sum::<u64>());
+            if planned.is_ok_and(|planned| stats.bytes_written + planned > max) {
+                return Err(CascadeError::CascadeLimitExceeded {
+                    limit: CascadeLimit::TotalBytes { max },
+                });
+            }
+            options.max_total_bytes = Some(max - stats.bytes_written);
+        }
+
+        let extracted = extract_any(&archive, password, &dest, &options)?;
+        stats.archives += 1;
+        stats.depth = stats.depth.max(depth);
+        stats.files += extracted.files;
+        stats.bytes_written += extracted.bytes_written;
+        if depth > 1 {
+            fs::remove_file(&archive)?;
+        }
+        if let Some(max) = config.max_total_extracted_bytes {
+            if stats.bytes_written > max {
+                return Err(CascadeError::CascadeLimitExceeded {
+                    limit: CascadeLimit::TotalBytes { max },
+                });
+            }
+        }
+
+        if !config.cascade {
+            continue;
+        }
+        for file in extracted.written_files {
+            let is_nested = config
+                .detect_archive_format(&file)
+                .ok()
+                .flatten()
+                .is_some_and(|format| format.is_supported());
+            if !is_nested {
+                continue;
+            }
+            let parent = file.parent().unwrap_or(&dest).to_path_buf();
+            let nested_dest = planned_dest(&file, &parent, &SmartModeOptions::from_config(config))
+                .unwrap_or_else(|_| parent.clone());
+            log_tr!(
+                info,
+                "发现第 {} 层嵌套的压缩包: {}",
+                "found a nested archive at depth {}: {}",
+                depth + 1,
+                file.display()
+            );
+            stats.files -= 1;
+            queue.push((file, nested_dest, depth + 1));
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use crate::{read_config_from, Config};
+
+    fn write_7z(path: &Path, name: &str, content: &[u8]) {
+        let mut writer = sevenz_rust::SevenZWriter::create(path).unwrap();
+        let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry.has_stream = true;
+        writer.push_archive_entry(entry, Some(content)).unwrap();
+        writer.finish().unwrap();
+    }
+
+    /// outer.7z holds middle.7z, which holds inner.7z with deep.txt.
+    fn nested_archive(dir: &Path) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        write_7z(&dir.join("inner.7z"), "deep.txt", b"deep");
+        let inner = fs::read(dir.join("inner.7z")).unwrap();
+        write_7z(&dir.join("middle.7z"), "inner.7z", &inner);
+        let middle = fs::read(dir.join("middle.7z")).unwrap();
+        write_7z(&dir.join("outer.7z"), "middle.7z", &middle);
+        dir.join("outer.7z")
+    }
+
+    fn config() -> Config {
+        read_config_from("nonexistent-settings.toml").unwrap()
+    }
+
+    #[test]
+    fn test_extract_cascading() {
+        let dir = test_dir("extract_cascading");
+        let outer = nested_archive(&dir);
+        let mut config = config();
+        config.config.smart_mode = false;
+
+        let dest = dir.join("out");
+        let stats = extract_cascading(&outer, "", &dest, &config.config).unwrap();
+        assert_eq!(stats.archives, 3);
+        assert_eq!(stats.depth, 3);
+        assert_eq!(stats.files, 1);
+        assert_eq!(fs::read_to_string(dest.join("deep.txt")).unwrap(), "deep");
+        assert!(!dest.join("middle.7z").exists());
+        assert!(!dest.join("inner.7z").exists());
+    }
+
+    #[test]
+    fn test_extract_cascading_disabled() {
+        let dir = test_dir("extract_cascading_disabled");
+        let outer = nested_archive(&dir);
+        let mut config = config();
+        config.config.smart_mode = false;
+        config.config.cascade = false;
+
+        let dest = dir.join("out");
+        let stats = extract_cascading(&outer, "", &dest, &config.config).unwrap();
+        assert_eq!(stats.archives, 1);
+        assert_eq!(stats.files, 1);
+        assert_eq!(
+            fs::read(dest.join("middle.7z")).unwrap(),
+            fs::read(dir.join("middle.7z")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cascade_limits() {
+        let dir = test_dir("cascade_limits");
+        let outer = nested_archive(&dir);
+        let mut config = config();
+        config.config.max_cascade_depth = 2;
+        let err = extract_cascading(&outer, "", &dir.join("deep"), &config.config).unwrap_err();
+        assert!(matches!(
+            err,
+            CascadeError::CascadeLimitExceeded {
+                limit: CascadeLimit::Depth { max: 2 }
+            }
+        ));
+
+        let mut config = self::config();
+        let inner_size = fs::metadata(dir.join("inner.7z")).unwrap().len();
+        config.config.max_total_extracted_bytes = Some(inner_size + 2);
+        let err = extract_cascading(&outer, "", &dir.join("big"), &config.config).unwrap_err();
+        assert!(matches!(
+            err,
+            CascadeError::CascadeLimitExceeded {
+                limit: CascadeLimit::TotalBytes { .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_cascade_byte_limit_without_listed_sizes() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = test_dir("cascade_byte_limit_without_listed_sizes");
+        fs::create_dir_all(&dir).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1000);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "big.txt", &[b'x'; 1000][..])
+            .unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&builder.into_inner().unwrap()).unwrap();
+        let tar_gz = gz.finish().unwrap();
+        let outer = dir.join("outer.7z");
+        write_7z(&outer, "inner.tar.gz", &tar_gz);
+
+        let mut config = config();
+        config.config.smart_mode = false;
+        config.config.max_total_extracted_bytes = Some(tar_gz.len() as u64 + 100);
+        let dest = dir.join("out");
+        let err = extract_cascading(&outer, "", &dest, &config.config).unwrap_err();
+        assert!(matches!(
+            err,
+            CascadeError::CascadeLimitExceeded {
+                limit: CascadeLimit::TotalBytes { .. }
+            }
+        ));
+        // stopped before big.txt was written, not after
+        let nested_dest = planned_dest(
+            dest.join("inner.tar.gz"),
+            &dest,
+            &SmartModeOptions::default(),
+        )
+        .unwrap();
+        assert!(!nested_dest.join("big.txt").exists());
+    }
+}
@@ -0,0 +1,468 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::i18n::log_tr;
+use crate::watch::base_dest_for;
+use crate::{
+    crack_password_blocking, delete_extracted_archive, enable_log_file, extract_any, merge_configs,
+    planned_dest, read_config, read_wordlist, resolve_paths, run_post_command, write_action_log,
+    write_default_config, ActionLog, ArchiveFormat, Config, CrackOptions, ExtractOptions, Outcome,
+    PartialConfig, PartialConfigSettings, PartialUserConfig, PathStyle, SmartModeOptions,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "cascading-extract",
+    version,
+    about = "Extracts 7z, tar and tar.gz archives and cracks the 7z ones"
+)]
+struct Cli {
+    #[command(flatten)]
+    overrides: Overrides,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Flags that override `settings.toml` for this run.
+#[derive(Debug, Args)]
+struct Overrides {
+    /// Threads trying passwords in parallel
+    #[arg(long, global = true)]
+    threads: Option<u8>,
+    /// Destination folder, may use the placeholders of `dest` in settings.toml
+    #[arg(long, global = true)]
+    dest: Option<String>,
+    #[arg(long, global = true)]
+    smart_mode: Option<bool>,
+    #[arg(long, global = true)]
+    delete_archive: Option<bool>,
+    #[arg(long, global = true)]
+    recursive_search: Option<bool>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Extracts archives, trying the given passwords or else the configured ones
+    Extract {
+        /// Archives, folders to search or glob patterns like "downloads/**/*.7z"
+        #[arg(required = true)]
+        archives: Vec<String>,
+        /// Can be given several times
+        #[arg(long = "password", short = 'p')]
+        passwords: Vec<String>,
+        /// Finds the password first, then extracts showing a progress bar.
+        /// Needs a build with the progress_bar feature
+        #[arg(long)]
+        progress: bool,
+    },
+    /// Tries every password of a wordlist, one per line, against an archive
+    Crack {
+        archive: PathBuf,
+        #[arg(long)]
+        wordlist: PathBuf,
+    },
+    /// Watches the configured folders and extracts new archives until Ctrl+C
+    #[cfg(feature = "async")]
+    Watch,
+    /// Writes a settings.toml with the default settings to start from
+    InitConfig {
+        #[arg(default_value = "settings.toml")]
+        path: PathBuf,
+    },
+}
+
+impl Overrides {
+    fn into_partial(self) -> PartialConfig {
+        PartialConfig {
+            config: PartialConfigSettings {
+                threads: self.threads,
+                dest: self.dest,
+                smart_mode: self.smart_mode,
+                delete_archive: self.delete_archive,
+                recursive_search: self.recursive_search,
+                ..Default::default()
+            },
+            user: PartialUserConfig::default(),
+        }
+    }
+}
+
+/// Runs the command line in `args`, the first item being the program name
+/// as with `std::env::args`. `settings.toml` provides the defaults, which
+/// the flags override.
+pub fn run_from_args(
+    args: impl Iterator<Item = String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        // --help and --version
+        Err(e) if !e.use_stderr() => {
+            e.print()?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Command::InitConfig { path } = &cli.command {
+        write_default_config(path)?;
+        log_tr!(info, "已写入 {}", "wrote {}", path.display());
+        return Ok(());
+    }
+    let config = merge_configs(read_config()?, cli.overrides.into_partial());
+    enable_log_file(&config.config)?;
+
+    match cli.command {
+        Command::Extract {
+            archives,
+            passwords,
+            progress,
+        } => {
+            let passwords = if passwords.is_empty() {
+                // unencrypted archives open with the empty password
+                std::iter::once(String::new())
+                    .chain(config.user.passwords.iter().flatten().cloned())
+                    .collect()
+            } else {
+                passwords
+            };
+            let passwords = passwords.into_iter().map(Arc::new).collect::<Vec<_>>();
+            let archives = resolve_paths(&archives, config.config.recursive_search)?;
+            if archives.is_empty() {
+                return Err("no archives to extract".into());
+            }
+            // one archive that fails doesn't keep the others from extracting
+            let mut failed = 0;
+            for archive in &archives {
+                if let Err(e) = crack(archive, &passwords, &config, progress) {
+                    log_tr!(error, "{}", "{}", e);
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                return Err(format!("{} of {} archives failed", failed, archives.len()).into());
+            }
+        }
+        Command::Crack { archive, wordlist } => {
+            let passwords = read_wordlist(wordlist, config.config.shard()?)?;
+            let password = crack(&archive, &passwords, &config, false)?;
+            println!("{}", password);
+        }
+        #[cfg(feature = "async")]
+        Command::Watch => {
+            use std::sync::atomic::{AtomicBool, Ordering};
+
+            let shutdown = Arc::new(AtomicBool::new(false));
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            builder.enable_all();
+            if config.config.low_priority {
+                builder.on_thread_start(crate::lower_thread_priority_or_warn);
+            }
+            let runtime = builder.build()?;
+            runtime.block_on(async {
+                let stop = shutdown.clone();
+                tokio::spawn(async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        log_tr!(
+                            info,
+                            "收到退出信号，停止监视",
+                            "shutdown signal received, stopping the watcher"
+                        );
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                });
+                crate::watch_and_extract(&config, shutdown.clone()).await;
+            });
+        }
+        Command::InitConfig { .. } => unreachable!("handled before reading the config"),
+    }
+    Ok(())
+}
+
+/// Extracts `archive` with the first of `passwords` that works and returns
+/// that password. Archives other than 7z have no password to find and are
+/// extracted as they are, returning the empty password. With `progress` the password is looked for first and the
+/// extraction then draws a progress bar, which the parallel attempts of a
+/// crack can't drive.
+fn crack(
+    archive: &Path,
+    passwords: &[Arc<String>],
+    config: &Config,
+    progress: bool,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let base_dest = base_dest_for(archive, None, &config.config.dest)?;
+    let dest = planned_dest(
+        archive,
+        &base_dest,
+        &SmartModeOptions::from_config(&config.config),
+    )?;
+    log_tr!(
+        info,
+        "开始处理 {} -> {}",
+        "processing {} -> {}",
+        archive.display(),
+        dest.display()
+    );
+    let mut action_log = config
+        .config
+        .write_action_log
+        .then(|| ActionLog::start(archive, &base_dest, &dest, config.config.smart_mode));
+
+    let format = config.config.detect_archive_format(archive).ok().flatten();
+    let outcome = match format {
+        // tar and tar.gz have no encryption, there is no password to find
+        Some(format) if format != ArchiveFormat::SevenZ => {
+            let options = ExtractOptions {
+                record_written_files: action_log.as_ref().map(|_| PathStyle::Relative),
+                ..ExtractOptions::from_config(&config.config)
+            };
+            let result = extract_any(archive, "", &dest, &options);
+            if let Some(mut log) = action_log.take() {
+                match &result {
+                    Ok(stats) => {
+                        log.record_stats(stats);
+                        log.outcome = "extracted".to_string();
+                    }
+                    Err(_) => log.outcome = "failed".to_string(),
+                }
+                write_action_log(log, &base_dest);
+            }
+            if let Err(e) = result {
+                return Err(format!("failed to extract {}: {}", archive.display(), e).into());
+            }
+            Outcome::Found(String::new())
+        }
+        #[cfg(feature = "progress_bar")]
+        _ if progress => extract_showing_progress(archive, passwords, &dest, config)?,
+        _ => {
+            if progress {
+                log_tr!(
+                    warn,
+                    "此版本未启用 progress_bar 功能，不显示进度条",
+                    "built without the progress_bar feature, no progress bar is shown"
+                );
+            }
+            crack_password_blocking(
+                &[archive],
+                passwords,
+                &dest,
+                config.config.threads as usize,
+                &CrackOptions::from_config(&config.config),
+            )
+        }
+    };
+    if let Some(mut log) = action_log {
+        log.record_outcome(&outcome, passwords);
+        if let Outcome::Found(password) = &outcome {
+            log.record_listing(password);
+        }
+        write_action_log(log, &base_dest);
+    }
+    match outcome {
+        Outcome::Found(password) => {
+            let hook = match &config.config.post_command {
+                Some(command) => run_post_command(command, archive, &dest).map(|_| ()),
+                None => Ok(()),
+            };
+            if config.config.delete_archive {
+                if let Err(e) = delete_extracted_archive(archive, &dest) {
+                    log_tr!(
+                        warn,
+                        "删除压缩包失败 {}: {}",
+                        "failed to delete archive {}: {}",
+                        archive.display(),
+                        e
+                    );
+                }
+            }
+            // the files stay extracted, but the run still fails
+            hook?;
+            Ok(password)
+        }
+        Outcome::NotFound { tried } => Err(format!(
+            "none of the passwords opened {} ({} attempts)",
+            archive.display(),
+            tried
+        )
+        .into()),
+        Outcome::TimedOut { tried } => Err(format!(
+            "gave up on {} after max_runtime_secs ({} attempts)",
+            archive.display(),
+            tried
+        )
+        .into()),
+        Outcome::Cancelled { tried } => {
+            Err(format!("cancelled {} after {} attempts", archive.display(), tried).into())
+        }
+        Outcome::ExtractFailed { error, .. } => Err(format!(
+            "found the password of {}, but extracting it failed: {}",
+            archive.display(),
+            error
+        )
+        .into()),
+        Outcome::Skipped { reason } => {
+            Err(format!("skipped {}: {}", archive.display(), reason).into())
+        }
+    }
+}
+
+#[cfg(feature = "progress_bar")]
+fn extract_showing_progress(
+    archive: &Path,
+    passwords: &[Arc<String>],
+    dest: &Path,
+    config: &Config,
+) -> Result<Outcome, crate::CascadeError> {
+    let passwords: Vec<String> = passwords.iter().map(|p| p.to_string()).collect();
+    let threads = config.config.threads as usize;
+    let Some(password) = crate::find_password(archive, &passwords, threads)? else {
+        return Ok(Outcome::NotFound {
+            tried: passwords.len() as u64,
+        });
+    };
+    let options = crate::ExtractOptions::from_config(&config.config);
+    crate::extract_with_progress_bar(archive, &password, dest, &options)?;
+    Ok(Outcome::Found(password))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        std::iter::once("cascading-extract")
+            .chain(line.split_whitespace())
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = Cli::try_parse_from(args("extract a.7z -p 1 --password 2 --threads 3")).unwrap();
+        assert_eq!(cli.overrides.threads, Some(3));
+        assert!(matches!(
+            cli.command,
+            Command::Extract { passwords, .. } if passwords == ["1", "2"]
+        ));
+        let cli = Cli::try_parse_from(args("--smart-mode false crack a.7z --wordlist w")).unwrap();
+        assert_eq!(cli.overrides.smart_mode, Some(false));
+        assert!(Cli::try_parse_from(args("crack a.7z")).is_err());
+        let cli = Cli::try_parse_from(args("extract a.7z downloads/*.7z")).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Extract { archives, .. } if archives == ["a.7z", "downloads/*.7z"]
+        ));
+        assert!(Cli::try_parse_from(args("extract")).is_err());
+    }
+
+    #[test]
+    fn test_run_from_args_extract_pattern() {
+        let dir = test_dir("cli_extract_pattern");
+        fs::create_dir_all(dir.join("in")).unwrap();
+        fs::copy("tests/7ziplogo.7z", dir.join("in/one.7z")).unwrap();
+        fs::copy("tests/7ziplogo_p.7z", dir.join("in/two.7z")).unwrap();
+        let dest = dir.join("out");
+
+        let line = format!(
+            "extract {}/in/*.7z -p 1151 --dest {} --delete-archive false --smart-mode false",
+            dir.display(),
+            dest.display()
+        );
+        run_from_args(args(&line)).unwrap();
+        assert!(dest.join("7ziplogo.png").exists());
+        assert!(dir.join("in/one.7z").exists());
+
+        let line = format!(
+            "extract {}/in/*.rar --dest {}",
+            dir.display(),
+            dest.display()
+        );
+        assert!(run_from_args(args(&line)).is_err());
+    }
+
+    #[test]
+    fn test_run_from_args_extract_tar_gz() {
+        let dir = test_dir("cli_extract_tar_gz");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("src.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            fs::File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        builder
+            .append_path_with_name("tests/7zFormat.txt", "7zFormat.txt")
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        let dest = dir.join("out");
+
+        let line = format!(
+            "extract {} -p 1151 --dest {} --delete-archive false --smart-mode false",
+            archive.display(),
+            dest.display()
+        );
+        run_from_args(args(&line)).unwrap();
+        assert_eq!(
+            fs::read(dest.join("7zFormat.txt")).unwrap(),
+            fs::read("tests/7zFormat.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_run_from_args_init_config() {
+        let dir = test_dir("cli_init_config");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        run_from_args(args(&format!("init-config {}", path.display()))).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), crate::DEFAULT_CONFIG);
+        assert!(run_from_args(args(&format!("init-config {}", path.display()))).is_err());
+    }
+
+    #[test]
+    fn test_run_from_args_crack() {
+        let dir = test_dir("cli_crack");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("7ziplogo_p.7z");
+        fs::copy("tests/7ziplogo_p.7z", &archive).unwrap();
+        let wordlist = dir.join("words.txt");
+        fs::write(&wordlist, "0000\n1151\n").unwrap();
+        let dest = dir.join("out");
+
+        let line = format!(
+            "crack {} --wordlist {} --dest {} --delete-archive false --smart-mode false",
+            archive.display(),
+            wordlist.display(),
+            dest.display()
+        );
+        run_from_args(args(&line)).unwrap();
+        assert!(dest.join("7ziplogo.png").exists());
+        assert!(archive.exists());
+
+        fs::write(&wordlist, "0000\n").unwrap();
+        assert!(run_from_args(args(&line)).is_err());
+    }
+
+    #[test]
+    fn test_delete_archive_extracted_into_its_folder() {
+        let dir = test_dir("cli_delete_into_own_folder");
+        fs::create_dir_all(dir.join("keep")).unwrap();
+        fs::write(dir.join("keep.txt"), "keep").unwrap();
+        let archive = dir.join("7ziplogo_p.7z");
+        fs::copy("tests/7ziplogo_p.7z", &archive).unwrap();
+        let wordlist = dir.join("words.txt");
+        fs::write(&wordlist, "1151\n").unwrap();
+
+        let line = format!(
+            "crack {} --wordlist {} --dest {} --delete-archive true --smart-mode false",
+            archive.display(),
+            wordlist.display(),
+            dir.display()
+        );
+        run_from_args(args(&line)).unwrap();
+        assert!(!archive.exists());
+        assert!(dir.join("7ziplogo.png").exists());
+        assert!(dir.join("keep").is_dir());
+        assert!(dir.join("keep.txt").exists());
+        assert!(wordlist.exists());
+    }
+}
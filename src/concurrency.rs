@@ -0,0 +1,163 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+
+use crate::i18n::log_tr;
+
+/// Upper bound of the `threads` setting on machines with up to this many
+/// CPUs. See `max_threads`.
+pub const MAX_THREADS: usize = 8;
+
+/// Upper bound of the `threads` setting: one thread per CPU, but never less
+/// than `MAX_THREADS`, so configs written for smaller machines keep working.
+pub fn max_threads() -> usize {
+    cpu_count().clamp(MAX_THREADS, u8::MAX as usize)
+}
+
+/// The thread count for `percent` of the CPUs, e.g. `75.0` for `"75%"`,
+/// rounded and kept between 1 and `max_threads`.
+pub fn threads_for_share(percent: f64) -> usize {
+    ((cpu_count() as f64 * percent / 100.0).round() as usize).clamp(1, max_threads())
+}
+
+fn cpu_count() -> usize {
+    thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+/// A starting point for the `threads` setting, judged for the current folder.
+/// See `recommended_threads_for`.
+pub fn recommended_threads() -> usize {
+    recommended_threads_for(Path::new("."))
+}
+
+/// Picks a thread count from the number of CPUs and the kind of disk `dest`
+/// is on. Every attempt writes to the destination, so on a spinning disk
+/// more than two threads mostly add seeking; SSDs (and disks we can't
+/// identify) get one thread per CPU. The result is between 1 and
+/// `MAX_THREADS`.
+pub fn recommended_threads_for(dest: &Path) -> usize {
+    let cpus = cpu_count();
+    let limit = if is_rotational(dest) == Some(true) {
+        2
+    } else {
+        MAX_THREADS
+    };
+    cpus.clamp(1, limit)
+}
+
+/// Lowers the CPU priority of the calling thread so extracting on it yields
+/// to interactive programs: nice 10 on Unix, background mode on Windows.
+/// Background mode and, on Linux, the lowest best-effort I/O class also
+/// make its disk access yield. Threads it spawns later inherit this on
+/// Unix. There is no way back up for the thread.
+pub fn lower_thread_priority() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        // on Linux this is the calling thread only, on other Unixes the
+        // whole process; a thread that is already nicer stays that way
+        let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        if current < 10 && unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+            const IOPRIO_BEST_EFFORT_LOWEST: libc::c_long = (2 << 13) | 7;
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_ioprio_set,
+                    IOPRIO_WHO_PROCESS,
+                    0,
+                    IOPRIO_BEST_EFFORT_LOWEST,
+                )
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Threading::{
+            GetCurrentThread, SetThreadPriority, THREAD_MODE_BACKGROUND_BEGIN,
+        };
+        if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// `lower_thread_priority` for the `low_priority` setting, where failing to
+/// lower it is no reason to stop extracting.
+pub(crate) fn lower_thread_priority_or_warn() {
+    if let Err(e) = lower_thread_priority() {
+        log_tr!(
+            warn,
+            "无法降低线程优先级: {}",
+            "cannot lower the thread priority: {}",
+            e
+        );
+    }
+}
+
+/// Whether `path` is on a spinning disk, or `None` if that can't be told.
+/// Only implemented on Linux, through `/sys/dev/block`.
+fn is_rotational(path: &Path) -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // destinations are often created later, so look at the nearest
+        // existing ancestor
+        let existing = path.ancestors().find(|p| p.exists())?;
+        let dev = fs::metadata(existing).ok()?.dev();
+        let major = ((dev >> 32) & !0xfff) | ((dev >> 8) & 0xfff);
+        let minor = ((dev >> 12) & !0xff) | (dev & 0xff);
+        let block = Path::new("/sys/dev/block").join(format!("{}:{}", major, minor));
+        // partitions keep the queue settings on their parent device
+        let rotational = fs::read_to_string(block.join("queue/rotational"))
+            .or_else(|_| fs::read_to_string(block.join("../queue/rotational")))
+            .ok()?;
+        Some(rotational.trim() == "1")
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recommended_threads() {
+        let threads = recommended_threads();
+        assert!((1..=MAX_THREADS).contains(&threads));
+        assert!(recommended_threads_for(Path::new("does/not/exist/yet")) >= 1);
+    }
+
+    #[test]
+    fn test_lower_thread_priority() {
+        thread::spawn(|| {
+            lower_thread_priority().unwrap();
+            // a second call keeps what the first one set
+            lower_thread_priority().unwrap();
+            #[cfg(unix)]
+            assert!(unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) } >= 10);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_threads_for_share() {
+        assert!(max_threads() >= MAX_THREADS);
+        assert_eq!(threads_for_share(100.0), cpu_count().min(max_threads()));
+        assert_eq!(threads_for_share(0.1), 1);
+        assert_eq!(threads_for_share(1000.0), max_threads());
+    }
+}
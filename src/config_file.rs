@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item};
+
+use crate::i18n::log_tr;
+use crate::wordlist::password_from_line;
+use crate::{max_threads, CascadeError, Config, DEFAULT_CONFIG};
+
+/// Tidies up a hand-edited `settings.toml` in place, e.g. for a setup
+/// wizard: settings missing from it are added as `DEFAULT_CONFIG` has them,
+/// with their comments, a `threads` count outside 1 to `max_threads` is
+/// clamped, and passwords listed twice are kept once, at their first
+/// position. Everything else, comments included, is left as written.
+///
+/// A missing file is created from `DEFAULT_CONFIG`. The file is only
+/// written when the result reads as a valid config; otherwise it is left
+/// alone and the reason returned.
+pub fn normalize_config_file<P: AsRef<Path>>(path: P) -> Result<(), CascadeError> {
+    let path = path.as_ref();
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DEFAULT_CONFIG.to_string(),
+        Err(e) => return Err(e.into()),
+    };
+    let normalized = normalize_config(&content)?;
+    if normalized != content {
+        log_tr!(
+            info,
+            "已整理配置文件 {}",
+            "normalized the config file {}",
+            path.display()
+        );
+    }
+    fs::write(path, normalized)?;
+    Ok(())
+}
+
+fn normalize_config(content: &str) -> Result<String, CascadeError> {
+    let mut document: DocumentMut = content.parse().map_err(invalid_config)?;
+    let defaults: DocumentMut = DEFAULT_CONFIG
+        .parse()
+        .expect("the default config is valid TOML");
+
+    for (section, default) in defaults.iter() {
+        let Some(default) = default.as_table() else {
+            continue;
+        };
+        let table = document
+            .entry(section)
+            .or_insert_with(|| Item::Table(toml_edit::Table::new()));
+        let Some(table) = table.as_table_mut() else {
+            return Err(invalid_config(format!("{} is not a table", section)));
+        };
+        for (key, value) in default.iter() {
+            if !table.contains_key(key) {
+                table.insert(key, value.clone());
+                // keep the comments that come with the default
+                if let (Some(mut added), Some(original)) = (table.key_mut(key), default.key(key)) {
+                    *added.leaf_decor_mut() = original.leaf_decor().clone();
+                }
+            }
+        }
+    }
+
+    if let Some(threads) = document["config"].get_mut("threads") {
+        if let Some(count) = threads.as_integer() {
+            let clamped = count.clamp(1, max_threads() as i64);
+            if clamped != count {
+                let decor = threads.as_value().map(|value| value.decor().clone());
+                *threads = toml_edit::value(clamped);
+                if let (Some(decor), Some(value)) = (decor, threads.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+        }
+    }
+
+    if let Some(passwords) = document["user"]
+        .get_mut("passwords")
+        .and_then(Item::as_array_mut)
+    {
+        let mut seen = HashSet::new();
+        passwords.retain(|entry| match entry.as_str().and_then(password_from_line) {
+            Some(password) => seen.insert(password.to_string()),
+            // comments and blank entries don't count
+            None => true,
+        });
+    }
+
+    let normalized = document.to_string();
+    toml::from_str::<Config>(&normalized).map_err(invalid_config)?;
+    Ok(normalized)
+}
+
+fn invalid_config(e: impl ToString) -> CascadeError {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use toml_edit::Value;
+
+    fn password_values(document: &DocumentMut) -> Vec<&str> {
+        document["user"]["passwords"]
+            .as_array()
+            .map(|array| array.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_normalize_config_file() {
+        let dir = test_dir("normalize_config_file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        fs::write(
+            &path,
+            r##"[config]
+# my own comment
+delete_archive = false
+threads = 0
+
+[user]
+passwords = ["1151", "# work", " 1151", "abc", "abc"]
+"##,
+        )
+        .unwrap();
+        normalize_config_file(&path).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("# my own comment\ndelete_archive = false"));
+        assert!(content.contains("threads = 1"));
+        // added with the comment from the default config
+        assert!(content.contains("# smart mode will create a new folder"));
+        let document: DocumentMut = content.parse().unwrap();
+        assert_eq!(password_values(&document), ["1151", "# work", "abc"]);
+        let config: Config = toml::from_str(&content).unwrap();
+        assert!(!config.config.delete_archive);
+        assert!(config.config.smart_mode);
+
+        // nothing left to do the second time
+        normalize_config_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        fs::write(&path, "[config]\nthreads = \"lots\"\n").unwrap();
+        assert!(normalize_config_file(&path).is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[config]\nthreads = \"lots\"\n"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Datelike, Local, Timelike};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::{catch_backend_panic, is_symlink_entry, open_archive_reader, CascadeError};
+
+/// Writes the contents of a 7z archive to a new zip at `out_zip`, entry by
+/// entry, without extracting anything to disk first. Folders, symlinks and
+/// modification times are kept; the zip itself is not encrypted. The zip is
+/// written next to `out_zip` first and only moved there once complete, so a
+/// failed conversion, e.g. with a wrong password, leaves no partial zip and
+/// keeps a file that was at `out_zip` before.
+pub fn convert_7z_to_zip<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    password: &str,
+    out_zip: Q,
+) -> Result<(), CascadeError> {
+    let out_zip = out_zip.as_ref();
+    let mut temp = out_zip.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = write_zip(path.as_ref(), password, &temp)
+        .and_then(|()| fs::rename(&temp, out_zip).map_err(CascadeError::from));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+fn write_zip(path: &Path, password: &str, out_zip: &Path) -> Result<(), CascadeError> {
+    let mut reader = open_archive_reader(path, password)?;
+    let mut zip = ZipWriter::new(File::create(out_zip)?);
+
+    catch_backend_panic(path, || {
+        Ok(reader.for_each_entries(|entry, data| {
+            let name = entry.name().replace('\\', "/");
+            let mut options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(entry.size() >= u32::MAX as u64);
+            if entry.has_last_modified_date {
+                if let Some(modified) = zip_time(entry.last_modified_date().into()) {
+                    options = options.last_modified_time(modified);
+                }
+            }
+
+            if entry.is_directory() {
+                zip.add_directory(name, options).map_err(zip_error)?;
+            } else if is_symlink_entry(entry) {
+                let mut target = String::new();
+                data.read_to_string(&mut target)?;
+                zip.add_symlink(name, target, options).map_err(zip_error)?;
+            } else {
+                zip.start_file(name, options).map_err(zip_error)?;
+                // a wrong password can decode to a stream that ends early
+                // instead of failing the CRC check
+                if io::copy(data, &mut zip)? != entry.size() {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+            }
+            Ok(true)
+        })?)
+    })?;
+
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// Zip stores local time with two second precision, and nothing before 1980.
+fn zip_time(time: SystemTime) -> Option<zip::DateTime> {
+    let local = DateTime::<Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(local.year()).ok()?,
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .ok()
+}
+
+pub(crate) fn zip_error(e: zip::result::ZipError) -> io::Error {
+    match e {
+        zip::result::ZipError::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::io::Read;
+
+    #[test]
+    fn test_convert_7z_to_zip() {
+        let dir = test_dir("convert_7z_to_zip");
+        fs::create_dir_all(dir.join("content/docs")).unwrap();
+        fs::copy("tests/7zFormat.txt", dir.join("content/docs/7zFormat.txt")).unwrap();
+        fs::copy("tests/7ziplogo.png", dir.join("content/7ziplogo.png")).unwrap();
+        let archive = dir.join("content.7z");
+        sevenz_rust::compress_to_path_encrypted(dir.join("content"), &archive, "1151".into())
+            .unwrap();
+        let modified = fs::metadata(dir.join("content/docs/7zFormat.txt"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        let out = dir.join("content.zip");
+        assert!(convert_7z_to_zip(&archive, "0000", &out).is_err());
+        // nothing is left behind, neither the zip nor its temporary file
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        convert_7z_to_zip(&archive, "1151", &out).unwrap();
+
+        // a failure keeps the zip converted before
+        let converted = fs::read(&out).unwrap();
+        assert!(convert_7z_to_zip(&archive, "0000", &out).is_err());
+        assert_eq!(fs::read(&out).unwrap(), converted);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        let mut zip = zip::ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        assert!(zip.by_name("docs/").unwrap().is_dir());
+        let mut file = zip.by_name("docs/7zFormat.txt").unwrap();
+        assert_eq!(file.last_modified(), zip_time(modified));
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        assert_eq!(content, fs::read("tests/7zFormat.txt").unwrap());
+        drop(file);
+        assert_eq!(
+            zip.by_name("7ziplogo.png").unwrap().size(),
+            fs::metadata("tests/7ziplogo.png").unwrap().len()
+        );
+    }
+}
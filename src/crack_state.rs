@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::log_tr;
+use crate::CascadeError;
+
+/// How often a cracking run with `CrackOptions::state_file` saves where it
+/// got to.
+pub const CRACK_STATE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where a cracking run got to in its password list, saved to
+/// `CrackOptions::state_file` and picked up again through
+/// `CrackOptions::resume_from`, so a crack of many hours survives a
+/// restart. Like a `Shard`, it refers to the list after case variants are
+/// added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrackState {
+    /// Every password before this index was tried against every archive.
+    /// Attempts finish out of order, so some after it may have been tried
+    /// too, and are tried again on resume.
+    pub next_index: usize,
+    /// How long the list was, to notice a different list on resume.
+    pub password_count: usize,
+}
+
+impl CrackState {
+    /// Reads a state written by a cracking run, `None` when there is no file,
+    /// as before the first run.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>, CascadeError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(
+                serde_json::from_str(&content).map_err(io::Error::from)?,
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the state as JSON, through a temporary file so a crash while
+    /// saving leaves the previous state rather than half of one.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, serde_json::to_string(self)?)?;
+        fs::rename(&temp, path)
+    }
+}
+
+/// Keeps the `CrackState` of a running crack as its attempts finish, and
+/// saves it now and then.
+#[derive(Debug)]
+pub(crate) struct CrackStateTracker {
+    path: Option<PathBuf>,
+    state: CrackState,
+    /// finished indices past `state.next_index`
+    done: BTreeSet<usize>,
+    last_saved: Instant,
+}
+
+impl CrackStateTracker {
+    /// Starts at `resume_from` when it was saved for a list of
+    /// `password_count` passwords, or else at the start of the list.
+    pub(crate) fn new(
+        path: Option<PathBuf>,
+        resume_from: Option<CrackState>,
+        password_count: usize,
+    ) -> Self {
+        let next_index = match resume_from {
+            Some(state) if state.password_count == password_count => {
+                log_tr!(
+                    info,
+                    "从第 {} 个密码继续上次的破解",
+                    "resuming the crack at password {}",
+                    state.next_index
+                );
+                state.next_index.min(password_count)
+            }
+            Some(state) => {
+                log_tr!(
+                    warn,
+                    "保存的进度属于 {} 个密码的列表，而不是 {} 个，从头开始",
+                    "the saved position is for a list of {} passwords, not {}, starting over",
+                    state.password_count,
+                    password_count
+                );
+                0
+            }
+            None => 0,
+        };
+        CrackStateTracker {
+            path,
+            state: CrackState {
+                next_index,
+                password_count,
+            },
+            done: BTreeSet::new(),
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// The first index that still has to be tried.
+    pub(crate) fn start(&self) -> usize {
+        self.state.next_index
+    }
+
+    /// Records that the password at `index` was tried against every archive,
+    /// saving the state once `CRACK_STATE_SAVE_INTERVAL` has passed.
+    pub(crate) fn finish(&mut self, index: usize) {
+        self.done.insert(index);
+        while self.done.remove(&self.state.next_index) {
+            self.state.next_index += 1;
+        }
+        if self.last_saved.elapsed() >= CRACK_STATE_SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    /// Saves the state one last time, or removes the file once the password
+    /// was found and there is nothing left to resume. Attempts that finish
+    /// after this aren't saved anymore.
+    pub(crate) fn end(&mut self, found: bool) {
+        if !found {
+            self.save();
+        }
+        if let Some(path) = self.path.take() {
+            if found {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        self.last_saved = Instant::now();
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = self.state.save(path) {
+            log_tr!(
+                warn,
+                "无法保存破解进度到 {}: {}",
+                "cannot save the crack position to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_crack_state_tracker() {
+        let dir = test_dir("crack_state_tracker");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crack.json");
+        assert_eq!(CrackState::load(&path).unwrap(), None);
+
+        let mut tracker = CrackStateTracker::new(Some(path.clone()), None, 10);
+        assert_eq!(tracker.start(), 0);
+        // out of order, 1 is still running
+        for index in [0, 2, 3] {
+            tracker.finish(index);
+        }
+        tracker.end(false);
+        let state = CrackState::load(&path).unwrap().unwrap();
+        assert_eq!(
+            state,
+            CrackState {
+                next_index: 1,
+                password_count: 10
+            }
+        );
+
+        assert_eq!(CrackStateTracker::new(None, Some(state), 10).start(), 1);
+        // a different list starts over
+        assert_eq!(CrackStateTracker::new(None, Some(state), 11).start(), 0);
+
+        CrackStateTracker::new(Some(path.clone()), Some(state), 10).end(true);
+        assert!(!path.exists());
+        let mut tracker = CrackStateTracker::new(Some(path.clone()), None, 10);
+        tracker.end(true);
+        tracker.last_saved -= CRACK_STATE_SAVE_INTERVAL;
+        tracker.finish(0);
+        assert!(!path.exists());
+    }
+}
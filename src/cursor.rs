@@ -0,0 +1,339 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use sevenz_rust::{BlockDecoder, SevenZArchiveEntry};
+
+use crate::i18n::log_tr;
+use crate::throttle::RateLimiter;
+use crate::{
+    apply_attributes, catch_backend_panic, check_archive_limits, clear_attributes,
+    create_symlink_in, free_path, is_symlink_entry, open_archive_file, visit_order, write_entry,
+    AttributePolicy, CascadeError, EntryOutcome, EntryReport, ExtractOptions, ExtractStats,
+    FailedEntry, OverwritePolicy, SymlinkPolicy,
+};
+
+/// Extracts a 7z archive one entry per `step`, for callers driving their
+/// own event loop, such as a single-threaded GUI, that want to do other
+/// work between entries without a thread or an async runtime. Between steps
+/// the cursor keeps the archive file open and its header read with the
+/// password, so a step only decodes and writes.
+///
+/// Entries come in the order `extract_with_options` visits them. Of the
+/// options only the archive limits, checked by `open`, and those about a
+/// single entry apply: the extension filters, `max_file_size`,
+/// `overwrite_policy`, `files_only`, `symlink_policy`, where `Deref` creates
+/// the link as there is no end of the run to copy the target at,
+/// `attribute_policy`, `continue_on_entry_error` and `max_bytes_per_sec`,
+/// over all the steps together.
+///
+/// `sevenz_rust` can't pause a decoder, so a step into a solid block decodes
+/// the block again from its start up to the entry. Entries that are skipped
+/// aren't decoded at all.
+pub struct ExtractionCursor {
+    path: PathBuf,
+    file: BufReader<File>,
+    archive: sevenz_rust::Archive,
+    password: sevenz_rust::Password,
+    dest: PathBuf,
+    options: ExtractOptions,
+    /// indices into `archive.files`, in the order of `visit_order`
+    order: Vec<usize>,
+    next: usize,
+    stats: ExtractStats,
+    limiter: Option<RateLimiter>,
+}
+
+impl ExtractionCursor {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        password: &str,
+        dest: &Path,
+        options: &ExtractOptions,
+    ) -> Result<Self, CascadeError> {
+        let path = path.as_ref();
+        check_archive_limits(path, password, options)?;
+        let mut file = open_archive_file(path)?;
+        let len = file.get_ref().metadata()?.len();
+        let password = sevenz_rust::Password::from(password);
+        let archive = catch_backend_panic(path, || {
+            Ok(sevenz_rust::Archive::read(
+                &mut file,
+                len,
+                password.as_slice(),
+            )?)
+        })?;
+        Ok(ExtractionCursor {
+            path: path.to_path_buf(),
+            file,
+            order: visit_order(&archive),
+            archive,
+            password,
+            dest: dest.to_path_buf(),
+            options: options.clone(),
+            next: 0,
+            stats: ExtractStats::default(),
+            limiter: RateLimiter::for_rate(options.max_bytes_per_sec),
+        })
+    }
+
+    /// Entries not stepped through yet.
+    pub fn remaining(&self) -> usize {
+        self.order.len() - self.next
+    }
+
+    /// What the steps so far did, counted as by `extract_with_options`.
+    pub fn stats(&self) -> &ExtractStats {
+        &self.stats
+    }
+
+    pub fn into_stats(self) -> ExtractStats {
+        self.stats
+    }
+
+    /// Extracts the next entry and reports what became of it, or returns
+    /// `None` once every entry was visited. After an error the next step
+    /// goes on with the following entry; whether to take it is up to the
+    /// caller.
+    pub fn step(&mut self) -> Option<Result<EntryReport, CascadeError>> {
+        let index = *self.order.get(self.next)?;
+        self.next += 1;
+        let report = self.extract(index).map(|outcome| EntryReport {
+            name: self.archive.files[index].name().to_string(),
+            outcome,
+        });
+        if let (Ok(report), true) = (&report, self.options.record_entry_outcomes) {
+            self.stats.entries.push(report.clone());
+        }
+        Some(report)
+    }
+
+    fn extract(&mut self, index: usize) -> Result<EntryOutcome, CascadeError> {
+        let entry = &self.archive.files[index];
+        let options = &self.options;
+        let stats = &mut self.stats;
+        let mut target = self.dest.join(entry.name());
+
+        if entry.is_directory() {
+            if options.files_only {
+                stats.skipped_directories += 1;
+                return Ok(EntryOutcome::SkippedFilter);
+            }
+            fs::create_dir_all(&target)?;
+            stats.directories += 1;
+            return Ok(EntryOutcome::Extracted);
+        }
+        let is_symlink = is_symlink_entry(entry);
+        if is_symlink && options.symlink_policy == SymlinkPolicy::Skip {
+            log_tr!(info, "跳过符号链接 {}", "skipping symlink {}", entry.name());
+            stats.skipped += 1;
+            return Ok(EntryOutcome::SkippedFilter);
+        }
+        if !options.allows_extension(entry.name()) {
+            stats.skipped += 1;
+            stats.filtered.push(entry.name().to_string());
+            return Ok(EntryOutcome::SkippedFilter);
+        }
+        if options.max_file_size.is_some_and(|max| entry.size() > max) {
+            log_tr!(
+                warn,
+                "文件过大 ({} 字节)，跳过 {}",
+                "file too large ({} bytes), skipping {}",
+                entry.size(),
+                entry.name()
+            );
+            stats.skipped += 1;
+            stats.too_large.push(entry.name().to_string());
+            return Ok(EntryOutcome::SkippedTooLarge);
+        }
+        if fs::symlink_metadata(&target).is_ok() {
+            match options.overwrite_policy {
+                OverwritePolicy::Overwrite => {
+                    let _ = clear_attributes(&target);
+                }
+                OverwritePolicy::Skip => {
+                    log_tr!(
+                        info,
+                        "文件已存在，跳过 {}",
+                        "file exists, skipping {}",
+                        target.display()
+                    );
+                    stats.skipped += 1;
+                    return Ok(EntryOutcome::SkippedExists);
+                }
+                OverwritePolicy::Rename => target = free_path(&target),
+            }
+        }
+
+        let (file, archive, password, dest, limiter) = (
+            &mut self.file,
+            &self.archive,
+            &self.password,
+            &self.dest,
+            &mut self.limiter,
+        );
+        let written = catch_backend_panic(&self.path, || {
+            Ok(with_entry_reader(
+                file,
+                archive,
+                password,
+                index,
+                |reader| {
+                    if is_symlink {
+                        let mut link_target = String::new();
+                        reader.read_to_string(&mut link_target)?;
+                        create_symlink_in(dest, &link_target, &target)?;
+                        return Ok(());
+                    }
+                    write_entry(entry, reader, &target, options.files_only, limiter.as_mut())
+                        .map(drop)
+                },
+            )?)
+        });
+        match written {
+            Ok(()) => {}
+            Err(e) if options.continue_on_entry_error => {
+                log_tr!(
+                    warn,
+                    "解压 {} 失败，已跳过: {}",
+                    "failed to extract {}, skipped: {}",
+                    entry.name(),
+                    e
+                );
+                let _ = fs::remove_file(&target);
+                stats.failed_entries.push(FailedEntry {
+                    name: entry.name().to_string(),
+                    error: e.to_string(),
+                });
+                return Ok(EntryOutcome::Failed(e.to_string()));
+            }
+            Err(e) => return Err(e),
+        }
+
+        if is_symlink {
+            stats.symlinks += 1;
+            return Ok(EntryOutcome::Extracted);
+        }
+        let policy = match options.files_only {
+            true => AttributePolicy::Clear,
+            false => options.attribute_policy,
+        };
+        if let Err(e) = apply_attributes(entry, &target, policy) {
+            log_tr!(
+                warn,
+                "无法设置 {} 的文件属性: {}",
+                "cannot set the attributes of {}: {}",
+                target.display(),
+                e
+            );
+        }
+        stats.files += 1;
+        stats.bytes_written += entry.size();
+        Ok(EntryOutcome::Extracted)
+    }
+}
+
+impl Iterator for ExtractionCursor {
+    type Item = Result<EntryReport, CascadeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
+    }
+}
+
+/// Hands `f` the data of `archive.files[index]`, decoding the entries before
+/// it in the same block into the void.
+fn with_entry_reader(
+    file: &mut BufReader<File>,
+    archive: &sevenz_rust::Archive,
+    password: &sevenz_rust::Password,
+    index: usize,
+    f: impl FnOnce(&mut dyn Read) -> Result<(), sevenz_rust::Error>,
+) -> Result<(), sevenz_rust::Error> {
+    let Some(block) = archive.stream_map.file_folder_index[index] else {
+        return f(&mut io::empty());
+    };
+    let mut before = index - archive.stream_map.folder_first_file_index[block];
+    let mut f = Some(f);
+    let mut each = |_: &SevenZArchiveEntry, reader: &mut dyn Read| {
+        if before > 0 {
+            before -= 1;
+            io::copy(reader, &mut io::sink())?;
+            return Ok(true);
+        }
+        if let Some(f) = f.take() {
+            f(reader)?;
+        }
+        Ok(false)
+    };
+    BlockDecoder::new(block, archive, password.as_slice(), file).for_each_entries(&mut each)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract_with_options;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_extraction_cursor() {
+        let dest = test_dir("extraction_cursor");
+        let options = ExtractOptions::default();
+        let mut cursor = ExtractionCursor::open("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(cursor.remaining(), 5);
+        let reports: Vec<EntryReport> = cursor.by_ref().map(Result::unwrap).collect();
+        assert_eq!(reports.len(), 5);
+        assert!(reports
+            .iter()
+            .all(|report| report.outcome == EntryOutcome::Extracted));
+        assert_eq!(cursor.remaining(), 0);
+        assert!(cursor.step().is_none());
+
+        let whole = test_dir("extraction_cursor_whole");
+        let expected = extract_with_options("tests/sample.7z", "", &whole, &options).unwrap();
+        let stats = cursor.into_stats();
+        assert_eq!(stats.files, expected.files);
+        assert_eq!(stats.directories, expected.directories);
+        assert_eq!(stats.bytes_written, expected.bytes_written);
+        for report in reports {
+            let path = dest.join(&report.name);
+            if path.is_file() {
+                assert_eq!(
+                    fs::read(&path).unwrap(),
+                    fs::read(whole.join(&report.name)).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_extraction_cursor_encrypted() {
+        let dest = test_dir("extraction_cursor_encrypted");
+        let mut cursor = ExtractionCursor::open(
+            "tests/7ziplogo_p.7z",
+            "1151",
+            &dest,
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+        let report = cursor.step().unwrap().unwrap();
+        assert_eq!(report.outcome, EntryOutcome::Extracted);
+        assert_eq!(
+            fs::read(dest.join("7ziplogo.png")).unwrap(),
+            fs::read("tests/7ziplogo.png").unwrap()
+        );
+
+        let options = ExtractOptions {
+            overwrite_policy: OverwritePolicy::Skip,
+            ..Default::default()
+        };
+        let mut cursor =
+            ExtractionCursor::open("tests/7ziplogo_p.7z", "1151", &dest, &options).unwrap();
+        assert_eq!(
+            cursor.step().unwrap().unwrap().outcome,
+            EntryOutcome::SkippedExists
+        );
+        assert_eq!(cursor.stats().skipped, 1);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::{Config, ConfigSettings, UserConfig};
+
+/// Settings only: none of them are secret.
+impl fmt::Display for ConfigSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Debug>(value: &Option<T>) -> String {
+            match value {
+                Some(value) => format!("{:?}", value),
+                None => "none".to_string(),
+            }
+        }
+        macro_rules! lines {
+            ($($name:ident = $value:expr),* $(,)?) => {
+                $(writeln!(f, "  {}: {}", stringify!($name), $value)?;)*
+            };
+        }
+
+        let formats: Vec<&str> = self.enabled_formats.iter().map(|f| f.name()).collect();
+        lines!(
+            delete_archive = self.delete_archive,
+            recursive_search = self.recursive_search,
+            threads = self.threads,
+            dest = format!("{:?}", self.dest),
+            smart_mode = self.smart_mode,
+            smart_mode_policy = format!("{:?}", self.smart_mode_policy),
+            smart_mode_threshold = self.smart_mode_threshold,
+            folder_collision = format!("{:?}", self.folder_collision),
+            adaptive_password_order = self.adaptive_password_order,
+            symlink_policy = format!("{:?}", self.symlink_policy),
+            max_bytes_per_sec = opt(&self.max_bytes_per_sec),
+            max_compression_ratio = self.max_compression_ratio,
+            allow_high_ratio = self.allow_high_ratio,
+            max_entries = opt(&self.max_entries),
+            max_memory = opt(&self.max_memory),
+            max_file_size = opt(&self.max_file_size),
+            cascade = self.cascade,
+            max_cascade_depth = self.max_cascade_depth,
+            max_total_extracted_bytes = opt(&self.max_total_extracted_bytes),
+            allow_extensions = format!("{:?}", self.allow_extensions),
+            skip_extensions = format!("{:?}", self.skip_extensions),
+            cleanup_on_failure = self.cleanup_on_failure,
+            atomic_extraction = self.atomic_extraction,
+            overwrite_policy = format!("{:?}", self.overwrite_policy),
+            normalize_case = opt(&self.normalize_case),
+            continue_on_entry_error = self.continue_on_entry_error,
+            entry_name_encoding = opt(&self.entry_name_encoding),
+            normalize_separators = self.normalize_separators,
+            files_only = self.files_only,
+            attribute_policy = format!("{:?}", self.attribute_policy),
+            incremental = self.incremental,
+            resume = self.resume,
+            allow_external_7z = self.allow_external_7z,
+            enabled_formats = format!("[{}]", formats.join(", ")),
+            signature_window = self.signature_window,
+            signature_cache_size = self.signature_cache_size,
+            read_buffer_size = self.read_buffer_size,
+            watch_interval_ms = self.watch_interval_ms,
+            max_concurrent_archives = self.max_concurrent_archives,
+            max_open_files = self.max_open_files,
+            ignore_hidden = self.ignore_hidden,
+            log_language = opt(&self.log_language),
+            log_passwords = self.log_passwords,
+            log_file = self.log_file,
+            log_file_max_bytes = self.log_file_max_bytes,
+            use_filename_as_password = self.use_filename_as_password,
+            try_case_variants = self.try_case_variants,
+            low_priority = self.low_priority,
+            report_rate = self.report_rate,
+            max_runtime_secs = opt(&self.max_runtime_secs),
+            wordlists = format!("{:?}", self.wordlists),
+            post_command = opt(&self.post_command),
+            write_action_log = self.write_action_log,
+            shard = format!("{} of {}", self.shard_index, self.shard_count),
+        );
+        Ok(())
+    }
+}
+
+/// Shows how many passwords there are, never the passwords themselves.
+impl fmt::Display for UserConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  passwords: {}", password_count(&self.passwords))?;
+        let folders: Vec<String> = self
+            .watch_folders
+            .iter()
+            .flatten()
+            .map(|folder| match &folder.dest {
+                Some(dest) => format!("{:?} -> {:?}", folder.path, dest),
+                None => format!("{:?}", folder.path),
+            })
+            .collect();
+        writeln!(f, "  watch_folders: [{}]", folders.join(", "))
+    }
+}
+
+/// The effective configuration, safe to log at startup.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[config]")?;
+        write!(f, "{}", self.config)?;
+        writeln!(f, "[user]")?;
+        write!(f, "{}", self.user)
+    }
+}
+
+impl UserConfig {
+    /// Like the derived `Debug`, with the password list replaced by its
+    /// length.
+    pub fn redacted_debug(&self) -> String {
+        format!(
+            "UserConfig {{ passwords: {}, watch_folders: {:?} }}",
+            password_count(&self.passwords),
+            self.watch_folders
+        )
+    }
+}
+
+impl Config {
+    /// Like the derived `Debug`, with the password list replaced by its
+    /// length.
+    pub fn redacted_debug(&self) -> String {
+        format!(
+            "Config {{ config: {:?}, user: {} }}",
+            self.config,
+            self.user.redacted_debug()
+        )
+    }
+}
+
+fn password_count(passwords: &Option<Vec<String>>) -> String {
+    match passwords {
+        Some(passwords) => format!("[{} entries]", passwords.len()),
+        None => "none".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::read_config;
+
+    #[test]
+    fn test_config_display_redacts_passwords() {
+        let config = read_config().unwrap();
+        let count = config.user.passwords.as_ref().unwrap().len();
+
+        for shown in [config.to_string(), config.redacted_debug()] {
+            assert!(shown.contains(&format!("passwords: [{} entries]", count)));
+            assert!(!shown.contains("1151"));
+        }
+        let shown = config.to_string();
+        assert!(shown.starts_with("[config]\n  delete_archive: true\n"));
+        assert!(shown.contains("  max_runtime_secs: none\n"));
+        assert!(shown.contains("  watch_folders: []\n"));
+    }
+}
@@ -0,0 +1,375 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::i18n::log_tr;
+use crate::{
+    extract_reporting, is_7z, is_7z_bytes, ArchiveSource, CascadeError, ExtractOptions,
+    ExtractStats,
+};
+
+/// Downloads up to this size, when the server announces it, are kept in
+/// memory; bigger ones and those of unknown size go to a temporary file.
+pub const URL_IN_MEMORY_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// What `extract_from_url_with_progress` reports progress on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlStage {
+    /// Counted in bytes received, out of the `Content-Length` if there is
+    /// one.
+    Downloading,
+    /// Counted in bytes written, out of the size of all files.
+    Extracting,
+}
+
+/// `extract_from_url_with_progress` with the default `ExtractOptions` and
+/// without progress reports.
+pub async fn extract_from_url(
+    url: &str,
+    password: &str,
+    dest: &Path,
+) -> Result<ExtractStats, CascadeError> {
+    extract_from_url_with_progress(
+        url,
+        password,
+        dest,
+        &ExtractOptions::default(),
+        |_, _, _| {},
+    )
+    .await
+}
+
+/// Downloads the 7z archive at `url` and extracts it to `dest` with
+/// `options`, as `extract_with_options` does for a file, so archives on the
+/// web need no manual download step. The body is streamed as it arrives,
+/// without range requests, into memory or, past `URL_IN_MEMORY_LIMIT`, a
+/// temporary file that is removed afterwards.
+///
+/// `progress` is called with the stage, the bytes done so far and the
+/// total, if known, as the download and then the extraction go on. HTTP
+/// and network errors are reported as `CascadeError::Io`. The archive is
+/// decoded on tokio's blocking pool.
+pub async fn extract_from_url_with_progress<F>(
+    url: &str,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    mut progress: F,
+) -> Result<ExtractStats, CascadeError>
+where
+    F: FnMut(UrlStage, u64, Option<u64>),
+{
+    log_tr!(info, "开始下载 {}", "downloading {}", url);
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(http_error)?;
+    let total = response.content_length();
+    let mut received = 0;
+    progress(UrlStage::Downloading, received, total);
+
+    if let Some(total) = total.filter(|&total| total <= URL_IN_MEMORY_LIMIT) {
+        let mut data = Vec::with_capacity(total as usize);
+        while let Some(chunk) = response.chunk().await.map_err(http_error)? {
+            data.extend_from_slice(&chunk);
+            received += chunk.len() as u64;
+            progress(UrlStage::Downloading, received, Some(total));
+        }
+        if !is_7z_bytes(&data) {
+            return Err(not_7z(url));
+        }
+        let download = Download::Memory(data);
+        return extract_in_background(download, password, dest, options, url, &mut progress).await;
+    }
+
+    let (temp, mut file) = TempFile::create()?;
+    while let Some(chunk) = response.chunk().await.map_err(http_error)? {
+        file.write_all(&chunk)?;
+        received += chunk.len() as u64;
+        progress(UrlStage::Downloading, received, total);
+    }
+    drop(file);
+    if !is_7z(&temp.0).unwrap_or(false) {
+        return Err(not_7z(url));
+    }
+    extract_in_background(
+        Download::File(temp),
+        password,
+        dest,
+        options,
+        url,
+        &mut progress,
+    )
+    .await
+}
+
+/// A downloaded archive.
+enum Download {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+/// `extract_reporting` on the blocking pool, passing its progress reports
+/// back to `progress` on this task. A temporary file is removed once it is
+/// done.
+async fn extract_in_background(
+    download: Download,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    url: &str,
+    progress: &mut dyn FnMut(UrlStage, u64, Option<u64>),
+) -> Result<ExtractStats, CascadeError> {
+    let (sender, mut reports) = tokio::sync::mpsc::unbounded_channel();
+    let (password, dest, url) = (password.to_string(), dest.to_path_buf(), url.to_string());
+    let options = options.clone();
+    let job = tokio::task::spawn_blocking(move || {
+        let source = match &download {
+            Download::Memory(data) => ArchiveSource::Bytes {
+                name: Path::new(&url),
+                data,
+            },
+            Download::File(temp) => ArchiveSource::File(&temp.0),
+        };
+        extract_reporting(
+            source,
+            &password,
+            &dest,
+            &options,
+            Some(&mut |report| {
+                let _ = sender.send((
+                    UrlStage::Extracting,
+                    report.bytes_done,
+                    Some(report.total_bytes),
+                ));
+            }),
+            None,
+        )
+    });
+    // ends once the job is done and has dropped the sender
+    while let Some((stage, done, total)) = reports.recv().await {
+        progress(stage, done, total);
+    }
+    job.await.map_err(io::Error::other)?
+}
+
+fn http_error(e: reqwest::Error) -> CascadeError {
+    io::Error::other(e).into()
+}
+
+fn not_7z(url: &str) -> CascadeError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a 7z archive: {}", url),
+    )
+    .into()
+}
+
+/// A download in the temp folder, removed when dropped, also when the
+/// future is dropped halfway.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Creates a file of its own in the temp folder, never opening one that
+    /// is already there, e.g. planted under a predictable name.
+    fn create() -> io::Result<(Self, File)> {
+        loop {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.subsec_nanos());
+            let path = env::temp_dir().join(format!(
+                "cascading-extract-download-{}-{}.7z",
+                std::process::id(),
+                nanos
+            ));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((TempFile(path), file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serves `body` once on a local port, announcing its length or not.
+    async fn serve_once(body: Vec<u8>, with_length: bool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let length = if with_length {
+                format!("Content-Length: {}\r\n", body.len())
+            } else {
+                String::new()
+            };
+            let head = format!("HTTP/1.1 200 OK\r\n{}Connection: close\r\n\r\n", length);
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+            socket.shutdown().await.unwrap();
+        });
+        format!("http://{}/7ziplogo_p.7z", addr)
+    }
+
+    #[tokio::test]
+    async fn test_extract_from_url() {
+        let body = fs::read("tests/7ziplogo_p.7z").unwrap();
+        let logo = fs::read("tests/7ziplogo.png").unwrap();
+        for with_length in [true, false] {
+            let dest = test_dir(&format!("extract_from_url_{}", with_length));
+            let url = serve_once(body.clone(), with_length).await;
+            let mut reports = Vec::new();
+            let options = ExtractOptions::default();
+            let stats = extract_from_url_with_progress(
+                &url,
+                "1151",
+                &dest,
+                &options,
+                |stage, done, total| reports.push((stage, done, total)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(stats.files, 1);
+            assert_eq!(fs::read(dest.join("7ziplogo.png")).unwrap(), logo);
+
+            let length = with_length.then_some(body.len() as u64);
+            assert!(reports.contains(&(UrlStage::Downloading, body.len() as u64, length)));
+            let size = logo.len() as u64;
+            assert_eq!(
+                reports.last(),
+                Some(&(UrlStage::Extracting, size, Some(size)))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_from_url_skips_unsafe_names() {
+        let dest = test_dir("extract_from_url_unsafe");
+        let archive = dest.with_extension("7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&archive).unwrap();
+        for name in ["../evil.txt", "/tmp/evil.txt", "ok.txt"] {
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some("x".as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let url = serve_once(fs::read(&archive).unwrap(), true).await;
+        let stats = extract_from_url(&url, "", &dest).await.unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 2));
+        assert!(dest.join("ok.txt").exists());
+        assert!(!dest.parent().unwrap().join("evil.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_crack_skips_unsafe_names() {
+        let dir = test_dir("crack_unsafe_names");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("unsafe.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&archive).unwrap();
+        for name in ["../evil_async.txt", "../evil_blocking.txt", "ok.txt"] {
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some("x".as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let passwords = vec![Arc::new(String::new())];
+
+        let dest = dir.join("async");
+        let found = crate::start_extraction(
+            Arc::from(vec![archive.clone()]),
+            passwords.clone(),
+            dest.clone(),
+            1,
+        )
+        .await;
+        assert!(found.is_some());
+        assert!(dest.join("ok.txt").exists());
+
+        let dest = dir.join("blocking");
+        let outcome = crate::crack_password_blocking(
+            &[&archive],
+            &passwords,
+            &dest,
+            1,
+            &crate::CrackOptions::default(),
+        );
+        assert!(matches!(outcome, crate::Outcome::Found(_)));
+        assert!(dest.join("ok.txt").exists());
+
+        let memory = dir.join("memory");
+        crate::try_extract_7z_from_bytes(&fs::read(&archive).unwrap(), "", &memory).unwrap();
+        assert!(memory.join("ok.txt").exists());
+        for name in ["evil_async.txt", "evil_blocking.txt"] {
+            assert!(!dir.join(name).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_from_url_follows_extract_options() {
+        let body = fs::read("tests/7ziplogo_p.7z").unwrap();
+        // in memory and through a temporary file
+        for with_length in [true, false] {
+            let dest = test_dir(&format!("extract_from_url_options_{}", with_length));
+            let url = serve_once(body.clone(), with_length).await;
+            let options = ExtractOptions {
+                max_entries: Some(0),
+                ..Default::default()
+            };
+            let result =
+                extract_from_url_with_progress(&url, "1151", &dest, &options, |_, _, _| {}).await;
+            assert!(matches!(result, Err(CascadeError::TooManyEntries { .. })));
+
+            let url = serve_once(body.clone(), with_length).await;
+            let options = ExtractOptions {
+                record_written_files: Some(crate::PathStyle::Relative),
+                ..Default::default()
+            };
+            let stats = extract_from_url_with_progress(&url, "1151", &dest, &options, |_, _, _| {})
+                .await
+                .unwrap();
+            assert_eq!(stats.written_files, [PathBuf::from("7ziplogo.png")]);
+        }
+    }
+
+    #[test]
+    fn test_temp_file_is_new() {
+        let (first, _) = TempFile::create().unwrap();
+        let (second, _) = TempFile::create().unwrap();
+        assert_ne!(first.0, second.0);
+        let path = first.0.clone();
+        assert!(path.exists());
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_extract_from_url_errors() {
+        let dest = test_dir("extract_from_url_errors");
+        let url = serve_once(fs::read("tests/7ziplogo.png").unwrap(), true).await;
+        assert!(extract_from_url(&url, "", &dest).await.is_err());
+        // nobody listens there any more
+        assert!(extract_from_url(&url, "", &dest).await.is_err());
+    }
+}
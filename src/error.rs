@@ -0,0 +1,210 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{ArchiveFormat, CascadeLimit};
+
+/// The error of the crate's functions. It is `Send + Sync`, as are the
+/// boxed errors some older functions return, so either can cross an
+/// `.await` or leave a spawned task.
+#[derive(Debug)]
+pub enum CascadeError {
+    Io(io::Error),
+    SevenZ(sevenz_rust::Error),
+    InvalidBruteForceSpec(String),
+    /// A `dest` template that can't be expanded, e.g. because of an unknown
+    /// placeholder.
+    InvalidTemplate(String),
+    /// An `entry_name_encoding` that `encoding_rs` doesn't know.
+    UnknownEncoding(String),
+    InvalidShard {
+        index: usize,
+        count: usize,
+    },
+    /// The archive expands to far more than its own size, like a
+    /// decompression bomb would.
+    SuspiciousRatio {
+        ratio: f64,
+    },
+    /// An entry index past the end of the archive's entry list.
+    IndexOutOfRange {
+        index: usize,
+        count: usize,
+    },
+    /// The archive has more entries than `max_entries` allows.
+    TooManyEntries {
+        count: usize,
+        max: usize,
+    },
+    /// The archive uses a compression method or filter that can't be
+    /// decoded. Supported are COPY, LZMA, LZMA2, BZIP2, DELTA, the BCJ
+    /// filters for x86, ARM, ARM Thumb, PowerPC and SPARC, BCJ2 and AES-256
+    /// encryption; PPMd, Deflate and ARM64 are not.
+    UnsupportedCoder {
+        coder: String,
+    },
+    /// A format that is recognized by its signature but can't be extracted
+    /// yet, see `ArchiveFormat::SUPPORTED`.
+    UnsupportedFormat {
+        detected: ArchiveFormat,
+    },
+    /// The archive was written in a 7z format version `sevenz_rust` can't
+    /// read, by a newer 7-Zip.
+    UnsupportedVersion {
+        major: u8,
+        minor: u8,
+    },
+    /// The header is encrypted, so not even the entry names can be read
+    /// without the password.
+    HeaderEncrypted,
+    /// Decoding the archive needs more memory than `max_memory` allows.
+    MemoryLimitExceeded {
+        needed: u64,
+        limit: u64,
+    },
+    /// The folder smart mode would extract into already exists and
+    /// `folder_collision` is `error`.
+    DestinationExists {
+        path: PathBuf,
+    },
+    /// `dir` holds no archive to pick from.
+    NoArchivesFound {
+        dir: PathBuf,
+    },
+    /// The `post_command` run after an extraction exited with an error. The
+    /// extracted files are still there.
+    PostCommandFailed {
+        command: String,
+        /// `None` when the command was killed by a signal.
+        code: Option<i32>,
+    },
+    /// `extract_cascading` hit `max_cascade_depth` or
+    /// `max_total_extracted_bytes`.
+    CascadeLimitExceeded {
+        limit: CascadeLimit,
+    },
+    /// `sevenz_rust` panicked on `path` instead of returning an error, as it
+    /// does on some malformed archives.
+    BackendPanic {
+        path: PathBuf,
+    },
+    /// The 7z program used by `extract_with_external_7z` failed.
+    ExternalToolFailed {
+        tool: PathBuf,
+        /// `None` when it was killed by a signal.
+        code: Option<i32>,
+        /// What it reported as errors, e.g. a wrong password.
+        message: String,
+    },
+}
+
+impl fmt::Display for CascadeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CascadeError::Io(e) => write!(f, "I/O error: {}", e),
+            CascadeError::SevenZ(e) => write!(f, "7z error: {}", e),
+            CascadeError::InvalidBruteForceSpec(reason) => {
+                write!(f, "invalid brute force spec: {}", reason)
+            }
+            CascadeError::InvalidTemplate(reason) => write!(f, "invalid dest template {}", reason),
+            CascadeError::UnknownEncoding(label) => write!(f, "unknown encoding {:?}", label),
+            CascadeError::InvalidShard { index, count } => {
+                write!(f, "invalid shard {} of {}", index, count)
+            }
+            CascadeError::SuspiciousRatio { ratio } => write!(
+                f,
+                "suspicious compression ratio {:.1}x, refusing to extract",
+                ratio
+            ),
+            CascadeError::IndexOutOfRange { index, count } => write!(
+                f,
+                "entry index {} out of range, the archive has {} entries",
+                index, count
+            ),
+            CascadeError::TooManyEntries { count, max } => write!(
+                f,
+                "archive has {} entries, more than the limit of {}",
+                count, max
+            ),
+            CascadeError::UnsupportedCoder { coder } => {
+                write!(f, "unsupported compression method {}", coder)
+            }
+            CascadeError::UnsupportedFormat { detected } => {
+                write!(f, "{} archives are not supported yet", detected)
+            }
+            CascadeError::UnsupportedVersion { major, minor } => write!(
+                f,
+                "the archive was created by a newer 7-Zip, format version {}.{} is not supported",
+                major, minor
+            ),
+            CascadeError::HeaderEncrypted => write!(
+                f,
+                "the archive header is encrypted, its entries can't be listed without the password"
+            ),
+            CascadeError::MemoryLimitExceeded { needed, limit } => write!(
+                f,
+                "decoding needs about {} bytes of memory, more than the limit of {}",
+                needed, limit
+            ),
+            CascadeError::DestinationExists { path } => {
+                write!(f, "the folder {} already exists", path.display())
+            }
+            CascadeError::NoArchivesFound { dir } => {
+                write!(f, "no archives found in {}", dir.display())
+            }
+            CascadeError::PostCommandFailed { command, code } => match code {
+                Some(code) => write!(f, "post command exited with {}: {}", code, command),
+                None => write!(f, "post command was killed: {}", command),
+            },
+            CascadeError::CascadeLimitExceeded { limit } => {
+                write!(f, "cascading extraction stopped, {}", limit)
+            }
+            CascadeError::BackendPanic { path } => {
+                write!(f, "the 7z decoder panicked on {}", path.display())
+            }
+            CascadeError::ExternalToolFailed {
+                tool,
+                code,
+                message,
+            } => match code {
+                Some(code) => write!(f, "{} exited with {}: {}", tool.display(), code, message),
+                None => write!(f, "{} was killed: {}", tool.display(), message),
+            },
+        }
+    }
+}
+
+impl std::error::Error for CascadeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CascadeError::Io(e) => Some(e),
+            CascadeError::SevenZ(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CascadeError {
+    fn from(e: io::Error) -> Self {
+        CascadeError::Io(e)
+    }
+}
+
+impl From<sevenz_rust::Error> for CascadeError {
+    fn from(e: sevenz_rust::Error) -> Self {
+        match e {
+            // fails the same way for every password, so it must not be
+            // mistaken for a wrong one
+            sevenz_rust::Error::UnsupportedCompressionMethod(coder) => {
+                CascadeError::UnsupportedCoder { coder }
+            }
+            sevenz_rust::Error::Unsupported(coder) => CascadeError::UnsupportedCoder {
+                coder: coder.into_owned(),
+            },
+            sevenz_rust::Error::UnsupportedVersion { major, minor } => {
+                CascadeError::UnsupportedVersion { major, minor }
+            }
+            e => CascadeError::SevenZ(e),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use sevenz_rust::SevenZMethod;
+
+use crate::{
+    detect_format_in_bytes, read_archive_with_password, ArchiveFormat, CascadeError,
+    FULL_SIGNATURE_WINDOW,
+};
+
+/// What writing the extracted files is assumed to cost, in bytes per
+/// second, a middling SSD. Stored data costs only this.
+pub const ASSUMED_DISK_BYTES_PER_SEC: f64 = 200.0 * 1024.0 * 1024.0;
+
+/// Bytes decoded by the LZMA2 benchmark of `estimate_extraction_time`.
+const BENCHMARK_BYTES: usize = 256 * 1024;
+
+/// How long extracting the archive should take on this machine, for
+/// choosing between a spinner and a progress bar rather than for showing.
+/// Every block costs its uncompressed size over the speed of each of its
+/// coders, plus writing that size out at `ASSUMED_DISK_BYTES_PER_SEC`.
+/// The LZMA2 speed is measured once per process with a short benchmark,
+/// the other coders are rated against it: Deflate and Zstandard decode
+/// faster, BZip2 and PPMd slower, and filters and AES cost next to nothing.
+///
+/// Works for 7z archives, which fail with `HeaderEncrypted` when their
+/// listing is encrypted, and plain tar, which is all copying. Other formats
+/// are `UnsupportedFormat`.
+pub fn estimate_extraction_time<P: AsRef<Path>>(path: P) -> Result<Duration, CascadeError> {
+    let path = path.as_ref();
+    let mut head = Vec::with_capacity(FULL_SIGNATURE_WINDOW);
+    File::open(path)?
+        .take(FULL_SIGNATURE_WINDOW as u64)
+        .read_to_end(&mut head)?;
+    let seconds = match detect_format_in_bytes(&head, &ArchiveFormat::ALL) {
+        Some(ArchiveFormat::SevenZ) => {
+            let archive = match read_archive_with_password(path, "") {
+                Ok(archive) => archive,
+                Err(CascadeError::SevenZ(sevenz_rust::Error::PasswordRequired)) => {
+                    return Err(CascadeError::HeaderEncrypted)
+                }
+                Err(e) => return Err(e),
+            };
+            let lzma2 = lzma2_bytes_per_sec();
+            archive
+                .folders
+                .iter()
+                .map(|folder| {
+                    let size = folder.get_unpack_size() as f64;
+                    let decoding: f64 = folder
+                        .coders
+                        .iter()
+                        .map(|coder| {
+                            size / (lzma2 * relative_speed(coder.decompression_method_id()))
+                        })
+                        .sum();
+                    decoding + size / ASSUMED_DISK_BYTES_PER_SEC
+                })
+                .sum()
+        }
+        Some(ArchiveFormat::Tar) => {
+            std::fs::metadata(path)?.len() as f64 / ASSUMED_DISK_BYTES_PER_SEC
+        }
+        Some(detected) => return Err(CascadeError::UnsupportedFormat { detected }),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a known archive format", path.display()),
+            )
+            .into())
+        }
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// How fast a coder decodes compared to LZMA2, from how 7-Zip's own
+/// benchmark rates them. Unknown coders count as fast as LZMA2.
+fn relative_speed(id: &[u8]) -> f64 {
+    match id {
+        // a stored block only costs the disk
+        SevenZMethod::ID_COPY => f64::INFINITY,
+        SevenZMethod::ID_LZMA | SevenZMethod::ID_LZMA2 => 1.0,
+        SevenZMethod::ID_DEFLATE | SevenZMethod::ID_DEFLATE64 => 3.0,
+        SevenZMethod::ID_ZSTD => 5.0,
+        SevenZMethod::ID_BZIP2 => 0.4,
+        // PPMd, which sevenz_rust can't decode but an external 7z can
+        [0x03, 0x04, 0x01] => 0.3,
+        SevenZMethod::ID_AES256SHA256 => 8.0,
+        SevenZMethod::ID_BCJ_X86
+        | SevenZMethod::ID_BCJ_PPC
+        | SevenZMethod::ID_BCJ_IA64
+        | SevenZMethod::ID_BCJ_ARM
+        | SevenZMethod::ID_BCJ_ARM_THUMB
+        | SevenZMethod::ID_BCJ_SPARC
+        | SevenZMethod::ID_BCJ2
+        | SevenZMethod::ID_DELTA => 20.0,
+        _ => 1.0,
+    }
+}
+
+/// LZMA2 decoding speed in bytes per second, benchmarked on first use on
+/// text-like data compressed into memory.
+fn lzma2_bytes_per_sec() -> f64 {
+    static RATE: OnceLock<f64> = OnceLock::new();
+    *RATE.get_or_init(|| benchmark_lzma2().unwrap_or(50.0 * 1024.0 * 1024.0))
+}
+
+fn benchmark_lzma2() -> Option<f64> {
+    // words from a small vocabulary in a varying order compress about as
+    // well as ordinary text
+    const WORDS: [&str; 8] = [
+        "archive ",
+        "password ",
+        "folder ",
+        "extract ",
+        "the ",
+        "of ",
+        "data\n",
+        "7z ",
+    ];
+    let mut data = Vec::with_capacity(BENCHMARK_BYTES);
+    let mut state = 0x2545_f491u32;
+    while data.len() < BENCHMARK_BYTES {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        data.extend_from_slice(WORDS[(state % 8) as usize].as_bytes());
+    }
+
+    let mut archive = Cursor::new(Vec::new());
+    let mut writer = sevenz_rust::SevenZWriter::new(&mut archive).ok()?;
+    let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+    entry.name = "benchmark".to_string();
+    entry.has_stream = true;
+    writer.push_archive_entry(entry, Some(&data[..])).ok()?;
+    writer.finish().ok()?;
+
+    let len = archive.get_ref().len() as u64;
+    archive.set_position(0);
+    let start = Instant::now();
+    let mut reader =
+        sevenz_rust::SevenZReader::new(archive, len, sevenz_rust::Password::empty()).ok()?;
+    let mut decoded = 0u64;
+    reader
+        .for_each_entries(|_, data| {
+            decoded += io::copy(data, &mut io::sink())?;
+            Ok(true)
+        })
+        .ok()?;
+    Some(decoded as f64 / start.elapsed().as_secs_f64().max(1e-6))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_extraction_time() {
+        let estimate = estimate_extraction_time("tests/sample.7z").unwrap();
+        assert!(estimate > Duration::ZERO);
+        // a few kilobytes
+        assert!(estimate < Duration::from_secs(1), "{:?}", estimate);
+        assert!(matches!(
+            estimate_extraction_time("tests/7ziplogo_p.7z"),
+            Err(CascadeError::HeaderEncrypted)
+        ));
+        assert!(matches!(
+            estimate_extraction_time("tests/zipcrypto.zip"),
+            Err(CascadeError::UnsupportedFormat {
+                detected: ArchiveFormat::Zip
+            })
+        ));
+    }
+
+    #[test]
+    fn test_relative_speed() {
+        assert!(relative_speed(SevenZMethod::ID_BZIP2) < relative_speed(SevenZMethod::ID_LZMA2));
+        assert!(relative_speed(SevenZMethod::ID_LZMA2) < relative_speed(SevenZMethod::ID_DEFLATE));
+        assert_eq!(relative_speed(SevenZMethod::ID_COPY), f64::INFINITY);
+    }
+}
@@ -0,0 +1,195 @@
+use std::env;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::i18n::log_tr;
+use crate::{CascadeError, ExtractStats, OverwritePolicy};
+
+/// The names `find_external_7z` looks for, in order: p7zip's full and
+/// standalone builds, then 7-Zip's own Linux and macOS build.
+pub const EXTERNAL_7Z_NAMES: [&str; 3] = ["7z", "7za", "7zz"];
+
+/// The first of `EXTERNAL_7Z_NAMES` found on `PATH`, for extracting what
+/// `sevenz_rust` can't decode, see `ExtractOptions::allow_external_7z`.
+pub fn find_external_7z() -> Option<PathBuf> {
+    find_in_path(env::var_os("PATH")?.as_os_str())
+}
+
+fn find_in_path(path: &OsStr) -> Option<PathBuf> {
+    EXTERNAL_7Z_NAMES.iter().find_map(|name| {
+        env::split_paths(path)
+            .map(|dir| dir.join(name).with_extension(env::consts::EXE_EXTENSION))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Extracts the archive to `dest` with the 7z program at `binary`, logging
+/// its output line by line as it goes. Existing files are overwritten,
+/// skipped or renamed as `overwrite` says, with 7z's `-aoa`, `-aos` or
+/// `-aou`. The password is written to 7z's prompt on stdin rather than
+/// passed with `-p`, where any user could read it in the process list. The
+/// stats come from the summary 7z prints at the end, or else from counting
+/// the files it reports.
+///
+/// An exit code other than 0, or 1 for warnings, is returned as
+/// `ExternalToolFailed` with the lines 7z flagged as errors.
+pub fn extract_with_external_7z<P: AsRef<Path>>(
+    binary: &Path,
+    path: P,
+    password: &str,
+    dest: &Path,
+    overwrite: OverwritePolicy,
+) -> Result<ExtractStats, CascadeError> {
+    let path = path.as_ref();
+    log_tr!(
+        info,
+        "使用 {} 解压 {}",
+        "extracting {} with {}",
+        path.display(),
+        binary.display()
+    );
+    let overwrite = match overwrite {
+        OverwritePolicy::Overwrite => "-aoa",
+        OverwritePolicy::Skip => "-aos",
+        OverwritePolicy::Rename => "-aou",
+    };
+    // 7z only asks for a password when it needs one; without one to give it
+    // reads the end of stdin and fails right away
+    let stdin = if password.is_empty() {
+        Stdio::null()
+    } else {
+        Stdio::piped()
+    };
+    let mut child = Command::new(binary)
+        .args(["x", overwrite, "-bb1", "-bse1"])
+        .arg(format!("-o{}", dest.display()))
+        .arg("--")
+        .arg(path)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pipe closes when dropped, so 7z never waits for more; it may
+        // also have exited without asking, which its exit code tells
+        let _ = writeln!(stdin, "{}", password);
+    }
+
+    let mut stats = ExtractStats::default();
+    let mut listed = 0;
+    let mut files = None;
+    let mut errors = Vec::new();
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let line = line?;
+        log_tr!(debug, "[7z] {}", "[7z] {}", line);
+        if line.starts_with("- ") {
+            listed += 1;
+        } else if let Some(count) = summary_value(&line, "Files:") {
+            files = Some(count as usize);
+        } else if let Some(count) = summary_value(&line, "Folders:") {
+            stats.directories = count as usize;
+        } else if let Some(size) = summary_value(&line, "Size:") {
+            stats.bytes_written = size;
+        } else if line.contains("ERROR") || line.contains("Wrong password") {
+            errors.push(line.trim().to_string());
+        }
+    }
+    let status = child.wait()?;
+    if !matches!(status.code(), Some(0 | 1)) {
+        return Err(CascadeError::ExternalToolFailed {
+            tool: binary.to_path_buf(),
+            code: status.code(),
+            message: errors.join("; "),
+        });
+    }
+    stats.files = files.unwrap_or(listed);
+    Ok(stats)
+}
+
+fn summary_value(line: &str, label: &str) -> Option<u64> {
+    line.strip_prefix(label)?.trim().parse().ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A stand-in for 7z that writes `a.txt` into the folder after `-o` and
+    /// prints what 7-Zip would, or fails like it does for a wrong password
+    /// read from stdin. Its arguments go to `args.txt` next to it.
+    fn fake_7z(dir: &Path) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let binary = dir.join("7z");
+        fs::write(
+            &binary,
+            r#"#!/bin/sh
+echo "$@" > "$(dirname "$0")/args.txt"
+read -r password
+if [ "$password" = wrong ]; then echo "ERROR: Wrong password : a.txt"; exit 2; fi
+for arg; do
+    case "$arg" in
+        -o*) out="${arg#-o}" ;;
+    esac
+done
+mkdir -p "$out" && printf abc > "$out/a.txt"
+echo "- a.txt"
+echo
+echo "Everything is Ok"
+echo
+echo "Files: 1"
+echo "Size:       3"
+"#,
+        )
+        .unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        binary
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        let dir = test_dir("find_external_7z");
+        let binary = fake_7z(&dir.join("bin"));
+        let path = env::join_paths([dir.join("missing"), dir.join("bin")]).unwrap();
+        assert_eq!(find_in_path(&path), Some(binary));
+        assert_eq!(find_in_path(dir.join("missing").as_os_str()), None);
+    }
+
+    #[test]
+    fn test_extract_with_external_7z() {
+        let dir = test_dir("extract_with_external_7z");
+        let binary = fake_7z(&dir);
+        let out = dir.join("out");
+        let stats =
+            extract_with_external_7z(&binary, "tests/sample.7z", "", &out, OverwritePolicy::Skip)
+                .unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.bytes_written, 3);
+        assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "abc");
+        let args = fs::read_to_string(dir.join("args.txt")).unwrap();
+        assert!(args.starts_with("x -aos "), "{}", args);
+
+        let err = extract_with_external_7z(
+            &binary,
+            "tests/sample.7z",
+            "wrong",
+            &out,
+            OverwritePolicy::Overwrite,
+        )
+        .unwrap_err();
+        let args = fs::read_to_string(dir.join("args.txt")).unwrap();
+        assert!(args.starts_with("x -aoa "), "{}", args);
+        assert!(!args.contains("wrong"), "{}", args);
+        match err {
+            CascadeError::ExternalToolFailed { code, message, .. } => {
+                assert_eq!(code, Some(2));
+                assert!(message.contains("Wrong password"), "{}", message);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+}
@@ -0,0 +1,3502 @@
+use encoding_rs::Encoding;
+use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use sevenz_rust::SevenZArchiveEntry;
+
+use crate::handles::with_file_handle;
+use crate::i18n::log_tr;
+use crate::staging::extract_staged;
+use crate::throttle::RateLimiter;
+use crate::{
+    catch_backend_panic, detect_format_in_bytes, estimate_remaining, extract_sfx,
+    extract_tar_recording, extract_with_external_7z, find_external_7z, is_tar_gz,
+    needs_wrapper_folder, open_archive_file, open_archive_reader,
+    read_archive_entries_with_password, read_archive_with_password, registered_extractor,
+    resolve_dest, sfx_payload_offset, write_to_sink, ArchiveFormat, CascadeError, CascadeLimit,
+    ConfigSettings, DiskSink, EntryInfo, FULL_SIGNATURE_WINDOW,
+};
+
+/// Set by p7zip when the high 16 bits of the attributes hold a Unix mode.
+pub(crate) const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// What to do with symlink entries. 7z stores a symlink as a small file whose
+/// content is the link target, flagged through the Unix mode bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Create a real symlink. Falls back to `Skip` with a warning when the
+    /// platform refuses, e.g. on Windows without the required privilege, and
+    /// for a link to an absolute path or one climbing out of `dest`, which
+    /// later writes through the link would follow.
+    #[default]
+    Create,
+    /// Leave the link out entirely.
+    Skip,
+    /// Write a copy of the file the link points to, as long as the target was
+    /// extracted from the same archive.
+    Deref,
+}
+
+/// What becomes of the read-only, hidden and system attributes of the
+/// files. Either way a read-only file in `dest` that an entry overwrites is
+/// made writable first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributePolicy {
+    /// Leaves every file written writable and, on Windows, neither hidden
+    /// nor system, so moving, overwriting or deleting it later works.
+    #[default]
+    Clear,
+    /// Applies what the archive stores: the read-only attribute, on Windows
+    /// the hidden and system ones too, and on Unix the whole mode when the
+    /// archive was made by p7zip.
+    Preserve,
+}
+
+/// What to do when a file is about to be written over an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    #[default]
+    Overwrite,
+    /// Keep the existing file and drop the entry.
+    Skip,
+    /// Write the entry next to the existing file as `name (1).ext`.
+    Rename,
+}
+
+/// Case applied to entry paths before they are written. Only the part below
+/// the destination folder is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseMode {
+    Lower,
+    Upper,
+    AsIs,
+}
+
+impl CaseMode {
+    pub fn apply(&self, name: &str) -> String {
+        match self {
+            CaseMode::Lower => name.to_lowercase(),
+            CaseMode::Upper => name.to_uppercase(),
+            CaseMode::AsIs => name.to_string(),
+        }
+    }
+}
+
+/// The order entries are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryOrder {
+    /// The order they are stored in.
+    #[default]
+    Natural,
+    /// Biggest solid block first, for a smoother progress bar when a few
+    /// large files sit among many small ones. Entries inside one block
+    /// depend on each other and keep their order, so a fully solid archive
+    /// is still written in natural order; only archives made of several
+    /// blocks, e.g. with solid mode off, are reordered.
+    LargestFirst,
+}
+
+/// How `ExtractStats::written_files` spells the paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// Relative to `dest`, as they appear in the archive after renaming.
+    #[default]
+    Relative,
+    Absolute,
+}
+
+/// Uncompressed size may be at most this many times the archive's size.
+pub const DEFAULT_MAX_COMPRESSION_RATIO: f64 = 100.0;
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub symlink_policy: SymlinkPolicy,
+    /// Caps how fast decompressed data is written out, so a big extraction
+    /// doesn't saturate a shared disk. `None` falls back to
+    /// `max_bytes_per_sec`, unlimited unless it was set.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Archives expanding beyond this ratio are rejected before anything is
+    /// written, unless `allow_high_ratio` is set.
+    pub max_compression_ratio: f64,
+    pub allow_high_ratio: bool,
+    /// Archives with more entries than this are rejected before anything is
+    /// written, since millions of tiny files can run a disk out of inodes.
+    /// `None` means no limit.
+    pub max_entries: Option<usize>,
+    /// Removes whatever a failed extraction created. Files that existed
+    /// before and were merely overwritten are left in place.
+    pub cleanup_on_failure: bool,
+    pub overwrite_policy: OverwritePolicy,
+    /// Entries that differ only in case end up on the same path once
+    /// normalized; `overwrite_policy` decides which one survives.
+    pub normalize_case: Option<CaseMode>,
+    /// Logs and skips entries that fail to extract instead of aborting, so
+    /// whatever is intact can be salvaged from a damaged archive. The
+    /// failures are listed in `ExtractStats::failed_entries`.
+    pub continue_on_entry_error: bool,
+    /// Encoding label such as `"gbk"` for archives whose entry names were
+    /// stored in a legacy code page and come out garbled.
+    pub entry_name_encoding: Option<String>,
+    /// Treats `\` in entry names as a folder separator, for archives made by
+    /// Windows tools that store paths like `docs\readme.txt`.
+    pub normalize_separators: bool,
+    /// Writes only the files, each to its path in the archive: folder
+    /// entries are skipped and counted in `ExtractStats::skipped_directories`,
+    /// the folders a file needs are created as it is written, and the times
+    /// stored for the files are not applied, so they carry the time of the
+    /// extraction. Empty folders in the archive don't appear in `dest`.
+    pub files_only: bool,
+    /// `files_only` ignores the stored attributes along with the times.
+    pub attribute_policy: AttributePolicy,
+    /// Lists every file and symlink written in `ExtractStats::written_files`,
+    /// so callers can post-process them without scanning `dest` again.
+    pub record_written_files: Option<PathStyle>,
+    pub entry_order: EntryOrder,
+    /// Leaves files alone whose size and CRC already match the entry, so
+    /// extracting again into a synced `dest` only writes what changed. They
+    /// are counted in `ExtractStats::unchanged`.
+    pub incremental: bool,
+    /// Records each entry as it completes in a sidecar next to the files,
+    /// see `partial_sidecar_path`, so a run that is interrupted can pick up
+    /// where it stopped: entries listed there whose size and CRC still
+    /// match are not written again, and blocks made only of such entries
+    /// are not even decoded. The entries of a solid block depend on each
+    /// other, so a block with anything left to do is decoded from its
+    /// start. The sidecar is removed once the archive is fully extracted.
+    /// Takes precedence over `cleanup_on_failure`, which would delete what
+    /// is there to resume.
+    pub resume: bool,
+    /// Extracts into a hidden folder next to `dest` and moves the result
+    /// into place only once every entry is written, so nothing watching
+    /// `dest` sees half an archive and a failed extraction leaves `dest` as
+    /// it was. When `dest` doesn't exist yet, as with the wrapper folder of
+    /// smart mode, the whole tree appears in one rename; otherwise each
+    /// entry at the top is moved into `dest` in turn, following
+    /// `overwrite_policy` for what is already there. A `dest` on another
+    /// filesystem than its parent gets a copy instead. `incremental` and
+    /// `resume` have nothing to work with in the fresh folder and are
+    /// ignored.
+    pub atomic: bool,
+    /// Keeps entries whose data decodes but fails the CRC check instead of
+    /// aborting, and reports every file in `ExtractStats::verification`.
+    pub verify: bool,
+    /// Archives whose decoder needs more memory than this, in bytes, are
+    /// rejected before anything is written. See `decode_memory_estimate`.
+    pub max_memory: Option<u64>,
+    /// Files larger than this, in bytes, are skipped and listed in
+    /// `ExtractStats::too_large`; the rest of the archive is extracted.
+    pub max_file_size: Option<u64>,
+    /// Gives up with `CascadeLimitExceeded` before writing a file that would
+    /// bring the bytes written past this. Checked as the archive is read, so
+    /// it holds for a `.tar.gz` or an encrypted header as well, whose sizes
+    /// can't be listed up front; `extract_cascading` sets it to what is left
+    /// of `max_total_extracted_bytes`.
+    pub max_total_bytes: Option<u64>,
+    /// Extensions of the files to extract, without the dot and ignoring
+    /// case; empty means every extension. See `allows_extension` for how it
+    /// combines with `skip_extensions`.
+    pub allow_extensions: Vec<String>,
+    /// Extensions of the files to leave out.
+    pub skip_extensions: Vec<String>,
+    /// Hashes the files as they are written into `ExtractStats::content_hash`,
+    /// so the same content delivered under another archive name can be
+    /// recognized later.
+    pub content_hash: bool,
+    /// Makes `extract_with_manifest_options` also write its manifest into
+    /// `dest`, as `MANIFEST_FILE_NAME`.
+    pub write_manifest: bool,
+    /// Lists what happened to every entry in `ExtractStats::entries`, to see
+    /// which of the options above left out which entry.
+    pub record_entry_outcomes: bool,
+    /// Adds up the bytes written under each entry at the top of `dest` in
+    /// `ExtractStats::folder_totals`, to show where the space went.
+    pub record_folder_totals: bool,
+    /// Makes `extract_any` hand 7z archives using a method `sevenz_rust`
+    /// can't decode, such as PPMd, to a 7z program on `PATH`, see
+    /// `find_external_7z`. That extraction ignores the options above.
+    pub allow_external_7z: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            symlink_policy: SymlinkPolicy::default(),
+            max_bytes_per_sec: None,
+            max_compression_ratio: DEFAULT_MAX_COMPRESSION_RATIO,
+            allow_high_ratio: false,
+            max_entries: None,
+            cleanup_on_failure: false,
+            overwrite_policy: OverwritePolicy::default(),
+            normalize_case: None,
+            continue_on_entry_error: false,
+            entry_name_encoding: None,
+            normalize_separators: true,
+            files_only: false,
+            attribute_policy: AttributePolicy::default(),
+            record_written_files: None,
+            entry_order: EntryOrder::default(),
+            incremental: false,
+            resume: false,
+            atomic: false,
+            verify: false,
+            max_memory: None,
+            max_file_size: None,
+            max_total_bytes: None,
+            allow_extensions: Vec::new(),
+            skip_extensions: Vec::new(),
+            content_hash: false,
+            write_manifest: false,
+            record_entry_outcomes: false,
+            record_folder_totals: false,
+            allow_external_7z: false,
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Whether a file called `name` passes the extension filters. A
+    /// non-empty `allow_extensions` wins: only files with one of those
+    /// extensions are extracted, files without an extension included only
+    /// through `""`. `skip_extensions` then removes from what is left, so an
+    /// extension in both lists is skipped. Folders are never filtered.
+    pub fn allows_extension(&self, name: &str) -> bool {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        let extension = match name.rfind('.') {
+            Some(dot) if dot > 0 => &name[dot + 1..],
+            _ => "",
+        };
+        let listed = |list: &[String]| {
+            list.iter()
+                .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+        };
+        (self.allow_extensions.is_empty() || listed(&self.allow_extensions))
+            && !listed(&self.skip_extensions)
+    }
+
+    pub fn from_config(config: &ConfigSettings) -> Self {
+        ExtractOptions {
+            symlink_policy: config.symlink_policy,
+            max_bytes_per_sec: config.max_bytes_per_sec,
+            max_compression_ratio: config.max_compression_ratio,
+            allow_high_ratio: config.allow_high_ratio,
+            max_entries: config.max_entries,
+            cleanup_on_failure: config.cleanup_on_failure,
+            overwrite_policy: config.overwrite_policy,
+            normalize_case: config.normalize_case,
+            continue_on_entry_error: config.continue_on_entry_error,
+            entry_name_encoding: config.entry_name_encoding.clone(),
+            normalize_separators: config.normalize_separators,
+            files_only: config.files_only,
+            attribute_policy: config.attribute_policy,
+            record_written_files: None,
+            entry_order: EntryOrder::default(),
+            incremental: config.incremental,
+            resume: config.resume,
+            atomic: config.atomic_extraction,
+            verify: false,
+            max_memory: config.max_memory,
+            max_file_size: config.max_file_size,
+            max_total_bytes: None,
+            allow_extensions: config.allow_extensions.clone(),
+            skip_extensions: config.skip_extensions.clone(),
+            content_hash: false,
+            write_manifest: false,
+            record_entry_outcomes: false,
+            record_folder_totals: false,
+            allow_external_7z: config.allow_external_7z,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractStats {
+    pub files: usize,
+    pub directories: usize,
+    /// Folder entries left out by `ExtractOptions::files_only`, not counted
+    /// in `skipped`.
+    pub skipped_directories: usize,
+    pub symlinks: usize,
+    pub skipped: usize,
+    /// Entries skipped for exceeding `ExtractOptions::max_file_size`, also
+    /// counted in `skipped`.
+    pub too_large: Vec<String>,
+    /// Entries left out by `ExtractOptions::allow_extensions` and
+    /// `skip_extensions`, also counted in `skipped`.
+    pub filtered: Vec<String>,
+    /// Files left as they were by `ExtractOptions::incremental`, or found
+    /// complete by `ExtractOptions::resume`.
+    pub unchanged: usize,
+    pub bytes_written: u64,
+    pub failed_entries: Vec<FailedEntry>,
+    /// Filled in when `ExtractOptions::record_written_files` is set, in the
+    /// order the files were written.
+    pub written_files: Vec<PathBuf>,
+    /// Filled in when `ExtractOptions::verify` is set.
+    pub verification: Option<VerificationReport>,
+    /// BLAKE3 of the name, size and data of every file written, in hex,
+    /// when `ExtractOptions::content_hash` is set. Files are hashed in the
+    /// order they are written, which depends on `ExtractOptions::entry_order`;
+    /// skipped and unchanged files are left out.
+    pub content_hash: Option<String>,
+    /// Filled in when `ExtractOptions::record_entry_outcomes` is set, in the
+    /// order the entries were visited.
+    pub entries: Vec<EntryReport>,
+    /// Bytes written per file or folder at the top of `dest`, by its name,
+    /// when `ExtractOptions::record_folder_totals` is set. They are counted
+    /// as in `bytes_written`, and the names are those after renaming.
+    pub folder_totals: HashMap<String, u64>,
+    /// Files whose path was already taken by an earlier entry of the same
+    /// archive, through a duplicate name or `normalize_case`.
+    /// `overwrite_policy` decided what became of them.
+    pub duplicates: Vec<String>,
+}
+
+/// Why an entry was or wasn't written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryOutcome {
+    Extracted,
+    /// Left out by `allow_extensions`, `skip_extensions` or
+    /// `symlink_policy`.
+    SkippedFilter,
+    /// Already in `dest`, and left alone by `OverwritePolicy::Skip` or
+    /// `incremental`.
+    SkippedExists,
+    /// Bigger than `max_file_size`.
+    SkippedTooLarge,
+    /// Failed with this error; only entries `continue_on_entry_error` got
+    /// past are reported, any other failure aborts the extraction.
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryReport {
+    /// The entry name in the archive.
+    pub name: String,
+    pub outcome: EntryOutcome,
+}
+
+/// Entry names sorted by whether their data matched the stored CRC. A wrong
+/// password usually fails to decode at all; an entry listed in `failed` was
+/// decoded, but its content is damaged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    pub ok: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// An entry skipped because of `continue_on_entry_error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedEntry {
+    pub name: String,
+    pub error: String,
+}
+
+pub fn is_symlink_entry(entry: &SevenZArchiveEntry) -> bool {
+    entry.has_windows_attributes
+        && entry.windows_attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0
+        && (entry.windows_attributes >> 16) & S_IFMT == S_IFLNK
+}
+
+/// Uncompressed size divided by the archive's size on disk.
+pub fn compression_ratio<P: AsRef<Path>>(path: P, password: &str) -> Result<f64, CascadeError> {
+    let archive_size = fs::metadata(&path)?.len();
+    let files = read_archive_entries_with_password(&path, password)?;
+    Ok(ratio_of(&files, archive_size))
+}
+
+fn ratio_of(files: &[SevenZArchiveEntry], archive_size: u64) -> f64 {
+    let total: u64 = files.iter().map(|f| f.size()).sum();
+    total as f64 / archive_size.max(1) as f64
+}
+
+pub fn check_compression_ratio<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    options: &ExtractOptions,
+) -> Result<(), CascadeError> {
+    if options.allow_high_ratio {
+        return Ok(());
+    }
+    let ratio = compression_ratio(path, password)?;
+    if ratio > options.max_compression_ratio {
+        return Err(CascadeError::SuspiciousRatio { ratio });
+    }
+    Ok(())
+}
+
+/// Roughly how much memory decoding takes, in bytes: the LZMA and LZMA2
+/// decoders allocate their whole dictionary up front, so this is the largest
+/// dictionary of any block. `sevenz_rust` has no setting to lower it, so an
+/// archive made with a big dictionary can only be refused, not decoded with
+/// less.
+pub fn decode_memory_estimate<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+) -> Result<u64, CascadeError> {
+    Ok(memory_estimate_of(&read_archive_with_password(
+        path, password,
+    )?))
+}
+
+fn memory_estimate_of(archive: &sevenz_rust::Archive) -> u64 {
+    archive
+        .folders
+        .iter()
+        .flat_map(|folder| folder.coders.iter())
+        .filter_map(|coder| {
+            let props = &coder.properties;
+            match coder.decompression_method_id() {
+                sevenz_rust::SevenZMethod::ID_LZMA if props.len() >= 5 => {
+                    Some(u32::from_le_bytes([props[1], props[2], props[3], props[4]]) as u64)
+                }
+                sevenz_rust::SevenZMethod::ID_LZMA2 => props.first().map(|&bits| {
+                    let bits = bits.min(40) as u64;
+                    if bits == 40 {
+                        u32::MAX as u64
+                    } else {
+                        (2 | (bits & 1)) << (bits / 2 + 11)
+                    }
+                }),
+                _ => None,
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Runs the checks against decompression bombs, `max_entries`,
+/// `max_compression_ratio` and `max_memory`, on a single listing of the
+/// archive.
+pub fn check_archive_limits<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    options: &ExtractOptions,
+) -> Result<(), CascadeError> {
+    check_limits_of(ArchiveSource::File(path.as_ref()), password, options)
+}
+
+fn check_limits_of(
+    source: ArchiveSource,
+    password: &str,
+    options: &ExtractOptions,
+) -> Result<(), CascadeError> {
+    if options.allow_high_ratio && options.max_entries.is_none() && options.max_memory.is_none() {
+        return Ok(());
+    }
+    let archive = source.read_archive(password)?;
+    let files = &archive.files;
+
+    if let Some(limit) = options.max_memory {
+        let needed = memory_estimate_of(&archive);
+        if needed > limit {
+            return Err(CascadeError::MemoryLimitExceeded { needed, limit });
+        }
+    }
+
+    if let Some(max) = options.max_entries {
+        if files.len() > max {
+            return Err(CascadeError::TooManyEntries {
+                count: files.len(),
+                max,
+            });
+        }
+    }
+    if !options.allow_high_ratio {
+        let ratio = ratio_of(files, source.len()?);
+        if ratio > options.max_compression_ratio {
+            return Err(CascadeError::SuspiciousRatio { ratio });
+        }
+    }
+    Ok(())
+}
+
+pub fn extract_with_options<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractStats, CascadeError> {
+    extract_reporting(
+        ArchiveSource::File(path.as_ref()),
+        password,
+        dest,
+        options,
+        None,
+        None,
+    )
+}
+
+/// `extract_with_options`, writing each entry to the path below `dest` that
+/// `remap` gives for its name as stored in the archive, or leaving it out
+/// when `remap` returns `None`. That covers flattening, moving a subtree
+/// elsewhere and renaming alike. The new paths still go through
+/// `normalize_separators` and `normalize_case`; ones that would leave
+/// `dest`, being absolute or going up with `..`, are skipped with a warning.
+///
+/// Returning the same path for two entries makes the second a duplicate,
+/// which `overwrite_policy` decides about as for any other name collision,
+/// and it is listed in `ExtractStats::duplicates`. Entries left out count
+/// as `skipped`. `resume` goes by the names as stored and is ignored here.
+pub fn extract_with_remap<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    remap: impl Fn(&str) -> Option<String>,
+) -> Result<ExtractStats, CascadeError> {
+    let options = ExtractOptions {
+        resume: false,
+        ..options.clone()
+    };
+    extract_reporting(
+        ArchiveSource::File(path.as_ref()),
+        password,
+        dest,
+        &options,
+        None,
+        Some(&remap),
+    )
+}
+
+/// The folders `extract_sorted_by_type` sorts files into by extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeCategories {
+    /// Each folder with the extensions, lower case and without the dot,
+    /// that go in it. The first folder listing an extension wins.
+    pub categories: Vec<(String, Vec<String>)>,
+    /// Where files with any other extension, or none, go.
+    pub other: String,
+}
+
+impl Default for TypeCategories {
+    /// `images`, `documents` and `archives`, with `other` for the rest.
+    fn default() -> Self {
+        let category = |name: &str, extensions: &[&str]| {
+            let extensions = extensions.iter().map(|e| e.to_string()).collect();
+            (name.to_string(), extensions)
+        };
+        TypeCategories {
+            categories: vec![
+                category(
+                    "images",
+                    &[
+                        "jpg", "jpeg", "png", "gif", "bmp", "webp", "tif", "tiff", "svg", "ico",
+                        "heic",
+                    ],
+                ),
+                category(
+                    "documents",
+                    &[
+                        "pdf", "txt", "md", "rtf", "doc", "docx", "odt", "xls", "xlsx", "ods",
+                        "csv", "ppt", "pptx", "odp", "epub",
+                    ],
+                ),
+                category(
+                    "archives",
+                    &["7z", "zip", "rar", "gz", "tgz", "bz2", "xz", "tar", "zst"],
+                ),
+            ],
+            other: "other".to_string(),
+        }
+    }
+}
+
+impl TypeCategories {
+    /// The folder for the file called `name`, going by its extension.
+    pub fn category_of(&self, name: &str) -> &str {
+        let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        let Some((_, extension)) = file_name.rsplit_once('.') else {
+            return &self.other;
+        };
+        let extension = extension.to_lowercase();
+        self.categories
+            .iter()
+            .find(|(_, extensions)| extensions.contains(&extension))
+            .map_or(&self.other, |(category, _)| category)
+    }
+}
+
+/// Extracts every file to `dest/<category>/`, sorted by extension into
+/// images, documents, archives and other with `TypeCategories::default`.
+/// See `extract_sorted_by_type_with`.
+pub fn extract_sorted_by_type<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+) -> Result<ExtractStats, CascadeError> {
+    extract_sorted_by_type_with(
+        path,
+        password,
+        dest,
+        &ExtractOptions::default(),
+        &TypeCategories::default(),
+    )
+}
+
+/// `extract_with_remap` moving each file into the folder `categories` picks
+/// for it, e.g. `docs/a.pdf` to `dest/documents/docs/a.pdf`; the folders
+/// inside the archive are kept below the category so files of the same
+/// name don't collide. Folder entries aren't extracted themselves, only
+/// those holding files are created, and count as `skipped`.
+pub fn extract_sorted_by_type_with<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    categories: &TypeCategories,
+) -> Result<ExtractStats, CascadeError> {
+    let path = path.as_ref();
+    let folders: HashSet<String> = read_archive_entries_with_password(path, password)?
+        .into_iter()
+        .filter(|entry| entry.is_directory())
+        .map(|entry| entry.name)
+        .collect();
+    extract_with_remap(path, password, dest, options, |name| {
+        (!folders.contains(name)).then(|| format!("{}/{}", categories.category_of(name), name))
+    })
+}
+
+/// Where an extraction is, see `extract_with_progress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractProgress<'a> {
+    /// The entry being decoded.
+    pub entry: &'a str,
+    /// Decoded so far, counting the entries that were skipped as well.
+    pub bytes_done: u64,
+    /// The unpacked size of the whole archive.
+    pub total_bytes: u64,
+    /// How long the rest takes at the speed so far, which
+    /// `max_bytes_per_sec` holds down.
+    pub eta: Option<Duration>,
+}
+
+/// `extract_with_options`, calling `on_progress` as the data of each entry
+/// is decoded, e.g. to drive a progress bar.
+pub fn extract_with_progress<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    mut on_progress: impl FnMut(&ExtractProgress),
+) -> Result<ExtractStats, CascadeError> {
+    extract_reporting(
+        ArchiveSource::File(path.as_ref()),
+        password,
+        dest,
+        options,
+        Some(&mut on_progress),
+        None,
+    )
+}
+
+/// Gives the new name of an entry, see `extract_with_remap`.
+pub(crate) type Remap<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Where `extract_reporting` reads an archive from. It is read more than
+/// once: for the limits, the listing and then the entries.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ArchiveSource<'a> {
+    File(&'a Path),
+    /// An archive already in memory, such as a download. `name` stands for
+    /// it in logs and names the `resume` sidecar.
+    #[cfg(feature = "http")]
+    Bytes {
+        name: &'a Path,
+        data: &'a [u8],
+    },
+}
+
+/// `Read + Seek` behind a single trait object.
+pub(crate) trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+impl<'a> ArchiveSource<'a> {
+    pub(crate) fn name(&self) -> &'a Path {
+        match *self {
+            ArchiveSource::File(path) => path,
+            #[cfg(feature = "http")]
+            ArchiveSource::Bytes { name, .. } => name,
+        }
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        match *self {
+            ArchiveSource::File(path) => Ok(fs::metadata(path)?.len()),
+            #[cfg(feature = "http")]
+            ArchiveSource::Bytes { data, .. } => Ok(data.len() as u64),
+        }
+    }
+
+    /// A reader at the start of the archive.
+    fn open(&self) -> io::Result<Box<dyn ReadSeek + 'a>> {
+        match *self {
+            ArchiveSource::File(path) => Ok(Box::new(open_archive_file(path)?)),
+            #[cfg(feature = "http")]
+            ArchiveSource::Bytes { data, .. } => Ok(Box::new(io::Cursor::new(data))),
+        }
+    }
+
+    fn read_archive(&self, password: &str) -> Result<sevenz_rust::Archive, CascadeError> {
+        match *self {
+            ArchiveSource::File(path) => read_archive_with_password(path, password),
+            #[cfg(feature = "http")]
+            ArchiveSource::Bytes { name, data } => catch_backend_panic(name, || {
+                Ok(sevenz_rust::Archive::read(
+                    &mut io::Cursor::new(data),
+                    data.len() as u64,
+                    sevenz_rust::Password::from(password).as_slice(),
+                )?)
+            }),
+        }
+    }
+}
+
+pub(crate) fn extract_reporting(
+    source: ArchiveSource,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    mut on_progress: Option<&mut dyn FnMut(&ExtractProgress)>,
+    remap: Option<Remap>,
+) -> Result<ExtractStats, CascadeError> {
+    if options.atomic {
+        return extract_staged(source, password, dest, options, on_progress, remap);
+    }
+    check_limits_of(source, password, options)?;
+    let encoding = options
+        .entry_name_encoding
+        .as_deref()
+        .map(|label| {
+            Encoding::for_label(label.trim().as_bytes())
+                .ok_or_else(|| CascadeError::UnknownEncoding(label.to_string()))
+        })
+        .transpose()?;
+
+    let normalize_name = |mut name: String| {
+        if options.normalize_separators && name.contains('\\') {
+            name = name.replace('\\', "/");
+        }
+        if let Some(mode) = options.normalize_case {
+            name = mode.apply(&name);
+        }
+        name
+    };
+    let target_of = |entry: &SevenZArchiveEntry| {
+        let split_backslashes = options.normalize_separators && entry.name().contains('\\');
+        if encoding.is_none() && options.normalize_case.is_none() && !split_backslashes {
+            return dest.join(entry.name());
+        }
+        let name = match encoding {
+            Some(encoding) => decode_entry_name(entry.name(), encoding),
+            None => entry.name().to_string(),
+        };
+        dest.join(normalize_name(name))
+    };
+
+    let mut stats = ExtractStats::default();
+    let dest_existed = dest.exists();
+    let mut resume = match options.resume {
+        true => Some(ResumeState::open(source, password, dest, &target_of)?),
+        false => None,
+    };
+    let skip_blocks = match &resume {
+        Some(resume) => {
+            stats.unchanged += resume.skipped_files;
+            resume.skip_blocks.clone()
+        }
+        None => HashSet::new(),
+    };
+    // paths that did not exist before this attempt, in creation order
+    let mut created: Vec<PathBuf> = Vec::new();
+    let mut limiter = RateLimiter::for_rate(options.max_bytes_per_sec);
+    // (entry name, link path, link target) resolved once every regular file
+    // exists
+    let mut deferred_links: Vec<(String, PathBuf, String)> = Vec::new();
+    // normalized paths already taken by an entry of this archive
+    let mut written: HashSet<PathBuf> = HashSet::new();
+    let mut verification = options.verify.then(VerificationReport::default);
+    let mut hasher = options.content_hash.then(blake3::Hasher::new);
+    let total_bytes = match on_progress {
+        Some(_) => source
+            .read_archive(password)?
+            .files
+            .iter()
+            .map(|entry| entry.size())
+            .sum(),
+        None => 0,
+    };
+    let mut bytes_done = 0;
+    let (started, rate_limit) = (Instant::now(), limiter.as_ref().map(RateLimiter::rate));
+    let absolute_dest = match options.record_written_files {
+        Some(PathStyle::Absolute) => Some(std::path::absolute(dest)?),
+        _ => None,
+    };
+    let record_written = |written_files: &mut Vec<PathBuf>, target: &Path| {
+        if options.record_written_files.is_none() {
+            return;
+        }
+        let relative = target.strip_prefix(dest).unwrap_or(target);
+        written_files.push(match &absolute_dest {
+            Some(absolute_dest) => absolute_dest.join(relative),
+            None => relative.to_path_buf(),
+        });
+    };
+    let add_to_folder_total = |totals: &mut HashMap<String, u64>, target: &Path, bytes: u64| {
+        if !options.record_folder_totals {
+            return;
+        }
+        let relative = target.strip_prefix(dest).unwrap_or(target);
+        if let Some(top) = relative.components().next() {
+            *totals
+                .entry(top.as_os_str().to_string_lossy().into_owned())
+                .or_default() += bytes;
+        }
+    };
+    let record_outcome = |entries: &mut Vec<EntryReport>, name: &str, outcome: EntryOutcome| {
+        if options.record_entry_outcomes {
+            entries.push(EntryReport {
+                name: name.to_string(),
+                outcome,
+            });
+        }
+    };
+
+    // set when the next file would pass `max_total_bytes`
+    let mut over_budget = None;
+
+    let (file, len) = (source.open()?, source.len()?);
+    let result = catch_backend_panic(source.name(), || {
+        Ok(for_each_entry_in_order(
+            file,
+            len,
+            dest,
+            password,
+            options.entry_order,
+            &skip_blocks,
+            |entry, reader, _| {
+                let mut progress;
+                let reader: &mut dyn Read = match on_progress.as_mut() {
+                    Some(on_progress) => {
+                        progress = ProgressReader {
+                            inner: reader,
+                            entry: entry.name(),
+                            done: &mut bytes_done,
+                            total: total_bytes,
+                            started,
+                            rate_limit,
+                            on_progress: &mut **on_progress,
+                        };
+                        &mut progress
+                    }
+                    None => reader,
+                };
+                let mut target = match remap {
+                    Some(remap) => {
+                        let remapped = remap(entry.name()).map(normalize_name);
+                        match remapped {
+                            Some(name) if is_inside(&name) => dest.join(name),
+                            _ => {
+                                if let Some(name) = remapped {
+                                    log_tr!(
+                                        warn,
+                                        "{} 的新路径 {} 在解压目标之外，跳过",
+                                        "skipping {}, its new path {} is outside the destination",
+                                        entry.name(),
+                                        name
+                                    );
+                                }
+                                io::copy(reader, &mut io::sink())?;
+                                stats.skipped += 1;
+                                record_outcome(
+                                    &mut stats.entries,
+                                    entry.name(),
+                                    EntryOutcome::SkippedFilter,
+                                );
+                                return Ok(true);
+                            }
+                        }
+                    }
+                    None => {
+                        let target = target_of(entry);
+                        // names with a root or a `..` would leave `dest`
+                        let inside = target
+                            .strip_prefix(dest)
+                            .is_ok_and(|name| is_inside(&name.to_string_lossy()));
+                        if !inside {
+                            log_tr!(
+                                warn,
+                                "{} 会写到解压目标之外，跳过",
+                                "skipping {}, it would be written outside the destination",
+                                entry.name()
+                            );
+                            io::copy(reader, &mut io::sink())?;
+                            stats.skipped += 1;
+                            record_outcome(
+                                &mut stats.entries,
+                                entry.name(),
+                                EntryOutcome::SkippedFilter,
+                            );
+                            return Ok(true);
+                        }
+                        target
+                    }
+                };
+                let index = resume.as_mut().and_then(|resume| resume.index_of(entry));
+                if let (Some(resume), Some(index)) = (&resume, index) {
+                    if resume.is_done(index, entry, &target) {
+                        io::copy(reader, &mut io::sink())?;
+                        stats.unchanged += 1;
+                        record_outcome(
+                            &mut stats.entries,
+                            entry.name(),
+                            EntryOutcome::SkippedExists,
+                        );
+                        return Ok(true);
+                    }
+                }
+
+                if options.files_only && entry.is_directory() {
+                    stats.skipped_directories += 1;
+                    record_outcome(
+                        &mut stats.entries,
+                        entry.name(),
+                        EntryOutcome::SkippedFilter,
+                    );
+                    return Ok(true);
+                }
+                if !entry.is_directory() {
+                    if !options.allows_extension(entry.name()) {
+                        log_tr!(
+                            debug,
+                            "扩展名被过滤，跳过 {}",
+                            "extension filtered out, skipping {}",
+                            entry.name()
+                        );
+                        io::copy(reader, &mut io::sink())?;
+                        stats.skipped += 1;
+                        stats.filtered.push(entry.name().to_string());
+                        record_outcome(
+                            &mut stats.entries,
+                            entry.name(),
+                            EntryOutcome::SkippedFilter,
+                        );
+                        return Ok(true);
+                    }
+                    if options.max_file_size.is_some_and(|max| entry.size() > max) {
+                        log_tr!(
+                            warn,
+                            "文件过大 ({} 字节)，跳过 {}",
+                            "file too large ({} bytes), skipping {}",
+                            entry.size(),
+                            entry.name()
+                        );
+                        io::copy(reader, &mut io::sink())?;
+                        stats.skipped += 1;
+                        stats.too_large.push(entry.name().to_string());
+                        record_outcome(
+                            &mut stats.entries,
+                            entry.name(),
+                            EntryOutcome::SkippedTooLarge,
+                        );
+                        return Ok(true);
+                    }
+                    if !written.insert(target.clone()) {
+                        if options.normalize_case.is_some() {
+                            log_tr!(
+                                warn,
+                                "路径规范化后发生冲突: {} -> {}",
+                                "path collision after normalizing: {} -> {}",
+                                entry.name(),
+                                target.display()
+                            );
+                        } else {
+                            log_tr!(
+                                warn,
+                                "压缩包中有重名的条目: {}",
+                                "the archive holds more than one entry named {}",
+                                entry.name()
+                            );
+                        }
+                        stats.duplicates.push(entry.name().to_string());
+                    }
+                    if options.incremental
+                        && !is_symlink_entry(entry)
+                        && is_unchanged(entry, &target)
+                    {
+                        log_tr!(
+                            debug,
+                            "文件未改变，跳过 {}",
+                            "file unchanged, skipping {}",
+                            target.display()
+                        );
+                        io::copy(reader, &mut io::sink())?;
+                        stats.unchanged += 1;
+                        record_outcome(
+                            &mut stats.entries,
+                            entry.name(),
+                            EntryOutcome::SkippedExists,
+                        );
+                        return Ok(true);
+                    }
+                    if fs::symlink_metadata(&target).is_ok() {
+                        match options.overwrite_policy {
+                            OverwritePolicy::Overwrite => {
+                                if let Err(e) = clear_attributes(&target) {
+                                    log_tr!(
+                                        debug,
+                                        "无法清除 {} 的只读属性: {}",
+                                        "cannot clear the read-only attribute of {}: {}",
+                                        target.display(),
+                                        e
+                                    );
+                                }
+                            }
+                            OverwritePolicy::Skip => {
+                                log_tr!(
+                                    info,
+                                    "文件已存在，跳过 {}",
+                                    "file exists, skipping {}",
+                                    target.display()
+                                );
+                                // solid blocks are read sequentially, so the data
+                                // still has to be consumed
+                                io::copy(reader, &mut io::sink())?;
+                                stats.skipped += 1;
+                                record_outcome(
+                                    &mut stats.entries,
+                                    entry.name(),
+                                    EntryOutcome::SkippedExists,
+                                );
+                                return Ok(true);
+                            }
+                            OverwritePolicy::Rename => target = free_path(&target),
+                        }
+                    }
+                }
+
+                if options.cleanup_on_failure {
+                    record_new_paths(&target, &mut created);
+                }
+
+                if is_symlink_entry(entry) {
+                    let mut link_target = String::new();
+                    reader.read_to_string(&mut link_target)?;
+                    match options.symlink_policy {
+                        SymlinkPolicy::Create => {
+                            match create_symlink_in(dest, &link_target, &target) {
+                                Ok(()) => {
+                                    stats.symlinks += 1;
+                                    record_written(&mut stats.written_files, &target);
+                                    record_outcome(
+                                        &mut stats.entries,
+                                        entry.name(),
+                                        EntryOutcome::Extracted,
+                                    );
+                                }
+                                Err(e) => {
+                                    log_tr!(
+                                        warn,
+                                        "无法创建符号链接 {}，已跳过: {}",
+                                        "cannot create symlink {}, skipped: {}",
+                                        entry.name(),
+                                        e
+                                    );
+                                    stats.skipped += 1;
+                                    record_outcome(
+                                        &mut stats.entries,
+                                        entry.name(),
+                                        EntryOutcome::Failed(e.to_string()),
+                                    );
+                                }
+                            }
+                        }
+                        SymlinkPolicy::Skip => {
+                            log_tr!(info, "跳过符号链接 {}", "skipping symlink {}", entry.name());
+                            stats.skipped += 1;
+                            record_outcome(
+                                &mut stats.entries,
+                                entry.name(),
+                                EntryOutcome::SkippedFilter,
+                            );
+                        }
+                        SymlinkPolicy::Deref => {
+                            deferred_links.push((entry.name().to_string(), target, link_target))
+                        }
+                    }
+                    return Ok(true);
+                }
+
+                if let Some(max) = options.max_total_bytes {
+                    if !entry.is_directory() && stats.bytes_written + entry.size() > max {
+                        over_budget = Some(max);
+                        return Ok(false);
+                    }
+                }
+
+                let mismatch = Cell::new(false);
+                let mut tolerant;
+                let reader: &mut dyn Read = if options.verify {
+                    tolerant = CrcTolerantReader {
+                        inner: reader,
+                        remaining: entry.size(),
+                        mismatch: &mismatch,
+                    };
+                    &mut tolerant
+                } else {
+                    reader
+                };
+                let mut hashing;
+                let reader: &mut dyn Read = match hasher.as_mut() {
+                    Some(hasher) if !entry.is_directory() => {
+                        hasher.update(entry.name().as_bytes());
+                        hasher.update(&entry.size().to_le_bytes());
+                        hashing = HashingReader {
+                            inner: reader,
+                            hasher,
+                        };
+                        &mut hashing
+                    }
+                    _ => reader,
+                };
+                let written =
+                    write_entry(entry, reader, &target, options.files_only, limiter.as_mut());
+                if written.is_ok() {
+                    if let (Some(resume), Some(index)) = (resume.as_mut(), index) {
+                        resume.record(index)?;
+                    }
+                }
+                match written {
+                    Ok(_) if entry.is_directory() => {
+                        stats.directories += 1;
+                        record_outcome(&mut stats.entries, entry.name(), EntryOutcome::Extracted);
+                    }
+                    Ok(_) => {
+                        let policy = match options.files_only {
+                            true => AttributePolicy::Clear,
+                            false => options.attribute_policy,
+                        };
+                        if let Err(e) = apply_attributes(entry, &target, policy) {
+                            log_tr!(
+                                warn,
+                                "无法设置 {} 的文件属性: {}",
+                                "cannot set the attributes of {}: {}",
+                                target.display(),
+                                e
+                            );
+                        }
+                        record_outcome(&mut stats.entries, entry.name(), EntryOutcome::Extracted);
+                        stats.files += 1;
+                        stats.bytes_written += entry.size();
+                        add_to_folder_total(&mut stats.folder_totals, &target, entry.size());
+                        record_written(&mut stats.written_files, &target);
+                        if let Some(report) = verification.as_mut() {
+                            if mismatch.get() {
+                                log_tr!(
+                                    warn,
+                                    "{} 的 CRC 校验失败，内容可能已损坏",
+                                    "CRC mismatch in {}, the content may be damaged",
+                                    entry.name()
+                                );
+                                report.failed.push(entry.name().to_string());
+                            } else {
+                                report.ok.push(entry.name().to_string());
+                            }
+                        }
+                    }
+
+                    Err(e) if options.continue_on_entry_error => {
+                        log_tr!(
+                            warn,
+                            "解压 {} 失败，已跳过: {}",
+                            "failed to extract {}, skipped: {}",
+                            entry.name(),
+                            e
+                        );
+                        if !entry.is_directory() {
+                            let _ = fs::remove_file(&target);
+                        }
+                        // keep the following entries of a solid block aligned
+                        let _ = io::copy(reader, &mut io::sink());
+                        record_outcome(
+                            &mut stats.entries,
+                            entry.name(),
+                            EntryOutcome::Failed(e.to_string()),
+                        );
+                        stats.failed_entries.push(FailedEntry {
+                            name: entry.name().to_string(),
+                            error: e.to_string(),
+                        });
+                    }
+                    Err(e) => return Err(e),
+                }
+                Ok(true)
+            },
+        )?)
+    });
+
+    let result = match (result, over_budget) {
+        (Ok(()), Some(max)) => Err(CascadeError::CascadeLimitExceeded {
+            limit: CascadeLimit::TotalBytes { max },
+        }),
+        (result, _) => result,
+    };
+    stats.verification = verification;
+    stats.content_hash = hasher.map(|hasher| hasher.finalize().to_hex().to_string());
+    if let Err(e) = result {
+        if options.cleanup_on_failure && resume.is_none() {
+            cleanup_created(dest, dest_existed, &created);
+        }
+        return Err(e);
+    }
+    if let Some(resume) = resume {
+        resume.finish()?;
+    }
+
+    for (name, link, link_target) in deferred_links {
+        match copy_link_target(dest, &link, &link_target) {
+            Ok(bytes) => {
+                stats.files += 1;
+                stats.bytes_written += bytes;
+                add_to_folder_total(&mut stats.folder_totals, &link, bytes);
+                record_written(&mut stats.written_files, &link);
+                record_outcome(&mut stats.entries, &name, EntryOutcome::Extracted);
+            }
+            Err(e) => {
+                log_tr!(
+                    warn,
+                    "无法解析符号链接 {}，已跳过: {}",
+                    "cannot resolve symlink {}, skipped: {}",
+                    link.display(),
+                    e
+                );
+                stats.skipped += 1;
+                record_outcome(
+                    &mut stats.entries,
+                    &name,
+                    EntryOutcome::Failed(e.to_string()),
+                );
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Writes the entry to `target` through a `DiskSink`, the same way
+/// `extract_to_sink` writes into any sink, within `max_open_files` and
+/// `limiter`. For `files_only` the modification time is left out.
+pub(crate) fn write_entry(
+    entry: &SevenZArchiveEntry,
+    reader: &mut dyn Read,
+    target: &Path,
+    files_only: bool,
+    mut limiter: Option<&mut RateLimiter>,
+) -> Result<bool, sevenz_rust::Error> {
+    let (root, name) = match (target.parent(), target.file_name()) {
+        (Some(root), Some(name)) => (root, Path::new(name)),
+        _ => (Path::new(""), target),
+    };
+    with_file_handle(|| {
+        let mut sink = DiskSink::new(root);
+        write_to_sink(
+            &mut sink,
+            entry,
+            reader,
+            name,
+            !files_only,
+            limiter.as_deref_mut(),
+        )
+    })?;
+    Ok(true)
+}
+
+/// Where `ExtractOptions::resume` keeps track of the entries of `archive`
+/// extracted into `dest`: `<archive file name>.partial` in `dest`, which the
+/// watcher ignores like any other unfinished download.
+pub fn partial_sidecar_path(archive: &Path, dest: &Path) -> PathBuf {
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dest.join(format!("{}.partial", name))
+}
+
+/// The sidecar of an `ExtractOptions::resume` run: one entry index per
+/// line, in the order of `read_7z_contents`.
+struct ResumeState {
+    sidecar: File,
+    sidecar_path: PathBuf,
+    done: HashSet<usize>,
+    /// indices of the entries with each name, to tell which one is visited
+    indices: HashMap<String, VecDeque<usize>>,
+    /// blocks with every file already done, which need no decoding
+    skip_blocks: HashSet<usize>,
+    /// files in `skip_blocks`
+    skipped_files: usize,
+}
+
+impl ResumeState {
+    fn open(
+        source: ArchiveSource,
+        password: &str,
+        dest: &Path,
+        target_of: &dyn Fn(&SevenZArchiveEntry) -> PathBuf,
+    ) -> Result<Self, CascadeError> {
+        fs::create_dir_all(dest)?;
+        let sidecar_path = partial_sidecar_path(source.name(), dest);
+        let done: HashSet<usize> = match fs::read_to_string(&sidecar_path) {
+            Ok(content) => content
+                .lines()
+                .filter_map(|l| l.trim().parse().ok())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let archive = source.read_archive(password)?;
+        let mut indices: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for (index, entry) in archive.files.iter().enumerate() {
+            indices
+                .entry(entry.name().to_string())
+                .or_default()
+                .push_back(index);
+        }
+
+        let mut skip_blocks = HashSet::new();
+        let mut skipped_files = 0;
+        let map = &archive.stream_map;
+        for (block, folder) in archive.folders.iter().enumerate() {
+            let first = map.folder_first_file_index[block];
+            let files = first..first + folder.num_unpack_sub_streams;
+            if !files.is_empty()
+                && files.clone().all(|index| {
+                    let entry = &archive.files[index];
+                    done.contains(&index) && is_unchanged(entry, &target_of(entry))
+                })
+            {
+                skip_blocks.insert(block);
+                skipped_files += files.len();
+            }
+        }
+        if !done.is_empty() {
+            log_tr!(
+                info,
+                "继续上次中断的解压，{} 个条目已完成: {}",
+                "resuming an interrupted extraction, {} entries already done: {}",
+                done.len(),
+                source.name().display()
+            );
+        }
+
+        let sidecar = File::options()
+            .create(true)
+            .append(true)
+            .open(&sidecar_path)?;
+        Ok(ResumeState {
+            sidecar,
+            sidecar_path,
+            done,
+            indices,
+            skip_blocks,
+            skipped_files,
+        })
+    }
+
+    fn index_of(&mut self, entry: &SevenZArchiveEntry) -> Option<usize> {
+        self.indices.get_mut(entry.name())?.pop_front()
+    }
+
+    /// Folders are cheap to create again and never count as done.
+    fn is_done(&self, index: usize, entry: &SevenZArchiveEntry, target: &Path) -> bool {
+        !entry.is_directory() && self.done.contains(&index) && is_unchanged(entry, target)
+    }
+
+    fn record(&mut self, index: usize) -> io::Result<()> {
+        writeln!(self.sidecar, "{}", index)?;
+        self.sidecar.flush()
+    }
+
+    fn finish(self) -> io::Result<()> {
+        drop(self.sidecar);
+        fs::remove_file(&self.sidecar_path)
+    }
+}
+
+/// Whether the relative path `name` stays below the folder it is joined to.
+fn is_inside(name: &str) -> bool {
+    let mut components = Path::new(name).components().peekable();
+    components.peek().is_some()
+        && components.all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Whether `target` is a regular file with the entry's size and CRC. Entries
+/// without a CRC are always written again.
+fn is_unchanged(entry: &SevenZArchiveEntry, target: &Path) -> bool {
+    if !entry.has_crc {
+        return false;
+    }
+    match fs::symlink_metadata(target) {
+        Ok(metadata) if metadata.is_file() && metadata.len() == entry.size() => {}
+        _ => return false,
+    }
+    file_crc32(target).is_ok_and(|crc| crc as u64 == entry.crc)
+}
+
+/// What extracting an archive into an existing `dest` would do to the files
+/// there, see `plan_overwrite`. Each list holds entry names, in archive
+/// order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverwritePlan {
+    /// Nothing is at their path yet.
+    pub new: Vec<String>,
+    /// Something else is at their path: a file that differs in size or
+    /// CRC, one that can't be compared as the entry has no CRC, or a folder
+    /// or link.
+    pub overwritten: Vec<String>,
+    /// Already there with their size and CRC, the files `incremental`
+    /// leaves alone.
+    pub identical: Vec<String>,
+}
+
+/// Compares the files of the archive with what `dest` holds, without
+/// decoding or writing anything, e.g. to show a confirmation before
+/// extracting into a populated folder. Entries are looked up at their name
+/// as stored, folders are left out.
+pub fn plan_overwrite<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+) -> Result<OverwritePlan, CascadeError> {
+    let mut plan = OverwritePlan::default();
+    for entry in read_archive_entries_with_password(path, password)? {
+        if entry.is_directory() {
+            continue;
+        }
+        let target = dest.join(entry.name());
+        let list = if fs::symlink_metadata(&target).is_err() {
+            &mut plan.new
+        } else if !is_symlink_entry(&entry) && is_unchanged(&entry, &target) {
+            &mut plan.identical
+        } else {
+            &mut plan.overwritten
+        };
+        list.push(entry.name().to_string());
+    }
+    Ok(plan)
+}
+
+fn file_crc32(path: &Path) -> io::Result<u32> {
+    const CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut digest = CRC32.digest();
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(digest.finalize());
+        }
+        digest.update(&buf[..read]);
+    }
+}
+
+/// Decodes every file of the archive without writing anything and checks it
+/// against its CRC. Errors other than a CRC mismatch, such as a wrong
+/// password, are returned as usual.
+pub fn verify_archive<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+) -> Result<VerificationReport, CascadeError> {
+    let mut reader = open_archive_reader(path.as_ref(), password)?;
+    let mut report = VerificationReport::default();
+    catch_backend_panic(path.as_ref(), || {
+        Ok(reader.for_each_entries(|entry, data| {
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            match io::copy(data, &mut io::sink()) {
+                Ok(size) if size == entry.size() => report.ok.push(entry.name().to_string()),
+                Ok(_) => report.failed.push(entry.name().to_string()),
+                Err(e) if is_checksum_error(&e) => report.failed.push(entry.name().to_string()),
+                Err(e) => return Err(e.into()),
+            }
+            Ok(true)
+        })?)
+    })?;
+    Ok(report)
+}
+
+fn is_checksum_error(e: &io::Error) -> bool {
+    matches!(
+        e.get_ref()
+            .and_then(|e| e.downcast_ref::<sevenz_rust::Error>()),
+        Some(sevenz_rust::Error::ChecksumVerificationFailed)
+    )
+}
+
+/// `extract_with_options` for whatever archive `path` is, going by its
+/// signature rather than its extension. Formats that are recognized but not
+/// in `ArchiveFormat::SUPPORTED` give `UnsupportedFormat`, files without
+/// any known signature an `InvalidData` I/O error.
+///
+/// Tar and tar.gz go through `try_extract_tar` and `try_extract_tar_gz`,
+/// which only follow `record_written_files` of `options`; self-extracting
+/// 7z archives go through `extract_sfx` and formats added with
+/// `register_extractor`, which are tried first, through their extractor,
+/// and these ignore `options`. A gzip file that isn't a tar is `UnsupportedFormat` too.
+pub fn extract_any<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractStats, CascadeError> {
+    let path = path.as_ref();
+    let mut head = Vec::with_capacity(FULL_SIGNATURE_WINDOW);
+    File::open(path)?
+        .take(FULL_SIGNATURE_WINDOW as u64)
+        .read_to_end(&mut head)?;
+    match detect_format_in_bytes(&head, &ArchiveFormat::ALL) {
+        Some(ArchiveFormat::Custom) => match registered_extractor(&head) {
+            Some(extractor) => {
+                log_tr!(
+                    info,
+                    "解压 {}，使用 {}",
+                    "extracting {} with {}",
+                    path.display(),
+                    extractor.name()
+                );
+                extractor.extract(path, password, dest)
+            }
+            None => Err(CascadeError::UnsupportedFormat {
+                detected: ArchiveFormat::Custom,
+            }),
+        },
+        Some(ArchiveFormat::SevenZ) => match extract_with_options(path, password, dest, options) {
+            Err(CascadeError::UnsupportedCoder { coder }) if options.allow_external_7z => {
+                let Some(binary) = find_external_7z() else {
+                    log_tr!(
+                        warn,
+                        "{} 使用了不支持的压缩方法 {}，且找不到 7z 程序",
+                        "{} uses the unsupported method {} and no 7z program was found",
+                        path.display(),
+                        coder
+                    );
+                    return Err(CascadeError::UnsupportedCoder { coder });
+                };
+                extract_with_external_7z(&binary, path, password, dest, options.overwrite_policy)
+            }
+            result => result,
+        },
+        Some(ArchiveFormat::Tar) => extract_tar_recording(path, false, dest, options),
+        Some(ArchiveFormat::Gzip) if is_tar_gz(path)? => {
+            extract_tar_recording(path, true, dest, options)
+        }
+        Some(
+            detected @ (ArchiveFormat::Zip
+            | ArchiveFormat::Rar
+            | ArchiveFormat::Gzip
+            | ArchiveFormat::Bzip2
+            | ArchiveFormat::Xz),
+        ) => Err(CascadeError::UnsupportedFormat { detected }),
+        None if sfx_payload_offset(path)?.is_some() => extract_sfx(path, password, dest),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a known archive format", path.display()),
+        )
+        .into()),
+    }
+}
+
+/// `extract_with_options`, calling `on_success` with the stats and `dest`
+/// once everything is written, e.g. to import the files somewhere else.
+pub fn extract_with_hook<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    on_success: impl Fn(&ExtractStats, &Path),
+) -> Result<ExtractStats, CascadeError> {
+    let stats = extract_with_options(path, password, dest, options)?;
+    on_success(&stats, dest);
+    Ok(stats)
+}
+
+/// `decompress_with_extract_fn_and_password` visiting the solid blocks in
+/// `order`, leaving out those in `skip_blocks`.
+fn for_each_entry_in_order<R: Read + Seek>(
+    mut file: R,
+    len: u64,
+    dest: &Path,
+    password: &str,
+    order: EntryOrder,
+    skip_blocks: &HashSet<usize>,
+    mut extract_fn: impl FnMut(
+        &SevenZArchiveEntry,
+        &mut dyn Read,
+        &PathBuf,
+    ) -> Result<bool, sevenz_rust::Error>,
+) -> Result<(), sevenz_rust::Error> {
+    if order == EntryOrder::Natural && skip_blocks.is_empty() {
+        return sevenz_rust::decompress_with_extract_fn_and_password(
+            file,
+            dest,
+            password.into(),
+            extract_fn,
+        );
+    }
+
+    let password = sevenz_rust::Password::from(password);
+    let archive = sevenz_rust::Archive::read(&mut file, len, password.as_slice())?;
+    fs::create_dir_all(dest)?;
+    let mut blocks: Vec<usize> = (0..archive.folders.len())
+        .filter(|block| !skip_blocks.contains(block))
+        .collect();
+    if order == EntryOrder::LargestFirst {
+        // stable, so blocks of equal size keep their natural order
+        blocks.sort_by_key(|&block| std::cmp::Reverse(archive.folders[block].get_unpack_size()));
+    }
+
+    let mut each = |entry: &SevenZArchiveEntry, reader: &mut dyn Read| {
+        extract_fn(entry, reader, &dest.join(entry.name()))
+    };
+    for block in blocks {
+        let decoder =
+            sevenz_rust::BlockDecoder::new(block, &archive, password.as_slice(), &mut file);
+        if !decoder.for_each_entries(&mut each)? {
+            return Ok(());
+        }
+    }
+    // entries without data, such as directories, don't belong to a block
+    for (index, entry) in archive.files.iter().enumerate() {
+        if archive.stream_map.file_folder_index[index].is_none() && !each(entry, &mut io::empty())?
+        {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Old Windows tools store names in the system code page, which reaches us
+/// as one char per byte (U+0000 to U+00FF). Such names are reassembled into
+/// bytes and decoded with `encoding`; real Unicode names are left alone, as
+/// are names that aren't valid in `encoding`.
+fn decode_entry_name(name: &str, encoding: &'static Encoding) -> String {
+    if name.is_ascii() || name.chars().any(|c| c as u32 > 0xff) {
+        return name.to_string();
+    }
+    let bytes: Vec<u8> = name.chars().map(|c| c as u8).collect();
+    match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+        Some(decoded) => decoded.into_owned(),
+        None => name.to_string(),
+    }
+}
+
+/// The first `name (n).ext` next to `path` that doesn't exist yet.
+pub(crate) fn free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+/// Remembers `target` and any of its missing parent folders.
+fn record_new_paths(target: &Path, created: &mut Vec<PathBuf>) {
+    let mut missing = Vec::new();
+    let mut current = Some(target);
+    while let Some(path) = current {
+        if fs::symlink_metadata(path).is_ok() {
+            break;
+        }
+        missing.push(path.to_path_buf());
+        current = path.parent();
+    }
+    created.extend(missing.into_iter().rev());
+}
+
+fn cleanup_created(dest: &Path, dest_existed: bool, created: &[PathBuf]) {
+    // a destination we created ourselves, like a smart-mode wrapper folder,
+    // goes away as a whole
+    if !dest_existed {
+        log_tr!(
+            info,
+            "解压失败，删除已创建的文件夹 {}",
+            "extraction failed, removing the created folder {}",
+            dest.display()
+        );
+        let _ = fs::remove_dir_all(dest);
+        return;
+    }
+
+    log_tr!(
+        info,
+        "解压失败，清理 {} 个已创建的文件",
+        "extraction failed, cleaning up {} created files",
+        created.len()
+    );
+    for path in created.iter().rev() {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => {
+                let _ = fs::remove_dir_all(path);
+            }
+            Ok(_) => {
+                let _ = fs::remove_file(path);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// "Extract here": extracts into the archive's own folder, applying smart
+/// mode. Returns the folder the contents were written to.
+pub fn extract_here<P: AsRef<Path>>(path: P, password: &str) -> Result<PathBuf, CascadeError> {
+    let path = path.as_ref();
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let files = read_archive_entries_with_password(path, password)?;
+    let dest = resolve_dest(path, parent, needs_wrapper_folder(&files));
+
+    extract_with_options(path, password, &dest, &ExtractOptions::default())?;
+    Ok(dest)
+}
+
+/// Decompresses the archive once and writes every entry to each of `dests`,
+/// e.g. to fill a mirror without extracting twice.
+///
+/// A destination that fails stops receiving entries while the others carry
+/// on; the returned results line up with `dests`. Errors reading the archive
+/// itself affect every destination and are returned as `Err`.
+pub fn extract_to_many<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dests: &[PathBuf],
+) -> Result<Vec<io::Result<()>>, CascadeError> {
+    let mut results: Vec<io::Result<()>> = dests.iter().map(|_| Ok(())).collect();
+    let mut archive = open_archive_reader(path.as_ref(), password)?;
+    let mut buf = vec![0u8; 64 * 1024];
+
+    catch_backend_panic(path.as_ref(), || {
+        Ok(archive.for_each_entries(|entry, reader| {
+            let mut outputs: Vec<(usize, File)> = Vec::new();
+            for (i, dest) in dests.iter().enumerate() {
+                if results[i].is_err() {
+                    continue;
+                }
+                let target = dest.join(entry.name());
+                let opened = if entry.is_directory() {
+                    fs::create_dir_all(&target).map(|_| None)
+                } else {
+                    target
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| File::create(&target))
+                        .map(Some)
+                };
+                match opened {
+                    Ok(Some(file)) => outputs.push((i, file)),
+                    Ok(None) => {}
+                    Err(e) => fail_dest(&mut results[i], &dests[i], e),
+                }
+            }
+
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                for (i, file) in outputs.iter_mut() {
+                    if results[*i].is_ok() {
+                        if let Err(e) = file.write_all(&buf[..n]) {
+                            fail_dest(&mut results[*i], &dests[*i], e);
+                        }
+                    }
+                }
+            }
+            Ok(true)
+        })?)
+    })?;
+
+    Ok(results)
+}
+
+/// Extracts only the entries at `indices`, positions in the list returned by
+/// `read_7z_contents`. Entries in a solid block still have to be decoded to
+/// reach the ones after them, they just aren't written.
+pub fn extract_indices<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    indices: &[usize],
+) -> Result<ExtractStats, CascadeError> {
+    let archive = open_archive_reader(path.as_ref(), password)?;
+    let count = archive.archive().files.len();
+    if let Some(&index) = indices.iter().find(|&&i| i >= count) {
+        return Err(CascadeError::IndexOutOfRange { index, count });
+    }
+    let wanted: HashSet<usize> = indices.iter().copied().collect();
+    extract_selected(path.as_ref(), archive, dest, |info| {
+        wanted.contains(&info.index).then(|| info.name.clone())
+    })
+}
+
+/// Extracts only the entries `predicate` picks, e.g. by name, size,
+/// modification time or how deep they are nested, keeping their path
+/// below `dest`. Folders a picked file is in are created whether or not
+/// their own entry is picked. The other ways of picking entries, such as
+/// `extract_indices` and `extract_subtree`, are built on the same loop.
+pub fn extract_where<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    predicate: impl Fn(&EntryInfo) -> bool,
+) -> Result<ExtractStats, CascadeError> {
+    let archive = open_archive_reader(path.as_ref(), password)?;
+    extract_selected(path.as_ref(), archive, dest, |info| {
+        predicate(info).then(|| info.name.clone())
+    })
+}
+
+/// Writes every entry `target_of` gives a path to there, relative to `dest`,
+/// and decodes the others into the void. Paths that would leave `dest`,
+/// being absolute or going up with `..`, are skipped with a warning, so
+/// none of the ways of picking entries can write outside it.
+fn extract_selected(
+    path: &Path,
+    mut archive: sevenz_rust::SevenZReader<BufReader<File>>,
+    dest: &Path,
+    target_of: impl Fn(&EntryInfo) -> Option<String>,
+) -> Result<ExtractStats, CascadeError> {
+    let order = visit_order(archive.archive());
+    let mut visited = 0;
+    let mut stats = ExtractStats::default();
+    let mut limiter = RateLimiter::for_rate(None);
+
+    catch_backend_panic(path, || {
+        Ok(archive.for_each_entries(|entry, reader| {
+            let index = order[visited];
+            visited += 1;
+            let Some(relative) = target_of(&EntryInfo::of(index, entry)) else {
+                io::copy(reader, &mut io::sink())?;
+                return Ok(true);
+            };
+            if !is_inside(&relative) {
+                log_tr!(
+                    warn,
+                    "{} 会写到解压目标之外，跳过",
+                    "skipping {}, it would be written outside the destination",
+                    entry.name()
+                );
+                io::copy(reader, &mut io::sink())?;
+                stats.skipped += 1;
+                return Ok(true);
+            }
+            let target = dest.join(relative);
+            write_entry(entry, reader, &target, false, limiter.as_mut())?;
+            if entry.is_directory() {
+                stats.directories += 1;
+            } else {
+                stats.files += 1;
+                stats.bytes_written += entry.size();
+            }
+            Ok(true)
+        })?)
+    })?;
+
+    Ok(stats)
+}
+
+/// Extracts only the folder `prefix` of the archive, e.g. `"docs/manual"`,
+/// with everything below it. Entries keep their full path, so this writes
+/// `dest/docs/manual/...`; see `extract_subtree_rebased` to drop the
+/// leading folders. `prefix` matches whole path components, `"docs"` does
+/// not select `docs2/`. An archive without such a folder extracts nothing.
+pub fn extract_subtree<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    prefix: &str,
+) -> Result<ExtractStats, CascadeError> {
+    extract_subtree_with(path, password, dest, prefix, false)
+}
+
+/// `extract_subtree`, but the extracted tree starts at the last component of
+/// `prefix`: `"docs/manual"` writes `dest/manual/...`, much like
+/// `tar --strip-components` for that subtree. The rebased paths go through
+/// the same check as any other, a name like `docs/manual/../../../x` is
+/// skipped rather than written outside `dest`.
+pub fn extract_subtree_rebased<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    prefix: &str,
+) -> Result<ExtractStats, CascadeError> {
+    extract_subtree_with(path, password, dest, prefix, true)
+}
+
+fn extract_subtree_with<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    prefix: &str,
+    rebase: bool,
+) -> Result<ExtractStats, CascadeError> {
+    let prefix = prefix.replace('\\', "/");
+    let prefix = prefix.trim_matches('/');
+    // what is cut off the front of every name when rebasing
+    let strip = match prefix.rfind('/') {
+        Some(end) if rebase => &prefix[..=end],
+        _ => "",
+    };
+    let archive = open_archive_reader(path.as_ref(), password)?;
+    extract_selected(path.as_ref(), archive, dest, |info| {
+        let name = info.name.replace('\\', "/");
+        let in_subtree = name
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        in_subtree.then(|| name[strip.len()..].to_string())
+    })
+}
+
+/// The indices into `archive.files` in the order `for_each_entries` visits
+/// them: folder by folder, then the entries without data.
+pub(crate) fn visit_order(archive: &sevenz_rust::Archive) -> Vec<usize> {
+    let map = &archive.stream_map;
+    let mut order = Vec::with_capacity(archive.files.len());
+    for (folder_index, folder) in archive.folders.iter().enumerate() {
+        let start = map.folder_first_file_index[folder_index];
+        order.extend(start..start + folder.num_unpack_sub_streams);
+    }
+    order.extend((0..archive.files.len()).filter(|&i| map.file_folder_index[i].is_none()));
+    order
+}
+
+fn fail_dest(result: &mut io::Result<()>, dest: &Path, e: io::Error) {
+    log_tr!(
+        warn,
+        "写入 {} 失败，停止向该目标解压: {}",
+        "writing to {} failed, no longer extracting there: {}",
+        dest.display(),
+        e
+    );
+    *result = Err(e);
+}
+
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+/// The Windows attributes `AttributePolicy` deals with: read-only, hidden
+/// and system.
+#[cfg(windows)]
+const MANAGED_ATTRIBUTES: u32 = FILE_ATTRIBUTE_READONLY | 0x2 | 0x4;
+
+/// Gives a file just written the attributes `policy` asks for.
+pub(crate) fn apply_attributes(
+    entry: &SevenZArchiveEntry,
+    target: &Path,
+    policy: AttributePolicy,
+) -> io::Result<()> {
+    match policy {
+        AttributePolicy::Clear => clear_attributes(target),
+        AttributePolicy::Preserve if entry.has_windows_attributes => {
+            preserve_attributes(entry.windows_attributes, target)
+        }
+        AttributePolicy::Preserve => Ok(()),
+    }
+}
+
+/// Makes `path` writable, and on Windows neither hidden nor system.
+pub(crate) fn clear_attributes(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() {
+        return Ok(());
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        let attributes = metadata.file_attributes();
+        if attributes & MANAGED_ATTRIBUTES != 0 {
+            set_windows_attributes(path, attributes & !MANAGED_ATTRIBUTES)?;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = metadata.permissions();
+        if permissions.readonly() {
+            permissions.set_mode(permissions.mode() | 0o200);
+            fs::set_permissions(path, permissions)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the `attributes` stored for an entry to the file at `path`.
+fn preserve_attributes(attributes: u32, path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        let current = fs::metadata(path)?.file_attributes();
+        let wanted = (current & !MANAGED_ATTRIBUTES) | (attributes & MANAGED_ATTRIBUTES);
+        if wanted != current {
+            set_windows_attributes(path, wanted)?;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0 {
+            let mode = (attributes >> 16) & 0o7777;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        } else if attributes & FILE_ATTRIBUTE_READONLY != 0 {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_windows_attributes(path: &Path, attributes: u32) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{SetFileAttributesW, FILE_ATTRIBUTE_NORMAL};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // no attributes at all has to be spelled FILE_ATTRIBUTE_NORMAL
+    let attributes = match attributes {
+        0 => FILE_ATTRIBUTE_NORMAL,
+        attributes => attributes,
+    };
+    if unsafe { SetFileAttributesW(wide.as_ptr(), attributes) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `create_symlink` for a link extracted into `dest`, refusing a target
+/// that is absolute or climbs out of `dest` with `..`.
+pub(crate) fn create_symlink_in(dest: &Path, link_target: &str, link: &Path) -> io::Result<()> {
+    if !link_stays_inside(dest, link, link_target) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("link target {} is outside the destination", link_target),
+        ));
+    }
+    create_symlink(link_target, link)
+}
+
+/// Whether `link_target`, read from `link`'s folder, names a path inside
+/// `dest`, judged from the names alone as the target may not exist yet.
+fn link_stays_inside(dest: &Path, link: &Path, link_target: &str) -> bool {
+    let target = link_target.replace('\\', "/");
+    // `C:` and the like are absolute on Windows whatever the platform
+    if target.starts_with('/') || target.as_bytes().get(1) == Some(&b':') {
+        return false;
+    }
+    let Ok(folder) = link.parent().unwrap_or(dest).strip_prefix(dest) else {
+        return false;
+    };
+    let mut depth = 0usize;
+    let folder = folder
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned());
+    for component in folder.chain(target.split('/').map(str::to_string)) {
+        match component.as_str() {
+            "" | "." => {}
+            ".." => match depth.checked_sub(1) {
+                Some(up) => depth = up,
+                None => return false,
+            },
+            _ => depth += 1,
+        }
+    }
+    true
+}
+
+pub(crate) fn create_symlink(link_target: &str, link: &Path) -> io::Result<()> {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(link_target, link)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::symlink_file(link_target, link)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symlinks are not supported on this platform",
+        ))
+    }
+}
+
+/// Reports what passes through to the callback of `extract_with_progress`.
+struct ProgressReader<'a> {
+    inner: &'a mut dyn Read,
+    entry: &'a str,
+    done: &'a mut u64,
+    total: u64,
+    /// when the extraction started, for the ETA
+    started: Instant,
+    rate_limit: Option<u64>,
+    on_progress: &'a mut dyn FnMut(&ExtractProgress),
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            *self.done += n as u64;
+            let mut rate = *self.done as f64 / self.started.elapsed().as_secs_f64();
+            if let Some(limit) = self.rate_limit {
+                rate = rate.min(limit as f64);
+            }
+            (self.on_progress)(&ExtractProgress {
+                entry: self.entry,
+                bytes_done: *self.done,
+                total_bytes: self.total,
+                eta: estimate_remaining(*self.done, Some(self.total), rate),
+            });
+        }
+        Ok(n)
+    }
+}
+
+/// Hands out the data of an entry that fails its CRC check, or ends early,
+/// instead of an error, remembering the mismatch.
+struct CrcTolerantReader<'a> {
+    inner: &'a mut dyn Read,
+    remaining: u64,
+    mismatch: &'a Cell<bool>,
+}
+
+impl Read for CrcTolerantReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match self.inner.read(buf) {
+            Ok(0) if self.remaining > 0 => {
+                self.mismatch.set(true);
+                0
+            }
+            Ok(n) => n,
+            // the checksum is checked with the last chunk, which was still
+            // read into `buf`
+            Err(e) if is_checksum_error(&e) && self.remaining <= buf.len() as u64 => {
+                self.mismatch.set(true);
+                self.remaining as usize
+            }
+            Err(e) => return Err(e),
+        };
+        self.remaining = self.remaining.saturating_sub(n as u64);
+        Ok(n)
+    }
+}
+
+/// Feeds everything read through it to `hasher`.
+struct HashingReader<'a> {
+    inner: &'a mut dyn Read,
+    hasher: &'a mut blake3::Hasher,
+}
+
+impl Read for HashingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Copies the file a link points to over the link's own path, refusing
+/// targets that resolve outside `dest`.
+fn copy_link_target(dest: &Path, link: &Path, link_target: &str) -> io::Result<u64> {
+    let base = link.parent().unwrap_or(dest);
+    let resolved = base.join(link_target).canonicalize()?;
+    if !resolved.starts_with(dest.canonicalize()?) || !resolved.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("link target {} is not a file in the archive", link_target),
+        ));
+    }
+    fs::copy(resolved, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    fn extract_symlink_fixture(name: &str, policy: SymlinkPolicy) -> (PathBuf, ExtractStats) {
+        let dest = test_dir(name);
+        let options = ExtractOptions {
+            symlink_policy: policy,
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/symlink.7z", "", &dest, &options).unwrap();
+        (dest, stats)
+    }
+
+    #[test]
+    fn test_extract_with_rate_limit() {
+        let dest = test_dir("rate_limit");
+        let options = ExtractOptions {
+            max_bytes_per_sec: Some(1024 * 1024),
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.bytes_written, 3677);
+        assert!(dest.join("inner/inner.txt").exists());
+    }
+
+    #[test]
+    fn test_suspicious_ratio() {
+        let dest = test_dir("suspicious_ratio");
+        let ratio = compression_ratio("tests/sample.7z", "").unwrap();
+        assert!(ratio > 1.0 && ratio < 2.0);
+
+        let mut options = ExtractOptions {
+            max_compression_ratio: 1.0,
+            ..Default::default()
+        };
+        let result = extract_with_options("tests/sample.7z", "", &dest, &options);
+        assert!(matches!(result, Err(CascadeError::SuspiciousRatio { .. })));
+        assert!(!dest.exists());
+
+        options.allow_high_ratio = true;
+        assert!(extract_with_options("tests/sample.7z", "", &dest, &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let dest = test_dir("max_entries");
+        let mut options = ExtractOptions {
+            max_entries: Some(4),
+            ..Default::default()
+        };
+        let result = extract_with_options("tests/sample.7z", "", &dest, &options);
+        assert!(matches!(
+            result,
+            Err(CascadeError::TooManyEntries { count: 5, max: 4 })
+        ));
+        assert!(!dest.exists());
+
+        options.max_entries = Some(5);
+        assert!(extract_with_options("tests/sample.7z", "", &dest, &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_memory() {
+        let needed = decode_memory_estimate("tests/sample.7z", "").unwrap();
+        assert!(needed > 0);
+
+        let dest = test_dir("max_memory");
+        let mut options = ExtractOptions {
+            max_memory: Some(needed - 1),
+            ..Default::default()
+        };
+        let result = extract_with_options("tests/sample.7z", "", &dest, &options);
+        assert!(matches!(
+            result,
+            Err(CascadeError::MemoryLimitExceeded { needed: n, .. }) if n == needed
+        ));
+        assert!(!dest.exists());
+
+        options.max_memory = Some(needed);
+        assert!(extract_with_options("tests/sample.7z", "", &dest, &options).is_ok());
+    }
+
+    /// A copy of `sample.7z` whose compressed data is damaged, so extraction
+    /// fails after the first entries have been written.
+    fn corrupt_sample(dir: &Path) -> PathBuf {
+        let mut bytes = fs::read("tests/sample.7z").unwrap();
+        for b in &mut bytes[1500..1600] {
+            *b = !*b;
+        }
+        let path = dir.join("corrupt.7z");
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_cleanup_on_failure() {
+        let dir = test_dir("cleanup_on_failure");
+        let archive = corrupt_sample(&dir);
+        let options = ExtractOptions {
+            cleanup_on_failure: true,
+            ..Default::default()
+        };
+
+        // a destination that already exists keeps its previous content
+        let dest = dir.join("existing");
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("keep.txt"), "keep").unwrap();
+        fs::write(dest.join("7ziplogo.png"), "old").unwrap();
+        assert!(extract_with_options(&archive, "", &dest, &options).is_err());
+        assert!(dest.join("keep.txt").exists());
+        assert!(dest.join("7ziplogo.png").exists());
+        assert!(!dest.join("7zFormat.txt").exists());
+
+        // a destination created by the attempt is removed entirely
+        let dest = dir.join("wrapper");
+        assert!(extract_with_options(&archive, "", &dest, &options).is_err());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn test_atomic_extraction() {
+        let dir = test_dir("atomic_extraction");
+        let archive = corrupt_sample(&dir);
+        let options = ExtractOptions {
+            atomic: true,
+            ..Default::default()
+        };
+        let staged = |dir: &Path| {
+            fs::read_dir(dir).unwrap().any(|entry| {
+                entry
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .contains(".partial")
+            })
+        };
+
+        let dest = dir.join("wrapper");
+        assert!(extract_with_options(&archive, "", &dest, &options).is_err());
+        assert!(!dest.exists());
+        assert!(!staged(&dir));
+
+        let dest = dir.join("existing");
+        fs::create_dir_all(&dest).unwrap();
+        assert!(extract_with_options(&archive, "", &dest, &options).is_err());
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+
+        let options = ExtractOptions {
+            record_written_files: Some(PathStyle::Absolute),
+            ..options
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.written_files.len(), 3);
+        let dest = std::path::absolute(&dest).unwrap();
+        for file in &stats.written_files {
+            assert!(file.starts_with(&dest), "{}", file.display());
+            assert!(file.is_file());
+        }
+        assert!(!staged(&dir));
+    }
+
+    #[test]
+    fn test_continue_on_entry_error() {
+        let dir = test_dir("continue_on_entry_error");
+        let archive = corrupt_sample(&dir);
+        let dest = dir.join("out");
+        let options = ExtractOptions {
+            continue_on_entry_error: true,
+            ..Default::default()
+        };
+
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        assert!(!stats.failed_entries.is_empty());
+        for failed in &stats.failed_entries {
+            assert!(!dest.join(&failed.name).is_file());
+        }
+        // entries outside the damaged stream still come out
+        assert!(dest.join("inner/inner-inner").is_dir());
+    }
+
+    #[test]
+    fn test_extract_here() {
+        let dir = test_dir("extract_here");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy("tests/sample.7z", dir.join("sample.7z")).unwrap();
+        fs::copy("tests/7ziplogo_p.7z", dir.join("7ziplogo_p.7z")).unwrap();
+
+        let dest = extract_here(dir.join("sample.7z"), "").unwrap();
+        assert_eq!(dest, dir.join("sample"));
+        assert!(dest.join("7zFormat.txt").exists());
+
+        let dest = extract_here(dir.join("7ziplogo_p.7z"), "1151").unwrap();
+        assert_eq!(dest, dir);
+        assert!(dir.join("7ziplogo.png").exists());
+    }
+
+    #[test]
+    fn test_extract_with_hook() {
+        let dest = test_dir("extract_with_hook");
+        let calls = std::cell::Cell::new(0);
+        let options = ExtractOptions::default();
+        let on_success = |stats: &ExtractStats, path: &Path| {
+            assert_eq!(stats.files, 1);
+            assert_eq!(path, dest);
+            calls.set(calls.get() + 1);
+        };
+
+        assert!(
+            extract_with_hook("tests/7ziplogo_p.7z", "0000", &dest, &options, on_success).is_err()
+        );
+        assert_eq!(calls.get(), 0);
+        extract_with_hook("tests/7ziplogo_p.7z", "1151", &dest, &options, on_success).unwrap();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let dest = test_dir("max_file_size");
+        let largest = read_archive_entries_with_password("tests/sample.7z", "")
+            .unwrap()
+            .into_iter()
+            .max_by_key(|entry| entry.size())
+            .unwrap();
+        let options = ExtractOptions {
+            max_file_size: Some(largest.size() - 1),
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.too_large, [largest.name().to_string()]);
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(stats.files, 2);
+        assert!(!dest.join(largest.name()).exists());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let dir = test_dir("content_hash");
+        fs::create_dir_all(&dir).unwrap();
+        let renamed = dir.join("renamed.7z");
+        fs::copy("tests/sample.7z", &renamed).unwrap();
+        let options = ExtractOptions {
+            content_hash: true,
+            ..Default::default()
+        };
+        let hash = |path: &Path, dest: &str| {
+            extract_with_options(path, "", &dir.join(dest), &options)
+                .unwrap()
+                .content_hash
+                .unwrap()
+        };
+
+        let first = hash(Path::new("tests/sample.7z"), "a");
+        assert_eq!(first.len(), 64);
+        assert_eq!(hash(&renamed, "b"), first);
+        assert_ne!(hash(Path::new("tests/7ziplogo.7z"), "c"), first);
+        let stats =
+            extract_with_options("tests/sample.7z", "", &dir.join("d"), &Default::default())
+                .unwrap();
+        assert_eq!(stats.content_hash, None);
+    }
+
+    #[test]
+    fn test_extract_any() {
+        let dir = test_dir("extract_any");
+        fs::create_dir_all(&dir).unwrap();
+        let options = ExtractOptions::default();
+
+        let stats = extract_any("tests/7ziplogo_p.7z", "1151", &dir.join("7z"), &options).unwrap();
+        assert_eq!(stats.files, 1);
+
+        // gzip, but not a tar inside
+        let gzip = dir.join("notes.gz");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"just some notes").unwrap();
+        fs::write(&gzip, encoder.finish().unwrap()).unwrap();
+        let err = extract_any(&gzip, "", &dir.join("gz"), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            CascadeError::UnsupportedFormat {
+                detected: ArchiveFormat::Gzip
+            }
+        ));
+        assert_eq!(err.to_string(), "gz archives are not supported yet");
+
+        assert!(matches!(
+            extract_any("tests/7ziplogo.png", "", &dir.join("png"), &options),
+            Err(CascadeError::Io(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+
+    /// An archive with `Readme.txt` and `README.txt`, which collide once their
+    /// case is normalized.
+    fn case_collision_archive(dir: &Path) -> PathBuf {
+        write_archive(
+            &dir.join("case.7z"),
+            &[("Readme.txt", "first"), ("README.txt", "second")],
+        )
+    }
+
+    fn write_archive(path: &Path, files: &[(&str, &str)]) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut writer = sevenz_rust::SevenZWriter::create(path).unwrap();
+        for (name, content) in files {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some(content.as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        path.to_path_buf()
+    }
+
+    /// Two files, the second one noise that LZMA2 stores uncompressed, with
+    /// a flipped byte the CRC catches but the decoder doesn't.
+    fn damaged_archive(dir: &Path) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("damaged.7z");
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut writer = sevenz_rust::SevenZWriter::create(&path).unwrap();
+        for (name, content) in [("good.txt", &b"intact content"[..]), ("bad.bin", &noise)] {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer.push_archive_entry(entry, Some(content)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let pos = bytes
+            .windows(16)
+            .position(|w| w == &noise[1000..1016])
+            .unwrap();
+        bytes[pos] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = test_dir("verify");
+        let archive = damaged_archive(&dir);
+        let report = verify_archive(&archive, "").unwrap();
+        assert_eq!(report.ok, ["good.txt"]);
+        assert_eq!(report.failed, ["bad.bin"]);
+        assert!(!report.is_ok());
+        assert!(verify_archive("tests/sample.7z", "").unwrap().is_ok());
+
+        let dest = dir.join("out");
+        assert!(extract_with_options(&archive, "", &dest, &ExtractOptions::default()).is_err());
+        let options = ExtractOptions {
+            verify: true,
+            ..Default::default()
+        };
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        assert_eq!(stats.verification, Some(report));
+        assert_eq!(stats.files, 2);
+        // kept in full, damage included
+        assert_eq!(fs::metadata(dest.join("bad.bin")).unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn test_entry_name_encoding() {
+        let dir = test_dir("entry_name_encoding");
+        // "中文.txt" in GBK, one char per byte
+        let archive = write_archive(
+            &dir.join("gbk.7z"),
+            &[
+                ("\u{d6}\u{d0}\u{ce}\u{c4}.txt", "gbk"),
+                ("日本.txt", "utf-8"),
+            ],
+        );
+        let options = ExtractOptions {
+            entry_name_encoding: Some("gbk".to_string()),
+            ..Default::default()
+        };
+        extract_with_options(&archive, "", &dir.join("out"), &options).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("out/中文.txt")).unwrap(), "gbk");
+        assert_eq!(
+            fs::read_to_string(dir.join("out/日本.txt")).unwrap(),
+            "utf-8"
+        );
+
+        let options = ExtractOptions {
+            entry_name_encoding: Some("no-such-encoding".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            extract_with_options(&archive, "", &dir.join("bad"), &options),
+            Err(CascadeError::UnknownEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_directories() {
+        let dir = test_dir("empty_directories");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("empty.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&archive).unwrap();
+        for name in ["empty", "nested/empty"] {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.is_directory = true;
+            writer.push_archive_entry::<&[u8]>(entry, None).unwrap();
+        }
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = "file.txt".to_string();
+        entry.has_stream = true;
+        writer
+            .push_archive_entry(entry, Some("content".as_bytes()))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let stats =
+            extract_with_options(&archive, "", &dir.join("out"), &ExtractOptions::default())
+                .unwrap();
+        assert_eq!(stats.directories, 2);
+        assert!(dir.join("out/empty").is_dir());
+        assert!(dir.join("out/nested/empty").is_dir());
+        assert_eq!(fs::read_dir(dir.join("out/empty")).unwrap().count(), 0);
+
+        crate::try_extract_7z_with_password(&archive, "", &dir.join("plain")).unwrap();
+        assert!(dir.join("plain/nested/empty").is_dir());
+    }
+
+    #[test]
+    fn test_record_written_files() {
+        let dest = test_dir("record_written_files");
+        let stats =
+            extract_with_options("tests/sample.7z", "", &dest, &ExtractOptions::default()).unwrap();
+        assert!(stats.written_files.is_empty());
+
+        let options = ExtractOptions {
+            record_written_files: Some(PathStyle::Relative),
+            overwrite_policy: OverwritePolicy::Rename,
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.written_files.len(), stats.files);
+        assert!(stats
+            .written_files
+            .contains(&PathBuf::from("7zFormat (1).txt")));
+        assert!(stats.written_files.iter().all(|f| dest.join(f).is_file()));
+
+        let options = ExtractOptions {
+            record_written_files: Some(PathStyle::Absolute),
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert!(stats
+            .written_files
+            .iter()
+            .all(|f| f.is_absolute() && f.is_file()));
+    }
+
+    #[test]
+    fn test_allows_extension() {
+        let mut options = ExtractOptions::default();
+        assert!(options.allows_extension("docs/readme.txt"));
+        options.skip_extensions = vec!["exe".to_string()];
+        assert!(!options.allows_extension("setup.EXE"));
+        assert!(options.allows_extension("readme"));
+
+        options.allow_extensions = vec![".txt".to_string(), "exe".to_string()];
+        assert!(options.allows_extension("docs\\readme.txt"));
+        assert!(!options.allows_extension("setup.exe"));
+        assert!(!options.allows_extension("image.png"));
+        assert!(!options.allows_extension("Makefile"));
+        assert!(!options.allows_extension(".txt"));
+        options.allow_extensions.push(String::new());
+        assert!(options.allows_extension("Makefile"));
+    }
+
+    #[test]
+    fn test_extension_filters() {
+        let dir = test_dir("extension_filters");
+        let archive = write_archive(
+            &dir.join("mixed.7z"),
+            &[("a.txt", "a"), ("b.exe", "b"), ("c.png", "c")],
+        );
+        let options = ExtractOptions {
+            allow_extensions: vec!["txt".to_string(), "exe".to_string()],
+            skip_extensions: vec!["exe".to_string()],
+            ..Default::default()
+        };
+        let dest = dir.join("out");
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(stats.filtered, ["b.exe", "c.png"]);
+        assert!(dest.join("a.txt").exists());
+        assert!(!dest.join("b.exe").exists());
+    }
+
+    #[test]
+    fn test_record_folder_totals() {
+        let dir = test_dir("record_folder_totals");
+        let archive = write_archive(
+            &dir.join("tree.7z"),
+            &[
+                ("docs/a.txt", "aaa"),
+                ("docs/deep/b.txt", "bb"),
+                ("img/c.png", "c"),
+                ("top.txt", "tttt"),
+            ],
+        );
+        let options = ExtractOptions {
+            record_folder_totals: true,
+            ..Default::default()
+        };
+        let stats = extract_with_options(&archive, "", &dir.join("out"), &options).unwrap();
+        assert_eq!(
+            stats.folder_totals,
+            HashMap::from([
+                ("docs".to_string(), 5),
+                ("img".to_string(), 1),
+                ("top.txt".to_string(), 4),
+            ])
+        );
+        assert_eq!(
+            stats.folder_totals.values().sum::<u64>(),
+            stats.bytes_written
+        );
+
+        let stats =
+            extract_with_options(&archive, "", &dir.join("quiet"), &ExtractOptions::default())
+                .unwrap();
+        assert!(stats.folder_totals.is_empty());
+    }
+
+    #[test]
+    fn test_record_entry_outcomes() {
+        let dir = test_dir("record_entry_outcomes");
+        let big = "b".repeat(100);
+        let archive = write_archive(
+            &dir.join("mixed.7z"),
+            &[("old.txt", "o"), ("big.txt", &big), ("new.txt", "n")],
+        );
+        let dest = dir.join("out");
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("old.txt"), "mine").unwrap();
+        let options = ExtractOptions {
+            max_file_size: Some(10),
+            overwrite_policy: OverwritePolicy::Skip,
+            record_entry_outcomes: true,
+            ..Default::default()
+        };
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        let outcomes: Vec<(&str, &EntryOutcome)> = stats
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), &e.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("old.txt", &EntryOutcome::SkippedExists),
+                ("big.txt", &EntryOutcome::SkippedTooLarge),
+                ("new.txt", &EntryOutcome::Extracted),
+            ]
+        );
+
+        let stats =
+            extract_with_options(&archive, "", &dir.join("quiet"), &ExtractOptions::default())
+                .unwrap();
+        assert!(stats.entries.is_empty());
+
+        let options = ExtractOptions {
+            symlink_policy: SymlinkPolicy::Skip,
+            record_entry_outcomes: true,
+            ..Default::default()
+        };
+        let stats =
+            extract_with_options("tests/symlink.7z", "", &dir.join("links"), &options).unwrap();
+        assert_eq!(stats.entries[0].outcome, EntryOutcome::Extracted);
+        assert_eq!(stats.entries[1].name, "link.txt");
+        assert_eq!(stats.entries[1].outcome, EntryOutcome::SkippedFilter);
+    }
+
+    #[test]
+    fn test_resume() {
+        let dir = test_dir("resume");
+        let archive = write_archive(
+            &dir.join("blocks.7z"),
+            &[("a.txt", "aaaa"), ("b.txt", "bbbb"), ("c.txt", "cccc")],
+        );
+        let options = ExtractOptions {
+            resume: true,
+            ..Default::default()
+        };
+        let dest = dir.join("out");
+        let sidecar = partial_sidecar_path(&archive, &dest);
+        assert_eq!(sidecar, dest.join("blocks.7z.partial"));
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        assert_eq!(stats.files, 3);
+        assert!(!sidecar.exists());
+
+        // as if interrupted after b.txt, which was damaged since
+        fs::remove_file(dest.join("c.txt")).unwrap();
+        fs::write(dest.join("b.txt"), "xxxx").unwrap();
+        fs::write(&sidecar, "0\n1\n").unwrap();
+        let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+        assert_eq!(stats.unchanged, 1);
+        assert_eq!(stats.files, 2);
+        assert_eq!(fs::read_to_string(dest.join("b.txt")).unwrap(), "bbbb");
+        assert_eq!(fs::read_to_string(dest.join("c.txt")).unwrap(), "cccc");
+        assert!(!sidecar.exists());
+    }
+
+    #[test]
+    fn test_resume_records_progress() {
+        let dir = test_dir("resume_records");
+        let noise: String = (0..4096u32)
+            .map(|i| char::from(b'a' + (i.wrapping_mul(2654435761) >> 27) as u8 % 26))
+            .collect();
+        let archive = write_archive(
+            &dir.join("broken.7z"),
+            &[("a.txt", "aaaa"), ("b.txt", &noise)],
+        );
+        // damage the packed data of the second block
+        let mut data = fs::read(&archive).unwrap();
+        let (pack_pos, first_size) = {
+            let mut file = File::open(&archive).unwrap();
+            let len = file.metadata().unwrap().len();
+            let parsed = sevenz_rust::Archive::read(&mut file, len, &[]).unwrap();
+            (parsed.pack_pos, parsed.pack_sizes[0])
+        };
+        let offset = (32 + pack_pos + first_size) as usize + 10;
+        data[offset] ^= 0xff;
+        fs::write(&archive, data).unwrap();
+
+        let options = ExtractOptions {
+            resume: true,
+            cleanup_on_failure: true,
+            ..Default::default()
+        };
+        let dest = dir.join("out");
+        assert!(extract_with_options(&archive, "", &dest, &options).is_err());
+        let sidecar = partial_sidecar_path(&archive, &dest);
+        assert_eq!(fs::read_to_string(sidecar).unwrap(), "0\n");
+        assert!(dest.join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_sorted_by_type() {
+        let dir = test_dir("extract_sorted_by_type");
+        let archive = write_archive(
+            &dir.join("mixed.7z"),
+            &[
+                ("photos/cat.JPG", "cat"),
+                ("readme.txt", "read me"),
+                ("inner.7z", "not really"),
+                ("Makefile", "all:"),
+                ("data.bin", "data"),
+            ],
+        );
+        let mut writer = sevenz_rust::SevenZWriter::create(dir.join("folders.7z")).unwrap();
+        let mut folder = SevenZArchiveEntry::new();
+        folder.name = "photos".to_string();
+        folder.is_directory = true;
+        writer.push_archive_entry::<&[u8]>(folder, None).unwrap();
+        writer.finish().unwrap();
+        let dest = dir.join("out");
+        let stats = extract_sorted_by_type(dir.join("folders.7z"), "", &dest).unwrap();
+        assert_eq!(stats.skipped, 1);
+        extract_sorted_by_type(&archive, "", &dest).unwrap();
+        assert!(dest.join("images/photos/cat.JPG").exists());
+        assert!(dest.join("documents/readme.txt").exists());
+        assert!(dest.join("archives/inner.7z").exists());
+        assert!(dest.join("other/Makefile").exists());
+        assert!(dest.join("other/data.bin").exists());
+        assert!(!dest.join("other/photos").exists());
+
+        let mut categories = TypeCategories::default();
+        categories
+            .categories
+            .insert(0, ("data".to_string(), vec!["bin".to_string()]));
+        categories.other = "misc".to_string();
+        assert_eq!(categories.category_of("x/data.BIN"), "data");
+        assert_eq!(categories.category_of("Makefile"), "misc");
+        assert_eq!(categories.category_of("a.tar.gz"), "archives");
+    }
+
+    #[test]
+    fn test_extract_with_remap() {
+        let dir = test_dir("extract_with_remap");
+        let archive = write_archive(
+            &dir.join("remap.7z"),
+            &[
+                ("docs/a.txt", "a"),
+                ("docs/deep/b.txt", "b"),
+                ("other/a.txt", "other"),
+                ("skip.txt", "s"),
+                ("evil.txt", "e"),
+            ],
+        );
+        let dest = dir.join("out");
+        let options = ExtractOptions {
+            overwrite_policy: OverwritePolicy::Rename,
+            ..Default::default()
+        };
+        // flatten everything into dest
+        let stats = extract_with_remap(&archive, "", &dest, &options, |name| match name {
+            "skip.txt" => None,
+            "evil.txt" => Some("../evil.txt".to_string()),
+            _ => name.rsplit('/').next().map(str::to_string),
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dest.join("b.txt")).unwrap(), "b");
+        // the collision went through overwrite_policy
+        assert_eq!(fs::read_to_string(dest.join("a (1).txt")).unwrap(), "other");
+        assert_eq!(stats.duplicates, ["other/a.txt"]);
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.skipped, 2);
+        assert!(!dest.join("skip.txt").exists());
+        assert!(!dir.join("evil.txt").exists());
+
+        let moved = dir.join("moved");
+        extract_with_remap(&archive, "", &moved, &ExtractOptions::default(), |name| {
+            name.strip_prefix("docs/")
+                .map(|rest| format!("manual/{}", rest))
+        })
+        .unwrap();
+        assert!(moved.join("manual/deep/b.txt").is_file());
+        assert!(!moved.join("other").exists());
+    }
+
+    #[test]
+    fn test_plan_overwrite() {
+        let dir = test_dir("plan_overwrite");
+        let archive = write_archive(
+            &dir.join("plan.7z"),
+            &[
+                ("same.txt", "same"),
+                ("changed.txt", "new"),
+                ("added.txt", "a"),
+            ],
+        );
+        let dest = dir.join("out");
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("same.txt"), "same").unwrap();
+        fs::write(dest.join("changed.txt"), "old").unwrap();
+
+        let plan = plan_overwrite(&archive, "", &dest).unwrap();
+        assert_eq!(
+            plan,
+            OverwritePlan {
+                new: vec!["added.txt".to_string()],
+                overwritten: vec!["changed.txt".to_string()],
+                identical: vec!["same.txt".to_string()],
+            }
+        );
+        // nothing was written
+        assert!(!dest.join("added.txt").exists());
+
+        let plan = plan_overwrite("tests/sample.7z", "", &dir.join("empty")).unwrap();
+        assert_eq!(plan.new.len(), 3);
+        assert!(plan.overwritten.is_empty() && plan.identical.is_empty());
+    }
+
+    #[test]
+    fn test_incremental() {
+        let dest = test_dir("incremental");
+        let options = ExtractOptions {
+            incremental: true,
+            ..Default::default()
+        };
+        let first = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert!(first.files > 1);
+        assert_eq!(first.unchanged, 0);
+
+        let again = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(again.files, 0);
+        assert_eq!(again.unchanged, first.files);
+
+        // same size, different content
+        let changed = dest.join("7zFormat.txt");
+        let mut content = fs::read(&changed).unwrap();
+        content[0] ^= 1;
+        fs::write(&changed, content).unwrap();
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.unchanged, first.files - 1);
+        assert_eq!(
+            fs::read(&changed).unwrap(),
+            fs::read("tests/7zFormat.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_entry_order_largest_first() {
+        let dir = test_dir("entry_order");
+        let big = "b".repeat(4096);
+        let medium = "m".repeat(512);
+        let archive = write_archive(
+            &dir.join("blocks.7z"),
+            &[
+                ("small.txt", "s"),
+                ("big.txt", &big),
+                ("medium.txt", &medium),
+            ],
+        );
+        let written = |order, dest: &str| {
+            let options = ExtractOptions {
+                entry_order: order,
+                record_written_files: Some(PathStyle::Relative),
+                ..Default::default()
+            };
+            extract_with_options(&archive, "", &dir.join(dest), &options)
+                .unwrap()
+                .written_files
+        };
+
+        assert_eq!(
+            written(EntryOrder::Natural, "natural"),
+            ["small.txt", "big.txt", "medium.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            written(EntryOrder::LargestFirst, "largest"),
+            ["big.txt", "medium.txt", "small.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("largest/big.txt")).unwrap(),
+            big
+        );
+    }
+
+    #[test]
+    fn test_normalize_separators() {
+        let dir = test_dir("normalize_separators");
+        let archive = write_archive(
+            &dir.join("windows.7z"),
+            &[("docs\\readme.txt", "readme"), ("docs\\img\\a.txt", "a")],
+        );
+        extract_with_options(&archive, "", &dir.join("out"), &ExtractOptions::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("out/docs/readme.txt")).unwrap(),
+            "readme"
+        );
+        assert!(dir.join("out/docs/img/a.txt").is_file());
+
+        let options = ExtractOptions {
+            normalize_separators: false,
+            ..Default::default()
+        };
+        extract_with_options(&archive, "", &dir.join("raw"), &options).unwrap();
+        if cfg!(unix) {
+            assert!(dir.join("raw/docs\\readme.txt").is_file());
+        }
+    }
+
+    #[test]
+    fn test_normalize_case() {
+        let dest = test_dir("normalize_case");
+        let options = ExtractOptions {
+            normalize_case: Some(CaseMode::Upper),
+            ..Default::default()
+        };
+        extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        let mut names: Vec<String> = fs::read_dir(dest.join("INNER"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["INNER-INNER", "INNER.TXT"]);
+        assert!(dest.join("7ZFORMAT.TXT").exists());
+    }
+
+    #[test]
+    fn test_normalize_case_collisions() {
+        let dir = test_dir("normalize_case_collisions");
+        let archive = case_collision_archive(&dir);
+        let extract = |name: &str, policy: OverwritePolicy| {
+            let dest = dir.join(name);
+            let options = ExtractOptions {
+                normalize_case: Some(CaseMode::Lower),
+                overwrite_policy: policy,
+                ..Default::default()
+            };
+            let stats = extract_with_options(&archive, "", &dest, &options).unwrap();
+            (dest, stats)
+        };
+
+        let (dest, _) = extract("overwrite", OverwritePolicy::Overwrite);
+        assert_eq!(
+            fs::read_to_string(dest.join("readme.txt")).unwrap(),
+            "second"
+        );
+
+        let (dest, stats) = extract("skip", OverwritePolicy::Skip);
+        assert_eq!(
+            fs::read_to_string(dest.join("readme.txt")).unwrap(),
+            "first"
+        );
+        assert_eq!(stats.skipped, 1);
+
+        let (dest, _) = extract("rename", OverwritePolicy::Rename);
+        assert_eq!(
+            fs::read_to_string(dest.join("readme.txt")).unwrap(),
+            "first"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("readme (1).txt")).unwrap(),
+            "second"
+        );
+    }
+
+    #[test]
+    fn test_extract_to_many() {
+        let dir = test_dir("extract_to_many");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("blocker"), "a file where a folder should be").unwrap();
+        let dests = [dir.join("a"), dir.join("blocker/b"), dir.join("c")];
+
+        let results = extract_to_many("tests/sample.7z", "", &dests).unwrap();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+        for dest in [&dests[0], &dests[2]] {
+            assert_eq!(
+                fs::read(dest.join("7zFormat.txt")).unwrap(),
+                fs::read("tests/7zFormat.txt").unwrap()
+            );
+            assert!(dest.join("inner/inner-inner").is_dir());
+        }
+
+        assert!(extract_to_many("tests/7ziplogo_p.7z", "0000", &dests).is_err());
+    }
+
+    #[test]
+    fn test_extract_indices() {
+        let dest = test_dir("extract_indices");
+        let files = crate::read_7z_contents("tests/sample.7z").unwrap();
+        let wanted: Vec<usize> = ["inner/inner.txt", "7ziplogo.png"]
+            .iter()
+            .map(|name| files.iter().position(|f| f.name() == *name).unwrap())
+            .collect();
+
+        let stats = extract_indices("tests/sample.7z", "", &dest, &wanted).unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(
+            fs::read(dest.join("7ziplogo.png")).unwrap(),
+            fs::read("tests/7ziplogo.png").unwrap()
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("inner/inner.txt"))
+                .unwrap()
+                .len(),
+            13
+        );
+        assert!(!dest.join("7zFormat.txt").exists());
+
+        assert!(matches!(
+            extract_indices("tests/sample.7z", "", &dest, &[1, 5]),
+            Err(CascadeError::IndexOutOfRange { index: 5, count: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_extract_where() {
+        let dest = test_dir("extract_where");
+        let stats = extract_where("tests/sample.7z", "", &dest, |info| {
+            !info.is_directory && info.name.contains('/') && info.modified.is_some()
+        })
+        .unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.directories, 0);
+        // the folder comes with the file, not through its own entry
+        assert!(dest.join("inner/inner.txt").is_file());
+        assert!(!dest.join("7ziplogo.png").exists());
+    }
+
+    #[test]
+    fn test_extract_selected_stays_inside_dest() {
+        let dir = test_dir("extract_selected_inside");
+        let archive = write_archive(
+            &dir.join("evil.7z"),
+            &[("../evil.txt", "e"), ("/tmp/abs.txt", "a"), ("ok.txt", "o")],
+        );
+        let dest = dir.join("out");
+        let stats = extract_where(&archive, "", &dest, |_| true).unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 2));
+        assert!(!dir.join("evil.txt").exists());
+        let stats = extract_indices(&archive, "", &dest, &[0, 2]).unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 1));
+        assert!(!dir.join("evil.txt").exists());
+        assert!(dest.join("ok.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_with_options_stays_inside_dest() {
+        let dir = test_dir("extract_with_options_inside");
+        let archive = write_archive(
+            &dir.join("evil.7z"),
+            &[
+                ("../evil.txt", "e"),
+                ("..\\evil2.txt", "e"),
+                ("ok.txt", "o"),
+            ],
+        );
+        let dest = dir.join("out");
+        let stats = extract_with_options(&archive, "", &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 2));
+        assert!(!dir.join("evil.txt").exists());
+        assert!(!dir.join("evil2.txt").exists());
+        assert!(dest.join("ok.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_subtree() {
+        let dir = test_dir("extract_subtree");
+        for name in ["docs/manual/a.txt", "docs/manual/img/b.txt", "docs2/c.txt"] {
+            let path = dir.join("source").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, name).unwrap();
+        }
+        let archive = dir.join("docs.7z");
+        sevenz_rust::compress_to_path(dir.join("source"), &archive).unwrap();
+
+        let dest = dir.join("full");
+        let stats = extract_subtree(&archive, "", &dest, "docs/manual/").unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(
+            fs::read_to_string(dest.join("docs/manual/img/b.txt")).unwrap(),
+            "docs/manual/img/b.txt"
+        );
+
+        let dest = dir.join("rebased");
+        let stats = extract_subtree_rebased(&archive, "", &dest, "docs\\manual").unwrap();
+        assert_eq!(stats.files, 2);
+        assert!(dest.join("manual/a.txt").exists());
+        assert!(dest.join("manual/img/b.txt").exists());
+        assert!(!dest.join("docs").exists());
+
+        let dest = dir.join("docs_only");
+        let stats = extract_subtree(&archive, "", &dest, "docs").unwrap();
+        assert_eq!(stats.files, 2);
+        assert!(!dest.join("docs2").exists());
+
+        // still inside the subtree by name, but not once resolved
+        let evil = write_archive(
+            &dir.join("evil.7z"),
+            &[("sub/deep/../../../x.txt", "x"), ("sub/deep/ok.txt", "o")],
+        );
+        let dest = dir.join("evil_out");
+        let stats = extract_subtree_rebased(&evil, "", &dest, "sub/deep").unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 1));
+        assert!(dest.join("deep/ok.txt").exists());
+        assert!(!dir.join("x.txt").exists());
+        let stats = extract_subtree(&evil, "", &dest, "sub").unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 1));
+    }
+
+    #[test]
+    fn test_is_symlink_entry() {
+        let files = crate::read_7z_contents("tests/symlink.7z").unwrap();
+        assert!(!is_symlink_entry(&files[0]));
+        assert!(is_symlink_entry(&files[1]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy_create() {
+        let (dest, stats) = extract_symlink_fixture("symlink_create", SymlinkPolicy::Create);
+        let link = dest.join("link.txt");
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(link).unwrap(), "hello symlink\n");
+        assert_eq!(stats.symlinks, 1);
+    }
+
+    #[test]
+    fn test_link_stays_inside() {
+        let dest = Path::new("out");
+        let inside = |link: &str, target: &str| link_stays_inside(dest, &dest.join(link), target);
+        assert!(inside("link.txt", "target.txt"));
+        assert!(inside("docs/link.txt", "../target.txt"));
+        assert!(inside("docs/link.txt", "./deep/../a.txt"));
+        assert!(!inside("link.txt", "../outside.txt"));
+        assert!(!inside("docs/link.txt", "../../outside.txt"));
+        assert!(!inside("docs/link.txt", "..\\..\\outside.txt"));
+        assert!(!inside("link.txt", "/etc/passwd"));
+        assert!(!inside("link.txt", "C:\\Windows"));
+        assert!(!inside("link.txt", "a/../../b"));
+    }
+
+    #[test]
+    fn test_symlink_policy_create_refuses_escaping_links() {
+        let dir = test_dir("symlink_create_escaping");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("links.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&path).unwrap();
+        for (name, target) in [("up.txt", "../secret.txt"), ("abs.txt", "/etc/passwd")] {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            entry.has_windows_attributes = true;
+            entry.windows_attributes = FILE_ATTRIBUTE_UNIX_EXTENSION | (S_IFLNK | 0o777) << 16;
+            writer
+                .push_archive_entry(entry, Some(target.as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let dest = dir.join("out");
+        let stats = extract_with_options(&path, "", &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!(stats.symlinks, 0);
+        assert_eq!(stats.skipped, 2);
+        assert!(fs::symlink_metadata(dest.join("up.txt")).is_err());
+        assert!(fs::symlink_metadata(dest.join("abs.txt")).is_err());
+    }
+
+    #[test]
+    fn test_symlink_policy_skip() {
+        let (dest, stats) = extract_symlink_fixture("symlink_skip", SymlinkPolicy::Skip);
+        assert!(dest.join("target.txt").exists());
+        assert!(fs::symlink_metadata(dest.join("link.txt")).is_err());
+        assert_eq!(stats.skipped, 1);
+    }
+
+    #[test]
+    fn test_symlink_policy_deref() {
+        let (dest, stats) = extract_symlink_fixture("symlink_deref", SymlinkPolicy::Deref);
+        let link = dest.join("link.txt");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_file());
+        assert_eq!(fs::read_to_string(link).unwrap(), "hello symlink\n");
+        assert_eq!(stats.files, 2);
+    }
+
+    #[test]
+    fn test_extract_with_progress() {
+        let dest = test_dir("extract_with_progress");
+        let total = crate::archive_total_size("tests/sample.7z").unwrap();
+        let mut reported = Vec::new();
+        let mut etas = Vec::new();
+        let options = ExtractOptions {
+            max_bytes_per_sec: Some(2000),
+            ..Default::default()
+        };
+        let stats = extract_with_progress("tests/sample.7z", "", &dest, &options, |progress| {
+            reported.push((progress.entry.to_string(), progress.bytes_done));
+            etas.push(progress.eta.unwrap());
+        })
+        .unwrap();
+        assert_eq!(stats.files, 3);
+        assert_eq!(reported.last().unwrap().1, total);
+        // no faster than the limit, even while the first second's burst lasts
+        let left = (total - reported[0].1) as f64 / 2000.0;
+        assert!(etas[0].as_secs_f64() >= left - 0.001, "{:?}", etas[0]);
+        assert_eq!(*etas.last().unwrap(), Duration::ZERO);
+        assert!(reported.windows(2).all(|w| w[0].1 <= w[1].1));
+        let mut entries: Vec<_> = reported.iter().map(|(entry, _)| entry.as_str()).collect();
+        entries.dedup();
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[test]
+    fn test_files_only() {
+        let dest = test_dir("files_only");
+        let options = ExtractOptions {
+            files_only: true,
+            record_written_files: Some(PathStyle::Relative),
+            ..Default::default()
+        };
+        let stats = extract_with_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.directories, 0);
+        assert_eq!(stats.skipped_directories, 2);
+        for file in &stats.written_files {
+            let modified = fs::metadata(dest.join(file)).unwrap().modified().unwrap();
+            assert!(modified.elapsed().unwrap() < Duration::from_secs(600));
+        }
+
+        let dest = test_dir("files_only_off");
+        let stats =
+            extract_with_options("tests/sample.7z", "", &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!(stats.directories, 2);
+        assert_eq!(stats.skipped_directories, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_attribute_policy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("attribute_policy");
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("attributes.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&archive).unwrap();
+        for (name, attributes) in [
+            ("readonly.txt", FILE_ATTRIBUTE_READONLY),
+            ("mode.txt", FILE_ATTRIBUTE_UNIX_EXTENSION | (0o100640 << 16)),
+        ] {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            entry.has_windows_attributes = true;
+            entry.windows_attributes = attributes;
+            writer
+                .push_archive_entry(entry, Some(&b"data"[..]))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let dest = dir.join("clear");
+        extract_with_options(&archive, "", &dest, &ExtractOptions::default()).unwrap();
+        let permissions = fs::metadata(dest.join("readonly.txt"))
+            .unwrap()
+            .permissions();
+        assert!(!permissions.readonly());
+
+        let dest = dir.join("preserve");
+        let options = ExtractOptions {
+            attribute_policy: AttributePolicy::Preserve,
+            ..Default::default()
+        };
+        extract_with_options(&archive, "", &dest, &options).unwrap();
+        let permissions = fs::metadata(dest.join("readonly.txt"))
+            .unwrap()
+            .permissions();
+        assert!(permissions.readonly());
+        let mode = fs::metadata(dest.join("mode.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o640);
+
+        // the read-only file is overwritten and ends up writable again
+        extract_with_options(&archive, "", &dest, &ExtractOptions::default()).unwrap();
+        let permissions = fs::metadata(dest.join("readonly.txt"))
+            .unwrap()
+            .permissions();
+        assert!(!permissions.readonly());
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::{clear_signature_cache, ArchiveFormat, CascadeError, ExtractStats};
+
+/// Extracts a format the crate doesn't know itself, see
+/// `register_extractor`.
+pub trait Extractor: Send + Sync {
+    /// A short name for the logs, e.g. `"pak"`.
+    fn name(&self) -> &str;
+    fn extract(
+        &self,
+        path: &Path,
+        password: &str,
+        dest: &Path,
+    ) -> Result<ExtractStats, CascadeError>;
+
+    /// Whether `password` opens the archive, for `verify_password_any`.
+    /// Formats that can't tell without extracting keep this default, which
+    /// fails with `UnsupportedFormat`.
+    fn verify_password(&self, _path: &Path, _password: &str) -> Result<bool, CascadeError> {
+        Err(CascadeError::UnsupportedFormat {
+            detected: ArchiveFormat::Custom,
+        })
+    }
+}
+
+type Detector = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+static EXTRACTORS: RwLock<Vec<(Detector, Arc<dyn Extractor>)>> = RwLock::new(Vec::new());
+
+/// Adds a format for the rest of the program's run: files whose head
+/// `detector` accepts are detected as `ArchiveFormat::Custom` and extracted
+/// by `extractor` in `extract_any`, and so in watch mode.
+///
+/// Registered detectors are tried before the built-in signatures, in the
+/// order they were registered, so a custom format can also take over files
+/// that look like a built-in one. A detector sees as many bytes as the
+/// caller reads for detection: `signature_window` from the config, or
+/// `FULL_SIGNATURE_WINDOW` in `extract_any`, fewer for shorter files.
+pub fn register_extractor(
+    detector: impl Fn(&[u8]) -> bool + Send + Sync + 'static,
+    extractor: Arc<dyn Extractor>,
+) {
+    EXTRACTORS
+        .write()
+        .unwrap()
+        .push((Box::new(detector), extractor));
+    clear_signature_cache();
+}
+
+/// The first registered extractor whose detector accepts `head`.
+pub fn registered_extractor(head: &[u8]) -> Option<Arc<dyn Extractor>> {
+    EXTRACTORS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(detector, _)| detector(head))
+        .map(|(_, extractor)| extractor.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use crate::{detect_archive_format, extract_any, ExtractOptions};
+    use std::fs;
+
+    const MAGIC: &[u8] = b"CXTEST01";
+
+    /// Copies everything after the magic to `dest/payload.bin`.
+    struct PayloadExtractor;
+
+    impl Extractor for PayloadExtractor {
+        fn name(&self) -> &str {
+            "cxtest"
+        }
+
+        fn extract(
+            &self,
+            path: &Path,
+            _password: &str,
+            dest: &Path,
+        ) -> Result<ExtractStats, CascadeError> {
+            let data = fs::read(path)?;
+            fs::create_dir_all(dest)?;
+            fs::write(dest.join("payload.bin"), &data[MAGIC.len()..])?;
+            Ok(ExtractStats {
+                files: 1,
+                bytes_written: (data.len() - MAGIC.len()) as u64,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_register_extractor() {
+        let dir = test_dir("register_extractor");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.cx");
+        fs::write(&path, [MAGIC, b"payload"].concat()).unwrap();
+        assert!(extract_any(&path, "", &dir.join("before"), &ExtractOptions::default()).is_err());
+
+        register_extractor(
+            |head: &[u8]| head.starts_with(MAGIC),
+            Arc::new(PayloadExtractor),
+        );
+        assert_eq!(
+            detect_archive_format(&path).unwrap(),
+            Some(ArchiveFormat::Custom)
+        );
+        let dest = dir.join("out");
+        let stats = extract_any(&path, "", &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(fs::read(dest.join("payload.bin")).unwrap(), b"payload");
+        assert_eq!(
+            registered_extractor(MAGIC).map(|e| e.name().to_string()),
+            Some("cxtest".to_string())
+        );
+        assert!(registered_extractor(b"something else").is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes hashed from each end of a large file.
+pub const FINGERPRINT_SAMPLE: u64 = 4096;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A cheap identity for an archive: the size plus the first and last
+/// `FINGERPRINT_SAMPLE` bytes (the whole file when it's small), hashed with
+/// FNV-1a. It only depends on the content, so it survives moves and renames
+/// and stays the same across runs and builds.
+pub fn archive_fingerprint<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, &len.to_le_bytes());
+
+    if len <= 2 * FINGERPRINT_SAMPLE {
+        let mut content = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut content)?;
+        return Ok(fnv1a(hash, &content));
+    }
+
+    let mut sample = vec![0u8; FINGERPRINT_SAMPLE as usize];
+    file.read_exact(&mut sample)?;
+    hash = fnv1a(hash, &sample);
+    file.seek(SeekFrom::End(-(FINGERPRINT_SAMPLE as i64)))?;
+    file.read_exact(&mut sample)?;
+    Ok(fnv1a(hash, &sample))
+}
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+
+    #[test]
+    fn test_archive_fingerprint() {
+        let dir = test_dir("archive_fingerprint");
+        fs::create_dir_all(&dir).unwrap();
+        let moved = dir.join("renamed.7z");
+        fs::copy("tests/sample.7z", &moved).unwrap();
+
+        let sample = archive_fingerprint("tests/sample.7z").unwrap();
+        assert_eq!(archive_fingerprint(&moved).unwrap(), sample);
+        assert_ne!(archive_fingerprint("tests/7ziplogo.7z").unwrap(), sample);
+
+        // large files are only sampled at both ends
+        let mut big = vec![0u8; 3 * FINGERPRINT_SAMPLE as usize];
+        fs::write(dir.join("big"), &big).unwrap();
+        let before = archive_fingerprint(dir.join("big")).unwrap();
+        big[FINGERPRINT_SAMPLE as usize + 1] = 1;
+        fs::write(dir.join("big"), &big).unwrap();
+        assert_eq!(archive_fingerprint(dir.join("big")).unwrap(), before);
+        big[0] = 1;
+        fs::write(dir.join("big"), &big).unwrap();
+        assert_ne!(archive_fingerprint(dir.join("big")).unwrap(), before);
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Deserialize;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::{open_archive_file, registered_extractor, MAX_PREALLOCATION};
+
+/// How many leading bytes are read when sniffing an archive signature.
+pub const DEFAULT_SIGNATURE_WINDOW: usize = 16;
+
+/// The smallest signature window that sees every format in
+/// `ArchiveFormat::ALL`, tar included.
+pub const FULL_SIGNATURE_WINDOW: usize = 262;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum ArchiveFormat {
+    #[serde(rename = "7z")]
+    SevenZ,
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "rar")]
+    Rar,
+    #[serde(rename = "gz")]
+    Gzip,
+    #[serde(rename = "bz2")]
+    Bzip2,
+    #[serde(rename = "xz")]
+    Xz,
+    /// The tar magic lives at offset 257, so it is only detected when the
+    /// signature window is at least 262 bytes.
+    #[serde(rename = "tar")]
+    Tar,
+    /// A format added with `register_extractor`.
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+impl ArchiveFormat {
+    pub const ALL: [ArchiveFormat; 8] = [
+        ArchiveFormat::SevenZ,
+        ArchiveFormat::Zip,
+        ArchiveFormat::Rar,
+        ArchiveFormat::Gzip,
+        ArchiveFormat::Bzip2,
+        ArchiveFormat::Xz,
+        ArchiveFormat::Tar,
+        ArchiveFormat::Custom,
+    ];
+
+    /// The formats `extract_any` can extract. Everything else in `ALL` is
+    /// only detected, so it can be reported by name. Gzip only counts when
+    /// it wraps a tar.
+    pub const SUPPORTED: [ArchiveFormat; 4] = [
+        ArchiveFormat::SevenZ,
+        ArchiveFormat::Gzip,
+        ArchiveFormat::Tar,
+        ArchiveFormat::Custom,
+    ];
+
+    pub fn is_supported(&self) -> bool {
+        match self {
+            ArchiveFormat::SevenZ
+            | ArchiveFormat::Gzip
+            | ArchiveFormat::Tar
+            | ArchiveFormat::Custom => true,
+            ArchiveFormat::Zip | ArchiveFormat::Rar | ArchiveFormat::Bzip2 | ArchiveFormat::Xz => {
+                false
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::SevenZ => "7z",
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Rar => "rar",
+            ArchiveFormat::Gzip => "gz",
+            ArchiveFormat::Bzip2 => "bz2",
+            ArchiveFormat::Xz => "xz",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Custom => "custom",
+        }
+    }
+
+    pub fn matches(&self, buf: &[u8]) -> bool {
+        match self {
+            ArchiveFormat::SevenZ => infer::archive::is_7z(buf),
+            ArchiveFormat::Zip => infer::archive::is_zip(buf),
+            ArchiveFormat::Rar => infer::archive::is_rar(buf),
+            ArchiveFormat::Gzip => infer::archive::is_gz(buf),
+            ArchiveFormat::Bzip2 => infer::archive::is_bz2(buf),
+            ArchiveFormat::Xz => infer::archive::is_xz(buf),
+            ArchiveFormat::Tar => infer::archive::is_tar(buf),
+            ArchiveFormat::Custom => registered_extractor(buf).is_some(),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub fn default_enabled_formats() -> Vec<ArchiveFormat> {
+    ArchiveFormat::ALL.to_vec()
+}
+
+pub fn detect_archive_format<P: AsRef<Path>>(path: P) -> io::Result<Option<ArchiveFormat>> {
+    detect_archive_format_with(path, DEFAULT_SIGNATURE_WINDOW, &ArchiveFormat::ALL)
+}
+
+/// Reads up to `window` bytes and returns the first format in `enabled` whose
+/// signature matches. Files shorter than the window are checked as-is.
+pub fn detect_archive_format_with<P: AsRef<Path>>(
+    path: P,
+    window: usize,
+    enabled: &[ArchiveFormat],
+) -> io::Result<Option<ArchiveFormat>> {
+    let mut buf = Vec::with_capacity(window.min(MAX_PREALLOCATION));
+    open_archive_file(path.as_ref())?
+        .take(window as u64)
+        .read_to_end(&mut buf)?;
+    Ok(detect_format_in_bytes(&buf, enabled))
+}
+
+/// The first format in `enabled` whose signature matches `buf`. Formats
+/// added with `register_extractor` come before all others.
+pub fn detect_format_in_bytes(buf: &[u8], enabled: &[ArchiveFormat]) -> Option<ArchiveFormat> {
+    if enabled.contains(&ArchiveFormat::Custom) && ArchiveFormat::Custom.matches(buf) {
+        return Some(ArchiveFormat::Custom);
+    }
+    enabled
+        .iter()
+        .copied()
+        .filter(|&format| format != ArchiveFormat::Custom)
+        .find(|format| format.matches(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_archive_format() {
+        assert_eq!(
+            detect_archive_format("tests/sample.7z").unwrap(),
+            Some(ArchiveFormat::SevenZ)
+        );
+        assert_eq!(
+            detect_archive_format("tests/7ziplogo_p.7z").unwrap(),
+            Some(ArchiveFormat::SevenZ)
+        );
+        assert_eq!(detect_archive_format("tests/7ziplogo.png").unwrap(), None);
+        assert_eq!(detect_archive_format("tests/7zFormat.txt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_detect_archive_format_with_restricted_formats() {
+        assert_eq!(
+            detect_archive_format_with("tests/sample.7z", 16, &[ArchiveFormat::Zip]).unwrap(),
+            None
+        );
+        assert_eq!(
+            detect_archive_format_with(
+                "tests/sample.7z",
+                16,
+                &[ArchiveFormat::Zip, ArchiveFormat::SevenZ]
+            )
+            .unwrap(),
+            Some(ArchiveFormat::SevenZ)
+        );
+    }
+
+    #[test]
+    fn test_supported_formats() {
+        for format in ArchiveFormat::ALL {
+            assert_eq!(
+                format.is_supported(),
+                ArchiveFormat::SUPPORTED.contains(&format)
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::i18n::log_tr;
+
+/// How often writing a file is retried when the system is out of file
+/// handles, and how long it waits before the first retry. The wait doubles
+/// after every attempt, to about five seconds in all.
+const HANDLE_RETRIES: u32 = 8;
+const HANDLE_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// 0 until `set_max_open_files` is called, for no limit.
+static MAX_OPEN_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Output files open right now, see `FilePermit`.
+static OPEN_FILES: Mutex<usize> = Mutex::new(0);
+static FILE_CLOSED: Condvar = Condvar::new();
+
+/// Caps how many extracted files are written at the same time across the
+/// whole process, from now on. Each extraction writes one file at a time,
+/// so this matters where many run at once: the threads of a crack, or the
+/// archives the watcher extracts side by side. `read_config` calls this
+/// with `max_open_files`. 0 lifts the limit.
+pub fn set_max_open_files(max: usize) {
+    MAX_OPEN_FILES.store(max, Ordering::Relaxed);
+    FILE_CLOSED.notify_all();
+}
+
+/// The limit set by `set_max_open_files`, `None` without one.
+pub fn max_open_files() -> Option<usize> {
+    match MAX_OPEN_FILES.load(Ordering::Relaxed) {
+        0 => None,
+        max => Some(max),
+    }
+}
+
+/// One of the files `max_open_files` allows, given back when dropped.
+struct FilePermit;
+
+impl FilePermit {
+    fn acquire() -> Self {
+        let mut open = OPEN_FILES.lock().unwrap();
+        while max_open_files().is_some_and(|max| *open >= max) {
+            open = FILE_CLOSED.wait(open).unwrap();
+        }
+        *open += 1;
+        FilePermit
+    }
+}
+
+impl Drop for FilePermit {
+    fn drop(&mut self) {
+        *OPEN_FILES.lock().unwrap() -= 1;
+        FILE_CLOSED.notify_one();
+    }
+}
+
+/// Runs `write`, which creates and writes one extracted file, once a file
+/// is free under `max_open_files`. When creating the file fails because the
+/// process or the system ran out of handles, `write` is called again after
+/// a pause, as long as the limit of `ulimit -n` is only hit for a moment;
+/// nothing has been read from the entry by then.
+pub(crate) fn with_file_handle<T>(
+    mut write: impl FnMut() -> Result<T, sevenz_rust::Error>,
+) -> Result<T, sevenz_rust::Error> {
+    let _permit = FilePermit::acquire();
+    let mut delay = HANDLE_RETRY_DELAY;
+    for _ in 0..HANDLE_RETRIES {
+        match write() {
+            Err(sevenz_rust::Error::FileOpen(e, name)) if is_out_of_handles(&e) => {
+                log_tr!(
+                    warn,
+                    "文件句柄不足，{} 将在 {:?} 后重试",
+                    "out of file handles, retrying {} in {:?}",
+                    name,
+                    delay
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    write()
+}
+
+/// `EMFILE` and `ENFILE`, or their Windows counterpart.
+fn is_out_of_handles(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    return matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE));
+    #[cfg(windows)]
+    return e.raw_os_error()
+        == Some(windows_sys::Win32::Foundation::ERROR_TOO_MANY_OPEN_FILES as i32);
+    #[cfg(not(any(unix, windows)))]
+    return false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_file_handle_retries() {
+        let mut attempts = 0;
+        let result = with_file_handle(|| {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(sevenz_rust::Error::FileOpen(
+                    io::Error::from_raw_os_error(out_of_handles_code()),
+                    "a.txt".to_string(),
+                )),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // other errors aren't retried
+        let mut attempts = 0;
+        let result: Result<(), _> = with_file_handle(|| {
+            attempts += 1;
+            Err(sevenz_rust::Error::FileOpen(
+                io::ErrorKind::PermissionDenied.into(),
+                "a.txt".to_string(),
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[cfg(unix)]
+    fn out_of_handles_code() -> i32 {
+        libc::EMFILE
+    }
+
+    #[cfg(windows)]
+    fn out_of_handles_code() -> i32 {
+        windows_sys::Win32::Foundation::ERROR_TOO_MANY_OPEN_FILES as i32
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use crate::i18n::log_tr;
+use crate::CascadeError;
+
+/// Placeholders understood by `run_post_command`.
+pub const POST_COMMAND_PLACEHOLDERS: [&str; 2] = ["dest", "archive"];
+
+/// The environment variables `run_post_command` passes the paths in.
+pub const POST_COMMAND_DEST_VAR: &str = "CASCADE_DEST";
+pub const POST_COMMAND_ARCHIVE_VAR: &str = "CASCADE_ARCHIVE";
+
+/// Runs `command` through the shell (`sh -c`, or `cmd /C` on Windows) once an
+/// archive is extracted. The folder the contents went to is passed in the
+/// `CASCADE_DEST` environment variable and the archive's path in
+/// `CASCADE_ARCHIVE`; `{dest}` and `{archive}` in the command stand for
+/// those variables, already quoted (`"$CASCADE_DEST"`, or
+/// `"%CASCADE_DEST%"` on Windows), so don't quote them again.
+///
+/// The paths never become part of the command text itself: in watch mode
+/// whoever drops a file picks its name, and a name like `a;rm -rf ~;.7z`
+/// must not run as a command.
+///
+/// A non-zero exit is returned as `PostCommandFailed`; the extracted files
+/// are left alone either way. This blocks until the command exits, async
+/// code calls it through `spawn_blocking`.
+pub fn run_post_command(
+    command: &str,
+    archive: &Path,
+    dest: &Path,
+) -> Result<ExitStatus, CascadeError> {
+    let command = command
+        .replace("{dest}", &variable(POST_COMMAND_DEST_VAR))
+        .replace("{archive}", &variable(POST_COMMAND_ARCHIVE_VAR));
+    log_tr!(
+        info,
+        "执行解压后命令: {} ({})",
+        "running post command: {} ({})",
+        command,
+        archive.display()
+    );
+
+    let status = shell(&command)
+        .env(POST_COMMAND_DEST_VAR, dest)
+        .env(POST_COMMAND_ARCHIVE_VAR, archive)
+        .status()?;
+    if !status.success() {
+        return Err(CascadeError::PostCommandFailed {
+            command,
+            code: status.code(),
+        });
+    }
+    log_tr!(
+        info,
+        "解压后命令已完成: {}",
+        "post command finished: {}",
+        status
+    );
+    Ok(status)
+}
+
+/// A reference to the environment variable `name`, quoted so the shell
+/// takes its value as a single word.
+#[cfg(windows)]
+fn variable(name: &str) -> String {
+    format!("\"%{}%\"", name)
+}
+
+#[cfg(not(windows))]
+fn variable(name: &str) -> String {
+    format!("\"${}\"", name)
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+
+    #[test]
+    fn test_run_post_command() {
+        let dest = test_dir("post_command");
+        fs::create_dir_all(&dest).unwrap();
+        let archive = Path::new("tests/sample.7z");
+
+        run_post_command("echo {archive} > {dest}/done.txt", archive, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("done.txt")).unwrap(),
+            "tests/sample.7z\n"
+        );
+
+        // a file name is never run as a command
+        let evil = dest.join("a;touch pwned;.7z");
+        run_post_command("echo {archive} > {dest}/evil.txt", &evil, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("evil.txt")).unwrap(),
+            format!("{}\n", evil.display())
+        );
+        assert!(!Path::new("pwned").exists());
+        assert!(!dest.join("pwned").exists());
+
+        assert!(matches!(
+            run_post_command("exit 3", archive, &dest),
+            Err(CascadeError::PostCommandFailed { code: Some(3), .. })
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+use serde::Deserialize;
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The language of the log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLanguage {
+    Zh,
+    En,
+}
+
+const UNSET: u8 = 0;
+static LOG_LANGUAGE: AtomicU8 = AtomicU8::new(UNSET);
+
+impl LogLanguage {
+    /// Chinese when `LANG` starts with `zh` or isn't set at all, as on most
+    /// Windows machines, English for any other `LANG`.
+    pub fn from_env() -> Self {
+        match env::var("LANG") {
+            Ok(lang) if !lang.is_empty() && !lang.to_ascii_lowercase().starts_with("zh") => {
+                LogLanguage::En
+            }
+            _ => LogLanguage::Zh,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            LogLanguage::Zh => 1,
+            LogLanguage::En => 2,
+        }
+    }
+}
+
+/// Switches the language of every log message from now on. `read_config`
+/// calls this when `log_language` is set.
+pub fn set_log_language(language: LogLanguage) {
+    LOG_LANGUAGE.store(language.to_u8(), Ordering::Relaxed);
+}
+
+/// The language set by `set_log_language`, or else `LogLanguage::from_env`.
+pub fn log_language() -> LogLanguage {
+    match LOG_LANGUAGE.load(Ordering::Relaxed) {
+        1 => LogLanguage::Zh,
+        2 => LogLanguage::En,
+        _ => {
+            let language = LogLanguage::from_env();
+            // a concurrent `set_log_language` wins
+            let _ = LOG_LANGUAGE.compare_exchange(
+                UNSET,
+                language.to_u8(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            language
+        }
+    }
+}
+
+/// Logs at `$level` with the message in the current `log_language`; every
+/// message of the crate is written in both languages right where it is
+/// logged:
+///
+/// ```text
+/// log_tr!(info, "开始解压 {}", "extracting {}", name);
+/// ```
+macro_rules! log_tr {
+    ($level:ident, $zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::log_language() {
+            $crate::i18n::LogLanguage::Zh => log::$level!($zh $(, $arg)*),
+            $crate::i18n::LogLanguage::En => log::$level!($en $(, $arg)*),
+        }
+    };
+}
+pub(crate) use log_tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_log_language() {
+        let before = log_language();
+        set_log_language(LogLanguage::En);
+        assert_eq!(log_language(), LogLanguage::En);
+        log_tr!(info, "测试 {}", "test {}", 1);
+        set_log_language(LogLanguage::Zh);
+        assert_eq!(log_language(), LogLanguage::Zh);
+        set_log_language(before);
+    }
+
+    #[test]
+    fn test_log_language_config() {
+        #[derive(Deserialize)]
+        struct Settings {
+            log_language: LogLanguage,
+        }
+        let settings: Settings = toml::from_str("log_language = \"en\"").unwrap();
+        assert_eq!(settings.log_language, LogLanguage::En);
+        assert!(toml::from_str::<Settings>("log_language = \"fr\"").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Instant;
+
+use sha2::Digest;
+
+use crate::i18n::log_tr;
+use crate::{catch_backend_panic, CascadeError};
+
+/// What 7-Zip uses unless told otherwise, `2^19` SHA-256 rounds.
+pub const DEFAULT_AES_KEY_ITERATIONS: u64 = 1 << 19;
+
+/// `numCyclesPower` values of 0x3f mean the key is the salted password
+/// itself, without any hashing.
+const NO_HASHING_POWER: u8 = 0x3f;
+
+const K_END: u8 = 0x00;
+const K_PACK_INFO: u8 = 0x06;
+const K_UNPACK_INFO: u8 = 0x07;
+const K_FOLDER: u8 = 0x0b;
+const K_SIZE: u8 = 0x09;
+const K_CRC: u8 = 0x0a;
+const K_ENCODED_HEADER: u8 = 0x17;
+
+/// How many SHA-256 rounds deriving the AES key takes, the `numCyclesPower`
+/// of the archive's first AES coder, or `None` for an archive without
+/// encryption. This is what each password attempt costs, so an archive
+/// made with more than `DEFAULT_AES_KEY_ITERATIONS` is proportionally
+/// slower to crack. Archives with an encrypted header are read too, the
+/// parameters of the header's own coder are stored in the clear. An archive
+/// storing the key without hashing gives `Some(0)`.
+pub fn aes_key_iterations<P: AsRef<Path>>(path: P) -> Result<Option<u64>, CascadeError> {
+    let path = path.as_ref();
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let read = catch_backend_panic(path, || Ok(sevenz_rust::Archive::read(&mut file, len, &[])))?;
+    let power = match read {
+        Ok(archive) => archive
+            .folders
+            .iter()
+            .flat_map(|folder| folder.coders.iter())
+            .find(|coder| {
+                coder.decompression_method_id() == sevenz_rust::SevenZMethod::ID_AES256SHA256
+            })
+            .map(|coder| coder.properties.first().copied().unwrap_or(0) & 0x3f),
+        Err(sevenz_rust::Error::PasswordRequired) => encoded_header_aes_power(&mut file)?,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(power.map(|power| match power {
+        NO_HASHING_POWER => 0,
+        power => 1 << power,
+    }))
+}
+
+/// SHA-256 rounds hashed to measure how fast this machine derives keys.
+const BENCHMARK_ROUNDS: u32 = 1 << 14;
+
+/// How many attempts per second `threads` threads can make against an
+/// archive whose key takes `iterations` rounds to derive, from a short
+/// benchmark of the same hashing. Decoding is left out, so this is an upper
+/// bound, close for archives with a big key cost. `None` when `iterations`
+/// is 0 and the key costs next to nothing.
+pub fn expected_attempt_rate(iterations: u64, threads: usize) -> Option<f64> {
+    if iterations == 0 {
+        return None;
+    }
+    // what 7-Zip hashes each round: salt, UTF-16 password and a counter
+    let block = [0u8; 24];
+    let start = Instant::now();
+    let mut sha = sha2::Sha256::default();
+    for round in 0..BENCHMARK_ROUNDS as u64 {
+        sha.update(block);
+        sha.update(round.to_le_bytes());
+    }
+    std::hint::black_box(sha.finalize());
+    let rounds_per_sec = BENCHMARK_ROUNDS as f64 / start.elapsed().as_secs_f64().max(1e-9);
+    Some(rounds_per_sec * threads.max(1) as f64 / iterations as f64)
+}
+
+/// Logs a warning for every archive in `paths` whose key derivation is
+/// slower than 7-Zip's default, since trying a long list on one of those
+/// can take many times longer than expected. Returns the largest number of
+/// iterations found.
+pub(crate) fn check_key_derivation<P: AsRef<Path>>(paths: &[P]) -> Option<u64> {
+    let mut most = None;
+    for path in paths {
+        if let Ok(Some(iterations)) = aes_key_iterations(path) {
+            most = most.max(Some(iterations));
+            if iterations > DEFAULT_AES_KEY_ITERATIONS {
+                log_tr!(
+                    warn,
+                    "{} 的密钥派生使用 {} 轮 SHA-256，每次尝试的耗时是默认值的 {} 倍",
+                    "{} derives its key with {} SHA-256 rounds, each attempt takes {} times as long as usual",
+                    path.as_ref().display(),
+                    iterations,
+                    iterations / DEFAULT_AES_KEY_ITERATIONS
+                );
+            }
+        }
+    }
+    most
+}
+
+/// The AES `numCyclesPower` of the coder that encodes the header, read from
+/// the streams info in front of the encrypted data.
+fn encoded_header_aes_power(file: &mut File) -> io::Result<Option<u8>> {
+    let mut signature_header = [0u8; 32];
+    file.rewind()?;
+    file.read_exact(&mut signature_header)?;
+    let offset = u64::from_le_bytes(signature_header[12..20].try_into().unwrap());
+    let size = u64::from_le_bytes(signature_header[20..28].try_into().unwrap());
+    file.seek(SeekFrom::Start(32 + offset))?;
+    let mut header = Vec::new();
+    file.take(size).read_to_end(&mut header)?;
+
+    let mut header = header.as_slice();
+    if read_u8(&mut header)? != K_ENCODED_HEADER {
+        return Ok(None);
+    }
+    let mut nid = read_u8(&mut header)?;
+    if nid == K_PACK_INFO {
+        skip_pack_info(&mut header)?;
+        nid = read_u8(&mut header)?;
+    }
+    if nid != K_UNPACK_INFO || read_u8(&mut header)? != K_FOLDER {
+        return Ok(None);
+    }
+    let _folders = read_number(&mut header)?;
+    // folders stored elsewhere don't occur in a header
+    if read_u8(&mut header)? != 0 {
+        return Ok(None);
+    }
+
+    for _ in 0..read_number(&mut header)? {
+        let flags = read_u8(&mut header)?;
+        let id = take(&mut header, (flags & 0x0f) as usize)?;
+        if flags & 0x10 != 0 {
+            read_number(&mut header)?;
+            read_number(&mut header)?;
+        }
+        let properties = if flags & 0x20 != 0 {
+            let len = read_number(&mut header)? as usize;
+            take(&mut header, len)?
+        } else {
+            &[]
+        };
+        if id == sevenz_rust::SevenZMethod::ID_AES256SHA256 {
+            return Ok(Some(properties.first().copied().unwrap_or(0) & 0x3f));
+        }
+    }
+    Ok(None)
+}
+
+fn skip_pack_info(header: &mut &[u8]) -> io::Result<()> {
+    read_number(header)?;
+    let streams = read_number(header)? as usize;
+    loop {
+        match read_u8(header)? {
+            K_END => return Ok(()),
+            K_SIZE => {
+                for _ in 0..streams {
+                    read_number(header)?;
+                }
+            }
+            K_CRC => {
+                let defined = if read_u8(header)? != 0 {
+                    streams
+                } else {
+                    take(header, streams.div_ceil(8))?
+                        .iter()
+                        .map(|byte| byte.count_ones() as usize)
+                        .sum()
+                };
+                take(header, defined * 4)?;
+            }
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+/// 7z's variable length numbers: the leading one bits of the first byte
+/// count the bytes that follow.
+fn read_number(header: &mut &[u8]) -> io::Result<u64> {
+    let first = read_u8(header)?;
+    let mut value = 0u64;
+    let mut mask = 0x80u8;
+    for i in 0..8 {
+        if first & mask == 0 {
+            let high = (first & mask.wrapping_sub(1)) as u64;
+            return Ok(value | (high << (8 * i)));
+        }
+        value |= (read_u8(header)? as u64) << (8 * i);
+        mask >>= 1;
+    }
+    Ok(value)
+}
+
+fn read_u8(header: &mut &[u8]) -> io::Result<u8> {
+    Ok(take(header, 1)?[0])
+}
+
+fn take<'a>(header: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if header.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (taken, rest) = header.split_at(len);
+    *header = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aes_key_iterations() {
+        assert_eq!(
+            aes_key_iterations("tests/7ziplogo_p.7z").unwrap(),
+            Some(DEFAULT_AES_KEY_ITERATIONS)
+        );
+        assert_eq!(aes_key_iterations("tests/sample.7z").unwrap(), None);
+        assert!(aes_key_iterations("tests/7ziplogo.png").is_err());
+    }
+
+    #[test]
+    fn test_expected_attempt_rate() {
+        assert_eq!(expected_attempt_rate(0, 4), None);
+        let one = expected_attempt_rate(DEFAULT_AES_KEY_ITERATIONS, 1).unwrap();
+        let slow = expected_attempt_rate(DEFAULT_AES_KEY_ITERATIONS << 4, 1).unwrap();
+        assert!(one > 0.0 && one.is_finite());
+        // two separate benchmarks, so only roughly 16 times apart
+        assert!(slow < one);
+    }
+
+    #[test]
+    fn test_read_number() {
+        let mut bytes: &[u8] = &[0x85, 0x70, 0x01, 0xc0, 0x34, 0x12];
+        assert_eq!(read_number(&mut bytes).unwrap(), 0x570);
+        assert_eq!(read_number(&mut bytes).unwrap(), 1);
+        assert_eq!(read_number(&mut bytes).unwrap(), 0x1234);
+        assert!(bytes.is_empty());
+    }
+}
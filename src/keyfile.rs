@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha2::Digest;
+
+use crate::{try_extract_7z_with_password, CascadeError};
+
+/// How the password is taken from a key file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFileMode {
+    /// The whole file is the password, trailing newline included.
+    RawBytes,
+    /// The SHA-256 of the file, as 64 lowercase hex digits.
+    Sha256Hex,
+    /// The file's text with leading and trailing whitespace removed, for a
+    /// password saved with an editor.
+    Trimmed,
+}
+
+/// The password `keyfile` stands for, to pass on to any function taking a
+/// password, e.g. to put it in front of a crack's list. `RawBytes` and
+/// `Trimmed` need the file to be UTF-8 text, as 7z passwords are text.
+pub fn keyfile_password(keyfile: &Path, mode: KeyFileMode) -> Result<String, CascadeError> {
+    let bytes = fs::read(keyfile)?;
+    if mode == KeyFileMode::Sha256Hex {
+        let digest = sha2::Sha256::digest(&bytes);
+        return Ok(digest.iter().map(|b| format!("{:02x}", b)).collect());
+    }
+    let text = String::from_utf8(bytes).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the key file {} is not UTF-8 text", keyfile.display()),
+        )
+    })?;
+    Ok(match mode {
+        KeyFileMode::Trimmed => text.trim().to_string(),
+        _ => text,
+    })
+}
+
+/// `try_extract_7z_with_password` with the password `keyfile` stands for.
+pub fn try_extract_7z_with_keyfile<P: AsRef<Path>>(
+    path: P,
+    keyfile: &Path,
+    dest: &Path,
+    mode: KeyFileMode,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let password = keyfile_password(keyfile, mode)?;
+    try_extract_7z_with_password(path, &password, dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_keyfile_password() {
+        let dir = test_dir("keyfile_password");
+        fs::create_dir_all(&dir).unwrap();
+        let keyfile = dir.join("key.txt");
+        fs::write(&keyfile, "1151\n").unwrap();
+        assert_eq!(
+            keyfile_password(&keyfile, KeyFileMode::RawBytes).unwrap(),
+            "1151\n"
+        );
+        fs::write(&keyfile, "1151").unwrap();
+        assert_eq!(
+            keyfile_password(&keyfile, KeyFileMode::Sha256Hex).unwrap(),
+            "a3ab7bbaf7390c1faafb17a4075a3a633882f897fa93ace3976bb64b0712842c"
+        );
+        fs::write(&keyfile, b"\xff\xfe").unwrap();
+        assert!(keyfile_password(&keyfile, KeyFileMode::Trimmed).is_err());
+        assert!(keyfile_password(&keyfile, KeyFileMode::Sha256Hex).is_ok());
+
+        fs::write(&keyfile, "  1151\r\n").unwrap();
+        let dest = dir.join("out");
+        try_extract_7z_with_keyfile("tests/7ziplogo_p.7z", &keyfile, &dest, KeyFileMode::Trimmed)
+            .unwrap();
+        assert!(dest.join("7ziplogo.png").exists());
+    }
+}
@@ -1,15 +1,102 @@
-use log::info;
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
-use std::ops::Deref;
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex, Semaphore};
+use std::io::{self, BufReader, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(feature = "async")]
+use tokio::sync::{mpsc, Semaphore};
 
-use sevenz_rust::default_entry_extract_fn;
+use crate::i18n::log_tr;
+
+mod action_log;
+mod bruteforce;
+mod cascade;
+mod cli;
+mod concurrency;
+mod config_file;
+mod convert;
+mod crack_state;
+mod cursor;
+mod display;
+#[cfg(feature = "http")]
+mod download;
+mod error;
+mod estimate;
+mod external;
+mod extract;
+mod extractor;
+mod fingerprint;
+mod format;
+mod handles;
+mod hook;
+mod i18n;
+mod kdf;
+mod keyfile;
+mod logging;
+mod manifest;
+mod merge;
+mod password_cache;
+mod preflight;
+#[cfg(feature = "progress_bar")]
+mod progress_bar;
+mod resolve;
+mod sfx;
+mod signature_cache;
+mod sink;
+mod staging;
+#[cfg(feature = "async")]
+mod stream;
+mod tarball;
+mod template;
+mod throttle;
+mod watch;
+mod wordlist;
+
+pub use action_log::*;
+pub use bruteforce::*;
+pub use cascade::*;
+pub use cli::run_from_args;
+pub use concurrency::*;
+pub use config_file::*;
+pub use convert::*;
+pub use crack_state::*;
+pub use cursor::*;
+#[cfg(feature = "http")]
+pub use download::*;
+pub use error::CascadeError;
+pub use estimate::*;
+pub use external::*;
+pub use extract::*;
+pub use extractor::*;
+pub use fingerprint::*;
+pub use format::*;
+pub use handles::*;
+pub use hook::*;
+pub use i18n::{log_language, set_log_language, LogLanguage};
+pub use kdf::*;
+pub use keyfile::*;
+pub use logging::*;
+pub use manifest::*;
+pub use merge::*;
+pub use password_cache::*;
+pub use preflight::*;
+#[cfg(feature = "progress_bar")]
+pub use progress_bar::*;
+pub use resolve::*;
+pub use sfx::*;
+pub use signature_cache::*;
+pub use sink::*;
+#[cfg(feature = "async")]
+pub use stream::*;
+pub use tarball::*;
+pub use template::*;
+pub use throttle::*;
+pub use watch::*;
+pub use wordlist::*;
 
 pub fn is_7z<P: AsRef<Path>>(path: P) -> io::Result<bool> {
     let mut buf = [0; 8]; // 7z files have a signature in the first few bytes
@@ -18,240 +105,5240 @@ pub fn is_7z<P: AsRef<Path>>(path: P) -> io::Result<bool> {
     Ok(infer::archive::is_7z(&buf))
 }
 
+/// Whether the 7z archive at `path` is all there: its start header points at
+/// a next header within the file, and that header matches its CRC. A file
+/// still being downloaded, or preallocated with zeros by a torrent client,
+/// gives `false`, as does a 7z too short to hold its start header. Files
+/// that aren't 7z archives can't be judged this way and give `true`.
+pub fn is_complete_archive<P: AsRef<Path>>(path: P) -> Result<bool, CascadeError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::with_capacity(32);
+    (&mut file).take(32).read_to_end(&mut head)?;
+    if head.len() < sfx::SIGNATURE.len() {
+        return Ok(!sfx::SIGNATURE.starts_with(&head));
+    }
+    if head[..6] != sfx::SIGNATURE {
+        return Ok(true);
+    }
+    if head.len() < 32 || !sfx::is_start_header(&head, len) {
+        return Ok(false);
+    }
+
+    let next_header_offset = u64::from_le_bytes(head[12..20].try_into().unwrap());
+    let next_header_size = u64::from_le_bytes(head[20..28].try_into().unwrap());
+    let next_header_crc = u32::from_le_bytes(head[28..32].try_into().unwrap());
+    file.seek(io::SeekFrom::Start(32 + next_header_offset))?;
+    let mut digest = sfx::CRC32.digest();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut remaining = next_header_size;
+    while remaining > 0 {
+        let want = remaining.min(buf.len() as u64) as usize;
+        let read = file.read(&mut buf[..want])?;
+        if read == 0 {
+            return Ok(false);
+        }
+        digest.update(&buf[..read]);
+        remaining -= read as u64;
+    }
+    Ok(digest.finalize() == next_header_crc)
+}
+
 pub fn try_extract_7z_with_password<P: AsRef<Path>>(
     path: P,
     password: &str,
     dest: &Path,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    sevenz_rust::decompress_with_extract_fn_and_password(
-        File::open(&path).unwrap(),
-        dest,
-        password.into(),
-        |entry, reader, dest| {
-            info!("开始解压 {}", entry.name());
-            let r = default_entry_extract_fn(entry, reader, dest);
-            info!("解压完成 {}", entry.name());
-            r
-        },
-    )
+    let context = log_context(path.as_ref(), None);
+    try_extract_7z_with_context(path, password, dest, &context)
+}
+
+fn try_extract_7z_with_context<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    context: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let file = open_archive_file(path.as_ref())?;
+    catch_backend_panic(path.as_ref(), || {
+        Ok(sevenz_rust::decompress_with_extract_fn_and_password(
+            file,
+            dest,
+            password.into(),
+            |entry, reader, _| {
+                log_tr!(
+                    info,
+                    "[{}] 开始解压 {}",
+                    "[{}] extracting {}",
+                    context,
+                    entry.name()
+                );
+                let r = write_entry_inside(entry, reader, dest, context);
+                log_tr!(
+                    info,
+                    "[{}] 解压完成 {}",
+                    "[{}] extracted {}",
+                    context,
+                    entry.name()
+                );
+                r
+            },
+        )?)
+    })
+    .map_err(|e| e.into())
+}
+
+/// Same as `is_7z`, for an archive that is already in memory.
+pub fn is_7z_bytes(data: &[u8]) -> bool {
+    infer::archive::is_7z(data)
+}
+
+/// Extracts an archive held in memory, e.g. one just downloaded, without
+/// writing it to a temporary file first.
+pub fn try_extract_7z_from_bytes(
+    data: &[u8],
+    password: &str,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    catch_backend_panic(Path::new("[memory]"), || {
+        Ok(sevenz_rust::decompress_with_extract_fn_and_password(
+            Cursor::new(data),
+            dest,
+            password.into(),
+            |entry, reader, _| {
+                log_tr!(
+                    info,
+                    "[memory] 开始解压 {}",
+                    "[memory] extracting {}",
+                    entry.name()
+                );
+                let r = write_entry_inside(entry, reader, dest, "memory");
+                log_tr!(
+                    info,
+                    "[memory] 解压完成 {}",
+                    "[memory] extracted {}",
+                    entry.name()
+                );
+                r
+            },
+        )?)
+    })
     .map_err(|e| e.into())
 }
 
+/// Writes the entry below `dest`, or skips it, reading it to the end so a
+/// wrong password still fails its CRC check, when its name has a root or a
+/// `..` that would put it outside, as `extract_with_options` does.
+fn write_entry_inside(
+    entry: &sevenz_rust::SevenZArchiveEntry,
+    reader: &mut dyn Read,
+    dest: &Path,
+    context: &str,
+) -> Result<bool, sevenz_rust::Error> {
+    let Some(relative) = sink_path(entry.name()) else {
+        log_tr!(
+            warn,
+            "[{}] {} 会写到解压目标之外，跳过",
+            "[{}] skipping {}, it would be written outside the destination",
+            context,
+            entry.name()
+        );
+        io::copy(reader, &mut io::sink())?;
+        return Ok(true);
+    };
+    write_entry(entry, reader, &dest.join(relative), false, None)
+}
+
+/// Short label prefixed to log lines so messages from parallel tasks can be
+/// told apart: the archive file name, plus the index of the password being
+/// tried when there is one.
+fn log_context(path: &Path, password_index: Option<usize>) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_else(|| path.to_string_lossy());
+    match password_index {
+        Some(index) => format!("{}#{}", name, index),
+        None => name.into_owned(),
+    }
+}
+
+/// Logs a warning for every file name that occurs more than once, as
+/// extracting such an archive puts both files on the same path.
 pub fn read_7z_contents<P: AsRef<Path>>(
     path: P,
-) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, Box<dyn std::error::Error>> {
-    let mut file = File::open(path)?;
-    let len = file.metadata()?.len();
-    let password = sevenz_rust::Password::empty();
-    let archive = sevenz_rust::Archive::read(&mut file, len, password.as_slice())?;
+) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let files = read_archive_entries(&path)?;
+    for name in duplicate_names(&files) {
+        log_tr!(
+            warn,
+            "{} 中有重名的条目: {}",
+            "{} holds more than one entry named {}",
+            path.as_ref().display(),
+            name
+        );
+    }
+    Ok(files)
+}
 
-    Ok(archive.files)
+/// Names shared by more than one file entry, once each, in the order their
+/// second occurrence appears.
+pub fn duplicate_names(files: &[sevenz_rust::SevenZArchiveEntry]) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for file in files.iter().filter(|file| !file.is_directory()) {
+        if !seen.insert(file.name()) && !duplicates.contains(&file.name()) {
+            duplicates.push(file.name());
+        }
+    }
+    duplicates
 }
 
-pub fn should_create_folder_when_extract_with_smart_mode<P: AsRef<Path>>(
+fn read_archive_entries<P: AsRef<Path>>(
+    path: P,
+) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, CascadeError> {
+    read_archive_entries_with_password(path, "")
+}
+
+/// Archives with encrypted headers can only be listed with the password.
+///
+/// The entries own no handle, the file is a local of
+/// `read_archive_with_password` and closes as it returns.
+/// `test_listing_releases_file_handles` keeps it that way: watchers list
+/// archives right before deleting them, and Windows refuses to delete a
+/// file that is still open.
+fn read_archive_entries_with_password<P: AsRef<Path>>(
     path: P,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let files = read_7z_contents(path)?;
+    password: &str,
+) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, CascadeError> {
+    Ok(read_archive_with_password(path, password)?.files)
+}
 
-    let mut root_file_count = 0;
-    let mut root_directory_count = 0;
-    let mut should_create = false;
+fn read_archive_with_password<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+) -> Result<sevenz_rust::Archive, CascadeError> {
+    let path = path.as_ref();
+    let mut file = open_archive_file(path)?;
+    warn_on_newer_version(path, &mut file)?;
+    let len = file.get_ref().metadata()?.len();
+    let password = sevenz_rust::Password::from(password);
+    catch_backend_panic(path, || {
+        Ok(sevenz_rust::Archive::read(
+            &mut file,
+            len,
+            password.as_slice(),
+        )?)
+    })
+}
 
-    for file in &files {
-        if root_file_count > 1 || root_directory_count > 1 {
-            break;
-        }
+/// The newest 7z format version `sevenz_rust` is known to read, the one
+/// 7-Zip has been writing for years. Older 7-Zips wrote 0.2 and 0.3.
+pub const NEWEST_KNOWN_7Z_VERSION: (u8, u8) = (0, 4);
 
-        if file.is_directory() {
-            if file.name().contains('/') {
-                continue;
-            } else {
-                root_directory_count += 1;
-            }
-        } else {
-            if file.name().contains('/') {
-                continue;
-            } else {
-                root_file_count += 1;
-            }
+/// The format version of a 7z archive, as (major, minor), from the two bytes
+/// right after the signature. Not reading the rest, this works on archives
+/// `sevenz_rust` fails on, to tell why.
+pub fn format_version<P: AsRef<Path>>(path: P) -> Result<(u8, u8), CascadeError> {
+    read_format_version(&mut File::open(path)?)
+}
+
+fn read_format_version(reader: &mut impl Read) -> Result<(u8, u8), CascadeError> {
+    let mut head = [0; 8];
+    reader.read_exact(&mut head)?;
+    if !infer::archive::is_7z(&head) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a 7z archive").into());
+    }
+    Ok((head[6], head[7]))
+}
+
+/// Logs a warning when `file` is a 7z archive newer than
+/// `NEWEST_KNOWN_7Z_VERSION`, then rewinds it. A newer minor version may
+/// still read fine, a newer major one fails with `UnsupportedVersion`.
+fn warn_on_newer_version(path: &Path, file: &mut BufReader<File>) -> io::Result<()> {
+    if let Ok(version) = read_format_version(file) {
+        if version > NEWEST_KNOWN_7Z_VERSION {
+            log_tr!(
+                warn,
+                "{} 由更新版本的 7-Zip 创建 (格式版本 {}.{})，可能无法读取",
+                "{} was created by a newer 7-Zip (format version {}.{}) and may not be readable",
+                path.display(),
+                version.0,
+                version.1
+            );
         }
     }
+    file.rewind()
+}
+
+/// The read buffer archives are opened with unless `read_buffer_size` says
+/// otherwise. Much larger than `BufReader`'s 8 KiB, as every read from a
+/// network share is a round trip.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// 0 until `set_read_buffer_size` is called.
+static READ_BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the buffer archives are read through when listing, detecting and
+/// extracting them from now on. `read_config` calls this with
+/// `read_buffer_size`. 0 goes back to `DEFAULT_READ_BUFFER_SIZE`.
+pub fn set_read_buffer_size(size: usize) {
+    READ_BUFFER_SIZE.store(size, Ordering::Relaxed);
+}
 
-    if root_file_count > 1 || root_directory_count > 1 {
-        should_create = true;
+/// The size set by `set_read_buffer_size`, or `DEFAULT_READ_BUFFER_SIZE`.
+pub fn read_buffer_size() -> usize {
+    match READ_BUFFER_SIZE.load(Ordering::Relaxed) {
+        0 => DEFAULT_READ_BUFFER_SIZE,
+        size => size,
     }
+}
 
-    Ok(should_create)
+/// Opens an archive for reading through a buffer of `read_buffer_size`.
+pub(crate) fn open_archive_file(path: &Path) -> io::Result<BufReader<File>> {
+    Ok(BufReader::with_capacity(
+        read_buffer_size(),
+        File::open(path)?,
+    ))
 }
 
-pub fn delete_archive<P: AsRef<Path>>(path: P) -> Result<(), Box<dyn std::error::Error>> {
-    std::fs::remove_file(path)?;
-    Ok(())
+/// `SevenZReader::open`, reading through `open_archive_file`. Reading the
+/// header is guarded by `catch_backend_panic`; decoding the entries
+/// afterwards happens outside it and needs its own.
+pub(crate) fn open_archive_reader(
+    path: &Path,
+    password: &str,
+) -> Result<sevenz_rust::SevenZReader<BufReader<File>>, CascadeError> {
+    let mut file = open_archive_file(path)?;
+    warn_on_newer_version(path, &mut file)?;
+    let len = file.get_ref().metadata()?.len();
+    catch_backend_panic(path, || {
+        Ok(sevenz_rust::SevenZReader::new(file, len, password.into())?)
+    })
 }
 
-pub async fn start_extraction<P: AsRef<Path> + Send + Sync + 'static>(
-    paths: Arc<[P]>,
-    passwords: Vec<Arc<String>>,
-    dest: P,
-    max_threads: usize,
-) {
-    let semaphore = Arc::new(Mutex::new(Semaphore::new(max_threads)));
-    let stop_flag = Arc::new(AtomicBool::new(false)); // Moved stop_flag outside the loop
+/// Runs `f`, turning a panic into `BackendPanic` so one malformed archive
+/// can't bring down a whole batch or watcher. The panic message still goes
+/// to stderr through the panic hook.
+pub(crate) fn catch_backend_panic<T>(
+    path: &Path,
+    f: impl FnOnce() -> Result<T, CascadeError>,
+) -> Result<T, CascadeError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        log_tr!(
+            error,
+            "解析 {} 时 7z 解码器崩溃",
+            "the 7z decoder panicked on {}",
+            path.display()
+        );
+        Err(CascadeError::BackendPanic {
+            path: path.to_path_buf(),
+        })
+    })
+}
 
-    // Explicitly specify the type parameter for the Sender
-    let (tx, mut rx) = mpsc::channel::<String>(passwords.len());
+/// The parts of an entry most callers need, without the rest of
+/// `SevenZArchiveEntry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Position in the archive's entry list, as used by `extract_indices`.
+    pub index: usize,
+    pub name: String,
+    pub size: u64,
+    pub is_directory: bool,
+    /// `None` when the archive doesn't store it.
+    pub modified: Option<SystemTime>,
+}
 
-    for password in passwords {
-        let tx = tx.clone();
-        let dest = dest.as_ref().to_owned();
-        let paths = paths.clone();
-        let semaphore = semaphore.clone(); // Clone the Arc
+impl EntryInfo {
+    pub(crate) fn of(index: usize, entry: &sevenz_rust::SevenZArchiveEntry) -> Self {
+        EntryInfo {
+            index,
+            name: entry.name().to_string(),
+            size: entry.size(),
+            is_directory: entry.is_directory(),
+            modified: entry
+                .has_last_modified_date
+                .then(|| entry.last_modified_date().into()),
+        }
+    }
+}
 
-        let stop_flag = stop_flag.clone(); // Clone the Arc
+/// The first entry matching `predicate`. The 7z header is read and every
+/// entry in it parsed as for any listing; only `predicate` stops at the
+/// match, the entries after it aren't passed to it.
+pub fn find_entry<P: AsRef<Path>>(
+    path: P,
+    predicate: impl Fn(&sevenz_rust::SevenZArchiveEntry) -> bool,
+) -> Result<Option<EntryInfo>, CascadeError> {
+    let files = read_archive_entries(path)?;
+    Ok(files
+        .iter()
+        .enumerate()
+        .find(|(_, entry)| predicate(entry))
+        .map(|(index, entry)| EntryInfo::of(index, entry)))
+}
 
-        tokio::spawn(async move {
-            let semaphore_ref = semaphore.lock().await;
-            let permit = semaphore_ref.acquire().await.unwrap();
+/// The entries at the root of the archive, in the order they first appear,
+/// e.g. for the top level of a tree view before the rest is expanded. A
+/// folder that only exists implicitly, through the paths of the entries
+/// inside it, is listed once as a directory of size 0, with the `index` of
+/// its first entry.
+pub fn list_root_entries<P: AsRef<Path>>(path: P) -> Result<Vec<EntryInfo>, CascadeError> {
+    let files = read_archive_entries(path)?;
+    let mut roots: Vec<EntryInfo> = Vec::new();
+    for (index, entry) in files.iter().enumerate() {
+        let name = root_component(entry.name());
+        let is_root = entry.name().trim_matches(['/', '\\']) == name;
+        let info = match is_root {
+            true => EntryInfo {
+                name: name.to_string(),
+                ..EntryInfo::of(index, entry)
+            },
+            false => EntryInfo {
+                index,
+                name: name.to_string(),
+                size: 0,
+                is_directory: true,
+                modified: None,
+            },
+        };
+        match roots.iter_mut().find(|root| root.name == name) {
+            // the folder's own entry, after entries inside it
+            Some(root) if is_root => *root = info,
+            Some(_) => {}
+            None => roots.push(info),
+        }
+    }
+    Ok(roots)
+}
 
-            for path in paths.iter() {
-                if let Ok(()) = try_extract_7z_with_password(&path, &password, &dest) {
-                    info!("解压成功: {}", path.as_ref().to_string_lossy());
-                    info!("找到正确的密码: {}", password);
-                    stop_flag.store(true, Ordering::Relaxed);
-                    tx.send(password.deref().clone()).await.unwrap();
-                    drop(permit);
-                    return;
-                }
+/// The name of the first entry, e.g. to label an archive in a list. The 7z
+/// header is read in full either way, but nothing else is looked at.
+/// Archives whose header is encrypted fail with `HeaderEncrypted`.
+pub fn first_entry_name<P: AsRef<Path>>(path: P) -> Result<Option<String>, CascadeError> {
+    match read_archive_entries(path) {
+        Ok(files) => Ok(files.into_iter().next().map(|entry| entry.name)),
+        Err(CascadeError::SevenZ(sevenz_rust::Error::PasswordRequired)) => {
+            Err(CascadeError::HeaderEncrypted)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Most bytes set aside up front for data whose size comes from the archive,
+/// which a crafted header can make up; past this the buffer grows as the
+/// data actually arrives.
+pub(crate) const MAX_PREALLOCATION: usize = 1 << 20;
+
+/// The content of the file entry called `name`, or `None` when there is no
+/// such file. Decoding stops right after it.
+pub fn extract_entry_to_bytes<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    name: &str,
+) -> Result<Option<Vec<u8>>, CascadeError> {
+    let mut reader = open_archive_reader(path.as_ref(), password)?;
+    let mut content = None;
+    catch_backend_panic(path.as_ref(), || {
+        Ok(reader.for_each_entries(|entry, data| {
+            if entry.is_directory() || entry.name() != name {
+                // later entries of a solid block need the data read in order
+                io::copy(data, &mut io::sink())?;
+                return Ok(true);
             }
+            let mut bytes = Vec::with_capacity(entry.size().min(MAX_PREALLOCATION as u64) as usize);
+            data.read_to_end(&mut bytes)?;
+            content = Some(bytes);
+            Ok(false)
+        })?)
+    })?;
+    Ok(content)
+}
 
-            drop(permit);
-        });
+/// Streams the only file of `path` to stdout, e.g. to pipe a compressed
+/// dump into another tool or a named pipe. See `extract_single_to_writer`.
+pub fn extract_single_to_stdout<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+) -> Result<u64, CascadeError> {
+    extract_single_to_writer(path, password, &mut io::stdout().lock())
+}
+
+/// Writes the content of the only file entry of `path` to `out` as it is
+/// decoded and returns its size. Folder entries don't count, but an archive
+/// with no file or more than one fails with `InvalidInput` before anything
+/// is written.
+pub fn extract_single_to_writer<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    out: &mut dyn Write,
+) -> Result<u64, CascadeError> {
+    let path = path.as_ref();
+    let mut reader = open_archive_reader(path, password)?;
+    let files = reader
+        .archive()
+        .files
+        .iter()
+        .filter(|entry| !entry.is_directory())
+        .count();
+    if files != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has {} files, expected one", path.display(), files),
+        )
+        .into());
     }
+    let mut written = 0;
+    catch_backend_panic(path, || {
+        Ok(reader.for_each_entries(|entry, data| {
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            written = io::copy(data, out)?;
+            Ok(false)
+        })?)
+    })?;
+    out.flush()?;
+    Ok(written)
+}
 
-    // Wait for the first successful extraction or stop flag
-    while let Some(password) = rx.recv().await {
-        info!("找到正确的密码: {}", password);
-        if stop_flag.load(Ordering::Relaxed) {
-            info!("发现正确密码，终止其他任务队列");
-            break;
+/// How much of an archive a password protects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionScope {
+    None,
+    /// The entry names and sizes can be listed without the password, only
+    /// the contents are encrypted.
+    ContentOnly,
+    /// The header is encrypted too, so nothing can be listed before the
+    /// password is known.
+    HeaderAndContent,
+}
+
+/// Tells apart archives whose file list can be previewed before the
+/// password is known from those whose header is encrypted as well.
+pub fn encryption_scope<P: AsRef<Path>>(path: P) -> Result<EncryptionScope, CascadeError> {
+    let path = path.as_ref();
+    let archive = {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        match catch_backend_panic(path, || Ok(sevenz_rust::Archive::read(&mut file, len, &[])))? {
+            Ok(archive) => archive,
+            Err(sevenz_rust::Error::PasswordRequired) => {
+                return Ok(EncryptionScope::HeaderAndContent)
+            }
+            Err(e) => return Err(e.into()),
         }
+    };
+    Ok(if encrypted_blocks(&archive).next().is_some() {
+        EncryptionScope::ContentOnly
+    } else {
+        EncryptionScope::None
+    })
+}
+
+/// `encryption_scope` of every archive, at most `threads` at a time on
+/// tokio's blocking pool, e.g. for a UI to sort a big library by which
+/// archives will need a password. The results are in the order of `paths`;
+/// an archive that makes the decoder panic comes back as `BackendPanic`.
+#[cfg(feature = "async")]
+pub async fn probe_encryption(
+    paths: &[PathBuf],
+    threads: usize,
+) -> Vec<(PathBuf, Result<EncryptionScope, CascadeError>)> {
+    let semaphore = Arc::new(Semaphore::new(threads.max(1)));
+    let mut tasks = Vec::with_capacity(paths.len());
+    for path in paths {
+        let semaphore = semaphore.clone();
+        let path = path.clone();
+        tasks.push(tokio::spawn(async move {
+            // the semaphore is never closed
+            let _permit = semaphore.acquire_owned().await;
+            tokio::task::spawn_blocking(move || encryption_scope(&path)).await
+        }));
+    }
+    let mut results = Vec::with_capacity(paths.len());
+    for (path, task) in paths.iter().zip(tasks) {
+        let scope = match task.await {
+            Ok(Ok(scope)) => scope,
+            // panics are caught inside, only a runtime shutting down gets here
+            Ok(Err(e)) | Err(e) => Err(io::Error::other(e).into()),
+        };
+        results.push((path.clone(), scope));
     }
+    results
 }
 
-pub fn extract_to_temp_folder<P: AsRef<Path> + Send + Sync>(
+/// Indices of the blocks whose content is AES encrypted.
+fn encrypted_blocks(archive: &sevenz_rust::Archive) -> impl Iterator<Item = usize> + '_ {
+    (0..archive.folders.len()).filter(|&block| {
+        archive.folders[block].coders.iter().any(|coder| {
+            coder.decompression_method_id() == sevenz_rust::SevenZMethod::ID_AES256SHA256
+        })
+    })
+}
+
+/// What an archive reveals about the tool that made it. 7z has no field
+/// naming the tool, so this is circumstantial: p7zip and 7-Zip on Linux or
+/// macOS store Unix modes, BCJ2 and the other branch filters come from
+/// 7-Zip's executable detection, and simple libraries often write a single
+/// LZMA or LZMA2 block per file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatorInfo {
+    /// The format version in the signature header, e.g. `(0, 4)`. Tools
+    /// differ in which version they write for the same features.
+    pub format_version: (u8, u8),
+    /// Names of the coders used by any block, in order of first use.
+    /// Unknown ones are shown as their hex id.
+    pub coders: Vec<String>,
+    /// Whether any block holds several files.
+    pub solid: bool,
+    /// Whether any entry stores a Unix mode in its attributes.
+    pub unix_attributes: bool,
+    /// Whether the header itself is compressed, as 7-Zip does by default.
+    pub header_compressed: bool,
+}
+
+/// `None` when the header is encrypted and nothing can be read without the
+/// password.
+pub fn archive_creator_info<P: AsRef<Path>>(path: P) -> Result<Option<CreatorInfo>, CascadeError> {
+    let path = path.as_ref();
+    let mut file = File::open(path)?;
+    let mut signature_header = [0u8; 32];
+    file.read_exact(&mut signature_header)?;
+    let next_header_offset = u64::from_le_bytes(signature_header[12..20].try_into().unwrap());
+    let len = file.metadata()?.len();
+    file.rewind()?;
+    let archive =
+        match catch_backend_panic(path, || Ok(sevenz_rust::Archive::read(&mut file, len, &[])))? {
+            Ok(archive) => archive,
+            Err(sevenz_rust::Error::PasswordRequired) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+    let mut header_id = [0u8; 1];
+    file.seek(io::SeekFrom::Start(32 + next_header_offset))?;
+    file.read_exact(&mut header_id)?;
+
+    let mut coders: Vec<String> = Vec::new();
+    for coder in archive
+        .folders
+        .iter()
+        .flat_map(|folder| folder.coders.iter())
+    {
+        let name = coder_name(coder.decompression_method_id());
+        if !coders.contains(&name) {
+            coders.push(name);
+        }
+    }
+
+    Ok(Some(CreatorInfo {
+        format_version: (signature_header[6], signature_header[7]),
+        coders,
+        solid: archive
+            .folders
+            .iter()
+            .any(|folder| folder.num_unpack_sub_streams > 1),
+        unix_attributes: archive.files.iter().any(|entry| {
+            entry.has_windows_attributes
+                && entry.windows_attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0
+        }),
+        // 0x17 is kEncodedHeader, 0x01 a plain kHeader
+        header_compressed: header_id[0] == 0x17,
+    }))
+}
+
+/// The coder with method `id` by name, or its hex id when unknown.
+fn coder_name(id: &[u8]) -> String {
+    match sevenz_rust::SevenZMethod::by_id(id) {
+        Some(method) => method.name().to_string(),
+        None => id.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+/// Each entry's name with the coders of the block holding its data, joined
+/// by ` + ` when there are several, e.g. `AES256SHA256 + LZMA2` or
+/// `BCJ + LZMA`. Directories and empty files have no data and are left out.
+/// Archives whose header is encrypted fail with `HeaderEncrypted`.
+pub fn entry_codecs<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>, CascadeError> {
+    let archive = match read_archive_with_password(path, "") {
+        Ok(archive) => archive,
+        Err(CascadeError::SevenZ(sevenz_rust::Error::PasswordRequired)) => {
+            return Err(CascadeError::HeaderEncrypted)
+        }
+        Err(e) => return Err(e),
+    };
+    let block_codecs: Vec<String> = archive
+        .folders
+        .iter()
+        .map(|folder| {
+            folder
+                .coders
+                .iter()
+                .map(|coder| coder_name(coder.decompression_method_id()))
+                .collect::<Vec<_>>()
+                .join(" + ")
+        })
+        .collect();
+
+    Ok(archive
+        .files
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let block = archive.stream_map.file_folder_index[index]?;
+            Some((entry.name().to_string(), block_codecs[block].clone()))
+        })
+        .collect())
+}
+
+pub fn read_7z_contents_bytes(
+    data: &[u8],
+) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut cursor = Cursor::new(data);
+    let archive = catch_backend_panic(Path::new("[memory]"), || {
+        Ok(sevenz_rust::Archive::read(
+            &mut cursor,
+            data.len() as u64,
+            &[],
+        )?)
+    })?;
+
+    Ok(archive.files)
+}
+
+/// The entries are only looked at until a second root shows up, and no
+/// names are copied.
+pub fn should_create_folder_when_extract_with_smart_mode<P: AsRef<Path>>(
     path: P,
-) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
-    let temp_dir = env::temp_dir().join("cascading-extract");
-    let temp_dir_path = temp_dir.to_path_buf();
-    std::fs::create_dir_all(&temp_dir_path)?;
-    try_extract_7z_with_password(&path, "", &temp_dir_path)?;
-    Ok(temp_dir_path)
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    should_create_folder_with_threshold(path, DEFAULT_SMART_MODE_THRESHOLD)
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    #[serde(rename = "config")]
-    pub config: ConfigSettings,
-    #[serde(rename = "user")]
-    pub user: UserConfig,
+/// `should_create_folder_when_extract_with_smart_mode`, wrapping only
+/// archives with more than `threshold` files or folders in their root.
+pub fn should_create_folder_with_threshold<P: AsRef<Path>>(
+    path: P,
+    threshold: u32,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(needs_wrapper_folder_with(
+        &read_archive_entries(path)?,
+        SmartModePolicy::CountBased,
+        threshold,
+    ))
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ConfigSettings {
-    pub delete_archive: bool,
-    pub recursive_search: bool,
-    #[serde(default = "default_threads")]
-    pub threads: u8,
-    pub dest: String,
-    pub smart_mode: bool,
+/// How many files or folders may sit in an archive's root before smart mode
+/// wraps it: more than one, like bandizip.
+pub const DEFAULT_SMART_MODE_THRESHOLD: u32 = 1;
+
+/// How smart mode decides whether an archive gets a folder of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartModePolicy {
+    /// Wraps unless everything sits under a single root file or folder.
+    #[default]
+    CountBased,
+    /// Like `CountBased`, but a single root file that is itself an archive
+    /// or an installer is wrapped too, since it usually gets unpacked or run
+    /// next and would otherwise spill its contents into `dest`.
+    ContentAware,
 }
 
-fn default_threads() -> u8 {
-    4
+/// What to do when the folder smart mode would wrap an archive in already
+/// exists, e.g. from an earlier extraction or another archive with the
+/// same stem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FolderCollisionPolicy {
+    /// Extract into the existing folder; `overwrite_policy` decides about
+    /// the files in it.
+    #[default]
+    Merge,
+    /// Use `name (1)`, `name (2)` and so on instead.
+    Rename,
+    /// Fail with `DestinationExists`.
+    Error,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UserConfig {
-    pub passwords: Option<Vec<String>>,
-    pub watch_folders: Option<Vec<String>>,
+/// Extensions of files that `SmartModePolicy::ContentAware` still wraps when
+/// they are the only thing in an archive.
+pub const WRAPPED_SINGLE_FILE_EXTENSIONS: &[&str] = &[
+    "7z", "zip", "rar", "tar", "gz", "tgz", "bz2", "xz", "iso", "exe", "msi", "dmg", "pkg", "deb",
+    "rpm", "apk",
+];
+
+/// Smart mode wraps the contents in a folder unless everything already sits
+/// under a single root file or folder. Any two roots count, so a root file
+/// next to a root folder is wrapped as well as two files or two folders.
+fn needs_wrapper_folder(files: &[sevenz_rust::SevenZArchiveEntry]) -> bool {
+    needs_wrapper_folder_with(
+        files,
+        SmartModePolicy::CountBased,
+        DEFAULT_SMART_MODE_THRESHOLD,
+    )
 }
 
-pub fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let settings_file = File::open("settings.toml")?;
-    let mut buf_reader = BufReader::new(settings_file);
+/// Wraps archives with more than `threshold` roots; below that `policy` may
+/// still wrap a single root.
+fn needs_wrapper_folder_with(
+    files: &[sevenz_rust::SevenZArchiveEntry],
+    policy: SmartModePolicy,
+    threshold: u32,
+) -> bool {
+    if files.is_empty() {
+        return false;
+    }
+    if root_count_exceeds(files, threshold) {
+        return true;
+    }
+    let Some(root) = common_root_of(files) else {
+        return false;
+    };
+    match policy {
+        SmartModePolicy::CountBased => false,
+        SmartModePolicy::ContentAware => files.iter().any(|f| {
+            !f.is_directory()
+                && root_component(f.name()) == f.name().trim_start_matches(['/', '\\'])
+                && has_extension_in(root, WRAPPED_SINGLE_FILE_EXTENSIONS)
+        }),
+    }
+}
 
-    let mut settings_content = String::new();
-    buf_reader.read_to_string(&mut settings_content)?;
+fn has_extension_in(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        .unwrap_or(false)
+}
 
-    let mut settings: Config = toml::from_str(&settings_content)?;
+/// Returns the top-level component shared by every entry, or `None` when the
+/// archive has several roots (or no entries at all).
+pub fn common_root<P: AsRef<Path>>(path: P) -> Result<Option<String>, CascadeError> {
+    Ok(common_root_of(&read_archive_entries(path)?).map(str::to_string))
+}
 
-    if settings.config.threads < 1 || settings.config.threads > 8 {
-        settings.config.threads = default_threads();
+/// Stops at the first entry under a different root.
+fn common_root_of(files: &[sevenz_rust::SevenZArchiveEntry]) -> Option<&str> {
+    let mut root: Option<&str> = None;
+    for file in files {
+        let component = root_component(file.name());
+        match root {
+            None => root = Some(component),
+            Some(r) if r != component => return None,
+            _ => {}
+        }
     }
+    root
+}
 
-    Ok(settings)
+/// Stops as soon as the archive has more than `threshold` roots.
+fn root_count_exceeds(files: &[sevenz_rust::SevenZArchiveEntry], threshold: u32) -> bool {
+    let mut roots = HashSet::new();
+    files.iter().any(|file| {
+        roots.insert(root_component(file.name()));
+        roots.len() as u64 > threshold as u64
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use sevenz_rust::Password;
+/// Backslashes count as separators here too, matching what extraction does
+/// with `normalize_separators`.
+fn root_component(name: &str) -> &str {
+    name.trim_start_matches(['/', '\\'])
+        .split(['/', '\\'])
+        .next()
+        .unwrap_or("")
+}
 
-    use super::*;
+/// Extensions of files that can run code when opened.
+pub const RISKY_EXTENSIONS: &[&str] = &["exe", "bat", "ps1", "sh", "scr", "js"];
 
-    #[test]
-    fn test_read_config() {
-        let config = read_config().unwrap();
-        assert_eq!(config.config.delete_archive, true);
-        assert_eq!(config.config.recursive_search, true);
-        assert_eq!(config.config.threads, 4);
-        assert_eq!(config.config.dest, "".to_string());
-        assert_eq!(config.config.smart_mode, true);
-        assert_eq!(config.user.passwords.unwrap()[0], "1151".to_string());
-        assert_eq!(config.user.watch_folders.unwrap().len(), 0);
+/// Names of the entries that look like executables or scripts, so a UI can
+/// warn before anything is extracted.
+pub fn contains_risky_files<P: AsRef<Path>>(path: P) -> Result<Vec<String>, CascadeError> {
+    let files = read_archive_entries(path)?;
+    Ok(files
+        .iter()
+        .filter(|f| !f.is_directory() && is_risky_name(f.name()))
+        .map(|f| f.name().to_string())
+        .collect())
+}
+
+fn is_risky_name(name: &str) -> bool {
+    has_extension_in(name, RISKY_EXTENSIONS)
+}
+
+/// Sum of the uncompressed sizes of all entries in the archive.
+pub fn archive_total_size<P: AsRef<Path>>(path: P) -> Result<u64, CascadeError> {
+    let files = read_archive_entries(path)?;
+    Ok(files.iter().map(|f| f.size()).sum())
+}
+
+/// Names smart mode's wrapper folder from an archive's stem.
+pub type FolderNameFn = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// The smart mode settings: whether an archive gets a folder of its own
+/// under the destination, and what that folder is called.
+#[derive(Clone)]
+pub struct SmartModeOptions {
+    /// Off, every archive goes to the destination itself.
+    pub enabled: bool,
+    pub policy: SmartModePolicy,
+    /// Archives with more than this many files or folders in their root are
+    /// wrapped, see `ConfigSettings::smart_mode_threshold`.
+    pub threshold: u32,
+    /// Applied when the wrapper folder already exists. The destination
+    /// itself is used as it is either way.
+    pub collision: FolderCollisionPolicy,
+    /// Names the wrapper folder from the archive's stem, e.g. to strip
+    /// version numbers or brackets. `None` uses the stem as it is.
+    pub folder_name: Option<FolderNameFn>,
+}
+
+impl Default for SmartModeOptions {
+    fn default() -> Self {
+        SmartModeOptions {
+            enabled: false,
+            policy: SmartModePolicy::default(),
+            threshold: DEFAULT_SMART_MODE_THRESHOLD,
+            collision: FolderCollisionPolicy::default(),
+            folder_name: None,
+        }
     }
+}
 
-    #[test]
-    fn test_is_7z() {
-        assert_eq!(is_7z("tests/sample.7z").unwrap(), true);
-        assert_eq!(is_7z("tests/7ziplogo_p.7z").unwrap(), true);
-        assert_eq!(is_7z("tests/7ziplogo.7z").unwrap(), true);
-        assert_eq!(is_7z("tests/7zFormat.txt").unwrap(), false);
-        assert_eq!(is_7z("tests/7ziplogo.png").unwrap(), false);
+impl std::fmt::Debug for SmartModeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmartModeOptions")
+            .field("enabled", &self.enabled)
+            .field("policy", &self.policy)
+            .field("threshold", &self.threshold)
+            .field("collision", &self.collision)
+            .field("folder_name", &self.folder_name.as_ref().map(|_| ".."))
+            .finish()
     }
+}
 
-    #[test]
-    fn show_7z_content() {
-        let mut file = std::fs::File::open("tests/sample.7z").unwrap();
-        let len = file.metadata().unwrap().len();
-        let password = Password::empty();
-        let archive = sevenz_rust::Archive::read(&mut file, len, password.as_slice()).unwrap();
-        let folder_count = archive.folders.len();
+impl SmartModeOptions {
+    /// Smart mode as the command line and the watcher apply it.
+    pub fn from_config(config: &ConfigSettings) -> Self {
+        SmartModeOptions {
+            enabled: config.smart_mode,
+            policy: config.smart_mode_policy,
+            threshold: config.smart_mode_threshold,
+            collision: config.folder_collision,
+            folder_name: None,
+        }
+    }
 
-        // println!("{:?}", archive.folders);
-        // println!("{:?}", archive.files);
-        for file in archive.files {
-            println!("{:?}", file.name());
-            println!("{:?}", file.is_directory());
+    /// The default settings, with smart mode on or off.
+    pub fn enabled(enabled: bool) -> Self {
+        SmartModeOptions {
+            enabled,
+            ..Default::default()
         }
-        assert_eq!(folder_count, 1);
     }
+}
 
-    #[test]
-    fn test_should_create_folder_when_extract_with_smart_mode() {
-        assert_eq!(
-            should_create_folder_when_extract_with_smart_mode("tests/sample.7z").unwrap(),
-            true
+/// The folder the archive's contents will land in: `base_dest` itself, or
+/// `base_dest/<archive stem>` when smart mode decides to wrap the contents,
+/// with `smart_mode.collision` applied when that folder already exists.
+pub fn planned_dest<P: AsRef<Path>>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: &SmartModeOptions,
+) -> Result<PathBuf, CascadeError> {
+    let path = path.as_ref();
+    let create_folder = smart_mode.enabled
+        && needs_wrapper_folder_with(
+            &smart_mode_entries(path)?,
+            smart_mode.policy,
+            smart_mode.threshold,
         );
-        assert_eq!(
-            should_create_folder_when_extract_with_smart_mode("tests/7ziplogo.7z").unwrap(),
-            false
+    let dest = match &smart_mode.folder_name {
+        Some(folder_name) => resolve_dest_with(path, base_dest, create_folder, &**folder_name),
+        None => resolve_dest(path, base_dest, create_folder),
+    };
+    if dest == base_dest {
+        return Ok(dest);
+    }
+    resolve_folder_collision(dest, smart_mode.collision)
+}
+
+/// `planned_dest` with the wrapping decision made by `policy`.
+#[deprecated(note = "use `planned_dest` with `SmartModeOptions::policy`")]
+pub fn planned_dest_with_policy<P: AsRef<Path>>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: bool,
+    policy: SmartModePolicy,
+) -> Result<PathBuf, CascadeError> {
+    let smart_mode = SmartModeOptions {
+        policy,
+        ..SmartModeOptions::enabled(smart_mode)
+    };
+    planned_dest(path, base_dest, &smart_mode)
+}
+
+/// The entries smart mode decides on: those of a 7z archive, or of a tar or
+/// tar.gz, which have to be read through to be listed.
+fn smart_mode_entries(path: &Path) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, CascadeError> {
+    let tars = [ArchiveFormat::Tar, ArchiveFormat::Gzip];
+    match detect_archive_format_with(path, FULL_SIGNATURE_WINDOW, &tars)? {
+        Some(ArchiveFormat::Tar) => tar_entries(path, false),
+        Some(ArchiveFormat::Gzip) if is_tar_gz(path)? => tar_entries(path, true),
+        _ => read_archive_entries(path),
+    }
+}
+
+/// `planned_dest_with_policy`, wrapping archives with more than `threshold`
+/// files or folders in their root, see `ConfigSettings::smart_mode_threshold`.
+#[deprecated(note = "use `planned_dest` with `SmartModeOptions::threshold`")]
+pub fn planned_dest_with_threshold<P: AsRef<Path>>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: bool,
+    policy: SmartModePolicy,
+    threshold: u32,
+) -> Result<PathBuf, CascadeError> {
+    let smart_mode = SmartModeOptions {
+        policy,
+        threshold,
+        ..SmartModeOptions::enabled(smart_mode)
+    };
+    planned_dest(path, base_dest, &smart_mode)
+}
+
+/// `planned_dest_with_policy`, with `collision` applied when the wrapper
+/// folder already exists. `base_dest` itself is used as it is either way.
+#[deprecated(note = "use `planned_dest` with `SmartModeOptions::collision`")]
+pub fn planned_dest_with_collision<P: AsRef<Path>>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: bool,
+    policy: SmartModePolicy,
+    collision: FolderCollisionPolicy,
+) -> Result<PathBuf, CascadeError> {
+    let smart_mode = SmartModeOptions {
+        policy,
+        collision,
+        ..SmartModeOptions::enabled(smart_mode)
+    };
+    planned_dest(path, base_dest, &smart_mode)
+}
+
+/// `dest` itself unless it exists and `policy` says otherwise.
+pub fn resolve_folder_collision(
+    dest: PathBuf,
+    policy: FolderCollisionPolicy,
+) -> Result<PathBuf, CascadeError> {
+    if std::fs::symlink_metadata(&dest).is_err() {
+        return Ok(dest);
+    }
+    match policy {
+        FolderCollisionPolicy::Merge => Ok(dest),
+        FolderCollisionPolicy::Error => Err(CascadeError::DestinationExists { path: dest }),
+        FolderCollisionPolicy::Rename => {
+            let name = dest
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let free = (1..)
+                .map(|n| dest.with_file_name(format!("{} ({})", name, n)))
+                .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+                .unwrap();
+            log_tr!(
+                info,
+                "{} 已存在，改为解压到 {}",
+                "{} already exists, extracting to {} instead",
+                dest.display(),
+                free.display()
+            );
+            Ok(free)
+        }
+    }
+}
+
+/// Like `planned_dest`, but the wrapper folder is named by
+/// `folder_name_fn(stem)`, e.g. to strip version numbers or brackets.
+#[deprecated(note = "use `planned_dest` with `SmartModeOptions::folder_name`")]
+pub fn planned_dest_with<P: AsRef<Path>, F: Fn(&str) -> String + Send + Sync + 'static>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: bool,
+    folder_name_fn: F,
+) -> Result<PathBuf, CascadeError> {
+    let smart_mode = SmartModeOptions {
+        folder_name: Some(Arc::new(folder_name_fn)),
+        ..SmartModeOptions::enabled(smart_mode)
+    };
+    planned_dest(path, base_dest, &smart_mode)
+}
+
+fn resolve_dest(path: &Path, base_dest: &Path, create_folder: bool) -> PathBuf {
+    resolve_dest_with(path, base_dest, create_folder, &|stem| stem.to_string())
+}
+
+fn resolve_dest_with(
+    path: &Path,
+    base_dest: &Path,
+    create_folder: bool,
+    folder_name_fn: &dyn Fn(&str) -> String,
+) -> PathBuf {
+    let stem = match path.file_stem() {
+        Some(stem) if create_folder => stem.to_string_lossy(),
+        _ => return base_dest.to_path_buf(),
+    };
+    // `data.tar.gz` goes to `data`, not `data.tar`
+    let stem = match stem.strip_suffix(".tar") {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string().into(),
+        _ => stem,
+    };
+    let name = folder_name_fn(&stem);
+    // the name must stay a single folder inside base_dest
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        log_tr!(
+            warn,
+            "无效的文件夹名 {:?}，使用 {}",
+            "invalid folder name {:?}, using {}",
+            name,
+            stem
+        );
+        return base_dest.join(&*stem);
+    }
+    base_dest.join(name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractionPlan {
+    pub dest: PathBuf,
+    pub total_bytes: u64,
+    pub entry_count: usize,
+    pub creates_wrapper_folder: bool,
+}
+
+/// Works out where and how much an extraction will write without touching
+/// the disk, e.g. to show a confirmation dialog first.
+pub fn estimate_extraction<P: AsRef<Path>>(
+    path: P,
+    base_dest: &Path,
+    smart_mode: bool,
+) -> Result<ExtractionPlan, CascadeError> {
+    let dest = planned_dest(&path, base_dest, &SmartModeOptions::enabled(smart_mode))?;
+
+    Ok(ExtractionPlan {
+        creates_wrapper_folder: dest != base_dest,
+        dest,
+        total_bytes: archive_total_size(&path)?,
+        entry_count: read_archive_entries(&path)?.len(),
+    })
+}
+
+/// How often `delete_archive` retries, and how long it waits before the
+/// first retry. The wait doubles after every attempt.
+const DELETE_RETRIES: u32 = 4;
+const DELETE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Deletes the archive, retrying for a short while if the system refuses.
+/// On Windows a handle that is still being closed, e.g. by a virus scanner
+/// that was triggered by the extraction, makes the delete fail with
+/// "access denied". The retries sleep the thread, so async code calls this
+/// through `spawn_blocking`, as the watcher does.
+///
+/// Only ever removes that one file: a directory at `path` is refused.
+pub fn delete_archive<P: AsRef<Path>>(
+    path: P,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        return Err(format!("{} is a directory, not an archive", path.display()).into());
+    }
+    let mut delay = DELETE_RETRY_DELAY;
+    for _ in 0..DELETE_RETRIES {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                log_tr!(
+                    warn,
+                    "删除 {} 失败，{:?} 后重试: {}",
+                    "failed to delete {}, retrying in {:?}: {}",
+                    path.display(),
+                    delay,
+                    e
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return Ok(result?),
+        }
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// `delete_archive` once the archive is extracted to `dest`. When the
+/// archive sits inside `dest`, e.g. because it was extracted into its own
+/// folder, a warning makes clear that only the archive file is deleted and
+/// everything else in `dest` stays.
+pub fn delete_extracted_archive<P: AsRef<Path>>(
+    path: P,
+    dest: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    if is_inside(path, dest) {
+        log_tr!(
+            warn,
+            "压缩包位于解压目标 {} 中，只删除压缩包本身: {}",
+            "the archive is inside the destination {}, deleting only the archive itself: {}",
+            dest.display(),
+            path.display()
+        );
+    }
+    delete_archive(path)
+}
+
+/// Whether `path` is somewhere below `dir`, after resolving both.
+fn is_inside(path: &Path, dir: &Path) -> bool {
+    match (path.canonicalize(), dir.canonicalize()) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false,
+    }
+}
+
+/// Settings for a cracking run, and how the archive it opens is written.
+#[derive(Debug, Clone, Default)]
+pub struct CrackOptions {
+    /// Logs every password tried, and the one that worked, at `debug` level.
+    /// Off by default; `info` logs never show more than `****`.
+    pub log_passwords: bool,
+    /// Logs the attempt rate and thread utilization every second, which
+    /// helps finding the `threads` value past which disk I/O is the limit.
+    pub report_rate: bool,
+    /// Gives up once this much time has passed without success, e.g. so a
+    /// scheduled job doesn't run forever on an archive it can't open.
+    pub max_runtime: Option<Duration>,
+    /// Updated as the run goes, for a UI to poll from another task.
+    pub progress: Option<Arc<Progress>>,
+    /// Every thread decodes on its own, so fewer threads are started when
+    /// `max_threads` of them would need more memory than this, in bytes.
+    /// Archives with an encrypted header can't be estimated and are run
+    /// with all threads.
+    pub max_memory: Option<u64>,
+    /// Tries each archive's own file name without the extension as its
+    /// password before the list, a common convention.
+    pub use_filename_as_password: bool,
+    /// Also tries each password in lower case, upper case and title case,
+    /// see `with_case_variants`. Up to four times the attempts.
+    pub try_case_variants: bool,
+    /// Runs the attempts at a lower CPU and I/O priority, see
+    /// `lower_thread_priority`: on the threads of `crack_password_blocking`,
+    /// and for `start_extraction` each on a thread of its own rather than
+    /// on the tokio runtime's threads, which are left alone.
+    pub low_priority: bool,
+    /// Stops the run once cancelled. Attempts write nothing, see `extract`,
+    /// so there is nothing to clean up; ones already running are waited for
+    /// first.
+    pub cancel: Option<CancelToken>,
+    /// Skips the passwords a previous run already tried, see `CrackState`.
+    /// Ignored when it was saved for a list of another length.
+    pub resume_from: Option<CrackState>,
+    /// Saves the `CrackState` here every `CRACK_STATE_SAVE_INTERVAL` and
+    /// when the run ends without a password, and removes the file once one
+    /// is found.
+    pub state_file: Option<PathBuf>,
+    /// Where `start_extraction_with_options` reports how the run goes, see
+    /// `CrackEvent` for which events may be dropped.
+    #[cfg(feature = "async")]
+    pub events: Option<mpsc::Sender<CrackEvent>>,
+    /// How the archive is extracted once its password is found. Attempts
+    /// only check a password, as `find_password` does, and write nothing;
+    /// the archive that opened is then extracted with `extract_with_options`.
+    pub extract: ExtractOptions,
+}
+
+impl CrackOptions {
+    pub fn from_config(config: &ConfigSettings) -> Self {
+        CrackOptions {
+            log_passwords: config.log_passwords,
+            report_rate: config.report_rate,
+            max_runtime: config.max_runtime_secs.map(Duration::from_secs),
+            progress: None,
+            max_memory: config.max_memory,
+            use_filename_as_password: config.use_filename_as_password,
+            try_case_variants: config.try_case_variants,
+            low_priority: config.low_priority,
+            cancel: None,
+            resume_from: None,
+            state_file: None,
+            #[cfg(feature = "async")]
+            events: None,
+            extract: ExtractOptions::from_config(config),
+        }
+    }
+}
+
+/// Each password followed by its lower case, upper case and title case
+/// forms, for archives made by tools that changed the case of the password.
+/// Every password appears once, at its first position.
+pub fn with_case_variants(passwords: &[Arc<String>]) -> Vec<Arc<String>> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut variants = Vec::with_capacity(passwords.len() * 4);
+    for password in passwords {
+        if seen.insert(password.to_string()) {
+            variants.push(password.clone());
+        }
+        for variant in [
+            password.to_lowercase(),
+            password.to_uppercase(),
+            title_case(password),
+        ] {
+            if seen.insert(variant.clone()) {
+                variants.push(Arc::new(variant));
+            }
+        }
+    }
+    variants
+}
+
+/// `with_case_variants` for numbered lines, each variant with the line of
+/// the password it was made from.
+#[cfg(feature = "async")]
+fn with_numbered_case_variants(numbered: &[(usize, Arc<String>)]) -> Vec<(usize, Arc<String>)> {
+    let mut seen: HashSet<Arc<String>> = HashSet::new();
+    let mut variants = Vec::with_capacity(numbered.len() * 4);
+    for (line, password) in numbered {
+        for variant in with_case_variants(std::slice::from_ref(password)) {
+            if seen.insert(variant.clone()) {
+                variants.push((*line, variant));
+            }
+        }
+    }
+    variants
+}
+
+/// The first letter of every word in upper case, the rest in lower case.
+fn title_case(s: &str) -> String {
+    let mut title = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            title.extend(c.to_uppercase());
+        } else {
+            title.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    title
+}
+
+/// The first archive in `paths` whose stem opens it, with that stem.
+fn try_filename_passwords<P: AsRef<Path>>(
+    paths: &[P],
+    progress: &Progress,
+) -> Option<(PathBuf, String)> {
+    paths.iter().find_map(|path| {
+        let path = path.as_ref();
+        let stem = path.file_stem()?.to_str()?;
+        if stem.is_empty() {
+            return None;
+        }
+        progress.record_attempt();
+        if !matches!(password_opens(path, stem, None), Ok(true)) {
+            return None;
+        }
+        log_tr!(
+            info,
+            "[{}] 文件名就是密码",
+            "[{}] the file name is the password",
+            log_context(path, None)
+        );
+        Some((path.to_path_buf(), stem.to_string()))
+    })
+}
+
+/// Extracts the archive a crack found the password of with
+/// `CrackOptions::extract`.
+fn extract_found(path: &Path, password: String, dest: &Path, options: &ExtractOptions) -> Outcome {
+    match extract_with_options(path, &password, dest, options) {
+        Ok(_) => Outcome::Found(password),
+        Err(e) => {
+            log_tr!(
+                warn,
+                "[{}] 找到了密码，但解压失败: {}",
+                "[{}] found the password, but extracting failed: {}",
+                log_context(path, None),
+                e
+            );
+            Outcome::ExtractFailed {
+                password,
+                error: e.to_string(),
+            }
+        }
+    }
+}
+
+/// `extract_found` on a blocking thread, off the runtime.
+#[cfg(feature = "async")]
+async fn extract_found_blocking(
+    path: PathBuf,
+    password: String,
+    dest: PathBuf,
+    options: &ExtractOptions,
+) -> Outcome {
+    let options = options.clone();
+    let found = password.clone();
+    tokio::task::spawn_blocking(move || extract_found(&path, found, &dest, &options))
+        .await
+        .unwrap_or_else(|e| Outcome::ExtractFailed {
+            password,
+            error: e.to_string(),
+        })
+}
+
+/// `max_threads`, lowered so the decoders of all threads fit in
+/// `max_memory`, but never below one.
+fn threads_within_memory<P: AsRef<Path>>(
+    paths: &[P],
+    max_threads: usize,
+    max_memory: Option<u64>,
+) -> usize {
+    let Some(limit) = max_memory else {
+        return max_threads;
+    };
+    let needed = paths
+        .iter()
+        .filter_map(|path| decode_memory_estimate(path, "").ok())
+        .max()
+        .unwrap_or(0);
+    let threads = match limit.checked_div(needed) {
+        Some(fit) => (fit as usize).clamp(1, max_threads.max(1)),
+        None => max_threads,
+    };
+    if threads < max_threads {
+        log_tr!(
+            info,
+            "每个线程解压约需 {} 字节内存，线程数降为 {}",
+            "decoding takes about {} bytes per thread, using {} threads",
+            needed,
+            threads
+        );
+    }
+    threads
+}
+
+/// Live counters of a cracking run, counted in attempts like
+/// `Outcome::NotFound::tried`.
+#[derive(Debug)]
+pub struct Progress {
+    tried: AtomicU64,
+    /// `u64::MAX` while the total is unknown
+    total: AtomicU64,
+    started: Mutex<Option<Instant>>,
+    /// `f64` bits of `expected_attempt_rate`, 0 while unknown
+    expected_rate: AtomicU64,
+}
+
+/// Below this many attempts, or within the first second, `Progress::rate`
+/// goes by `expected_attempt_rate` instead of the attempts made so far.
+const MIN_MEASURED_ATTEMPTS: u64 = 8;
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress {
+            tried: AtomicU64::new(0),
+            total: AtomicU64::new(u64::MAX),
+            started: Mutex::new(None),
+            expected_rate: AtomicU64::new(0),
+        }
+    }
+
+    /// Attempts per second since the run started. Until there are enough
+    /// attempts to go by, this is the rate the archive's AES key cost
+    /// predicts, see `expected_attempt_rate`; `None` when neither is known.
+    pub fn rate(&self) -> Option<f64> {
+        let elapsed = self.started.lock().unwrap().map(|start| start.elapsed());
+        let tried = self.tried();
+        match elapsed {
+            Some(elapsed)
+                if tried >= MIN_MEASURED_ATTEMPTS && elapsed >= Duration::from_secs(1) =>
+            {
+                Some(tried as f64 / elapsed.as_secs_f64())
+            }
+            _ => match f64::from_bits(self.expected_rate.load(Ordering::Relaxed)) {
+                rate if rate > 0.0 => Some(rate),
+                _ => None,
+            },
+        }
+    }
+
+    /// `estimate_remaining` at the current `rate`, for showing e.g.
+    /// "~4h remaining at 1200 pw/s".
+    pub fn remaining_time(&self) -> Option<Duration> {
+        estimate_remaining(self.tried(), self.total(), self.rate()?)
+    }
+
+    pub fn tried(&self) -> u64 {
+        self.tried.load(Ordering::Relaxed)
+    }
+
+    /// Attempts in the whole run, or `None` when the passwords come from a
+    /// source of unknown length.
+    pub fn total(&self) -> Option<u64> {
+        match self.total.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            total => Some(total),
+        }
+    }
+
+    /// Attempts left to make, for the denominator of a progress bar; `None`
+    /// calls for an indeterminate one.
+    pub fn remaining(&self) -> Option<u64> {
+        self.total().map(|total| total.saturating_sub(self.tried()))
+    }
+
+    fn begin(&self, total: Option<u64>) {
+        self.tried.store(0, Ordering::Relaxed);
+        self.total
+            .store(total.unwrap_or(u64::MAX), Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+        self.expected_rate.store(0, Ordering::Relaxed);
+    }
+
+    /// Looks at the key cost of `paths`, warning about slow ones, and
+    /// predicts the rate from it for the start of the run.
+    fn expect_key_cost<P: AsRef<Path>>(&self, paths: &[P], threads: usize) {
+        let rate = check_key_derivation(paths)
+            .and_then(|iterations| expected_attempt_rate(iterations, threads));
+        if let Some(rate) = rate {
+            self.expected_rate.store(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    fn record_attempt(&self) {
+        self.tried.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::new()
+    }
+}
+
+/// Cancels a cracking run from another task or thread, see
+/// `CrackOptions::cancel`. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How long the `total - tried` attempts left take at `rate` attempts per
+/// second. `None` when the total is unknown, e.g. for a streamed wordlist,
+/// or the rate isn't positive. The rate should account for what each
+/// attempt costs, which the AES key derivation dominates; `Progress::rate`
+/// does.
+pub fn estimate_remaining(tried: u64, total: Option<u64>, rate: f64) -> Option<Duration> {
+    let left = total?.saturating_sub(tried);
+    if !(rate > 0.0 && rate.is_finite()) {
+        return None;
+    }
+    Duration::try_from_secs_f64(left as f64 / rate).ok()
+}
+
+/// How a cracking run ended. `tried` counts attempts, that is passwords
+/// tried against each archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Found(String),
+    /// Every password was tried and none of them worked.
+    NotFound {
+        tried: u64,
+    },
+    /// `max_runtime` passed first.
+    TimedOut {
+        tried: u64,
+    },
+    /// `CrackOptions::cancel` was cancelled first.
+    Cancelled {
+        tried: u64,
+    },
+    /// The password was found, but extracting with `CrackOptions::extract`
+    /// failed, e.g. on one of its limits.
+    ExtractFailed {
+        password: String,
+        error: String,
+    },
+    /// The archive wasn't tried, e.g. a batch archive whose wrapper folder
+    /// exists under `FolderCollisionPolicy::Error`.
+    Skipped {
+        reason: String,
+    },
+}
+
+/// Sent to `CrackOptions::events` during a run.
+///
+/// `Progress` is best-effort: it is sent with `try_send` after each attempt
+/// and dropped while the channel is full, so a slow receiver never holds up
+/// the attempts. As the counts only grow, the next one that fits brings the
+/// receiver up to date. The final `Succeeded` or `Failed` is guaranteed:
+/// it is sent once the attempts have stopped, waiting for room if need be.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrackEvent {
+    Progress {
+        tried: u64,
+        total: Option<u64>,
+    },
+    Succeeded {
+        password: String,
+        /// Attempts made before the one that worked, counting the other
+        /// archives and the attempts running alongside it, so roughly how
+        /// far down the list the password was.
+        attempts_before_success: u64,
+        elapsed: Duration,
+    },
+    /// Any outcome other than `Outcome::Found`.
+    Failed {
+        outcome: Outcome,
+    },
+}
+
+/// What `start_extraction` returns when a password worked.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrackSuccess {
+    pub password: String,
+    /// Attempts made before the one that worked, counting the other
+    /// archives and the attempts running alongside it, so roughly how far
+    /// down the list the password was.
+    pub attempts_before_success: u64,
+    pub elapsed: Duration,
+}
+
+impl Outcome {
+    pub fn password(&self) -> Option<&str> {
+        match self {
+            Outcome::Found(password) => Some(password),
+            _ => None,
+        }
+    }
+
+    pub fn into_password(self) -> Option<String> {
+        match self {
+            Outcome::Found(password) => Some(password),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+const RATE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often `start_extraction_with_options` looks at `CrackOptions::cancel`.
+#[cfg(feature = "async")]
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Stands in for the password in `info` level logs, which often end up in
+/// files or log aggregators. The password itself is returned to the caller.
+const REDACTED_PASSWORD: &str = "****";
+
+/// Tries every password against the archives in parallel and returns the
+/// first password that opened one and extracted it, with how many attempts
+/// it took and how long, or `None` once all attempts have failed.
+#[cfg(feature = "async")]
+pub async fn start_extraction<P: AsRef<Path> + Send + Sync + 'static>(
+    paths: Arc<[P]>,
+    passwords: Vec<Arc<String>>,
+    dest: P,
+    max_threads: usize,
+) -> Option<CrackSuccess> {
+    let (outcome, attempts_before_success, elapsed) = crack_reporting(
+        paths,
+        passwords,
+        dest,
+        max_threads,
+        &CrackOptions::default(),
+    )
+    .await;
+    Some(CrackSuccess {
+        password: outcome.into_password()?,
+        attempts_before_success,
+        elapsed,
+    })
+}
+
+/// `start_extraction` with `options`, telling apart a search that ran out of
+/// passwords from one that ran out of time.
+#[cfg(feature = "async")]
+pub async fn start_extraction_with_options<P: AsRef<Path> + Send + Sync + 'static>(
+    paths: Arc<[P]>,
+    passwords: Vec<Arc<String>>,
+    dest: P,
+    max_threads: usize,
+    options: &CrackOptions,
+) -> Outcome {
+    crack_reporting(paths, passwords, dest, max_threads, options)
+        .await
+        .0
+}
+
+/// `crack_with_options`, logging and sending the final event, with how
+/// long the run took.
+#[cfg(feature = "async")]
+async fn crack_reporting<P: AsRef<Path> + Send + Sync + 'static>(
+    paths: Arc<[P]>,
+    passwords: Vec<Arc<String>>,
+    dest: P,
+    max_threads: usize,
+    options: &CrackOptions,
+) -> (Outcome, u64, Duration) {
+    let started = Instant::now();
+    let (outcome, attempts_before_success) =
+        crack_with_options(paths, passwords, dest, max_threads, options).await;
+    let elapsed = started.elapsed();
+    if let Outcome::Found(_) = outcome {
+        log_tr!(
+            info,
+            "找到密码之前尝试了 {} 次，用时 {:?}",
+            "the password was found after {} attempts in {:?}",
+            attempts_before_success,
+            elapsed
+        );
+    }
+    if let Some(events) = &options.events {
+        let event = match &outcome {
+            Outcome::Found(password) => CrackEvent::Succeeded {
+                password: password.clone(),
+                attempts_before_success,
+                elapsed,
+            },
+            outcome => CrackEvent::Failed {
+                outcome: outcome.clone(),
+            },
+        };
+        // a receiver that is gone doesn't want it anymore
+        let _ = events.send(event).await;
+    }
+    (outcome, attempts_before_success, elapsed)
+}
+
+/// The outcome, and when the password was found, how many attempts came
+/// before the one that found it.
+#[cfg(feature = "async")]
+async fn crack_with_options<P: AsRef<Path> + Send + Sync + 'static>(
+    paths: Arc<[P]>,
+    passwords: Vec<Arc<String>>,
+    dest: P,
+    max_threads: usize,
+    options: &CrackOptions,
+) -> (Outcome, u64) {
+    let passwords = if options.try_case_variants {
+        with_case_variants(&passwords)
+    } else {
+        passwords
+    };
+    let log_passwords = options.log_passwords;
+    let tracker = CrackStateTracker::new(
+        options.state_file.clone(),
+        options.resume_from,
+        passwords.len(),
+    );
+    let start = tracker.start();
+    let tracker = Arc::new(Mutex::new(tracker));
+    let max_threads = threads_within_memory(&paths, max_threads, options.max_memory);
+    // Tasks wait on the semaphore directly; wrapping it in a mutex held for
+    // the whole attempt used to let only one task run at a time.
+    let semaphore = Arc::new(Semaphore::new(max_threads));
+    let stop_flag = Arc::new(AtomicBool::new(false)); // Moved stop_flag outside the loop
+    let progress = options.progress.clone().unwrap_or_default();
+    let filename_attempts = if options.use_filename_as_password {
+        paths.len() as u64
+    } else {
+        0
+    };
+    progress.begin(Some(
+        (passwords.len() - start) as u64 * paths.len() as u64 + filename_attempts,
+    ));
+    if options
+        .cancel
+        .as_ref()
+        .is_some_and(CancelToken::is_cancelled)
+    {
+        return (Outcome::Cancelled { tried: 0 }, 0);
+    }
+    progress.expect_key_cost(&paths, max_threads);
+    // a file name that opens the archive skips the list, the run then ends
+    // the same way as for a password from it
+    let filename_hit = options
+        .use_filename_as_password
+        .then(|| try_filename_passwords(&paths, &progress))
+        .flatten();
+    let reporter = options.report_rate.then(|| {
+        tokio::spawn(report_attempt_rate(
+            progress.clone(),
+            semaphore.clone(),
+            max_threads,
+        ))
+    });
+
+    // Explicitly specify the type parameter for the Sender
+    // a channel needs room for at least one message, even for an empty list
+    let (tx, mut rx) = mpsc::channel::<(String, u64, PathBuf)>(passwords.len().max(1));
+
+    let mut tasks = Vec::with_capacity(max_threads);
+    // A task is only spawned once a permit is free for it, so at most
+    // `max_threads` exist at a time and a run that stops early leaves no
+    // queue of waiting tasks behind.
+    let spawn_tasks = async {
+        let tx = tx;
+        for (index, password) in passwords.into_iter().enumerate().skip(start) {
+            // the semaphore is never closed, but there is no reason to panic
+            // if it ever is
+            let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                break;
+            };
+            if stop_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            tasks.retain(|task: &tokio::task::JoinHandle<()>| !task.is_finished());
+            let tx = tx.clone();
+            let tracker = tracker.clone();
+            let paths = paths.clone();
+            let stop_flag = stop_flag.clone(); // Clone the Arc
+            let progress = progress.clone();
+            let events = options.events.clone();
+            let low_priority = options.low_priority;
+
+            tasks.push(tokio::spawn(async move {
+                for path in paths.iter() {
+                    if stop_flag.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let context = log_context(path.as_ref(), Some(index));
+                    if log_passwords {
+                        log_tr!(
+                            debug,
+                            "[{}] 尝试密码: {}",
+                            "[{}] trying password: {}",
+                            context,
+                            password
+                        );
+                    }
+                    progress.record_attempt();
+                    let attempts_before = progress.tried() - 1;
+                    if let Some(events) = &events {
+                        // a full channel drops the event, see `CrackEvent`
+                        let _ = events.try_send(CrackEvent::Progress {
+                            tried: progress.tried(),
+                            total: progress.total(),
+                        });
+                    }
+                    let opened = if low_priority {
+                        let (path, password, stop_flag) = (
+                            path.as_ref().to_path_buf(),
+                            password.clone(),
+                            stop_flag.clone(),
+                        );
+                        run_at_low_priority(move || {
+                            password_opens(&path, &password, Some(&stop_flag))
+                        })
+                        .await
+                        .and_then(|opened| opened)
+                    } else {
+                        password_opens(path.as_ref(), &password, Some(&stop_flag))
+                    };
+                    if let Ok(true) = opened {
+                        log_tr!(
+                            info,
+                            "[{}] 找到正确的密码: {}",
+                            "[{}] found the password: {}",
+                            context,
+                            REDACTED_PASSWORD
+                        );
+                        if log_passwords {
+                            log_tr!(
+                                debug,
+                                "[{}] 正确的密码: {}",
+                                "[{}] the password is: {}",
+                                context,
+                                password
+                            );
+                        }
+                        stop_flag.store(true, Ordering::Relaxed);
+                        // the receiver is gone if the caller dropped our future,
+                        // there is nobody left to tell
+                        let _ = tx
+                            .send((
+                                password.to_string(),
+                                attempts_before,
+                                path.as_ref().to_path_buf(),
+                            ))
+                            .await;
+                        drop(permit);
+                        return;
+                    }
+                    // each attempt runs synchronously, give other tasks and
+                    // cancellation a chance before the next one
+                    tokio::task::yield_now().await;
+                }
+                tracker.lock().unwrap().finish(index);
+
+                drop(permit);
+            }));
+        }
+        // Only the tasks hold senders once this returns, so `recv` returns
+        // `None` when they have all given up.
+    };
+
+    // Wait for the first successful extraction or stop flag
+    let wait_for_password = async {
+        tokio::pin!(spawn_tasks);
+        let mut spawning = true;
+        loop {
+            let found = tokio::select! {
+                () = &mut spawn_tasks, if spawning => {
+                    spawning = false;
+                    continue;
+                }
+                found = rx.recv() => found,
+            };
+            let Some(found) = found else {
+                break;
+            };
+            if stop_flag.load(Ordering::Relaxed) {
+                log_tr!(
+                    info,
+                    "发现正确密码，终止其他任务队列",
+                    "password found, stopping the remaining tasks"
+                );
+                return Some(found);
+            }
+        }
+        None
+    };
+    let mut attempts_before_success = 0;
+    let mut found_in = None;
+    let wait_for_cancel = async {
+        match &options.cancel {
+            Some(token) => {
+                while !token.is_cancelled() {
+                    tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
+                }
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let wait_for_password = async {
+        tokio::select! {
+            found = wait_for_password => found.map(|(password, attempts_before, path)| {
+                attempts_before_success = attempts_before;
+                found_in = Some(path);
+                Outcome::Found(password)
+            }),
+            () = wait_for_cancel => Some(Outcome::Cancelled { tried: 0 }),
+        }
+    };
+    let (outcome, found_in, attempts_before_success) = match filename_hit {
+        Some((path, password)) => {
+            drop(wait_for_password);
+            let attempts_before = progress.tried() - 1;
+            (Some(Outcome::Found(password)), Some(path), attempts_before)
+        }
+        None => {
+            let outcome = match options.max_runtime {
+                Some(max_runtime) => {
+                    match tokio::time::timeout(max_runtime, wait_for_password).await {
+                        Ok(outcome) => outcome,
+                        Err(_) => {
+                            // attempts already running finish on their own,
+                            // everything else is stopped here
+                            stop_flag.store(true, Ordering::Relaxed);
+                            for task in &tasks {
+                                task.abort();
+                            }
+                            let tried = progress.tried();
+                            log_tr!(
+                                warn,
+                                "超过最长运行时间 {:?}，已尝试 {} 次",
+                                "max runtime {:?} exceeded after {} attempts",
+                                max_runtime,
+                                tried
+                            );
+                            Some(Outcome::TimedOut { tried })
+                        }
+                    }
+                }
+                None => wait_for_password.await,
+            };
+            (outcome, found_in, attempts_before_success)
+        }
+    };
+
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    if let Some(Outcome::Cancelled { .. }) = outcome {
+        stop_flag.store(true, Ordering::Relaxed);
+        for task in &tasks {
+            task.abort();
+        }
+        // an attempt that is running goes on until it returns
+        for task in tasks {
+            let _ = task.await;
+        }
+        tracker.lock().unwrap().end(false);
+        let tried = progress.tried();
+        log_tr!(
+            info,
+            "已取消，已尝试 {} 次",
+            "cancelled after {} attempts",
+            tried
+        );
+        return (Outcome::Cancelled { tried }, 0);
+    }
+    tracker
+        .lock()
+        .unwrap()
+        .end(matches!(outcome, Some(Outcome::Found(_))));
+    let outcome = match (outcome, found_in) {
+        (Some(Outcome::Found(password)), Some(path)) => {
+            extract_found_blocking(path, password, dest.as_ref().to_owned(), &options.extract).await
+        }
+        (outcome, _) => outcome.unwrap_or_else(|| Outcome::NotFound {
+            tried: progress.tried(),
+        }),
+    };
+    (outcome, attempts_before_success)
+}
+
+/// Runs `attempt` on a thread of its own at a lower priority, see
+/// `CrackOptions::low_priority`, waiting for it without holding up the
+/// runtime. Dropping the future, as aborting the task does, leaves the
+/// thread to finish on its own: attempts only check a password and write
+/// nothing, and they give up soon once the crack's stop flag is set. An
+/// attempt that panicked is an error.
+#[cfg(feature = "async")]
+async fn run_at_low_priority<T: Send + 'static>(
+    attempt: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, CascadeError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        lower_thread_priority_or_warn();
+        let _ = tx.send(attempt());
+    });
+    rx.await
+        .map_err(|_| io::Error::other("the attempt ended without a result").into())
+}
+
+/// Logs the attempt rate and how many permits are in use once per
+/// `RATE_REPORT_INTERVAL`, until aborted.
+#[cfg(feature = "async")]
+async fn report_attempt_rate(
+    progress: Arc<Progress>,
+    semaphore: Arc<Semaphore>,
+    max_threads: usize,
+) {
+    let mut interval = tokio::time::interval(RATE_REPORT_INTERVAL);
+    // the first tick completes immediately
+    interval.tick().await;
+    let mut sampler = RateSampler::new(Instant::now());
+
+    loop {
+        interval.tick().await;
+        let total = progress.tried();
+        let rate = sampler.sample(Instant::now(), total);
+        let busy = max_threads.saturating_sub(semaphore.available_permits());
+        let left = match progress.remaining_time() {
+            Some(left) => format!("{}s", left.as_secs()),
+            None => "?".to_string(),
+        };
+        log_tr!(
+            info,
+            "尝试速度: {:.1} 次/秒，共 {} 次，线程占用 {}/{}，预计剩余 {}",
+            "rate: {:.1} attempts/s, {} in total, threads busy {}/{}, about {} left",
+            rate,
+            total,
+            busy,
+            max_threads,
+            left
+        );
+    }
+}
+
+/// The attempt rate between one report and the next.
+#[cfg(feature = "async")]
+struct RateSampler {
+    last: Instant,
+    last_total: u64,
+}
+
+#[cfg(feature = "async")]
+impl RateSampler {
+    fn new(now: Instant) -> Self {
+        RateSampler {
+            last: now,
+            last_total: 0,
+        }
+    }
+
+    /// Attempts per second since the previous sample, `total` being the
+    /// attempts made so far.
+    fn sample(&mut self, now: Instant, total: u64) -> f64 {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        let rate = total.saturating_sub(self.last_total) as f64 / elapsed.max(f64::EPSILON);
+        (self.last, self.last_total) = (now, total);
+        rate
+    }
+}
+
+/// Settings for a batch of archives cracked one after another.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// How each archive is cracked and extracted.
+    pub crack: CrackOptions,
+    /// Where under the base destination each archive goes, see
+    /// `planned_dest`. An archive whose wrapper folder exists under
+    /// `FolderCollisionPolicy::Error` is skipped.
+    pub smart_mode: SmartModeOptions,
+    /// Moves a password that opened one archive to the front of the list for
+    /// the remaining archives, since archives from the same source usually
+    /// share a password.
+    pub adaptive_password_order: bool,
+}
+
+#[cfg(feature = "async")]
+impl BatchOptions {
+    pub fn from_config(config: &ConfigSettings) -> Self {
+        BatchOptions {
+            crack: CrackOptions::from_config(config),
+            smart_mode: SmartModeOptions::from_config(config),
+            adaptive_password_order: config.adaptive_password_order,
+        }
+    }
+}
+
+/// The archives of a batch, cracked one at a time with the password order
+/// carried from one archive to the next.
+#[cfg(feature = "async")]
+pub(crate) struct BatchRun {
+    passwords: Vec<Arc<String>>,
+    base_dest: PathBuf,
+    max_threads: usize,
+    options: BatchOptions,
+}
+
+#[cfg(feature = "async")]
+impl BatchRun {
+    pub(crate) fn new(
+        passwords: Vec<Arc<String>>,
+        base_dest: PathBuf,
+        max_threads: usize,
+        options: BatchOptions,
+    ) -> Self {
+        BatchRun {
+            passwords,
+            base_dest,
+            max_threads,
+            options,
+        }
+    }
+
+    /// Cracks `path` into its `planned_dest`, trying the passwords in `cache`
+    /// first and recording the one that worked. `cancel` replaces
+    /// `CrackOptions::cancel` for this archive.
+    pub(crate) async fn crack(
+        &mut self,
+        path: &Path,
+        cache: Option<&mut PasswordCache>,
+        cancel: Option<CancelToken>,
+    ) -> Outcome {
+        let dest = match planned_dest(path, &self.base_dest, &self.options.smart_mode) {
+            Ok(dest) => dest,
+            Err(e @ CascadeError::DestinationExists { .. }) => {
+                log_tr!(warn, "跳过 {}: {}", "skipping {}: {}", path.display(), e);
+                return Outcome::Skipped {
+                    reason: e.to_string(),
+                };
+            }
+            // entries that can't be listed without the password
+            Err(_) => self.base_dest.clone(),
+        };
+        let passwords = match &cache {
+            Some(cache) => cache.ordered(&self.passwords),
+            None => self.passwords.clone(),
+        };
+        let mut options = self.options.crack.clone();
+        if cancel.is_some() {
+            options.cancel = cancel;
+        }
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![path.to_path_buf()]),
+            passwords,
+            dest,
+            self.max_threads,
+            &options,
+        )
+        .await;
+
+        if let Some(password) = outcome.password() {
+            if let Some(cache) = cache {
+                cache.record(password);
+            }
+            if self.options.adaptive_password_order {
+                promote_password(&mut self.passwords, password);
+            }
+        }
+        tokio::task::yield_now().await;
+        outcome
+    }
+}
+
+/// Cracks each archive in turn into its `planned_dest` under `base_dest`,
+/// returning how each one ended. With `cache` its passwords are tried first
+/// for every archive and each password that worked is recorded; pass the
+/// same cache to later batches, or persist it with `PasswordCache::save`, to
+/// carry it over.
+#[cfg(feature = "async")]
+pub async fn crack_batch_with_options(
+    paths: &[PathBuf],
+    passwords: Vec<Arc<String>>,
+    base_dest: &Path,
+    max_threads: usize,
+    options: &BatchOptions,
+    mut cache: Option<&mut PasswordCache>,
+) -> Vec<(PathBuf, Outcome)> {
+    let mut run = BatchRun::new(
+        passwords,
+        base_dest.to_path_buf(),
+        max_threads,
+        options.clone(),
+    );
+    let mut results = Vec::with_capacity(paths.len());
+    for path in paths {
+        let outcome = run.crack(path, cache.as_deref_mut(), None).await;
+        results.push((path.clone(), outcome));
+    }
+    results
+}
+
+/// Cracks each archive in turn, returning the password found for each one.
+///
+/// With `adaptive_password_order`, a password that opened one archive is
+/// moved to the front of the candidate list for the remaining archives,
+/// since archives from the same source usually share a password.
+#[cfg(feature = "async")]
+#[deprecated(note = "use `crack_batch_with_options`")]
+pub async fn crack_batch(
+    paths: &[PathBuf],
+    passwords: Vec<Arc<String>>,
+    dest: &Path,
+    max_threads: usize,
+    adaptive_password_order: bool,
+) -> Vec<(PathBuf, Option<String>)> {
+    let options = BatchOptions {
+        adaptive_password_order,
+        ..Default::default()
+    };
+    found_passwords(
+        crack_batch_with_options(paths, passwords, dest, max_threads, &options, None).await,
+    )
+}
+
+/// `crack_batch` extracting each archive to its `planned_dest` under
+/// `base_dest`, so with `smart_mode` every archive whose contents need a
+/// wrapper folder gets one. Archives whose entries can't be listed without
+/// the password go to `base_dest` itself.
+#[cfg(feature = "async")]
+#[deprecated(note = "use `crack_batch_with_options` with `BatchOptions::smart_mode`")]
+pub async fn crack_batch_with_smart_mode(
+    paths: &[PathBuf],
+    passwords: Vec<Arc<String>>,
+    base_dest: &Path,
+    max_threads: usize,
+    adaptive_password_order: bool,
+    smart_mode: bool,
+) -> Vec<(PathBuf, Option<String>)> {
+    let options = BatchOptions {
+        smart_mode: SmartModeOptions::enabled(smart_mode),
+        adaptive_password_order,
+        ..Default::default()
+    };
+    found_passwords(
+        crack_batch_with_options(paths, passwords, base_dest, max_threads, &options, None).await,
+    )
+}
+
+/// The password each archive of a batch opened with, if any.
+#[cfg(feature = "async")]
+fn found_passwords(results: Vec<(PathBuf, Outcome)>) -> Vec<(PathBuf, Option<String>)> {
+    results
+        .into_iter()
+        .map(|(path, outcome)| (path, outcome.into_password()))
+        .collect()
+}
+
+/// One archive of a `crack_batch_cancellable` run.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct BatchHandle {
+    pub path: PathBuf,
+    token: CancelToken,
+}
+
+#[cfg(feature = "async")]
+impl BatchHandle {
+    /// Stops this archive alone: a run in progress stops once the attempts
+    /// running have finished, one still waiting is skipped. Either way its
+    /// result is `Outcome::Cancelled`.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+/// A running `crack_batch_cancellable`, with one handle per archive in the
+/// order given.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct CancellableBatch {
+    pub handles: Vec<BatchHandle>,
+    pub results: tokio::task::JoinHandle<Vec<(PathBuf, Outcome)>>,
+}
+
+/// `crack_batch_with_options` in a background task, returning right away
+/// with a handle to cancel each archive on its own, e.g. from a queue UI.
+/// The handles take the place of `CrackOptions::cancel`. Must be called from
+/// within a tokio runtime.
+#[cfg(feature = "async")]
+pub fn crack_batch_cancellable(
+    paths: Vec<PathBuf>,
+    passwords: Vec<Arc<String>>,
+    base_dest: PathBuf,
+    max_threads: usize,
+    options: BatchOptions,
+) -> CancellableBatch {
+    let handles: Vec<BatchHandle> = paths
+        .into_iter()
+        .map(|path| BatchHandle {
+            path,
+            token: CancelToken::new(),
+        })
+        .collect();
+    let queue = handles.clone();
+
+    let results = tokio::spawn(async move {
+        let mut run = BatchRun::new(passwords, base_dest, max_threads, options);
+        let mut results = Vec::with_capacity(queue.len());
+        for handle in queue {
+            let outcome = run.crack(&handle.path, None, Some(handle.token)).await;
+            results.push((handle.path, outcome));
+        }
+        results
+    });
+
+    CancellableBatch { handles, results }
+}
+
+/// `crack_batch` trying the passwords in `cache` first for every archive,
+/// and recording each password that worked. Pass the same cache to later
+/// batches, or persist it with `PasswordCache::save`, to carry it over.
+#[cfg(feature = "async")]
+#[deprecated(note = "use `crack_batch_with_options` with a cache")]
+pub async fn crack_batch_with_cache(
+    paths: &[PathBuf],
+    passwords: &[Arc<String>],
+    dest: &Path,
+    max_threads: usize,
+    cache: &mut PasswordCache,
+) -> Vec<(PathBuf, Option<String>)> {
+    let options = BatchOptions::default();
+    found_passwords(
+        crack_batch_with_options(
+            paths,
+            passwords.to_vec(),
+            dest,
+            max_threads,
+            &options,
+            Some(cache),
+        )
+        .await,
+    )
+}
+
+/// Tries the wordlists one after the other, e.g. a short list of likely
+/// passwords before a huge one, until a password extracts the archives.
+/// Each list is read only once the ones before it have failed. `max_runtime`
+/// covers all the lists together; once it runs out the remaining lists are
+/// given up on. A password found whose archive then fails to extract is
+/// returned as an error.
+#[cfg(feature = "async")]
+pub async fn start_extraction_with_wordlists<P: AsRef<Path> + Clone + Send + Sync + 'static>(
+    paths: Arc<[P]>,
+    wordlists: &[PathBuf],
+    dest: P,
+    max_threads: usize,
+    options: &CrackOptions,
+    shard: Shard,
+) -> Result<Option<WordlistHit>, CascadeError> {
+    let deadline = options.max_runtime.map(|d| Instant::now() + d);
+    let mut options = options.clone();
+    // the variants are made here, each remembering the line it came from
+    let case_variants = std::mem::take(&mut options.try_case_variants);
+    for wordlist in wordlists {
+        if let Some(deadline) = deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+            options.max_runtime = Some(left);
+        }
+        let mut numbered = read_wordlist_numbered(wordlist, shard)?;
+        if numbered.is_empty() {
+            continue;
+        }
+        if case_variants {
+            numbered = with_numbered_case_variants(&numbered);
+        }
+        log_tr!(
+            info,
+            "尝试密码表 {} ({} 个密码)",
+            "trying wordlist {} ({} passwords)",
+            wordlist.display(),
+            numbered.len()
+        );
+        let passwords = numbered.iter().map(|(_, p)| p.clone()).collect();
+        match start_extraction_with_options(
+            paths.clone(),
+            passwords,
+            dest.clone(),
+            max_threads,
+            &options,
+        )
+        .await
+        {
+            Outcome::Found(password) => {
+                let line = numbered
+                    .iter()
+                    .find(|(_, p)| p.as_str() == password)
+                    .map(|(line, _)| *line);
+                if let Some(line) = line {
+                    log_tr!(
+                        info,
+                        "密码在第 {} 行，来自 {}",
+                        "the password is on line {} of {}",
+                        line,
+                        wordlist.display()
+                    );
+                }
+                return Ok(Some(WordlistHit {
+                    password,
+                    wordlist: wordlist.clone(),
+                    line,
+                }));
+            }
+            Outcome::NotFound { .. } => {}
+            Outcome::TimedOut { .. } | Outcome::Cancelled { .. } => return Ok(None),
+            Outcome::ExtractFailed { error, .. } => return Err(io::Error::other(error).into()),
+            Outcome::Skipped { reason } => return Err(io::Error::other(reason).into()),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "async")]
+fn promote_password(passwords: &mut Vec<Arc<String>>, password: &str) {
+    if let Some(pos) = passwords.iter().position(|p| p.as_str() == password) {
+        let hit = passwords.remove(pos);
+        passwords.insert(0, hit);
+    }
+}
+
+/// The synchronous counterpart of `start_extraction`, for builds without the
+/// `async` feature: `max_threads` OS threads take passwords from the list in
+/// order until one of them opens an archive, which is then extracted with
+/// `CrackOptions::extract`.
+pub fn crack_password_blocking<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    passwords: &[Arc<String>],
+    dest: &Path,
+    max_threads: usize,
+    options: &CrackOptions,
+) -> Outcome {
+    let variants;
+    let passwords = if options.try_case_variants {
+        variants = with_case_variants(passwords);
+        &variants
+    } else {
+        passwords
+    };
+    let tracker = CrackStateTracker::new(
+        options.state_file.clone(),
+        options.resume_from,
+        passwords.len(),
+    );
+    let next = AtomicUsize::new(tracker.start());
+    let progress = options.progress.clone().unwrap_or_default();
+    let filename_attempts = if options.use_filename_as_password {
+        paths.len() as u64
+    } else {
+        0
+    };
+    progress.begin(Some(
+        (passwords.len() - tracker.start()) as u64 * paths.len() as u64 + filename_attempts,
+    ));
+    if options
+        .cancel
+        .as_ref()
+        .is_some_and(CancelToken::is_cancelled)
+    {
+        return Outcome::Cancelled { tried: 0 };
+    }
+    let threads = threads_within_memory(paths, max_threads, options.max_memory);
+    progress.expect_key_cost(paths, threads);
+    // a file name that opens the archive skips the list, the run then ends
+    // the same way as for a password from it
+    let filename_hit = options
+        .use_filename_as_password
+        .then(|| try_filename_passwords(paths, &progress))
+        .flatten();
+    let skip_list = filename_hit.is_some();
+    let found: Mutex<Option<(String, PathBuf)>> =
+        Mutex::new(filename_hit.map(|(path, password)| (password, path)));
+    let tracker = Mutex::new(tracker);
+    let stop_flag = AtomicBool::new(false);
+    let deadline = options.max_runtime.map(|d| Instant::now() + d);
+    let timed_out = AtomicBool::new(false);
+    let cancelled = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        if skip_list {
+            return;
+        }
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                if options.low_priority {
+                    lower_thread_priority_or_warn();
+                }
+                while !stop_flag.load(Ordering::Relaxed) {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        stop_flag.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    if options
+                        .cancel
+                        .as_ref()
+                        .is_some_and(CancelToken::is_cancelled)
+                    {
+                        cancelled.store(true, Ordering::Relaxed);
+                        stop_flag.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(password) = passwords.get(index) else {
+                        break;
+                    };
+                    let mut finished = true;
+                    for path in paths {
+                        if stop_flag.load(Ordering::Relaxed) {
+                            finished = false;
+                            break;
+                        }
+                        let context = log_context(path.as_ref(), Some(index));
+                        if options.log_passwords {
+                            log_tr!(
+                                debug,
+                                "[{}] 尝试密码: {}",
+                                "[{}] trying password: {}",
+                                context,
+                                password
+                            );
+                        }
+                        progress.record_attempt();
+                        if let Ok(true) = password_opens(path.as_ref(), password, Some(&stop_flag))
+                        {
+                            log_tr!(
+                                info,
+                                "[{}] 找到正确的密码: {}",
+                                "[{}] found the password: {}",
+                                context,
+                                REDACTED_PASSWORD
+                            );
+                            stop_flag.store(true, Ordering::Relaxed);
+                            *found.lock().unwrap() =
+                                Some((password.to_string(), path.as_ref().to_path_buf()));
+                            finished = false;
+                            break;
+                        }
+                    }
+                    if finished {
+                        tracker.lock().unwrap().finish(index);
+                    }
+                }
+            });
+        }
+    });
+
+    let tried = progress.tried();
+    let found = found.into_inner().unwrap();
+    tracker.into_inner().unwrap().end(found.is_some());
+    match found {
+        Some((password, path)) => extract_found(&path, password, dest, &options.extract),
+        None if cancelled.load(Ordering::Relaxed) => Outcome::Cancelled { tried },
+        None if timed_out.load(Ordering::Relaxed) => Outcome::TimedOut { tried },
+        None => Outcome::NotFound { tried },
+    }
+}
+
+/// Finds which of `passwords` opens the archive without writing anything, to
+/// check a wordlist against one representative archive before a big batch.
+/// Each password is checked by decrypting the header and then decoding the
+/// smallest encrypted block into the void, on up to `threads` threads.
+///
+/// An archive that isn't encrypted opens with any password, so the first
+/// one is returned.
+pub fn find_password<P: AsRef<Path> + Sync>(
+    path: P,
+    passwords: &[String],
+    threads: usize,
+) -> Result<Option<String>, CascadeError> {
+    let path = path.as_ref();
+    if encryption_scope(path)? == EncryptionScope::None {
+        return Ok(passwords.first().cloned());
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop_flag = AtomicBool::new(false);
+    let found: Mutex<Result<Option<String>, CascadeError>> = Mutex::new(Ok(None));
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let Some(password) = passwords.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let result = match password_opens(path, password, None) {
+                        Ok(false) => continue,
+                        Ok(true) => Ok(Some(password.clone())),
+                        Err(e) => Err(e),
+                    };
+                    stop_flag.store(true, Ordering::Relaxed);
+                    let mut found = found.lock().unwrap();
+                    if matches!(*found, Ok(None)) {
+                        *found = result;
+                    }
+                }
+            });
+        }
+    });
+
+    found.into_inner().unwrap()
+}
+
+/// Whether `password` opens the archive, whatever its format, e.g. to try a
+/// candidate on a batch mixing 7z and zip files. 7z archives are checked as
+/// in `find_password`, zip files by decrypting their first encrypted file
+/// into the void, so ZipCrypto's one byte check can't let a wrong password
+/// through. Formats without encryption, such as tar, open with any
+/// password. Rar and formats added with `register_extractor` that don't
+/// implement `Extractor::verify_password` fail with `UnsupportedFormat`.
+pub fn verify_password_any<P: AsRef<Path>>(path: P, password: &str) -> Result<bool, CascadeError> {
+    let path = path.as_ref();
+    let mut head = Vec::with_capacity(FULL_SIGNATURE_WINDOW);
+    open_archive_file(path)?
+        .take(FULL_SIGNATURE_WINDOW as u64)
+        .read_to_end(&mut head)?;
+    match detect_format_in_bytes(&head, &ArchiveFormat::ALL) {
+        Some(ArchiveFormat::Custom) => match registered_extractor(&head) {
+            Some(extractor) => extractor.verify_password(path, password),
+            None => Err(CascadeError::UnsupportedFormat {
+                detected: ArchiveFormat::Custom,
+            }),
+        },
+        Some(ArchiveFormat::SevenZ) => password_opens(path, password, None),
+        Some(ArchiveFormat::Zip) => zip_password_opens(path, password),
+        Some(detected @ ArchiveFormat::Rar) => Err(CascadeError::UnsupportedFormat { detected }),
+        Some(
+            ArchiveFormat::Tar | ArchiveFormat::Gzip | ArchiveFormat::Bzip2 | ArchiveFormat::Xz,
+        ) => Ok(true),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a known archive format", path.display()),
+        )
+        .into()),
+    }
+}
+
+/// The archive comment, which sometimes carries a hint for the password,
+/// for showing next to a password prompt. Only zip archives have one that
+/// can be read: `sevenz_rust` skips the comments of 7z archives, and the
+/// other formats have none. `None` as well for an empty comment.
+pub fn password_hint<P: AsRef<Path>>(path: P) -> Result<Option<String>, CascadeError> {
+    let path = path.as_ref();
+    let mut head = Vec::with_capacity(FULL_SIGNATURE_WINDOW);
+    open_archive_file(path)?
+        .take(FULL_SIGNATURE_WINDOW as u64)
+        .read_to_end(&mut head)?;
+    if detect_format_in_bytes(&head, &ArchiveFormat::ALL) != Some(ArchiveFormat::Zip) {
+        return Ok(None);
+    }
+    let zip = zip::ZipArchive::new(open_archive_file(path)?).map_err(zip_error)?;
+    let comment = String::from_utf8_lossy(zip.comment()).trim().to_string();
+    Ok((!comment.is_empty()).then_some(comment))
+}
+
+/// Whether an archive of `format` needs a password, as far as can be told
+/// without one. Archives that can't be read count as not encrypted.
+pub(crate) fn is_encrypted(path: &Path, format: ArchiveFormat) -> bool {
+    match format {
+        ArchiveFormat::SevenZ => {
+            encryption_scope(path).is_ok_and(|scope| scope != EncryptionScope::None)
+        }
+        ArchiveFormat::Zip => open_archive_file(path)
+            .ok()
+            .and_then(|file| zip::ZipArchive::new(file).ok())
+            .and_then(|mut zip| first_encrypted_zip_entry(&mut zip).ok())
+            .flatten()
+            .is_some(),
+        _ => false,
+    }
+}
+
+/// The index of the first encrypted file in a zip archive.
+fn first_encrypted_zip_entry(
+    zip: &mut zip::ZipArchive<BufReader<File>>,
+) -> Result<Option<usize>, CascadeError> {
+    for index in 0..zip.len() {
+        if zip.by_index_raw(index).map_err(zip_error)?.encrypted() {
+            return Ok(Some(index));
+        }
+    }
+    Ok(None)
+}
+
+fn zip_password_opens(path: &Path, password: &str) -> Result<bool, CascadeError> {
+    let mut zip = zip::ZipArchive::new(open_archive_file(path)?).map_err(zip_error)?;
+    let Some(index) = first_encrypted_zip_entry(&mut zip)? else {
+        return Ok(true);
+    };
+    let mut file = match zip.by_index_decrypt(index, password.as_bytes()) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::InvalidPassword) => return Ok(false),
+        Err(e) => return Err(zip_error(e).into()),
+    };
+    // a wrong key that passed the check byte fails the CRC at the end
+    Ok(io::copy(&mut file, &mut io::sink()).is_ok())
+}
+
+/// Every password with the archives it opens, to prune a wordlist down to
+/// the passwords a collection actually uses. Each of the passwords is
+/// checked against each archive as in `find_password`, on up to `threads`
+/// threads. Archives that aren't encrypted are listed under every password;
+/// passwords that open nothing map to an empty list.
+///
+/// The archives of each password are in the order of `paths`.
+pub fn password_coverage(
+    paths: &[PathBuf],
+    passwords: &[String],
+    threads: usize,
+) -> Result<HashMap<String, Vec<PathBuf>>, CascadeError> {
+    let mut encrypted = Vec::with_capacity(paths.len());
+    for path in paths {
+        encrypted.push(encryption_scope(path)? != EncryptionScope::None);
+    }
+
+    // one flag per password and archive, password major
+    let opens: Vec<AtomicBool> = (0..passwords.len() * paths.len())
+        .map(|i| AtomicBool::new(!encrypted[i % paths.len()]))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let error: Mutex<Option<CascadeError>> = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= opens.len() || error.lock().unwrap().is_some() {
+                    break;
+                }
+                let (password, path) = (i / paths.len(), i % paths.len());
+                if !encrypted[path] {
+                    continue;
+                }
+                match password_opens(&paths[path], &passwords[password], None) {
+                    Ok(true) => opens[i].store(true, Ordering::Relaxed),
+                    Ok(false) => {}
+                    Err(e) => {
+                        error.lock().unwrap().get_or_insert(e);
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = error.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    let mut coverage: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for (password_index, password) in passwords.iter().enumerate() {
+        let opened = coverage.entry(password.clone()).or_default();
+        for (path_index, path) in paths.iter().enumerate() {
+            let i = password_index * paths.len() + path_index;
+            if opens[i].load(Ordering::Relaxed) && !opened.contains(path) {
+                opened.push(path.clone());
+            }
+        }
+    }
+    Ok(coverage)
+}
+
+/// Errors that every password would run into, such as an unsupported coder,
+/// are returned; anything else means the password is wrong. Once `stop` is
+/// set the check gives up before the next entry and reports `false`, so a
+/// crack that timed out or was cancelled doesn't wait for a large block.
+fn password_opens(
+    path: &Path,
+    password: &str,
+    stop: Option<&AtomicBool>,
+) -> Result<bool, CascadeError> {
+    let stopped = || stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+    if stopped() {
+        return Ok(false);
+    }
+    let mut file = open_archive_file(path)?;
+    let len = file.get_ref().metadata()?.len();
+    let password = sevenz_rust::Password::from(password);
+    let archive = match catch_backend_panic(path, || {
+        Ok(sevenz_rust::Archive::read(
+            &mut file,
+            len,
+            password.as_slice(),
+        ))
+    })? {
+        Ok(archive) => archive,
+        Err(e) => return wrong_password_or(e),
+    };
+
+    let Some(block) =
+        encrypted_blocks(&archive).min_by_key(|&block| archive.folders[block].get_unpack_size())
+    else {
+        // only the header was encrypted
+        return Ok(true);
+    };
+    let decoder = sevenz_rust::BlockDecoder::new(block, &archive, password.as_slice(), &mut file);
+    // the CRC is only checked once an entry is read to the end, and garbage
+    // sometimes decodes to a stream that just ends early
+    let mut complete = true;
+    let result = catch_backend_panic(path, || {
+        Ok(decoder.for_each_entries(&mut |entry, data| {
+            complete = !stopped() && io::copy(data, &mut io::sink())? == entry.size();
+            Ok(complete)
+        }))
+    })?;
+    match result {
+        Ok(_) => Ok(complete),
+        Err(e) => wrong_password_or(e),
+    }
+}
+
+fn wrong_password_or(e: sevenz_rust::Error) -> Result<bool, CascadeError> {
+    match CascadeError::from(e) {
+        e @ (CascadeError::UnsupportedCoder { .. } | CascadeError::UnsupportedVersion { .. }) => {
+            Err(e)
+        }
+        _ => Ok(false),
+    }
+}
+
+pub fn extract_to_temp_folder<P: AsRef<Path> + Send + Sync>(
+    path: P,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let temp_dir = env::temp_dir().join("cascading-extract");
+    let temp_dir_path = temp_dir.to_path_buf();
+    std::fs::create_dir_all(&temp_dir_path)?;
+    try_extract_7z_with_password(&path, "", &temp_dir_path)?;
+    Ok(temp_dir_path)
+}
+
+/// Extracts into a new, empty folder under `parent` and returns that folder.
+/// Unlike `extract_to_temp_folder`, concurrent calls never share a folder,
+/// so the caller can remove it once done.
+pub fn extract_to_temp_folder_in<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    parent: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let stem = path
+        .as_ref()
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_dir = parent.join(format!(
+        "{}-{}-{}",
+        stem,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&temp_dir)?;
+
+    if let Err(e) = try_extract_7z_with_password(&path, password, &temp_dir) {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        return Err(e);
+    }
+    Ok(temp_dir)
+}
+
+/// Re-encrypts an archive: extracts it with `old_password` and compresses
+/// the contents into `out_path` with `new_password`. An empty new password
+/// produces an unencrypted archive. The temporary copy is removed in any
+/// case.
+pub fn repackage_7z<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    old_password: &str,
+    new_password: &str,
+    out_path: Q,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let temp_root = env::temp_dir().join("cascading-extract-repackage");
+    let temp_dir = extract_to_temp_folder_in(&path, old_password, &temp_root)?;
+    let result = sevenz_rust::compress_to_path_encrypted(&temp_dir, out_path, new_password.into());
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    Ok(result?)
+}
+
+/// How the entries of two archives differ, each list sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveDiff {
+    /// Entries only in the second archive.
+    pub added: Vec<String>,
+    /// Entries only in the first archive.
+    pub removed: Vec<String>,
+    /// Entries in both whose size or CRC differ, or that are a file in one
+    /// and a folder in the other.
+    pub changed: Vec<String>,
+}
+
+impl ArchiveDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the entry lists of archives `a` and `b` by name, size and CRC,
+/// e.g. to check that `repackage_7z` kept every file. Only the headers are
+/// read; CRCs are compared when both entries store one. The nameless root
+/// folder entry `sevenz_rust` writes when compressing a folder is ignored.
+pub fn diff_archives<P: AsRef<Path>, Q: AsRef<Path>>(
+    a: P,
+    b: Q,
+    password_a: &str,
+    password_b: &str,
+) -> Result<ArchiveDiff, CascadeError> {
+    let named = |entries: Vec<sevenz_rust::SevenZArchiveEntry>| {
+        entries
+            .into_iter()
+            .filter(|entry| !entry.name().is_empty())
+            .collect::<Vec<_>>()
+    };
+    let entries_a = named(read_archive_entries_with_password(a, password_a)?);
+    let entries_b = named(read_archive_entries_with_password(b, password_b)?);
+    let by_name: HashMap<&str, &sevenz_rust::SevenZArchiveEntry> = entries_b
+        .iter()
+        .map(|entry| (entry.name(), entry))
+        .collect();
+    let names_a: HashSet<&str> = entries_a.iter().map(|entry| entry.name()).collect();
+
+    let mut diff = ArchiveDiff::default();
+    for entry in &entries_a {
+        match by_name.get(entry.name()) {
+            None => diff.removed.push(entry.name().to_string()),
+            Some(other) => {
+                let crc_differs = entry.has_crc && other.has_crc && entry.crc != other.crc;
+                if entry.is_directory() != other.is_directory()
+                    || entry.size() != other.size()
+                    || crc_differs
+                {
+                    diff.changed.push(entry.name().to_string());
+                }
+            }
+        }
+    }
+    diff.added = entries_b
+        .iter()
+        .filter(|entry| !names_a.contains(entry.name()))
+        .map(|entry| entry.name().to_string())
+        .collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    Ok(diff)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(rename = "config")]
+    pub config: ConfigSettings,
+    #[serde(rename = "user")]
+    pub user: UserConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigSettings {
+    pub delete_archive: bool,
+    pub recursive_search: bool,
+    /// Either a count or a share of the CPUs such as `"75%"`, resolved to a
+    /// count when the config is read. Left out, `read_config` picks one for
+    /// `dest`, see `default_threads_for`.
+    #[serde(default = "default_threads", deserialize_with = "deserialize_threads")]
+    pub threads: u8,
+    pub dest: String,
+    pub smart_mode: bool,
+    #[serde(default)]
+    pub smart_mode_policy: SmartModePolicy,
+    /// Smart mode wraps archives with more than this many files or folders
+    /// in their root.
+    #[serde(default = "default_smart_mode_threshold")]
+    pub smart_mode_threshold: u32,
+    #[serde(default)]
+    pub folder_collision: FolderCollisionPolicy,
+    #[serde(default)]
+    pub adaptive_password_order: bool,
+    #[serde(default)]
+    pub symlink_policy: SymlinkPolicy,
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    #[serde(default = "default_max_compression_ratio")]
+    pub max_compression_ratio: f64,
+    #[serde(default)]
+    pub allow_high_ratio: bool,
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    #[serde(default)]
+    pub max_memory: Option<u64>,
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    /// Whether `extract_cascading` goes on to extract the archives found
+    /// inside; with `false` they are left as regular files.
+    #[serde(default = "default_cascade")]
+    pub cascade: bool,
+    /// See `extract_cascading`.
+    #[serde(default = "default_max_cascade_depth")]
+    pub max_cascade_depth: usize,
+    #[serde(default)]
+    pub max_total_extracted_bytes: Option<u64>,
+    /// See `ExtractOptions::allows_extension`.
+    #[serde(default)]
+    pub allow_extensions: Vec<String>,
+    #[serde(default)]
+    pub skip_extensions: Vec<String>,
+    #[serde(default)]
+    pub cleanup_on_failure: bool,
+    #[serde(default)]
+    pub atomic_extraction: bool,
+    #[serde(default)]
+    pub overwrite_policy: OverwritePolicy,
+    #[serde(default)]
+    pub normalize_case: Option<CaseMode>,
+    #[serde(default)]
+    pub continue_on_entry_error: bool,
+    #[serde(default)]
+    pub entry_name_encoding: Option<String>,
+    #[serde(default = "default_normalize_separators")]
+    pub normalize_separators: bool,
+    /// See `ExtractOptions::files_only`.
+    #[serde(default)]
+    pub files_only: bool,
+    #[serde(default)]
+    pub attribute_policy: AttributePolicy,
+    #[serde(default)]
+    pub incremental: bool,
+    /// See `ExtractOptions::resume`.
+    #[serde(default)]
+    pub resume: bool,
+    /// See `ExtractOptions::allow_external_7z`.
+    #[serde(default)]
+    pub allow_external_7z: bool,
+    #[serde(default = "default_enabled_formats")]
+    pub enabled_formats: Vec<ArchiveFormat>,
+    #[serde(default = "default_signature_window")]
+    pub signature_window: usize,
+    /// See `set_signature_cache_size`.
+    #[serde(default = "default_signature_cache_size")]
+    pub signature_cache_size: usize,
+    /// See `set_read_buffer_size`.
+    #[serde(default = "default_read_buffer_size")]
+    pub read_buffer_size: usize,
+    #[serde(default = "default_watch_interval_ms")]
+    pub watch_interval_ms: u64,
+    /// How many archives the watcher extracts at the same time, each with
+    /// its own `threads`. At least 1.
+    #[serde(default = "default_max_concurrent_archives")]
+    pub max_concurrent_archives: usize,
+    /// See `set_max_open_files`.
+    #[serde(default)]
+    pub max_open_files: usize,
+    #[serde(default = "default_ignore_hidden")]
+    pub ignore_hidden: bool,
+    /// Overrides the `LANG` based choice, see `LogLanguage::from_env`.
+    #[serde(default)]
+    pub log_language: Option<LogLanguage>,
+    #[serde(default)]
+    pub log_passwords: bool,
+    /// See `enable_log_file`.
+    #[serde(default)]
+    pub log_file: bool,
+    #[serde(default = "default_log_file_max_bytes")]
+    pub log_file_max_bytes: u64,
+    #[serde(default)]
+    pub use_filename_as_password: bool,
+    #[serde(default)]
+    pub try_case_variants: bool,
+    #[serde(default)]
+    pub low_priority: bool,
+    #[serde(default)]
+    pub report_rate: bool,
+    #[serde(default)]
+    pub max_runtime_secs: Option<u64>,
+    /// Tried in order by the watcher once the `passwords` setting failed.
+    #[serde(default)]
+    pub wordlists: Vec<String>,
+    /// Shell command run after each successful extraction, see
+    /// `run_post_command`.
+    #[serde(default)]
+    pub post_command: Option<String>,
+    /// Writes an `ActionLog` for each archive to `action_log_path`.
+    #[serde(default)]
+    pub write_action_log: bool,
+    #[serde(default)]
+    pub shard_index: usize,
+    #[serde(default = "default_shard_count")]
+    pub shard_count: usize,
+}
+
+impl ConfigSettings {
+    /// Detects the archive format using the configured signature window,
+    /// only considering `enabled_formats`.
+    pub fn detect_archive_format<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<Option<ArchiveFormat>> {
+        detect_archive_format_with(path, self.detection_window(), &self.enabled_formats)
+    }
+
+    /// `signature_window`, widened to `FULL_SIGNATURE_WINDOW` while tar is
+    /// enabled, as its magic sits at offset 257.
+    pub fn detection_window(&self) -> usize {
+        match self.enabled_formats.contains(&ArchiveFormat::Tar) {
+            true => self.signature_window.max(FULL_SIGNATURE_WINDOW),
+            false => self.signature_window,
+        }
+    }
+
+    pub fn shard(&self) -> Result<Shard, CascadeError> {
+        Shard::new(self.shard_index, self.shard_count)
+    }
+}
+
+/// What a config without `threads` gets when deserialized on its own;
+/// `read_config` replaces it with `default_threads_for`.
+fn default_threads() -> u8 {
+    recommended_threads() as u8
+}
+
+/// `recommended_threads_for` the folder archives will be extracted to:
+/// `dest`, or with an empty one, next to them in the first watch folder.
+/// Placeholders don't matter, `is_rotational` looks at the nearest folder
+/// that exists.
+pub(crate) fn default_threads_for(config: &Config) -> u8 {
+    let dest = match config.config.dest.as_str() {
+        "" | "." => config
+            .user
+            .watch_folders
+            .as_deref()
+            .and_then(|folders| folders.first())
+            .map_or(".", |folder| folder.path.as_str()),
+        dest => dest,
+    };
+    recommended_threads_for(Path::new(dest)) as u8
+}
+
+/// `threads` as written in the config.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ThreadsSetting {
+    Count(u8),
+    Share(String),
+}
+
+impl ThreadsSetting {
+    fn resolve(self) -> Result<u8, String> {
+        let share = match self {
+            ThreadsSetting::Count(count) => return Ok(count),
+            ThreadsSetting::Share(share) => share,
+        };
+        share
+            .trim()
+            .strip_suffix('%')
+            .and_then(|percent| percent.trim().parse::<f64>().ok())
+            .filter(|percent| percent.is_finite() && *percent > 0.0)
+            .map(|percent| threads_for_share(percent) as u8)
+            .ok_or_else(|| {
+                format!("invalid threads {share:?}, expected a number or a share like \"75%\"")
+            })
+    }
+}
+
+fn deserialize_threads<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    ThreadsSetting::deserialize(deserializer)?
+        .resolve()
+        .map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn deserialize_optional_threads<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u8>, D::Error> {
+    deserialize_threads(deserializer).map(Some)
+}
+
+/// Whether `threads` is within 1 and `max_threads`; other values are
+/// replaced by `default_threads_for` when the config is read.
+fn valid_threads(threads: u8) -> bool {
+    (1..=max_threads()).contains(&(threads as usize))
+}
+
+fn default_read_buffer_size() -> usize {
+    DEFAULT_READ_BUFFER_SIZE
+}
+
+fn default_log_file_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_signature_cache_size() -> usize {
+    DEFAULT_SIGNATURE_CACHE_SIZE
+}
+
+fn default_signature_window() -> usize {
+    DEFAULT_SIGNATURE_WINDOW
+}
+
+fn default_watch_interval_ms() -> u64 {
+    2000
+}
+
+fn default_max_concurrent_archives() -> usize {
+    1
+}
+
+fn default_ignore_hidden() -> bool {
+    true
+}
+
+fn default_shard_count() -> usize {
+    1
+}
+
+fn default_cascade() -> bool {
+    true
+}
+
+fn default_smart_mode_threshold() -> u32 {
+    DEFAULT_SMART_MODE_THRESHOLD
+}
+
+fn default_max_cascade_depth() -> usize {
+    5
+}
+
+fn default_max_compression_ratio() -> f64 {
+    DEFAULT_MAX_COMPRESSION_RATIO
+}
+
+fn default_normalize_separators() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserConfig {
+    pub passwords: Option<Vec<String>>,
+    pub watch_folders: Option<Vec<WatchFolder>>,
+}
+
+/// Trims the configured passwords and drops blank entries and entries
+/// starting with `#`, which can be used to annotate the list. An entry
+/// `@path` is replaced by the passwords in that wordlist file, in place;
+/// `@@` stands for a password that really starts with `@`. A relative path
+/// is taken from `base`, the folder of the config file, and may hold the
+/// wildcards of `resolve_paths`, e.g. `@wordlists/*.txt` for every list in
+/// that folder in name order. A plain path that doesn't exist is an error,
+/// a pattern matching nothing is only logged.
+fn normalize_passwords(passwords: Vec<String>, base: &Path) -> Result<Vec<String>, CascadeError> {
+    let mut normalized = Vec::with_capacity(passwords.len());
+    for entry in &passwords {
+        let Some(password) = wordlist::password_from_line(entry) else {
+            continue;
+        };
+        let file = match password.strip_prefix('@') {
+            Some(escaped) if escaped.starts_with('@') => {
+                normalized.push(escaped.to_string());
+                continue;
+            }
+            Some(file) => base.join(file.trim()),
+            None => {
+                normalized.push(password.to_string());
+                continue;
+            }
+        };
+        let files: Vec<PathBuf> = match file.iter().any(has_wildcard) {
+            true => expand_pattern(&file.to_string_lossy())?
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect(),
+            false => vec![file.clone()],
+        };
+        if files.is_empty() {
+            log_tr!(
+                warn,
+                "{} 没有匹配到任何密码文件",
+                "{} matches no wordlists",
+                file.display()
+            );
+        }
+        for file in files {
+            normalized.extend(
+                read_wordlist(&file, Shard::ALL)?
+                    .into_iter()
+                    .map(|p| p.to_string()),
+            );
+        }
+    }
+    Ok(normalized)
+}
+
+/// The `settings.toml` shipped with the program, every setting explained and
+/// the optional ones commented out. `read_config` falls back to it.
+pub const DEFAULT_CONFIG: &str = include_str!("../settings.toml");
+
+/// Writes `DEFAULT_CONFIG` to `path` as a template to start from. An existing
+/// file is never overwritten and fails with `AlreadyExists`.
+pub fn write_default_config<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let mut file = File::options().write(true).create_new(true).open(path)?;
+    io::Write::write_all(&mut file, DEFAULT_CONFIG.as_bytes())
+}
+
+/// `read_config_from` for `settings.toml` in the current folder.
+pub fn read_config() -> Result<Config, Box<dyn std::error::Error + Send + Sync>> {
+    read_config_from("settings.toml")
+}
+
+/// Reads the config at `path`. A missing file isn't an error on a first
+/// run: the defaults of `DEFAULT_CONFIG` are used with a warning.
+pub fn read_config_from<P: AsRef<Path>>(
+    path: P,
+) -> Result<Config, Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    let settings_content = match File::open(path) {
+        Ok(settings_file) => {
+            let mut buf_reader = BufReader::new(settings_file);
+            let mut settings_content = String::new();
+            buf_reader.read_to_string(&mut settings_content)?;
+            settings_content
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log_tr!(
+                warn,
+                "找不到配置文件 {}, 使用默认设置",
+                "{} not found, using the default settings",
+                path.display()
+            );
+            DEFAULT_CONFIG.to_string()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut settings: Config = toml::from_str(&settings_content)?;
+
+    if let Some(passwords) = settings.user.passwords.take() {
+        let base = path.parent().unwrap_or(Path::new(""));
+        settings.user.passwords = Some(normalize_passwords(passwords, base)?);
+    }
+
+    let threads_given = toml::from_str::<toml::Table>(&settings_content)
+        .ok()
+        .is_some_and(|table| table.get("config").and_then(|c| c.get("threads")).is_some());
+    if !threads_given || !valid_threads(settings.config.threads) {
+        settings.config.threads = default_threads_for(&settings);
+    }
+    if let Some(language) = settings.config.log_language {
+        set_log_language(language);
+    }
+    set_read_buffer_size(settings.config.read_buffer_size);
+    set_max_open_files(settings.config.max_open_files);
+    set_max_bytes_per_sec(settings.config.max_bytes_per_sec.unwrap_or(0));
+    set_signature_cache_size(settings.config.signature_cache_size);
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use sevenz_rust::Password;
+
+    use super::*;
+
+    /// A fresh, empty scratch directory for tests that write to disk.
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join("cascading-extract-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_read_config() {
+        let config = read_config().unwrap();
+        assert_eq!(config.config.delete_archive, true);
+        assert_eq!(config.config.recursive_search, true);
+        assert_eq!(config.config.threads, 4);
+        assert_eq!(config.config.dest, "".to_string());
+        assert_eq!(config.config.smart_mode, true);
+        assert_eq!(config.config.smart_mode_policy, SmartModePolicy::CountBased);
+        assert_eq!(config.config.smart_mode_threshold, 1);
+        assert_eq!(config.config.adaptive_password_order, true);
+        assert_eq!(config.config.symlink_policy, SymlinkPolicy::Create);
+        assert_eq!(config.config.max_bytes_per_sec, None);
+        assert_eq!(config.config.max_compression_ratio, 100.0);
+        assert_eq!(config.config.allow_high_ratio, false);
+        assert_eq!(config.config.max_entries, Some(100000));
+        assert_eq!(config.config.cleanup_on_failure, true);
+        assert_eq!(config.config.atomic_extraction, false);
+        assert_eq!(config.config.overwrite_policy, OverwritePolicy::Overwrite);
+        assert_eq!(config.config.normalize_case, None);
+        assert_eq!(config.config.continue_on_entry_error, false);
+        assert_eq!(config.config.entry_name_encoding, None);
+        assert_eq!(config.config.normalize_separators, true);
+        assert_eq!(config.config.files_only, false);
+        assert_eq!(config.config.attribute_policy, AttributePolicy::Clear);
+        assert_eq!(config.config.enabled_formats, ArchiveFormat::ALL.to_vec());
+        assert_eq!(config.config.signature_window, 16);
+        assert_eq!(
+            config.config.signature_cache_size,
+            DEFAULT_SIGNATURE_CACHE_SIZE
+        );
+        assert_eq!(config.config.watch_interval_ms, 2000);
+        assert_eq!(config.config.max_concurrent_archives, 1);
+        assert_eq!(config.config.max_open_files, 0);
+        assert_eq!(config.config.ignore_hidden, true);
+        assert_eq!(config.config.shard().unwrap(), Shard::ALL);
+        assert_eq!(config.config.log_language, None);
+        assert_eq!(config.config.post_command, None);
+        assert_eq!(config.config.write_action_log, false);
+        assert_eq!(config.config.incremental, false);
+        assert_eq!(config.config.resume, false);
+        assert_eq!(config.config.allow_external_7z, false);
+        assert_eq!(config.config.max_memory, None);
+        assert_eq!(config.config.max_file_size, None);
+        assert!(config.config.cascade);
+        assert_eq!(config.config.max_cascade_depth, 5);
+        assert_eq!(config.config.max_total_extracted_bytes, None);
+        assert!(config.config.allow_extensions.is_empty());
+        assert!(config.config.skip_extensions.is_empty());
+        assert!(config.config.wordlists.is_empty());
+        assert_eq!(config.config.use_filename_as_password, false);
+        assert_eq!(config.config.try_case_variants, false);
+        assert_eq!(config.config.low_priority, false);
+        assert_eq!(config.config.folder_collision, FolderCollisionPolicy::Merge);
+        assert_eq!(config.config.log_passwords, false);
+        assert_eq!(config.config.log_file, false);
+        assert_eq!(config.config.log_file_max_bytes, 10485760);
+        assert_eq!(config.config.read_buffer_size, DEFAULT_READ_BUFFER_SIZE);
+        assert_eq!(config.config.report_rate, false);
+        assert_eq!(config.config.max_runtime_secs, None);
+        assert_eq!(config.user.passwords.unwrap()[0], "1151".to_string());
+        assert_eq!(config.user.watch_folders.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_is_complete_archive() {
+        let dir = test_dir("is_complete_archive");
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = std::fs::read("tests/sample.7z").unwrap();
+        assert!(is_complete_archive("tests/sample.7z").unwrap());
+        assert!(is_complete_archive("tests/7ziplogo_p.7z").unwrap());
+        assert!(is_complete_archive("tests/7ziplogo.png").unwrap());
+
+        let truncated = dir.join("truncated.7z");
+        std::fs::write(&truncated, &data[..data.len() - 10]).unwrap();
+        assert!(!is_complete_archive(&truncated).unwrap());
+        std::fs::write(&truncated, &data[..3]).unwrap();
+        assert!(!is_complete_archive(&truncated).unwrap());
+
+        // the full length is there, but the tail is still zeros
+        let preallocated = dir.join("preallocated.7z");
+        let mut zeroed = data.clone();
+        let half = zeroed.len() / 2;
+        zeroed[half..].fill(0);
+        std::fs::write(&preallocated, zeroed).unwrap();
+        assert!(!is_complete_archive(&preallocated).unwrap());
+    }
+
+    #[test]
+    fn test_missing_config() {
+        let dir = test_dir("missing_config");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        let config = read_config_from(&path).unwrap();
+        assert_eq!(config.config.dest, "");
+        assert!(config.user.passwords.is_some());
+        assert!(!path.exists());
+
+        write_default_config(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+        assert_eq!(
+            write_default_config(&path).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(read_config_from(&path).unwrap().config.threads, 4);
+    }
+
+    #[test]
+    fn test_threads_setting() {
+        let parse = |value: &str| {
+            toml::from_str::<PartialConfigSettings>(&format!("threads = {value}"))
+                .map(|settings| settings.threads.unwrap())
+        };
+        assert_eq!(parse("4").unwrap(), 4);
+        assert_eq!(
+            parse("\"100%\"").unwrap() as usize,
+            threads_for_share(100.0)
+        );
+        assert_eq!(parse("\" 1 % \"").unwrap(), 1);
+        assert!(parse("\"75\"").is_err());
+        assert!(parse("\"0%\"").is_err());
+        assert!(parse("\"-50%\"").is_err());
+        assert!(valid_threads(MAX_THREADS as u8));
+        assert!(!valid_threads(0));
+
+        // judged for the destination, its placeholders left to the ancestors
+        let mut config: Config = toml::from_str(DEFAULT_CONFIG).unwrap();
+        config.config.dest = "tests/{year}".to_string();
+        assert_eq!(
+            default_threads_for(&config) as usize,
+            recommended_threads_for(Path::new("tests"))
+        );
+    }
+
+    #[test]
+    fn test_normalize_passwords() {
+        let passwords = ["# site A", " 1151 ", "", "#old", "password"]
+            .map(str::to_string)
+            .to_vec();
+        assert_eq!(
+            normalize_passwords(passwords, Path::new("")).unwrap(),
+            ["1151", "password"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_passwords_file_references() {
+        let dir = test_dir("normalize_passwords_file_references");
+        std::fs::create_dir_all(&dir).unwrap();
+        let wordlist = dir.join("common.txt");
+        std::fs::write(&wordlist, "# common\n123456\nqwerty\n").unwrap();
+
+        let passwords = vec![
+            "1151".to_string(),
+            format!("@{}", wordlist.display()),
+            "@@home".to_string(),
+        ];
+        assert_eq!(
+            normalize_passwords(passwords, Path::new("")).unwrap(),
+            ["1151", "123456", "qwerty", "@home"]
+        );
+        let missing = vec![format!("@{}", dir.join("missing.txt").display())];
+        assert!(normalize_passwords(missing, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_normalize_passwords_wildcards() {
+        let dir = test_dir("normalize_passwords_wildcards");
+        std::fs::create_dir_all(dir.join("lists")).unwrap();
+        std::fs::write(dir.join("lists/b.txt"), "qwerty\n").unwrap();
+        std::fs::write(dir.join("lists/a.txt"), "123456\n").unwrap();
+        std::fs::write(dir.join("lists/notes.md"), "not a password\n").unwrap();
+        let settings = dir.join("settings.toml");
+        let content = DEFAULT_CONFIG.replacen(
+            "passwords = [",
+            "passwords = [\"@lists/*.txt\", \"@lists/*.rar\",",
+            1,
+        );
+        std::fs::write(&settings, content).unwrap();
+
+        // relative to the config file, not the current folder
+        let passwords = read_config_from(&settings).unwrap().user.passwords.unwrap();
+        assert_eq!(passwords[..3], ["123456", "qwerty", "1151"]);
+    }
+
+    /// A hand-written archive whose single file is stored with Deflate,
+    /// which `sevenz_rust` can list but not decode.
+    fn deflate_archive(path: &Path) {
+        fn crc32(data: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for &byte in data {
+                crc ^= byte as u32;
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        }
+
+        let packed = b"hello";
+        #[rustfmt::skip]
+        let mut header = vec![
+            0x01, // header
+            0x04, // main streams info
+            0x06, 0x00, 0x01, 0x09, packed.len() as u8, 0x00, // pack info
+            0x07, 0x0b, 0x01, 0x00, // unpack info, one folder
+            0x01, 0x03, 0x04, 0x01, 0x08, // one simple coder: Deflate
+            0x0c, packed.len() as u8, 0x00, // unpacked size
+            0x08, 0x00, // substreams info, one stream per folder
+            0x00, // end of streams info
+            0x05, 0x01, // files info, one file
+            0x11, 13, 0x00, // names, not external
+        ];
+        header.extend("a.txt\0".encode_utf16().flat_map(u16::to_le_bytes));
+        header.extend([0x00, 0x00]);
+
+        let mut start_header = Vec::new();
+        start_header.extend((packed.len() as u64).to_le_bytes());
+        start_header.extend((header.len() as u64).to_le_bytes());
+        start_header.extend(crc32(&header).to_le_bytes());
+
+        let mut file = vec![b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, 0x00, 0x04];
+        file.extend(crc32(&start_header).to_le_bytes());
+        file.extend(start_header);
+        file.extend(packed);
+        file.extend(header);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, file).unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_coder() {
+        let dir = test_dir("unsupported_coder");
+        let archive = dir.join("deflate.7z");
+        deflate_archive(&archive);
+        assert_eq!(read_7z_contents(&archive).unwrap()[0].name(), "a.txt");
+
+        let is_unsupported = |e: &CascadeError| matches!(e, CascadeError::UnsupportedCoder { coder } if coder == "DEFLATE");
+        let err = extract_with_options(&archive, "", &dir.join("out"), &ExtractOptions::default())
+            .unwrap_err();
+        assert!(is_unsupported(&err), "{}", err);
+        let err = try_extract_7z_with_password(&archive, "", &dir.join("out")).unwrap_err();
+        assert!(is_unsupported(err.downcast_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_find_entry() {
+        let entry = find_entry("tests/sample.7z", |e| e.name().ends_with(".txt"))
+            .unwrap()
+            .unwrap();
+        let files = read_7z_contents("tests/sample.7z").unwrap();
+        assert_eq!(files[entry.index].name(), entry.name);
+        assert!(!entry.is_directory);
+        assert_eq!(
+            find_entry("tests/sample.7z", |e| e.name() == "missing").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        // two files called a.txt, "first" and then "second"
+        let files = read_7z_contents("tests/duplicates.7z").unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(duplicate_names(&files), ["a.txt"]);
+        assert!(duplicate_names(&read_7z_contents("tests/sample.7z").unwrap()).is_empty());
+
+        let dir = test_dir("duplicate_names");
+        for (policy, kept) in [
+            (OverwritePolicy::Overwrite, "second"),
+            (OverwritePolicy::Skip, "first"),
+            (OverwritePolicy::Rename, "first"),
+        ] {
+            let dest = dir.join(format!("{:?}", policy));
+            let options = ExtractOptions {
+                overwrite_policy: policy,
+                ..Default::default()
+            };
+            let stats = extract_with_options("tests/duplicates.7z", "", &dest, &options).unwrap();
+            assert_eq!(stats.duplicates, ["a.txt"]);
+            assert_eq!(std::fs::read_to_string(dest.join("a.txt")).unwrap(), kept);
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("Rename/a (1).txt")).unwrap(),
+            "second"
+        );
+    }
+
+    #[test]
+    fn test_verify_password_any() {
+        assert!(verify_password_any("tests/7ziplogo_p.7z", "1151").unwrap());
+        assert!(!verify_password_any("tests/7ziplogo_p.7z", "0000").unwrap());
+        assert!(verify_password_any("tests/sample.7z", "anything").unwrap());
+        // ZipCrypto, made with `zip -P 1151`
+        assert!(verify_password_any("tests/zipcrypto.zip", "1151").unwrap());
+        for wrong in ["0000", "1152", "", "password"] {
+            assert!(!verify_password_any("tests/zipcrypto.zip", wrong).unwrap());
+        }
+        assert!(verify_password_any("tests/7ziplogo.png", "").is_err());
+    }
+
+    #[test]
+    fn test_password_hint() {
+        use std::io::Write;
+
+        let dir = test_dir("password_hint");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hint.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.set_comment("password: the year it was made\n");
+        zip.start_file("a.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"a").unwrap();
+        zip.finish().unwrap();
+
+        assert_eq!(
+            password_hint(&path).unwrap().as_deref(),
+            Some("password: the year it was made")
+        );
+        assert_eq!(password_hint("tests/zipcrypto.zip").unwrap(), None);
+        assert_eq!(password_hint("tests/7ziplogo_p.7z").unwrap(), None);
+        assert!(password_hint("tests/missing.zip").is_err());
+    }
+
+    #[test]
+    fn test_read_buffer_size() {
+        // other tests run meanwhile, any size has to work for them too
+        set_read_buffer_size(16);
+        assert_eq!(read_7z_contents("tests/sample.7z").unwrap().len(), 5);
+        let dest = test_dir("read_buffer_size");
+        let stats =
+            extract_with_options("tests/sample.7z", "", &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!(stats.files, 3);
+        set_read_buffer_size(0);
+        assert_eq!(read_buffer_size(), DEFAULT_READ_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn test_backend_panic() {
+        let dir = test_dir("backend_panic");
+        std::fs::create_dir_all(&dir).unwrap();
+        // makes the LZMA range decoder of `lzma-rust` index past its buffer
+        let mut data = std::fs::read("tests/sample.7z").unwrap();
+        for (offset, byte) in [(35, 0x06), (2120, 0x92), (2341, 0x42), (2486, 0x4c)] {
+            data[offset] = byte;
+        }
+        let path = dir.join("panics.7z");
+        std::fs::write(&path, data).unwrap();
+
+        let is_backend_panic =
+            |e: &CascadeError| matches!(e, CascadeError::BackendPanic { path: p } if *p == path);
+        let err = extract_with_options(&path, "", &dir.join("out"), &ExtractOptions::default())
+            .unwrap_err();
+        assert!(is_backend_panic(&err), "{}", err);
+        let err = try_extract_7z_with_password(&path, "", &dir.join("out")).unwrap_err();
+        assert!(is_backend_panic(err.downcast_ref().unwrap()));
+
+        // every other way of decoding an archive is guarded as well
+        assert!(is_backend_panic(&verify_archive(&path, "").unwrap_err()));
+        let err = extract_entry_to_bytes(&path, "", "7zFormat.txt").unwrap_err();
+        assert!(is_backend_panic(&err));
+        let err = extract_indices(&path, "", &dir.join("out"), &[0, 1, 2]).unwrap_err();
+        assert!(is_backend_panic(&err));
+        let mut sink = MemorySink::default();
+        assert!(is_backend_panic(
+            &extract_to_sink(&path, "", &mut sink).unwrap_err()
+        ));
+        let err = convert_7z_to_zip(&path, "", dir.join("out.zip")).unwrap_err();
+        assert!(is_backend_panic(&err));
+        let data = std::fs::read(&path).unwrap();
+        let err = try_extract_7z_from_bytes(&data, "", &dir.join("out")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref().unwrap(),
+            CascadeError::BackendPanic { .. }
+        ));
+    }
+
+    #[test]
+    fn test_list_root_entries() {
+        let dir = test_dir("list_root_entries");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tree.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&path).unwrap();
+        for name in [
+            "docs/a.txt",
+            "docs/deep/b.txt",
+            "readme.txt",
+            "docs",
+            "src\\main.rs",
+        ] {
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.is_directory = name == "docs";
+            entry.has_stream = !entry.is_directory;
+            let data = (!entry.is_directory).then_some(&b"data"[..]);
+            writer.push_archive_entry(entry, data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let roots = list_root_entries(&path).unwrap();
+        let names: Vec<&str> = roots.iter().map(|root| root.name.as_str()).collect();
+        assert_eq!(names, ["docs", "readme.txt", "src"]);
+        assert!(roots[0].is_directory);
+        assert_eq!(roots[0].index, 3);
+        assert_eq!(roots[1].size, 4);
+        assert!(!roots[1].is_directory);
+        assert!(roots[2].is_directory);
+        assert_eq!(roots[2].size, 0);
+    }
+
+    #[test]
+    fn test_extract_entry_to_bytes() {
+        let content = extract_entry_to_bytes("tests/sample.7z", "", "7zFormat.txt")
+            .unwrap()
+            .unwrap();
+        let dest = test_dir("extract_entry_to_bytes");
+        try_extract_7z_with_password("tests/sample.7z", "", &dest).unwrap();
+        assert_eq!(content, std::fs::read(dest.join("7zFormat.txt")).unwrap());
+        assert_eq!(
+            extract_entry_to_bytes("tests/sample.7z", "", "missing").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_single_to_writer() {
+        let mut out = Vec::new();
+        let written = extract_single_to_writer("tests/7ziplogo.7z", "", &mut out).unwrap();
+        assert_eq!(written, out.len() as u64);
+        assert_eq!(out, std::fs::read("tests/7ziplogo.png").unwrap());
+
+        let mut out = Vec::new();
+        assert!(extract_single_to_writer("tests/sample.7z", "", &mut out).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_errors_cross_tasks() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<CascadeError>();
+        let listed = tokio::spawn(async { read_7z_contents("tests/missing.7z") })
+            .await
+            .unwrap();
+        assert!(listed.is_err());
+        let config = tokio::spawn(async { read_config_from("tests/7zFormat.txt") })
+            .await
+            .unwrap();
+        assert!(config.is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_probe_encryption() {
+        let paths: Vec<PathBuf> = [
+            "tests/7ziplogo.7z",
+            "tests/7ziplogo_p.7z",
+            "tests/missing.7z",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let results = probe_encryption(&paths, 2).await;
+        assert_eq!(
+            results.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            paths.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(*results[0].1.as_ref().unwrap(), EncryptionScope::None);
+        assert_eq!(
+            *results[1].1.as_ref().unwrap(),
+            EncryptionScope::HeaderAndContent
+        );
+        assert!(matches!(results[2].1, Err(CascadeError::Io(_))));
+    }
+
+    #[test]
+    fn test_encryption_scope() {
+        assert_eq!(
+            encryption_scope("tests/7ziplogo.7z").unwrap(),
+            EncryptionScope::None
+        );
+        assert_eq!(
+            encryption_scope("tests/7ziplogo_p.7z").unwrap(),
+            EncryptionScope::HeaderAndContent
+        );
+        assert!(encryption_scope("tests/7zFormat.txt").is_err());
+
+        // sevenz_rust leaves the header of the archives it writes readable
+        let dir = test_dir("encryption_scope");
+        std::fs::create_dir_all(dir.join("content")).unwrap();
+        std::fs::copy("tests/7ziplogo.png", dir.join("content/7ziplogo.png")).unwrap();
+        let archive = dir.join("content_only.7z");
+        sevenz_rust::compress_to_path_encrypted(dir.join("content"), &archive, "1151".into())
+            .unwrap();
+        assert_eq!(
+            encryption_scope(&archive).unwrap(),
+            EncryptionScope::ContentOnly
+        );
+    }
+
+    #[test]
+    fn test_format_version() {
+        assert_eq!(format_version("tests/sample.7z").unwrap(), (0, 3));
+        assert!(format_version("tests/zipcrypto.zip").is_err());
+
+        let dir = test_dir("format_version");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut bytes = std::fs::read("tests/sample.7z").unwrap();
+        // a newer minor version only warns, the start header CRC doesn't
+        // cover it
+        bytes[7] = 5;
+        std::fs::write(dir.join("minor.7z"), &bytes).unwrap();
+        assert_eq!(format_version(dir.join("minor.7z")).unwrap(), (0, 5));
+        assert_eq!(read_archive_entries(dir.join("minor.7z")).unwrap().len(), 5);
+
+        bytes[6] = 1;
+        std::fs::write(dir.join("major.7z"), &bytes).unwrap();
+        assert!(matches!(
+            read_archive_entries(dir.join("major.7z")),
+            Err(CascadeError::UnsupportedVersion { major: 1, minor: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_first_entry_name() {
+        assert_eq!(
+            first_entry_name("tests/7ziplogo.7z").unwrap().as_deref(),
+            Some("7ziplogo.png")
+        );
+        assert!(matches!(
+            first_entry_name("tests/7ziplogo_p.7z"),
+            Err(CascadeError::HeaderEncrypted)
+        ));
+
+        let dir = test_dir("first_entry_name");
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.7z");
+        sevenz_rust::SevenZWriter::create(&empty)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(first_entry_name(&empty).unwrap(), None);
+    }
+
+    #[test]
+    fn test_archive_creator_info() {
+        let info = archive_creator_info("tests/sample.7z").unwrap().unwrap();
+        assert_eq!(info.format_version, (0, 3));
+        assert_eq!(info.coders, ["LZMA2"]);
+        assert!(info.solid);
+        assert!(!info.unix_attributes);
+        assert!(info.header_compressed);
+        assert!(
+            archive_creator_info("tests/symlink.7z")
+                .unwrap()
+                .unwrap()
+                .unix_attributes
+        );
+        assert_eq!(archive_creator_info("tests/7ziplogo_p.7z").unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_password() {
+        let passwords: Vec<String> = ["0000", "1234", "1151", "9999"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            find_password("tests/7ziplogo_p.7z", &passwords, 2).unwrap(),
+            Some("1151".to_string())
+        );
+        assert_eq!(
+            find_password("tests/7ziplogo_p.7z", &passwords[..2], 2).unwrap(),
+            None
+        );
+        assert_eq!(
+            find_password("tests/7ziplogo.7z", &passwords, 2).unwrap(),
+            Some("0000".to_string())
+        );
+
+        let dir = test_dir("find_password");
+        std::fs::create_dir_all(dir.join("content")).unwrap();
+        std::fs::copy("tests/sample.7z", dir.join("content/sample.7z")).unwrap();
+        let archive = dir.join("content_only.7z");
+        sevenz_rust::compress_to_path_encrypted(dir.join("content"), &archive, "1234".into())
+            .unwrap();
+        assert_eq!(
+            find_password(&archive, &passwords, 1).unwrap(),
+            Some("1234".to_string())
+        );
+    }
+
+    #[test]
+    fn test_is_7z() {
+        assert_eq!(is_7z("tests/sample.7z").unwrap(), true);
+        assert_eq!(is_7z("tests/7ziplogo_p.7z").unwrap(), true);
+        assert_eq!(is_7z("tests/7ziplogo.7z").unwrap(), true);
+        assert_eq!(is_7z("tests/7zFormat.txt").unwrap(), false);
+        assert_eq!(is_7z("tests/7ziplogo.png").unwrap(), false);
+    }
+
+    #[test]
+    fn test_extract_from_bytes() {
+        let data = std::fs::read("tests/7ziplogo_p.7z").unwrap();
+        assert_eq!(is_7z_bytes(&data), true);
+        assert_eq!(is_7z_bytes(b"not an archive"), false);
+
+        let dest = test_dir("extract_from_bytes");
+        assert!(try_extract_7z_from_bytes(&data, "0000", &dest).is_err());
+        try_extract_7z_from_bytes(&data, "1151", &dest).unwrap();
+        assert!(dest.join("7ziplogo.png").exists());
+
+        let data = std::fs::read("tests/sample.7z").unwrap();
+        assert_eq!(read_7z_contents_bytes(&data).unwrap().len(), 5);
+    }
+
+    /// Listing and detection must not keep the archive open; checked through
+    /// `/proc/self/fd` so a leak shows up without exhausting the handle limit.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_listing_releases_file_handles() {
+        let dir = test_dir("listing_releases_file_handles");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("sample.7z");
+        std::fs::copy("tests/sample.7z", &archive).unwrap();
+
+        is_7z(&archive).unwrap();
+        detect_archive_format(&archive).unwrap();
+        read_7z_contents(&archive).unwrap();
+        should_create_folder_when_extract_with_smart_mode(&archive).unwrap();
+        archive_total_size(&archive).unwrap();
+        assert!(try_extract_7z_with_password(&archive, "", &dir.join("out")).is_ok());
+
+        let archive = archive.canonicalize().unwrap();
+        let open = std::fs::read_dir("/proc/self/fd")
+            .unwrap()
+            .filter_map(|fd| std::fs::read_link(fd.ok()?.path()).ok())
+            .any(|target| target == archive);
+        assert_eq!(open, false);
+        delete_archive(&archive).unwrap();
+    }
+
+    #[test]
+    fn test_delete_archive_after_listing() {
+        let dir = test_dir("delete_archive_after_listing");
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("sample.7z");
+        std::fs::copy("tests/sample.7z", &archive).unwrap();
+
+        read_7z_contents(&archive).unwrap();
+        delete_archive(&archive).unwrap();
+        assert_eq!(archive.exists(), false);
+        assert!(delete_archive(&archive).is_err());
+
+        std::fs::create_dir_all(dir.join("folder.7z")).unwrap();
+        assert!(delete_archive(dir.join("folder.7z")).is_err());
+        assert!(dir.join("folder.7z").is_dir());
+    }
+
+    #[test]
+    fn test_repackage_7z() {
+        let dir = test_dir("repackage_7z");
+        let out = dir.join("repackaged.7z");
+        assert!(repackage_7z("tests/7ziplogo_p.7z", "0000", "new", &out).is_err());
+        repackage_7z("tests/7ziplogo_p.7z", "1151", "new", &out).unwrap();
+
+        assert!(try_extract_7z_with_password(&out, "1151", &dir.join("old")).is_err());
+        try_extract_7z_with_password(&out, "new", &dir.join("new")).unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("new/7ziplogo.png")).unwrap(),
+            std::fs::read("tests/7ziplogo.png").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_planned_dest_collision() {
+        let base = test_dir("folder_collision");
+        let plan = |collision| {
+            let smart_mode = SmartModeOptions {
+                collision,
+                ..SmartModeOptions::enabled(true)
+            };
+            planned_dest("tests/sample.7z", &base, &smart_mode)
+        };
+        assert_eq!(
+            plan(FolderCollisionPolicy::Error).unwrap(),
+            base.join("sample")
+        );
+
+        std::fs::create_dir_all(base.join("sample")).unwrap();
+        assert_eq!(
+            plan(FolderCollisionPolicy::Merge).unwrap(),
+            base.join("sample")
+        );
+        assert_eq!(
+            plan(FolderCollisionPolicy::Rename).unwrap(),
+            base.join("sample (1)")
+        );
+        assert!(matches!(
+            plan(FolderCollisionPolicy::Error),
+            Err(CascadeError::DestinationExists { path }) if path == base.join("sample")
+        ));
+
+        // no wrapper folder, nothing to collide with
+        let smart_mode = SmartModeOptions {
+            collision: FolderCollisionPolicy::Error,
+            ..SmartModeOptions::enabled(true)
+        };
+        let dest = planned_dest("tests/7ziplogo.7z", &base, &smart_mode).unwrap();
+        assert_eq!(dest, base);
+    }
+
+    #[test]
+    fn test_diff_archives() {
+        let dir = test_dir("diff_archives");
+        let out = dir.join("repackaged.7z");
+        repackage_7z("tests/7ziplogo_p.7z", "1151", "new", &out).unwrap();
+        let diff = diff_archives("tests/7ziplogo_p.7z", &out, "1151", "new").unwrap();
+        assert!(diff.is_empty(), "{:?}", diff);
+
+        let source = dir.join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("7ziplogo.png"), "not the logo").unwrap();
+        std::fs::write(source.join("extra.txt"), "extra").unwrap();
+        let other = dir.join("other.7z");
+        sevenz_rust::compress_to_path(&source, &other).unwrap();
+        let diff = diff_archives("tests/7ziplogo.7z", &other, "", "").unwrap();
+        assert_eq!(diff.added, ["extra.txt"]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed, ["7ziplogo.png"]);
+
+        let diff = diff_archives(&other, "tests/7ziplogo.7z", "", "").unwrap();
+        assert_eq!(diff.removed, ["extra.txt"]);
+    }
+
+    #[test]
+    fn show_7z_content() {
+        let mut file = std::fs::File::open("tests/sample.7z").unwrap();
+        let len = file.metadata().unwrap().len();
+        let password = Password::empty();
+        let archive = sevenz_rust::Archive::read(&mut file, len, password.as_slice()).unwrap();
+        let folder_count = archive.folders.len();
+
+        // println!("{:?}", archive.folders);
+        // println!("{:?}", archive.files);
+        for file in archive.files {
+            println!("{:?}", file.name());
+            println!("{:?}", file.is_directory());
+        }
+        assert_eq!(folder_count, 1);
+    }
+
+    #[test]
+    fn test_smart_mode_policy() {
+        let dir = test_dir("smart_mode_policy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let single = |name: &str| {
+            let path = dir.join(format!("{}.7z", name.replace('/', "_")));
+            let mut writer = sevenz_rust::SevenZWriter::create(&path).unwrap();
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some(&b"data"[..]))
+                .unwrap();
+            writer.finish().unwrap();
+            path
+        };
+        let wraps = |path: &Path, policy| {
+            let smart_mode = SmartModeOptions {
+                policy,
+                ..SmartModeOptions::enabled(true)
+            };
+            planned_dest(path, Path::new("out"), &smart_mode).unwrap() != Path::new("out")
+        };
+
+        let installer = single("setup.EXE");
+        assert!(!wraps(&installer, SmartModePolicy::CountBased));
+        assert!(wraps(&installer, SmartModePolicy::ContentAware));
+        let nested = single("inner.zip");
+        assert!(wraps(&nested, SmartModePolicy::ContentAware));
+        let movie = single("movie.mkv");
+        assert!(!wraps(&movie, SmartModePolicy::ContentAware));
+        // a folder that merely contains an installer stays a single root
+        let in_folder = single("app/setup.exe");
+        assert!(!wraps(&in_folder, SmartModePolicy::ContentAware));
+        assert!(wraps(
+            Path::new("tests/sample.7z"),
+            SmartModePolicy::ContentAware
+        ));
+    }
+
+    #[test]
+    fn test_should_create_folder_when_extract_with_smart_mode() {
+        assert_eq!(
+            should_create_folder_when_extract_with_smart_mode("tests/sample.7z").unwrap(),
+            true
+        );
+        assert_eq!(
+            should_create_folder_when_extract_with_smart_mode("tests/7ziplogo.7z").unwrap(),
+            false
+        );
+    }
+
+    #[test]
+    fn test_needs_wrapper_folder_mixed_roots() {
+        let entries = |names: &[&str]| -> Vec<sevenz_rust::SevenZArchiveEntry> {
+            names
+                .iter()
+                .map(|name| {
+                    let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+                    entry.name = name.trim_end_matches('/').to_string();
+                    entry.is_directory = name.ends_with('/');
+                    entry
+                })
+                .collect()
+        };
+        // one file next to one folder is wrapped, not only two of a kind
+        assert!(needs_wrapper_folder(&entries(&[
+            "readme.txt",
+            "docs/",
+            "docs/a.txt"
+        ])));
+        assert!(needs_wrapper_folder(&entries(&[
+            "readme.txt",
+            "docs/a.txt"
+        ])));
+        assert!(needs_wrapper_folder(&entries(&["a.txt", "b.txt"])));
+        assert!(!needs_wrapper_folder(&entries(&[
+            "docs/",
+            "docs/a.txt",
+            "docs/b.txt"
+        ])));
+        assert!(!needs_wrapper_folder(&entries(&["readme.txt"])));
+    }
+
+    #[test]
+    fn test_smart_mode_threshold() {
+        let dir = test_dir("smart_mode_threshold");
+        std::fs::create_dir_all(&dir).unwrap();
+        let with_roots = |count: usize| {
+            let path = dir.join(format!("{}.7z", count));
+            let mut writer = sevenz_rust::SevenZWriter::create(&path).unwrap();
+            for i in 0..count {
+                // two files under each root, so files aren't counted as roots
+                for name in ["a.txt", "b.txt"] {
+                    let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+                    entry.name = format!("root{}/{}", i, name);
+                    entry.has_stream = true;
+                    writer
+                        .push_archive_entry(entry, Some(&b"data"[..]))
+                        .unwrap();
+                }
+            }
+            writer.finish().unwrap();
+            path
+        };
+        let wraps = |roots, threshold| {
+            should_create_folder_with_threshold(with_roots(roots), threshold).unwrap()
+        };
+
+        assert!(!wraps(1, 1));
+        assert!(wraps(2, 1));
+        assert!(!wraps(2, 3));
+        assert!(!wraps(3, 3));
+        assert!(wraps(4, 3));
+        // zero wraps every archive, even one with a single root
+        assert!(wraps(1, 0));
+        assert_eq!(
+            planned_dest(
+                with_roots(3),
+                Path::new("out"),
+                &SmartModeOptions {
+                    threshold: 3,
+                    ..SmartModeOptions::enabled(true)
+                }
+            )
+            .unwrap(),
+            Path::new("out")
+        );
+    }
+
+    #[test]
+    fn test_common_root() {
+        assert_eq!(common_root("tests/sample.7z").unwrap(), None);
+        assert_eq!(
+            common_root("tests/7ziplogo.7z").unwrap(),
+            Some("7ziplogo.png".to_string())
+        );
+        assert_eq!(root_component("inner/inner-inner"), "inner");
+        assert_eq!(root_component("inner"), "inner");
+        assert_eq!(root_component("inner\\inner-inner"), "inner");
+    }
+
+    #[test]
+    fn test_contains_risky_files() {
+        assert!(contains_risky_files("tests/sample.7z").unwrap().is_empty());
+        assert_eq!(is_risky_name("setup.EXE"), true);
+        assert_eq!(is_risky_name("scripts/install.sh"), true);
+        assert_eq!(is_risky_name("readme.txt"), false);
+        assert_eq!(is_risky_name("exe"), false);
+    }
+
+    #[test]
+    fn test_archive_total_size() {
+        assert_eq!(archive_total_size("tests/sample.7z").unwrap(), 3677);
+        assert_eq!(archive_total_size("tests/7ziplogo.7z").unwrap(), 1417);
+    }
+
+    #[test]
+    fn test_planned_dest_folder_name() {
+        let base = Path::new("out");
+        let strip_version = |stem: &str| stem.trim_end_matches(['-', '.', '0', '1']).to_string();
+        let named = |folder_name: fn(&str) -> String| SmartModeOptions {
+            folder_name: Some(Arc::new(folder_name)),
+            ..SmartModeOptions::enabled(true)
+        };
+        assert_eq!(
+            planned_dest(
+                "tests/sample.7z",
+                base,
+                &named(|stem| format!("[{}]", stem))
+            )
+            .unwrap(),
+            base.join("[sample]")
+        );
+        assert_eq!(
+            resolve_dest_with(Path::new("album-1.0.1.7z"), base, true, &strip_version),
+            base.join("album")
+        );
+        // names that would leave base_dest fall back to the stem
+        assert_eq!(
+            resolve_dest_with(Path::new("a.7z"), base, true, &|_| "../x".to_string()),
+            base.join("a")
+        );
+        // no wrapper folder, nothing to name
+        assert_eq!(
+            planned_dest("tests/7ziplogo.7z", base, &named(|_| unreachable!())).unwrap(),
+            base
+        );
+    }
+
+    #[test]
+    fn test_estimate_extraction() {
+        let plan = estimate_extraction("tests/sample.7z", Path::new("out"), true).unwrap();
+        assert_eq!(plan.dest, Path::new("out").join("sample"));
+        assert_eq!(plan.total_bytes, 3677);
+        assert_eq!(plan.entry_count, 5);
+        assert_eq!(plan.creates_wrapper_folder, true);
+
+        let plan = estimate_extraction("tests/sample.7z", Path::new("out"), false).unwrap();
+        assert_eq!(plan.dest, Path::new("out"));
+        assert_eq!(plan.creates_wrapper_folder, false);
+
+        let plan = estimate_extraction("tests/7ziplogo.7z", Path::new("out"), true).unwrap();
+        assert_eq!(plan.dest, Path::new("out"));
+        assert_eq!(plan.entry_count, 1);
+        assert_eq!(plan.creates_wrapper_folder, false);
+    }
+
+    #[test]
+    fn test_crack_password_blocking() {
+        let dest = test_dir("crack_password_blocking");
+        let paths = [PathBuf::from("tests/7ziplogo_p.7z")];
+        let passwords: Vec<Arc<String>> = ["0000", "1111", "1151", "2222"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let outcome =
+            crack_password_blocking(&paths, &passwords, &dest, 2, &CrackOptions::default());
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        assert!(dest.join("7ziplogo.png").exists());
+
+        let progress = Arc::new(Progress::new());
+        assert_eq!(progress.remaining(), None);
+        let options = CrackOptions {
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let outcome = crack_password_blocking(&paths, &passwords[..2], &dest, 2, &options);
+        assert_eq!(outcome, Outcome::NotFound { tried: 2 });
+        assert_eq!(progress.total(), Some(2));
+        assert_eq!(progress.remaining(), Some(0));
+    }
+
+    #[test]
+    fn test_crack_password_blocking_resume() {
+        let dir = test_dir("crack_password_blocking_resume");
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [PathBuf::from("tests/7ziplogo_p.7z")];
+        let passwords: Vec<Arc<String>> = ["1151", "0000", "1111"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let state_file = dir.join("crack.json");
+        let options = CrackOptions {
+            resume_from: Some(CrackState {
+                next_index: 1,
+                password_count: 3,
+            }),
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        // the password is before the saved position
+        let outcome = crack_password_blocking(&paths, &passwords, &dir.join("out"), 2, &options);
+        assert_eq!(outcome, Outcome::NotFound { tried: 2 });
+        assert_eq!(
+            CrackState::load(&state_file).unwrap(),
+            Some(CrackState {
+                next_index: 3,
+                password_count: 3
+            })
+        );
+
+        let options = CrackOptions {
+            resume_from: CrackState::load(&state_file).unwrap(),
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        // another list starts over
+        let outcome =
+            crack_password_blocking(&paths, &passwords[..2], &dir.join("out"), 2, &options);
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        assert!(!state_file.exists());
+    }
+
+    #[test]
+    fn test_estimate_remaining() {
+        assert_eq!(
+            estimate_remaining(400, Some(4_320_400), 1200.0),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(estimate_remaining(10, Some(10), 5.0), Some(Duration::ZERO));
+        assert_eq!(estimate_remaining(10, None, 5.0), None);
+        assert_eq!(estimate_remaining(10, Some(20), 0.0), None);
+        assert_eq!(estimate_remaining(10, Some(20), f64::NAN), None);
+
+        let progress = Progress::new();
+        assert_eq!(progress.rate(), None);
+        progress.begin(Some(100));
+        progress.expect_key_cost(&["tests/7ziplogo_p.7z"], 2);
+        let rate = progress.rate().unwrap();
+        assert!(rate > 0.0);
+        let left = progress.remaining_time().unwrap().as_secs_f64();
+        assert!((left - 100.0 / rate).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_with_case_variants() {
+        let passwords: Vec<Arc<String>> = ["Secret", "hello world", "SECRET"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let variants: Vec<String> = with_case_variants(&passwords)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            variants,
+            [
+                "Secret",
+                "secret",
+                "SECRET",
+                "hello world",
+                "HELLO WORLD",
+                "Hello World"
+            ]
+        );
+
+        let dir = test_dir("try_case_variants");
+        std::fs::create_dir_all(dir.join("content")).unwrap();
+        std::fs::copy("tests/7zFormat.txt", dir.join("content/7zFormat.txt")).unwrap();
+        let archive = dir.join("upper.7z");
+        sevenz_rust::compress_to_path_encrypted(dir.join("content"), &archive, "LEGACY".into())
+            .unwrap();
+        let passwords = [Arc::new("legacy".to_string())];
+        let options = CrackOptions {
+            try_case_variants: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            crack_password_blocking(&[&archive], &passwords, &dir.join("out"), 2, &options),
+            Outcome::Found("LEGACY".to_string())
+        );
+    }
+
+    #[test]
+    fn test_use_filename_as_password() {
+        let dir = test_dir("use_filename_as_password");
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [dir.join("1151.7z")];
+        std::fs::copy("tests/7ziplogo_p.7z", &paths[0]).unwrap();
+        let passwords = vec![Arc::new("0000".to_string())];
+        let dest = dir.join("out");
+
+        let outcome =
+            crack_password_blocking(&paths, &passwords, &dest, 2, &CrackOptions::default());
+        assert_eq!(outcome, Outcome::NotFound { tried: 1 });
+        // the state of an earlier run goes once the name opens the archive
+        let state_file = dir.join("crack.json");
+        CrackState {
+            next_index: 0,
+            password_count: 1,
+        }
+        .save(&state_file)
+        .unwrap();
+        let options = CrackOptions {
+            use_filename_as_password: true,
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        let outcome = crack_password_blocking(&paths, &passwords, &dest, 2, &options);
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        assert!(dest.join("7ziplogo.png").exists());
+        assert!(!state_file.exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_filename_password() {
+        let dir = test_dir("start_extraction_filename_password");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("tests/7ziplogo_p.7z", dir.join("1151.7z")).unwrap();
+        let state_file = dir.join("crack.json");
+        CrackState {
+            next_index: 0,
+            password_count: 1,
+        }
+        .save(&state_file)
+        .unwrap();
+        let progress = Arc::new(Progress::new());
+        let options = CrackOptions {
+            use_filename_as_password: true,
+            state_file: Some(state_file.clone()),
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![dir.join("1151.7z")]),
+            vec![Arc::new("0000".to_string())],
+            dir.join("out"),
+            2,
+            &options,
+        )
+        .await;
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        assert!(!state_file.exists());
+        // only the file name was tried
+        assert_eq!(progress.tried(), 1);
+    }
+
+    #[test]
+    fn test_entry_codecs() {
+        // the two folders of sample.7z have no data
+        let codecs = entry_codecs("tests/sample.7z").unwrap();
+        assert_eq!(codecs.len(), 3);
+        assert!(codecs.iter().all(|(_, codec)| codec == "LZMA2"));
+
+        let dir = test_dir("entry_codecs");
+        std::fs::create_dir_all(dir.join("in")).unwrap();
+        std::fs::write(dir.join("in/a.txt"), "a").unwrap();
+        sevenz_rust::compress_to_path_encrypted(dir.join("in"), dir.join("a.7z"), "pw".into())
+            .unwrap();
+        assert_eq!(
+            entry_codecs(dir.join("a.7z")).unwrap(),
+            [("a.txt".to_string(), "AES256SHA256 + LZMA2".to_string())]
+        );
+        assert!(matches!(
+            entry_codecs("tests/7ziplogo_p.7z"),
+            Err(CascadeError::HeaderEncrypted)
+        ));
+    }
+
+    #[test]
+    fn test_password_coverage() {
+        let dir = test_dir("password_coverage");
+        std::fs::create_dir_all(dir.join("content")).unwrap();
+        std::fs::copy("tests/7zFormat.txt", dir.join("content/7zFormat.txt")).unwrap();
+        let content_only = dir.join("content_only.7z");
+        sevenz_rust::compress_to_path_encrypted(dir.join("content"), &content_only, "pw".into())
+            .unwrap();
+        let paths = [
+            PathBuf::from("tests/7ziplogo_p.7z"),
+            PathBuf::from("tests/sample.7z"),
+            content_only.clone(),
+        ];
+        let passwords: Vec<String> = ["1151", "pw", "nope"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+
+        let coverage = password_coverage(&paths, &passwords, 3).unwrap();
+        assert_eq!(coverage.len(), 3);
+        assert_eq!(coverage["1151"], [paths[0].clone(), paths[1].clone()]);
+        assert_eq!(coverage["pw"], [paths[1].clone(), content_only]);
+        assert_eq!(coverage["nope"], [paths[1].clone()]);
+    }
+
+    #[test]
+    fn test_threads_within_memory() {
+        let paths = [PathBuf::from("tests/sample.7z")];
+        let needed = decode_memory_estimate(&paths[0], "").unwrap();
+        assert_eq!(threads_within_memory(&paths, 8, None), 8);
+        assert_eq!(threads_within_memory(&paths, 8, Some(needed * 3)), 3);
+        assert_eq!(threads_within_memory(&paths, 8, Some(needed * 100)), 8);
+        assert_eq!(threads_within_memory(&paths, 8, Some(1)), 1);
+        // the header is encrypted, nothing to estimate
+        let encrypted = [PathBuf::from("tests/7ziplogo_p.7z")];
+        assert_eq!(threads_within_memory(&encrypted, 8, Some(1)), 8);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_promote_password() {
+        let mut passwords: Vec<Arc<String>> = ["a", "b", "c"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        promote_password(&mut passwords, "c");
+        assert_eq!(passwords[0].as_str(), "c");
+        assert_eq!(passwords[1].as_str(), "a");
+        promote_password(&mut passwords, "missing");
+        assert_eq!(passwords.len(), 3);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_batch() {
+        let dest = env::temp_dir()
+            .join("cascading-extract-tests")
+            .join("crack_batch");
+        let paths = vec![
+            PathBuf::from("tests/7ziplogo_p.7z"),
+            PathBuf::from("tests/7ziplogo_p.7z"),
+        ];
+        let passwords = vec![
+            Arc::new("password".to_string()),
+            Arc::new("1151".to_string()),
+        ];
+        let options = BatchOptions {
+            adaptive_password_order: true,
+            ..Default::default()
+        };
+        let results = crack_batch_with_options(&paths, passwords, &dest, 2, &options, None).await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1, Outcome::Found("1151".to_string()));
+        assert_eq!(results[1].1, Outcome::Found("1151".to_string()));
+        assert!(dest.join("7ziplogo.png").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_batch_with_smart_mode() {
+        let dest = test_dir("crack_batch_with_smart_mode");
+        let paths = vec![
+            PathBuf::from("tests/sample.7z"),
+            PathBuf::from("tests/7ziplogo.7z"),
+        ];
+        let passwords = vec![Arc::new(String::new())];
+
+        let on = BatchOptions {
+            smart_mode: SmartModeOptions::enabled(true),
+            ..Default::default()
+        };
+        let results =
+            crack_batch_with_options(&paths, passwords.clone(), &dest.join("on"), 2, &on, None)
+                .await;
+        assert!(results
+            .iter()
+            .all(|(_, outcome)| outcome.password().is_some()));
+        assert!(dest.join("on/sample/7zFormat.txt").exists());
+        assert!(dest.join("on/7ziplogo.png").exists());
+
+        let off = BatchOptions::default();
+        crack_batch_with_options(&paths, passwords, &dest.join("off"), 2, &off, None).await;
+        assert!(dest.join("off/7zFormat.txt").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_batch_follows_smart_mode_options() {
+        let dest = test_dir("crack_batch_smart_mode_options");
+        let paths = vec![PathBuf::from("tests/sample.7z")];
+        let passwords = vec![Arc::new(String::new())];
+        std::fs::create_dir_all(dest.join("taken/sample")).unwrap();
+
+        let options = |collision| BatchOptions {
+            smart_mode: SmartModeOptions {
+                collision,
+                ..SmartModeOptions::enabled(true)
+            },
+            ..Default::default()
+        };
+        let results = crack_batch_with_options(
+            &paths,
+            passwords.clone(),
+            &dest.join("taken"),
+            2,
+            &options(FolderCollisionPolicy::Error),
+            None,
+        )
+        .await;
+        assert!(matches!(results[0].1, Outcome::Skipped { .. }));
+
+        crack_batch_with_options(
+            &paths,
+            passwords.clone(),
+            &dest.join("taken"),
+            2,
+            &options(FolderCollisionPolicy::Rename),
+            None,
+        )
+        .await;
+        assert!(dest.join("taken/sample (1)/7zFormat.txt").exists());
+
+        // a threshold above the root count leaves the entries unwrapped
+        let options = BatchOptions {
+            smart_mode: SmartModeOptions {
+                threshold: u32::MAX,
+                ..SmartModeOptions::enabled(true)
+            },
+            ..Default::default()
+        };
+        crack_batch_with_options(&paths, passwords, &dest.join("high"), 2, &options, None).await;
+        assert!(dest.join("high/7zFormat.txt").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_with_wordlists() {
+        let dir = test_dir("start_extraction_with_wordlists");
+        std::fs::create_dir_all(&dir).unwrap();
+        let short = dir.join("short.txt");
+        let long = dir.join("long.txt");
+        std::fs::write(&short, "0000\n1111\n").unwrap();
+        std::fs::write(&long, "# more\n2222\n\n1151\n").unwrap();
+        let paths: Arc<[PathBuf]> = Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]);
+        let options = CrackOptions::default();
+
+        let hit = start_extraction_with_wordlists(
+            paths.clone(),
+            &[short.clone(), long.clone()],
+            dir.join("out"),
+            2,
+            &options,
+            Shard::ALL,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            hit,
+            Some(WordlistHit {
+                password: "1151".to_string(),
+                wordlist: long.clone(),
+                line: Some(4),
+            })
+        );
+
+        // a case variant comes with the line it was made from
+        let secret = dir.join("secret");
+        std::fs::create_dir_all(&secret).unwrap();
+        std::fs::copy("tests/7zFormat.txt", secret.join("7zFormat.txt")).unwrap();
+        let archive = dir.join("secret.7z");
+        sevenz_rust::compress_to_path_encrypted(&secret, &archive, "Secret".into()).unwrap();
+        let words = dir.join("words.txt");
+        std::fs::write(&words, "0000\nsecret\n").unwrap();
+        let variants = CrackOptions {
+            try_case_variants: true,
+            ..Default::default()
+        };
+        let hit = start_extraction_with_wordlists(
+            Arc::from(vec![archive]),
+            std::slice::from_ref(&words),
+            dir.join("secret_out"),
+            2,
+            &variants,
+            Shard::ALL,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!((hit.password.as_str(), hit.line), ("Secret", Some(2)));
+
+        // the file name has no line
+        let named = dir.join("1151.7z");
+        std::fs::copy("tests/7ziplogo_p.7z", &named).unwrap();
+        let filename = CrackOptions {
+            use_filename_as_password: true,
+            ..Default::default()
+        };
+        let hit = start_extraction_with_wordlists(
+            Arc::from(vec![named]),
+            &[words],
+            dir.join("named_out"),
+            2,
+            &filename,
+            Shard::ALL,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!((hit.password.as_str(), hit.line), ("1151", None));
+
+        let missing = dir.join("missing.txt");
+        let result = start_extraction_with_wordlists(
+            paths,
+            &[short, missing],
+            dir.join("out"),
+            2,
+            &options,
+            Shard::ALL,
+        )
+        .await;
+        assert!(matches!(result, Err(CascadeError::Io(_))));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_batch_with_cache() {
+        let dest = test_dir("crack_batch_with_cache");
+        let paths = vec![PathBuf::from("tests/7ziplogo_p.7z")];
+        let passwords = vec![Arc::new("password".to_string())];
+        let mut cache = PasswordCache::default();
+        cache.seed(["1151"]);
+
+        // only the cache knows the password
+        let options = BatchOptions::default();
+        let results =
+            crack_batch_with_options(&paths, passwords, &dest, 2, &options, Some(&mut cache)).await;
+        assert_eq!(results[0].1, Outcome::Found("1151".to_string()));
+        assert_eq!(cache.passwords(), ["1151"]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_start_extraction_report_rate() {
+        let dest = test_dir("report_rate");
+        let passwords = ["0000", "1111", "1151"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let options = CrackOptions {
+            report_rate: true,
+            ..Default::default()
+        };
+        let found = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords,
+            dest.clone(),
+            2,
+            &options,
+        )
+        .await;
+        assert_eq!(found, Outcome::Found("1151".to_string()));
+        assert!(dest.join("7ziplogo.png").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_low_priority() {
+        #[cfg(unix)]
+        {
+            let before = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+            let nice = run_at_low_priority(|| unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) });
+            assert!(nice.await.unwrap() >= 10);
+            // the runtime's own thread keeps its priority
+            assert_eq!(unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) }, before);
+        }
+        // a panic becomes an error rather than taking the task down
+        assert!(run_at_low_priority(|| panic!("attempt")).await.is_err());
+
+        let options = CrackOptions {
+            low_priority: true,
+            ..Default::default()
+        };
+        let passwords = ["0000", "1151"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let found = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords,
+            test_dir("start_extraction_low_priority"),
+            2,
+            &options,
+        )
+        .await;
+        assert_eq!(found, Outcome::Found("1151".to_string()));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_rate_sampler() {
+        let start = Instant::now();
+        let mut sampler = RateSampler::new(start);
+        assert_eq!(sampler.sample(start + Duration::from_secs(2), 10), 5.0);
+        // only what happened since the last report counts
+        assert_eq!(sampler.sample(start + Duration::from_secs(3), 13), 3.0);
+        assert_eq!(sampler.sample(start + Duration::from_secs(4), 13), 0.0);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_max_runtime() {
+        let dest = test_dir("max_runtime");
+        let passwords: Vec<Arc<String>> = (0..200).map(|i| Arc::new(i.to_string())).collect();
+        let progress = Arc::new(Progress::new());
+        let options = CrackOptions {
+            max_runtime: Some(Duration::from_millis(200)),
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords,
+            dest,
+            2,
+            &options,
+        )
+        .await;
+        assert!(matches!(outcome, Outcome::TimedOut { tried } if tried > 0 && tried < 200));
+        let remaining = progress.remaining().unwrap();
+        assert!(remaining > 0 && remaining + progress.tried() == 200);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_password_opens_gives_up_once_stopped() {
+        let path = Path::new("tests/7ziplogo_p.7z");
+        let stop = AtomicBool::new(true);
+        assert!(!password_opens(path, "1151", Some(&stop)).unwrap());
+        stop.store(false, Ordering::Relaxed);
+        assert!(password_opens(path, "1151", Some(&stop)).unwrap());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_resume() {
+        let dir = test_dir("start_extraction_resume");
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Arc<[PathBuf]> = Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]);
+        let passwords: Vec<Arc<String>> = ["1151", "0000", "1111"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let state_file = dir.join("crack.json");
+        let options = CrackOptions {
+            resume_from: Some(CrackState {
+                next_index: 1,
+                password_count: 3,
+            }),
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        let outcome = start_extraction_with_options(
+            paths.clone(),
+            passwords.clone(),
+            dir.join("out"),
+            2,
+            &options,
+        )
+        .await;
+        assert_eq!(outcome, Outcome::NotFound { tried: 2 });
+        assert_eq!(
+            CrackState::load(&state_file).unwrap().unwrap().next_index,
+            3
+        );
+
+        let options = CrackOptions {
+            state_file: Some(state_file.clone()),
+            ..Default::default()
+        };
+        let outcome =
+            start_extraction_with_options(paths, passwords, dir.join("out"), 2, &options).await;
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        assert!(!state_file.exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_events_never_block() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let progress = Arc::new(Progress::new());
+        let options = CrackOptions {
+            progress: Some(progress.clone()),
+            events: Some(tx),
+            ..Default::default()
+        };
+        let passwords: Vec<Arc<String>> = (0..10).map(|i| Arc::new(i.to_string())).collect();
+        let run = tokio::spawn(async move {
+            start_extraction_with_options(
+                Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+                passwords,
+                test_dir("crack_events"),
+                2,
+                &options,
+            )
+            .await
+        });
+
+        // nobody reads until every attempt is made
+        let start = Instant::now();
+        while progress.tried() < 10 {
+            assert!(start.elapsed() < Duration::from_secs(30));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(run.await.unwrap(), Outcome::NotFound { tried: 10 });
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            CrackEvent::Progress {
+                total: Some(10),
+                ..
+            }
+        ));
+        assert_eq!(
+            events[2],
+            CrackEvent::Failed {
+                outcome: Outcome::NotFound { tried: 10 }
+            }
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_succeeded_event() {
+        let (tx, mut rx) = mpsc::channel(100);
+        let options = CrackOptions {
+            events: Some(tx),
+            ..Default::default()
+        };
+        let passwords: Vec<Arc<String>> = ["a", "b", "c", "1151", "d"]
+            .into_iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords,
+            test_dir("crack_succeeded_event"),
+            1,
+            &options,
+        )
+        .await;
+        assert_eq!(outcome, Outcome::Found("1151".to_string()));
+        drop(options);
+        let mut last = None;
+        while let Some(event) = rx.recv().await {
+            last = Some(event);
+        }
+        match last {
+            Some(CrackEvent::Succeeded {
+                password,
+                attempts_before_success,
+                elapsed,
+            }) => {
+                assert_eq!(password, "1151");
+                // one thread tries the passwords in order
+                assert_eq!(attempts_before_success, 3);
+                assert!(elapsed > Duration::ZERO);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let passwords: Vec<Arc<String>> = ["a", "b", "1151"]
+            .into_iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let success = start_extraction(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords,
+            test_dir("crack_succeeded_event_result"),
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(success.password, "1151");
+        assert_eq!(success.attempts_before_success, 2);
+        assert!(success.elapsed > Duration::ZERO);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_empty_list() {
+        let dest = test_dir("start_extraction_empty_list");
+        let paths: Arc<[PathBuf]> = Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]);
+        let found = tokio::spawn(start_extraction(paths.clone(), vec![], dest.clone(), 2))
+            .await
+            .unwrap();
+        assert!(found.is_none());
+        let outcome =
+            start_extraction_with_options(paths, vec![], dest, 2, &CrackOptions::default()).await;
+        assert_eq!(outcome, Outcome::NotFound { tried: 0 });
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_extracts_with_extract_options() {
+        let dir = test_dir("crack_extract_options");
+        let passwords = vec![Arc::new("0000".to_string()), Arc::new(String::new())];
+
+        // attempts write nothing, the archive then comes out as
+        // `extract_with_options` writes it
+        let dest = dir.join("links");
+        let outcome = crack_password_blocking(
+            &["tests/symlink.7z"],
+            &passwords,
+            &dest,
+            2,
+            &CrackOptions::default(),
+        );
+        assert!(matches!(outcome, Outcome::Found(_)));
+        #[cfg(unix)]
+        assert!(std::fs::symlink_metadata(dest.join("link.txt"))
+            .unwrap()
+            .is_symlink());
+
+        let options = CrackOptions {
+            extract: ExtractOptions {
+                max_entries: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let outcome = crack_password_blocking(
+            &["tests/sample.7z"],
+            &passwords,
+            &dir.join("blocking"),
+            2,
+            &options,
+        );
+        assert!(matches!(outcome, Outcome::ExtractFailed { .. }));
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/sample.7z")]),
+            passwords,
+            dir.join("async"),
+            2,
+            &options,
+        )
+        .await;
+        assert!(matches!(outcome, Outcome::ExtractFailed { .. }));
+        assert!(!dir.join("async").join("7zFormat.txt").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_start_extraction_cancel() {
+        let dest = test_dir("start_extraction_cancel");
+        let passwords: Vec<Arc<String>> = (0..200).map(|i| Arc::new(i.to_string())).collect();
+        let token = CancelToken::new();
+        let options = CrackOptions {
+            cancel: Some(token.clone()),
+            ..Default::default()
+        };
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        let outcome = start_extraction_with_options(
+            Arc::from(vec![PathBuf::from("tests/7ziplogo_p.7z")]),
+            passwords.clone(),
+            dest.clone(),
+            2,
+            &options,
+        )
+        .await;
+        assert!(matches!(outcome, Outcome::Cancelled { tried } if tried < 200));
+        assert!(!dest.exists());
+
+        let outcome = crack_password_blocking(
+            &[PathBuf::from("tests/7ziplogo_p.7z")],
+            &passwords,
+            &dest,
+            2,
+            &options,
+        );
+        assert_eq!(outcome, Outcome::Cancelled { tried: 0 });
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_crack_batch_cancellable() {
+        let dest = test_dir("crack_batch_cancellable");
+        let passwords: Vec<Arc<String>> = ["0000", "1151"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let batch = crack_batch_cancellable(
+            vec![
+                PathBuf::from("tests/sample.7z"),
+                PathBuf::from("tests/7ziplogo_p.7z"),
+            ],
+            passwords,
+            dest.clone(),
+            2,
+            BatchOptions {
+                smart_mode: SmartModeOptions::enabled(true),
+                ..Default::default()
+            },
+        );
+        assert_eq!(batch.handles.len(), 2);
+        batch.handles[0].cancel();
+        assert!(batch.handles[0].is_cancelled());
+
+        let results = batch.results.await.unwrap();
+        assert_eq!(results[0].1, Outcome::Cancelled { tried: 0 });
+        assert_eq!(results[1].1, Outcome::Found("1151".to_string()));
+        assert!(dest.join("7ziplogo.png").exists());
+        assert!(!dest.join("sample").exists());
+    }
+
+    #[test]
+    fn test_log_context() {
+        assert_eq!(log_context(Path::new("tests/sample.7z"), None), "sample.7z");
+        assert_eq!(
+            log_context(Path::new("tests/sample.7z"), Some(3)),
+            "sample.7z#3"
         );
     }
 
     #[test]
     fn test_my_logger() {
         my_logger::init();
-        info!("test");
+        log::info!("test");
         log::error!("test");
         log::warn!("test");
     }
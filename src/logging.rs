@@ -0,0 +1,199 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Local;
+use log::{Log, Metadata, Record};
+
+use crate::ConfigSettings;
+
+/// The file `enable_log_file` writes to, in the folder given by
+/// `log_file_path`.
+pub const LOG_FILE_NAME: &str = "cascading-extract.log";
+/// How many rotated files are kept next to the log, as `.1` (the newest) up
+/// to `.3`.
+pub const LOG_FILE_BACKUPS: usize = 3;
+
+static LOG_FILE: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+/// Installs the logger of the program: the console, filtered by `RUST_LOG`
+/// and `info` by default, plus the log file once `enable_log_file` has
+/// opened one. Does nothing when a logger was installed already.
+pub fn init_logging() {
+    let console =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let max_level = console.filter().max(log::LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(Logger { console })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Where the log file goes: `dest` when it names a single folder, or else
+/// the current folder, as it does for `dest = ""` or a `dest` with
+/// placeholders.
+pub fn log_file_path(config: &ConfigSettings) -> PathBuf {
+    let dir = if config.dest.is_empty() || config.dest.contains('{') {
+        Path::new(".")
+    } else {
+        Path::new(&config.dest)
+    };
+    dir.join(LOG_FILE_NAME)
+}
+
+/// Also writes every log message of level `info` and above to
+/// `log_file_path` when `log_file` is set, rotating the file once it grows
+/// past `log_file_max_bytes`. Passwords only show up in it as far as
+/// `log_passwords` lets them into the log at all.
+pub fn enable_log_file(config: &ConfigSettings) -> io::Result<()> {
+    if !config.log_file {
+        return Ok(());
+    }
+    let file = RotatingFile::open(log_file_path(config), config.log_file_max_bytes)?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+struct Logger {
+    console: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if record.level() > log::Level::Info {
+            return;
+        }
+        if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+            let line = format!(
+                "{} {:<5} {}: {}\n",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            // there is nowhere left to report a failing log file
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// A file that is moved aside to `<path>.1` once writing more would take it
+/// past `max_bytes`, shifting older rotations up to `LOG_FILE_BACKUPS`.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, creating it and its folder as needed.
+    pub fn open<P: Into<PathBuf>>(path: P, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::options().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            file,
+            len,
+        })
+    }
+
+    fn backup(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..LOG_FILE_BACKUPS).rev() {
+            let from = self.backup(index);
+            if from.exists() {
+                fs::rename(from, self.backup(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.backup(1))?;
+        self.file = File::options().create(true).append(true).open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_config;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = test_dir("rotating_file");
+        let path = dir.join(LOG_FILE_NAME);
+        let mut file = RotatingFile::open(&path, 10).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n", "fifth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fifth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("cascading-extract.log.1")).unwrap(),
+            "fourth\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("cascading-extract.log.3")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("cascading-extract.log.4").exists());
+
+        // appends after a restart, rotating on the next write that doesn't fit
+        let mut file = RotatingFile::open(&path, 10).unwrap();
+        file.write_all(b"sixth\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "sixth\n");
+    }
+
+    #[test]
+    fn test_log_file_path() {
+        let mut config = read_config().unwrap().config;
+        assert_eq!(log_file_path(&config), Path::new(".").join(LOG_FILE_NAME));
+        config.dest = "D:/media/extraction".to_string();
+        assert_eq!(
+            log_file_path(&config),
+            Path::new("D:/media/extraction").join(LOG_FILE_NAME)
+        );
+        config.dest = "D:/media/{archive_stem}".to_string();
+        assert_eq!(log_file_path(&config), Path::new(".").join(LOG_FILE_NAME));
+    }
+}
@@ -1,3 +1,7 @@
 fn main() {
-    println!("Hello, world!");
+    cascading_extract::init_logging();
+    if let Err(e) = cascading_extract::run_from_args(std::env::args()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
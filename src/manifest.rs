@@ -0,0 +1,309 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::{
+    extract_to_sink, extract_with_options, read_archive_entries_with_password, sink_path,
+    CascadeError, ExtractOptions, FileSink,
+};
+
+/// The file `extract_with_manifest_options` writes into `dest` when
+/// `ExtractOptions::write_manifest` is set.
+pub const MANIFEST_FILE_NAME: &str = "extract-manifest.json";
+
+/// What an extraction wrote, for automation further down the line.
+///
+/// The `password` is stored as it is, so keep the manifest as private as
+/// the archive itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Manifest {
+    pub archive: PathBuf,
+    pub password: String,
+    pub dest: PathBuf,
+    pub files: Vec<ManifestFile>,
+    /// When the extraction finished, in RFC 3339 and local time.
+    pub extracted_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestFile {
+    /// The entry name in the archive.
+    pub name: String,
+    pub size: u64,
+    /// `None` for entries stored without a CRC.
+    pub crc: Option<u32>,
+    /// BLAKE3 of the content in hex, set by `extract_to_cas`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a manifest always serializes")
+    }
+}
+
+/// `extract_with_manifest_options` with the default options.
+pub fn extract_with_manifest<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+) -> Result<Manifest, CascadeError> {
+    extract_with_manifest_options(path, password, dest, &ExtractOptions::default())
+}
+
+/// `extract_with_options`, describing the files of the archive that were
+/// extracted. Files skipped for `max_file_size` or the extension filters, or
+/// failing with `continue_on_entry_error`, are left out; those that `overwrite_policy` or
+/// `incremental` left alone are still listed, as their content is in `dest`
+/// either way.
+pub fn extract_with_manifest_options<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> Result<Manifest, CascadeError> {
+    let path = path.as_ref();
+    let stats = extract_with_options(path, password, dest, options)?;
+    let files = read_archive_entries_with_password(path, password)?
+        .into_iter()
+        .filter(|entry| !entry.is_directory())
+        .filter(|entry| {
+            !stats.too_large.iter().any(|name| name == entry.name())
+                && !stats.filtered.iter().any(|name| name == entry.name())
+                && !stats.failed_entries.iter().any(|f| f.name == entry.name())
+        })
+        .map(|entry| ManifestFile {
+            crc: entry.has_crc.then_some(entry.crc as u32),
+            size: entry.size(),
+            name: entry.name,
+            hash: None,
+        })
+        .collect();
+
+    let manifest = Manifest {
+        archive: path.to_path_buf(),
+        password: password.to_string(),
+        dest: dest.to_path_buf(),
+        files,
+        extracted_at: Local::now().to_rfc3339(),
+    };
+    if options.write_manifest {
+        fs::write(dest.join(MANIFEST_FILE_NAME), manifest.to_json())?;
+    }
+    Ok(manifest)
+}
+
+/// Where `extract_to_cas` keeps the content with BLAKE3 `hash` in
+/// `store_dir`: `aa/bb/aabb...`, after the first two bytes of the hash.
+pub fn cas_path(store_dir: &Path, hash: &str) -> PathBuf {
+    store_dir.join(&hash[..2]).join(&hash[2..4]).join(hash)
+}
+
+/// Writes the content of every file in the archive to the content-addressed
+/// store `store_dir`, see `cas_path`, instead of a folder tree. Files with
+/// the same content, in this archive or any other extracted into the same
+/// store, are stored once. The manifest maps each entry name to the `hash`
+/// of its content; its `dest` is `store_dir`. Symlinks and folders have no
+/// content of their own and are left out, as in `extract_to_sink`.
+pub fn extract_to_cas<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    store_dir: &Path,
+) -> Result<Manifest, CascadeError> {
+    let path = path.as_ref();
+    fs::create_dir_all(store_dir)?;
+    let mut sink = CasSink {
+        store_dir: store_dir.to_path_buf(),
+        current: None,
+        hashes: Vec::new(),
+    };
+    let result = extract_to_sink(path, password, &mut sink).and_then(|_| Ok(sink.finish()?));
+    if let Some(current) = sink.current.take() {
+        let _ = fs::remove_file(current.temp);
+    }
+    result?;
+
+    let files = read_archive_entries_with_password(path, password)?
+        .into_iter()
+        .filter_map(|entry| {
+            let relative = sink_path(entry.name())?;
+            let (_, hash) = sink.hashes.iter().find(|(path, _)| *path == relative)?;
+            Some(ManifestFile {
+                crc: entry.has_crc.then_some(entry.crc as u32),
+                size: entry.size(),
+                hash: Some(hash.clone()),
+                name: entry.name,
+            })
+        })
+        .collect();
+    Ok(Manifest {
+        archive: path.to_path_buf(),
+        password: password.to_string(),
+        dest: store_dir.to_path_buf(),
+        files,
+        extracted_at: Local::now().to_rfc3339(),
+    })
+}
+
+/// Tells apart the temporary files of concurrent extractions.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+struct CasFile {
+    path: PathBuf,
+    temp: PathBuf,
+    file: File,
+    hasher: blake3::Hasher,
+}
+
+/// Writes each file to a temporary name in the store, hashing it as it
+/// goes, and moves it to its `cas_path` once the next one starts.
+struct CasSink {
+    store_dir: PathBuf,
+    current: Option<CasFile>,
+    /// (path in the archive, hash) of every file stored
+    hashes: Vec<(PathBuf, String)>,
+}
+
+impl CasSink {
+    fn finish(&mut self) -> io::Result<()> {
+        let Some(mut current) = self.current.take() else {
+            return Ok(());
+        };
+        current.file.flush()?;
+        drop(current.file);
+        let hash = current.hasher.finalize().to_hex().to_string();
+        let target = cas_path(&self.store_dir, &hash);
+        if target.exists() {
+            fs::remove_file(&current.temp)?;
+        } else {
+            fs::create_dir_all(target.parent().unwrap())?;
+            fs::rename(&current.temp, &target)?;
+        }
+        self.hashes.push((current.path, hash));
+        Ok(())
+    }
+}
+
+impl FileSink for CasSink {
+    fn create_dir(&mut self, _path: &Path) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn create_file(&mut self, path: &Path) -> io::Result<()> {
+        self.finish()?;
+        let temp = self.store_dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.current = Some(CasFile {
+            path: path.to_path_buf(),
+            file: File::create(&temp)?,
+            temp,
+            hasher: blake3::Hasher::new(),
+        });
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.current {
+            Some(current) => {
+                current.hasher.update(data);
+                current.file.write_all(data)
+            }
+            None => Err(io::Error::other("write before create_file")),
+        }
+    }
+
+    /// The same content is shared by files with different times, so there
+    /// is none to keep.
+    fn set_mtime(&mut self, _path: &Path, _mtime: SystemTime) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_extract_with_manifest() {
+        let dest = test_dir("extract_with_manifest");
+        let manifest = extract_with_manifest("tests/7ziplogo_p.7z", "1151", &dest).unwrap();
+        assert_eq!(manifest.password, "1151");
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.files[0].name, "7ziplogo.png");
+        assert_eq!(
+            manifest.files[0].size,
+            fs::metadata("tests/7ziplogo.png").unwrap().len()
+        );
+        assert!(manifest.files[0].crc.is_some());
+        assert!(!dest.join(MANIFEST_FILE_NAME).exists());
+
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+        assert_eq!(json["files"][0]["name"], "7ziplogo.png");
+        assert_eq!(json["archive"], "tests/7ziplogo_p.7z");
+    }
+
+    #[test]
+    fn test_write_manifest() {
+        let dest = test_dir("write_manifest");
+        let options = ExtractOptions {
+            write_manifest: true,
+            ..Default::default()
+        };
+        let manifest =
+            extract_with_manifest_options("tests/sample.7z", "", &dest, &options).unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert_eq!(
+            fs::read_to_string(dest.join(MANIFEST_FILE_NAME)).unwrap(),
+            manifest.to_json()
+        );
+    }
+
+    #[test]
+    fn test_extract_to_cas() {
+        let store = test_dir("extract_to_cas");
+        let manifest = extract_to_cas("tests/7ziplogo_p.7z", "1151", &store).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        let hash = manifest.files[0].hash.clone().unwrap();
+        let stored = cas_path(&store, &hash);
+        assert!(stored.starts_with(store.join(&hash[..2]).join(&hash[2..4])));
+        assert_eq!(
+            fs::read(&stored).unwrap(),
+            fs::read("tests/7ziplogo.png").unwrap()
+        );
+
+        // the same image, from an archive without a password
+        let other = extract_to_cas("tests/7ziplogo.7z", "", &store).unwrap();
+        assert_eq!(other.files[0].hash, Some(hash));
+        let json: serde_json::Value = serde_json::from_str(&other.to_json()).unwrap();
+        assert!(json["files"][0]["hash"].is_string());
+
+        let manifest = extract_to_cas("tests/sample.7z", "", &store).unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert!(manifest
+            .files
+            .iter()
+            .all(|f| cas_path(&store, f.hash.as_ref().unwrap()).is_file()));
+        assert!(extract_to_cas("tests/7ziplogo_p.7z", "0000", &store).is_err());
+        let leftovers = fs::read_dir(&store)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Deserialize;
+
+use crate::{
+    default_threads_for, deserialize_optional_threads, valid_threads, ArchiveFormat,
+    AttributePolicy, CaseMode, Config, FolderCollisionPolicy, LogLanguage, OverwritePolicy,
+    SmartModePolicy, SymlinkPolicy, WatchFolder,
+};
+
+/// A config layer where every field is optional, e.g. environment variables
+/// or command line flags. Fields left as `None` keep the value of the layer
+/// below. Optional settings such as `max_bytes_per_sec` can be set by a
+/// layer but not cleared again.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PartialConfig {
+    pub config: PartialConfigSettings,
+    pub user: PartialUserConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PartialConfigSettings {
+    pub delete_archive: Option<bool>,
+    pub recursive_search: Option<bool>,
+    #[serde(deserialize_with = "deserialize_optional_threads")]
+    pub threads: Option<u8>,
+    pub dest: Option<String>,
+    pub smart_mode: Option<bool>,
+    pub smart_mode_policy: Option<SmartModePolicy>,
+    pub smart_mode_threshold: Option<u32>,
+    pub folder_collision: Option<FolderCollisionPolicy>,
+    pub adaptive_password_order: Option<bool>,
+    pub symlink_policy: Option<SymlinkPolicy>,
+    pub max_bytes_per_sec: Option<u64>,
+    pub max_compression_ratio: Option<f64>,
+    pub allow_high_ratio: Option<bool>,
+    pub max_entries: Option<usize>,
+    pub max_memory: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub cascade: Option<bool>,
+    pub max_cascade_depth: Option<usize>,
+    pub max_total_extracted_bytes: Option<u64>,
+    pub allow_extensions: Option<Vec<String>>,
+    pub skip_extensions: Option<Vec<String>>,
+    pub cleanup_on_failure: Option<bool>,
+    pub atomic_extraction: Option<bool>,
+    pub overwrite_policy: Option<OverwritePolicy>,
+    pub normalize_case: Option<CaseMode>,
+    pub continue_on_entry_error: Option<bool>,
+    pub entry_name_encoding: Option<String>,
+    pub normalize_separators: Option<bool>,
+    pub files_only: Option<bool>,
+    pub attribute_policy: Option<AttributePolicy>,
+    pub incremental: Option<bool>,
+    pub resume: Option<bool>,
+    pub allow_external_7z: Option<bool>,
+    pub enabled_formats: Option<Vec<ArchiveFormat>>,
+    pub signature_window: Option<usize>,
+    pub signature_cache_size: Option<usize>,
+    pub read_buffer_size: Option<usize>,
+    pub watch_interval_ms: Option<u64>,
+    pub max_concurrent_archives: Option<usize>,
+    pub max_open_files: Option<usize>,
+    pub ignore_hidden: Option<bool>,
+    pub log_language: Option<LogLanguage>,
+    pub log_passwords: Option<bool>,
+    pub log_file: Option<bool>,
+    pub log_file_max_bytes: Option<u64>,
+    pub use_filename_as_password: Option<bool>,
+    pub try_case_variants: Option<bool>,
+    pub low_priority: Option<bool>,
+    pub report_rate: Option<bool>,
+    pub max_runtime_secs: Option<u64>,
+    pub wordlists: Option<Vec<String>>,
+    pub post_command: Option<String>,
+    pub write_action_log: Option<bool>,
+    pub shard_index: Option<usize>,
+    pub shard_count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PartialUserConfig {
+    /// Replaces the whole list rather than appending to it.
+    pub passwords: Option<Vec<String>>,
+    pub watch_folders: Option<Vec<WatchFolder>>,
+}
+
+/// Applies `override_` on top of `base`. Layers are merged one at a time,
+/// lowest precedence first: defaults, then the file, the environment and
+/// finally the command line.
+pub fn merge_configs(base: Config, override_: PartialConfig) -> Config {
+    let mut merged = base;
+    let settings = &mut merged.config;
+    let partial = override_.config;
+
+    macro_rules! take {
+        ($($field:ident),* $(,)?) => {
+            $(
+                if let Some(value) = partial.$field {
+                    settings.$field = value;
+                }
+            )*
+        };
+    }
+    take!(
+        delete_archive,
+        recursive_search,
+        threads,
+        dest,
+        smart_mode,
+        smart_mode_policy,
+        smart_mode_threshold,
+        folder_collision,
+        adaptive_password_order,
+        symlink_policy,
+        max_compression_ratio,
+        allow_high_ratio,
+        cascade,
+        max_cascade_depth,
+        allow_extensions,
+        skip_extensions,
+        cleanup_on_failure,
+        atomic_extraction,
+        overwrite_policy,
+        continue_on_entry_error,
+        normalize_separators,
+        files_only,
+        attribute_policy,
+        incremental,
+        resume,
+        allow_external_7z,
+        enabled_formats,
+        signature_window,
+        signature_cache_size,
+        read_buffer_size,
+        watch_interval_ms,
+        max_concurrent_archives,
+        max_open_files,
+        ignore_hidden,
+        log_passwords,
+        log_file,
+        log_file_max_bytes,
+        use_filename_as_password,
+        try_case_variants,
+        low_priority,
+        report_rate,
+        wordlists,
+        write_action_log,
+        shard_index,
+        shard_count,
+    );
+    if partial.max_bytes_per_sec.is_some() {
+        settings.max_bytes_per_sec = partial.max_bytes_per_sec;
+    }
+    if partial.log_language.is_some() {
+        settings.log_language = partial.log_language;
+    }
+    if partial.max_runtime_secs.is_some() {
+        settings.max_runtime_secs = partial.max_runtime_secs;
+    }
+    if partial.max_entries.is_some() {
+        settings.max_entries = partial.max_entries;
+    }
+    if partial.max_memory.is_some() {
+        settings.max_memory = partial.max_memory;
+    }
+    if partial.max_file_size.is_some() {
+        settings.max_file_size = partial.max_file_size;
+    }
+    if partial.max_total_extracted_bytes.is_some() {
+        settings.max_total_extracted_bytes = partial.max_total_extracted_bytes;
+    }
+    if partial.normalize_case.is_some() {
+        settings.normalize_case = partial.normalize_case;
+    }
+    if partial.entry_name_encoding.is_some() {
+        settings.entry_name_encoding = partial.entry_name_encoding;
+    }
+    if partial.post_command.is_some() {
+        settings.post_command = partial.post_command;
+    }
+    if override_.user.passwords.is_some() {
+        merged.user.passwords = override_.user.passwords;
+    }
+    if override_.user.watch_folders.is_some() {
+        merged.user.watch_folders = override_.user.watch_folders;
+    }
+    // same rule as `read_config`, for the merged destination
+    if !valid_threads(merged.config.threads) {
+        merged.config.threads = default_threads_for(&merged);
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_config, recommended_threads};
+
+    #[test]
+    fn test_merge_configs() {
+        let env: PartialConfig = toml::from_str(
+            r#"
+            [config]
+            dest = "from-env"
+            threads = 2
+            "#,
+        )
+        .unwrap();
+        let cli = PartialConfig {
+            config: PartialConfigSettings {
+                threads: Some(6),
+                normalize_case: Some(CaseMode::Lower),
+                ..Default::default()
+            },
+            user: PartialUserConfig {
+                passwords: Some(vec!["1151".to_string()]),
+                ..Default::default()
+            },
+        };
+
+        let config = merge_configs(merge_configs(read_config().unwrap(), env), cli);
+        assert_eq!(config.config.dest, "from-env");
+        assert_eq!(config.config.threads, 6);
+        assert_eq!(config.config.normalize_case, Some(CaseMode::Lower));
+        assert!(config.config.delete_archive);
+        assert_eq!(config.user.passwords.unwrap(), ["1151"]);
+        assert_eq!(config.user.watch_folders.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_merge_configs_rejects_bad_threads() {
+        let partial = PartialConfig {
+            config: PartialConfigSettings {
+                threads: Some(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = merge_configs(read_config().unwrap(), partial);
+        assert_eq!(config.config.threads as usize, recommended_threads());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{read_wordlist, CascadeError, Shard};
+
+/// How many passwords a `PasswordCache` keeps by default.
+pub const DEFAULT_PASSWORD_CACHE_SIZE: usize = 16;
+
+/// Passwords that recently opened an archive, most recent first. Archives
+/// from the same source usually share a password, so these are tried before
+/// the rest of the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordCache {
+    passwords: Vec<String>,
+    capacity: usize,
+}
+
+impl PasswordCache {
+    pub fn new(capacity: usize) -> Self {
+        PasswordCache {
+            passwords: Vec::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Reads a cache written by `save`. A missing file gives an empty cache,
+    /// so the first run needs no special casing.
+    pub fn load<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, CascadeError> {
+        let mut cache = PasswordCache::new(capacity);
+        match read_wordlist(path, Shard::ALL) {
+            Ok(passwords) => {
+                // the file is most recent first, record oldest first
+                for password in passwords.iter().rev() {
+                    cache.record(password);
+                }
+                Ok(cache)
+            }
+            Err(CascadeError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(cache),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes one password per line, most recent first, in the format of
+    /// `read_wordlist`. Passwords that start with `#` or with whitespace
+    /// don't survive the round trip.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut content = self.passwords.join("\n");
+        content.push('\n');
+        fs::write(path, content)
+    }
+
+    /// Adds `passwords` as if each had just worked, the last one ending up
+    /// first.
+    pub fn seed<I: IntoIterator<Item = S>, S: AsRef<str>>(&mut self, passwords: I) {
+        for password in passwords {
+            self.record(password.as_ref());
+        }
+    }
+
+    /// Moves `password` to the front, dropping the oldest entry when full.
+    pub fn record(&mut self, password: &str) {
+        self.passwords.retain(|p| p != password);
+        self.passwords.insert(0, password.to_string());
+        self.passwords.truncate(self.capacity);
+    }
+
+    /// Most recent first.
+    pub fn passwords(&self) -> &[String] {
+        &self.passwords
+    }
+
+    /// The cached passwords followed by the rest of `passwords`, each one
+    /// appearing once. Cached passwords are tried even when `passwords`
+    /// doesn't contain them, e.g. when seeded from an earlier run.
+    pub fn ordered(&self, passwords: &[Arc<String>]) -> Vec<Arc<String>> {
+        let mut ordered: Vec<Arc<String>> =
+            Vec::with_capacity(passwords.len() + self.passwords.len());
+        for cached in &self.passwords {
+            match passwords.iter().find(|p| p.as_str() == cached) {
+                Some(password) => ordered.push(password.clone()),
+                None => ordered.push(Arc::new(cached.clone())),
+            }
+        }
+        ordered.extend(
+            passwords
+                .iter()
+                .filter(|p| !self.passwords.iter().any(|cached| cached == p.as_str()))
+                .cloned(),
+        );
+        ordered
+    }
+}
+
+impl Default for PasswordCache {
+    fn default() -> Self {
+        PasswordCache::new(DEFAULT_PASSWORD_CACHE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_password_cache_order() {
+        let mut cache = PasswordCache::new(2);
+        cache.seed(["a", "b", "c"]);
+        assert_eq!(cache.passwords(), ["c", "b"]);
+        cache.record("b");
+        assert_eq!(cache.passwords(), ["b", "c"]);
+
+        let passwords: Vec<Arc<String>> = ["x", "c", "y", "b"]
+            .iter()
+            .map(|p| Arc::new(p.to_string()))
+            .collect();
+        let ordered = |cache: &PasswordCache| -> Vec<String> {
+            cache
+                .ordered(&passwords)
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        };
+        assert_eq!(ordered(&cache), ["b", "c", "x", "y"]);
+
+        cache.record("z");
+        assert_eq!(ordered(&cache), ["z", "b", "x", "c", "y"]);
+    }
+
+    #[test]
+    fn test_password_cache_persistence() {
+        let dir = test_dir("password_cache");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.txt");
+        assert_eq!(
+            PasswordCache::load(&path, 4).unwrap(),
+            PasswordCache::new(4)
+        );
+
+        let mut cache = PasswordCache::new(4);
+        cache.seed(["1111", "1151"]);
+        cache.save(&path).unwrap();
+        assert_eq!(PasswordCache::load(&path, 4).unwrap(), cache);
+    }
+}
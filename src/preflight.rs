@@ -0,0 +1,291 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    archive_total_size, check_archive_limits, ArchiveFormat, CascadeError, Config, ExtractOptions,
+};
+
+/// Something that would make a batch fail, found by `preflight`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreflightProblem {
+    /// Nothing can be written in `dest`, or the folder it would be created
+    /// in.
+    DestNotWritable(String),
+    /// The archives unpack to more than is free on the disk of `dest`.
+    NotEnoughSpace {
+        needed: u64,
+        available: u64,
+    },
+    /// The archive can't be opened or its header can't be read.
+    Unreadable(String),
+    /// `None` when no known signature matched.
+    UnsupportedFormat(Option<ArchiveFormat>),
+    SuspiciousRatio(f64),
+    TooManyEntries {
+        count: usize,
+        max: usize,
+    },
+    MemoryLimitExceeded {
+        needed: u64,
+        limit: u64,
+    },
+}
+
+/// What `preflight` found out about one archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveCheck {
+    pub path: PathBuf,
+    pub format: Option<ArchiveFormat>,
+    /// The size of the contents, when it can be told without extracting:
+    /// not for gzip, and not for 7z archives with an encrypted header.
+    pub unpacked_size: Option<u64>,
+    pub problems: Vec<PreflightProblem>,
+}
+
+impl ArchiveCheck {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreflightReport {
+    /// Problems with `dest` as a whole.
+    pub dest_problems: Vec<PreflightProblem>,
+    /// One entry per archive, in the order given.
+    pub archives: Vec<ArchiveCheck>,
+    /// The sum of the known `unpacked_size`s.
+    pub total_size: u64,
+    /// Free space on the disk of `dest`, `None` when it can't be told.
+    pub available_space: Option<u64>,
+}
+
+impl PreflightReport {
+    pub fn is_ok(&self) -> bool {
+        self.dest_problems.is_empty() && self.archives.iter().all(ArchiveCheck::is_ok)
+    }
+}
+
+/// Runs every check that can be done before extracting `paths` into `dest`,
+/// so all problems can be shown at once instead of failing one archive at a
+/// time: whether `dest` is writable and has room for everything, and for
+/// each archive whether it is readable, in a supported and enabled format,
+/// and within `max_entries`, `max_compression_ratio` and `max_memory`.
+///
+/// Nothing is created; `dest` may not exist yet, in which case the first
+/// existing folder above it is checked. Archives with an encrypted header
+/// can't be listed without the password, their size and limits are left
+/// unchecked.
+pub fn preflight(paths: &[PathBuf], dest: &Path, config: &Config) -> PreflightReport {
+    let options = ExtractOptions::from_config(&config.config);
+    let archives: Vec<ArchiveCheck> = paths
+        .iter()
+        .map(|path| check_archive(path, config, &options))
+        .collect();
+    let total_size = archives.iter().filter_map(|a| a.unpacked_size).sum();
+
+    let mut dest_problems = Vec::new();
+    let existing = dest.ancestors().find(|dir| dir.is_dir());
+    let available_space = existing.and_then(|dir| available_space(dir).ok());
+    match existing {
+        Some(dir) => {
+            if let Err(e) = check_writable(dir) {
+                dest_problems.push(PreflightProblem::DestNotWritable(e.to_string()));
+            }
+        }
+        None => dest_problems.push(PreflightProblem::DestNotWritable(format!(
+            "no existing folder above {}",
+            dest.display()
+        ))),
+    }
+    if let Some(available) = available_space {
+        if total_size > available {
+            dest_problems.push(PreflightProblem::NotEnoughSpace {
+                needed: total_size,
+                available,
+            });
+        }
+    }
+
+    PreflightReport {
+        dest_problems,
+        archives,
+        total_size,
+        available_space,
+    }
+}
+
+fn check_archive(path: &Path, config: &Config, options: &ExtractOptions) -> ArchiveCheck {
+    let mut check = ArchiveCheck {
+        path: path.to_path_buf(),
+        format: None,
+        unpacked_size: None,
+        problems: Vec::new(),
+    };
+    match config.config.detect_archive_format(path) {
+        Ok(format) => check.format = format,
+        Err(e) => {
+            check
+                .problems
+                .push(PreflightProblem::Unreadable(e.to_string()));
+            return check;
+        }
+    }
+    match check.format {
+        Some(ArchiveFormat::SevenZ) => {}
+        // tar isn't compressed, its contents take up about as much as it does
+        Some(ArchiveFormat::Tar) => {
+            check.unpacked_size = fs::metadata(path).ok().map(|m| m.len());
+            return check;
+        }
+        Some(format) if format.is_supported() => return check,
+        format => {
+            check
+                .problems
+                .push(PreflightProblem::UnsupportedFormat(format));
+            return check;
+        }
+    }
+
+    let problem = match check_archive_limits(path, "", options) {
+        Ok(()) => None,
+        Err(CascadeError::SevenZ(sevenz_rust::Error::PasswordRequired)) => return check,
+        Err(CascadeError::SuspiciousRatio { ratio }) => {
+            Some(PreflightProblem::SuspiciousRatio(ratio))
+        }
+        Err(CascadeError::TooManyEntries { count, max }) => {
+            Some(PreflightProblem::TooManyEntries { count, max })
+        }
+        Err(CascadeError::MemoryLimitExceeded { needed, limit }) => {
+            Some(PreflightProblem::MemoryLimitExceeded { needed, limit })
+        }
+        Err(e) => {
+            check
+                .problems
+                .push(PreflightProblem::Unreadable(e.to_string()));
+            return check;
+        }
+    };
+    check.problems.extend(problem);
+    check.unpacked_size = archive_total_size(path).ok();
+    check
+}
+
+/// Creates and removes a file in `dir`.
+fn check_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(
+        ".cascading-extract-preflight-{}",
+        std::process::id()
+    ));
+    fs::File::create(&probe)?;
+    fs::remove_file(probe)
+}
+
+/// The space left for the current user on the disk holding `path`, in
+/// bytes.
+pub fn available_space<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    #[cfg(unix)]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let wide: Vec<u16> = path
+            .as_ref()
+            .as_os_str()
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+        let mut available = 0u64;
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                wide.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(available)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "free space can't be told on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_config;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_preflight() {
+        let dir = test_dir("preflight");
+        fs::create_dir_all(&dir).unwrap();
+        let unsupported = dir.join("notes.xz");
+        fs::write(&unsupported, [0xfd, b'7', b'z', b'X', b'Z', 0x00, 0, 0]).unwrap();
+        let paths = vec![
+            PathBuf::from("tests/sample.7z"),
+            PathBuf::from("tests/7ziplogo_p.7z"),
+            unsupported.clone(),
+            dir.join("missing.7z"),
+        ];
+        let mut config = read_config().unwrap();
+        config.config.max_entries = Some(4);
+
+        let report = preflight(&paths, &dir.join("out/nested"), &config);
+        assert!(
+            report.dest_problems.is_empty(),
+            "{:?}",
+            report.dest_problems
+        );
+        assert!(!dir.join("out").exists());
+        assert!(report.available_space.is_some());
+        assert!(!report.is_ok());
+
+        let [sample, encrypted, xz, missing] = &report.archives[..] else {
+            panic!("{:?}", report.archives);
+        };
+        assert_eq!(
+            sample.problems,
+            [PreflightProblem::TooManyEntries { count: 5, max: 4 }]
+        );
+        assert_eq!(
+            sample.unpacked_size,
+            Some(archive_total_size(&paths[0]).unwrap())
+        );
+        assert!(encrypted.is_ok());
+        assert_eq!(encrypted.unpacked_size, None);
+        assert_eq!(
+            xz.problems,
+            [PreflightProblem::UnsupportedFormat(Some(ArchiveFormat::Xz))]
+        );
+        assert!(matches!(
+            missing.problems[..],
+            [PreflightProblem::Unreadable(_)]
+        ));
+        assert_eq!(report.total_size, sample.unpacked_size.unwrap());
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::{extract_with_progress, CascadeError, ExtractOptions, ExtractStats};
+
+const TEMPLATE: &str = "{bar:30} {percent:>3}% {binary_bytes_per_sec:>12} ETA {eta:>4} {wide_msg}";
+
+/// `extract_with_progress` drawing a progress bar on stderr, with the file
+/// being decoded, how far along the archive is, the speed and the time
+/// left. The bar is cleared once the extraction ends either way. Needs the
+/// `progress_bar` feature.
+pub fn extract_with_progress_bar<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractStats, CascadeError> {
+    let bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template(TEMPLATE)
+            .expect("the template is valid")
+            .progress_chars("=> "),
+    );
+    let mut current = String::new();
+    let result = extract_with_progress(path, password, dest, options, |progress| {
+        if progress.entry != current {
+            current = progress.entry.to_string();
+            bar.set_message(current.clone());
+        }
+        bar.set_length(progress.total_bytes);
+        bar.set_position(progress.bytes_done);
+    });
+    bar.finish_and_clear();
+    result
+}
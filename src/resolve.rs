@@ -0,0 +1,284 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::i18n::log_tr;
+use crate::{detect_archive_format, find_archives, CascadeError};
+
+/// Turns paths and glob patterns such as `downloads/**/*.7z` into the
+/// archives they name, in the order of the patterns and without duplicates.
+/// Files that aren't detected as an archive are left out, and folders,
+/// whether named or matched, are searched with `find_archives`, `recursive`
+/// deciding whether their subfolders are too.
+///
+/// Within a path component `*` matches any run of characters, `?` a single
+/// one and `[abc]`, `[a-z]` or `[!abc]` one of a set; `**` on its own
+/// matches any number of folders. As in a shell, wildcards only match names
+/// starting with a dot when the pattern spells out the dot. A pattern that
+/// matches no archive is logged and otherwise ignored; an invalid one, such
+/// as an unclosed `[`, is an error.
+pub fn resolve_paths(patterns: &[String], recursive: bool) -> Result<Vec<PathBuf>, CascadeError> {
+    let mut seen = HashSet::new();
+    let mut archives = Vec::new();
+    for pattern in patterns {
+        let mut found = Vec::new();
+        for path in expand_pattern(pattern)? {
+            if path.is_dir() {
+                found.extend(find_archives(&path, recursive)?);
+            } else if let Ok(Some(_)) = detect_archive_format(&path) {
+                found.push(path);
+            }
+        }
+        if found.is_empty() {
+            log_tr!(
+                warn,
+                "{} 没有匹配到任何压缩包",
+                "{} matches no archives",
+                pattern
+            );
+        }
+        archives.extend(found.into_iter().filter(|path| seen.insert(path.clone())));
+    }
+    Ok(archives)
+}
+
+/// The existing paths `pattern` matches, sorted.
+pub(crate) fn expand_pattern(pattern: &str) -> Result<BTreeSet<PathBuf>, CascadeError> {
+    let mut base = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in Path::new(pattern).components() {
+        match component {
+            Component::Normal(name) if rest.is_empty() && !has_wildcard(name) => base.push(name),
+            Component::Normal(name) => {
+                let name = name.to_string_lossy().into_owned();
+                check_pattern(&name, pattern)?;
+                rest.push(name);
+            }
+            _ if rest.is_empty() => base.push(component),
+            // `..` and `.` after a wildcard
+            _ => rest.push(component.as_os_str().to_string_lossy().into_owned()),
+        }
+    }
+    if base.as_os_str().is_empty() {
+        base.push(".");
+    }
+    let mut matched = BTreeSet::new();
+    expand(&base, &rest, &mut matched);
+    // keep `./` off the paths when the pattern started with a wildcard
+    if pattern.starts_with('.') {
+        return Ok(matched);
+    }
+    Ok(matched
+        .into_iter()
+        .map(|path| match path.strip_prefix(".") {
+            Ok(stripped) => stripped.to_path_buf(),
+            Err(_) => path,
+        })
+        .collect())
+}
+
+fn expand(base: &Path, rest: &[String], matched: &mut BTreeSet<PathBuf>) {
+    let Some((first, rest_after)) = rest.split_first() else {
+        if fs::symlink_metadata(base).is_ok() {
+            matched.insert(base.to_path_buf());
+        }
+        return;
+    };
+    if !has_wildcard(first.as_ref()) {
+        return expand(&base.join(first), rest_after, matched);
+    }
+    if first == "**" {
+        expand(base, rest_after, matched);
+    }
+    let entries = match fs::read_dir(base) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            log_tr!(
+                warn,
+                "无法读取文件夹 {}，已跳过: {}",
+                "cannot read folder {}, skipped: {}",
+                base.display(),
+                e
+            );
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let path = entry.path();
+        if first == "**" {
+            // symlinked folders aren't followed, they could loop
+            if !name.starts_with('.') && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                expand(&path, rest, matched);
+            }
+        } else if matches_name(first, &name) {
+            expand(&path, rest_after, matched);
+        }
+    }
+}
+
+pub(crate) fn has_wildcard(name: &std::ffi::OsStr) -> bool {
+    name.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn check_pattern(name: &str, pattern: &str) -> Result<(), CascadeError> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut i = 0;
+    let mut valid = !name.contains("**") || name == "**";
+    while valid && i < chars.len() {
+        if chars[i] == '[' {
+            match set_end(&chars[i + 1..]) {
+                Some(end) => i += end + 1,
+                None => valid = false,
+            }
+        }
+        i += 1;
+    }
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid pattern {:?}", pattern),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Where the `]` closing the set that starts `pattern` is, the part after
+/// the `[`. A `]` right after the `[` or `[!` belongs to the set.
+fn set_end(pattern: &[char]) -> Option<usize> {
+    let start = match pattern.first() {
+        Some('!') => 2,
+        _ => 1,
+    };
+    let offset = pattern.get(start..)?.iter().position(|&c| c == ']')?;
+    Some(start + offset)
+}
+
+/// Whether the file name `name` matches the single component `pattern`.
+fn matches_name(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+/// Walks the pattern and the name side by side. On a mismatch the last `*`
+/// takes one more character and the walk resumes after it. Earlier stars
+/// never need to be revisited, so this takes at most the pattern's length
+/// times the name's steps instead of trying every way to split the name.
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // the pattern after the last `*`, and where in the name it resumes
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some(next) = match_one(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+        } else if let Some((after, from)) = star {
+            p = after;
+            n = from + 1;
+            star = Some((after, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Where the pattern continues if its token at `p` matches `c`.
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '*' => None,
+        '?' => Some(p + 1),
+        '[' => {
+            let rest = &pattern[p + 1..];
+            // checked by `check_pattern`
+            let end = set_end(rest)?;
+            let (negated, set) = match rest[0] {
+                '!' => (true, &rest[1..end]),
+                _ => (false, &rest[..end]),
+            };
+            let mut in_set = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    in_set |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    in_set |= set[i] == c;
+                    i += 1;
+                }
+            }
+            (in_set != negated).then_some(p + end + 2)
+        }
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_matches_name() {
+        assert!(matches_name("*.7z", "a.7z"));
+        assert!(!matches_name("*.7z", "a.7z.part"));
+        assert!(!matches_name("*.7z", ".hidden.7z"));
+        assert!(matches_name(".*.7z", ".hidden.7z"));
+        assert!(matches_name("part?.7z", "part1.7z"));
+        assert!(matches_name("part[0-9].7z", "part5.7z"));
+        assert!(!matches_name("part[!0-9].7z", "part5.7z"));
+        assert!(matches_name("[]x].7z", "].7z"));
+        assert!(!matches_name("[a-c].7z", "-.7z"));
+        assert!(matches_name("*a*b", "xaxxab"));
+        assert!(!matches_name("*a", "xab"));
+        // would take ages with backtracking over every split
+        let name = "a".repeat(200);
+        assert!(!matches_name(&format!("{}b", "*a".repeat(20)), &name));
+        assert!(check_pattern("[[]", "[[]").is_ok());
+        assert!(check_pattern("part[0-9.7z", "part[0-9.7z").is_err());
+        assert!(check_pattern("a**", "a**").is_err());
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let dir = test_dir("resolve_paths");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::copy("tests/sample.7z", dir.join("top.7z")).unwrap();
+        fs::copy("tests/sample.7z", dir.join("a/one.7z")).unwrap();
+        fs::copy("tests/7ziplogo.7z", dir.join("a/b/two.7z")).unwrap();
+        // not an archive, whatever the name says
+        fs::write(dir.join("a/fake.7z"), "text").unwrap();
+
+        let pattern = |p: &str| format!("{}/{}", dir.display(), p);
+        assert_eq!(
+            resolve_paths(&[pattern("**/*.7z")], false).unwrap(),
+            [
+                dir.join("a/b/two.7z"),
+                dir.join("a/one.7z"),
+                dir.join("top.7z")
+            ]
+        );
+        assert_eq!(
+            resolve_paths(&[pattern("a/*.7z"), pattern("a")], false).unwrap(),
+            [dir.join("a/one.7z")]
+        );
+        assert_eq!(
+            resolve_paths(&[pattern("a")], true).unwrap().len(),
+            2,
+            "a folder is searched like find_archives does"
+        );
+        assert!(resolve_paths(&[pattern("*.rar")], true).unwrap().is_empty());
+        assert!(resolve_paths(&[pattern("[a.7z")], true).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::i18n::log_tr;
+use crate::throttle::RateLimiter;
+use crate::{catch_backend_panic, sink_path, write_entry, CascadeError, ExtractStats};
+
+/// How far into a file the 7z payload of a self-extracting archive is looked
+/// for. The stubs 7-Zip ships are a few hundred KiB.
+pub const SFX_SCAN_LIMIT: u64 = 4 * 1024 * 1024;
+
+pub(crate) const SIGNATURE: [u8; 6] = [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c];
+pub(crate) const CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Where the 7z payload of a self-extracting archive starts, or `None` when
+/// there is none in the first `SFX_SCAN_LIMIT` bytes. The stub itself
+/// usually contains the signature bytes too, so a match only counts when
+/// the start header after it has a valid CRC and points inside the file.
+/// A plain 7z, with the signature at offset 0, gives `None` as well.
+pub fn sfx_payload_offset<P: AsRef<Path>>(path: P) -> io::Result<Option<u64>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file)
+        .take(SFX_SCAN_LIMIT + 32)
+        .read_to_end(&mut head)?;
+
+    Ok((1..head.len().saturating_sub(31))
+        .find(|&offset| is_start_header(&head[offset..offset + 32], len - offset as u64))
+        .map(|offset| offset as u64))
+}
+
+/// Whether `header` is a 7z signature header whose next header lies within
+/// the `remaining` bytes of the file.
+pub(crate) fn is_start_header(header: &[u8], remaining: u64) -> bool {
+    if header[..6] != SIGNATURE {
+        return false;
+    }
+    let crc = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    if CRC32.checksum(&header[12..32]) != crc {
+        return false;
+    }
+    let next_header_offset = u64::from_le_bytes(header[12..20].try_into().unwrap());
+    let next_header_size = u64::from_le_bytes(header[20..28].try_into().unwrap());
+    32u64
+        .checked_add(next_header_offset)
+        .and_then(|end| end.checked_add(next_header_size))
+        .is_some_and(|end| end <= remaining)
+}
+
+/// Extracts the 7z payload of a self-extracting archive to `dest` without
+/// running the stub. Files that aren't SFX archives fail with
+/// `InvalidData`. Entries whose name would leave `dest` are skipped with a
+/// warning, the payload of an `.exe` from anywhere is no more trustworthy
+/// than the stub.
+pub fn extract_sfx<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    dest: &Path,
+) -> Result<ExtractStats, CascadeError> {
+    let path = path.as_ref();
+    let Some(offset) = sfx_payload_offset(path)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no 7z payload found in {}", path.display()),
+        )
+        .into());
+    };
+    log_tr!(
+        info,
+        "在偏移 {} 处找到自解压包的 7z 数据: {}",
+        "found the 7z payload of an SFX archive at offset {}: {}",
+        offset,
+        path.display()
+    );
+
+    catch_backend_panic(path, || {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() - offset;
+        let payload = Payload::new(file, offset)?;
+        let mut reader = sevenz_rust::SevenZReader::new(payload, len, password.into())?;
+        let mut stats = ExtractStats::default();
+        let mut limiter = RateLimiter::for_rate(None);
+        reader.for_each_entries(|entry, data| {
+            let Some(relative) = sink_path(entry.name()) else {
+                log_tr!(
+                    warn,
+                    "{} 会写到解压目标之外，跳过",
+                    "skipping {}, it would be written outside the destination",
+                    entry.name()
+                );
+                io::copy(data, &mut io::sink())?;
+                stats.skipped += 1;
+                return Ok(true);
+            };
+            write_entry(entry, data, &dest.join(relative), false, limiter.as_mut())?;
+            if entry.is_directory() {
+                stats.directories += 1;
+            } else {
+                stats.files += 1;
+                stats.bytes_written += entry.size();
+            }
+            Ok(true)
+        })?;
+        Ok(stats)
+    })
+}
+
+/// The part of a file from `offset` on, seen as a file of its own.
+struct Payload {
+    file: File,
+    offset: u64,
+}
+
+impl Payload {
+    fn new(mut file: File, offset: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Payload { file, offset })
+    }
+}
+
+impl Read for Payload {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for Payload {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(self.offset + pos),
+            pos => pos,
+        };
+        let absolute = self.file.seek(pos)?;
+        absolute
+            .checked_sub(self.offset)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the payload"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// `tests/sample.7z` behind a made up stub that contains the signature
+    /// bytes, as real stubs do.
+    fn sfx_archive(dir: &Path) -> (PathBuf, u64) {
+        sfx_archive_of(dir, Path::new("tests/sample.7z"))
+    }
+
+    fn sfx_archive_of(dir: &Path, payload: &Path) -> (PathBuf, u64) {
+        let mut data = b"MZ\x90\x00".to_vec();
+        data.resize(100, 0);
+        data.extend_from_slice(&SIGNATURE);
+        data.resize(1024, 0xcc);
+        let offset = data.len() as u64;
+        data.extend(fs::read(payload).unwrap());
+        let path = dir.join("setup.exe");
+        fs::write(&path, data).unwrap();
+        (path, offset)
+    }
+
+    #[test]
+    fn test_sfx_payload_offset() {
+        let dir = test_dir("sfx_payload_offset");
+        fs::create_dir_all(&dir).unwrap();
+        let (path, offset) = sfx_archive(&dir);
+        assert!(!crate::is_7z(&path).unwrap());
+        assert_eq!(sfx_payload_offset(&path).unwrap(), Some(offset));
+        assert_eq!(sfx_payload_offset("tests/sample.7z").unwrap(), None);
+        assert_eq!(sfx_payload_offset("tests/7ziplogo.png").unwrap(), None);
+    }
+
+    #[test]
+    fn test_extract_sfx() {
+        let dir = test_dir("extract_sfx");
+        fs::create_dir_all(&dir).unwrap();
+        let (path, _) = sfx_archive(&dir);
+        let stats = extract_sfx(&path, "", &dir.join("out")).unwrap();
+        assert_eq!(stats.files, 3);
+        assert_eq!(
+            fs::read(dir.join("out/7zFormat.txt")).unwrap(),
+            fs::read("tests/7zFormat.txt").unwrap()
+        );
+        assert!(extract_sfx("tests/7ziplogo.png", "", &dir.join("png")).is_err());
+    }
+
+    #[test]
+    fn test_extract_sfx_skips_unsafe_names() {
+        let dir = test_dir("extract_sfx_unsafe");
+        fs::create_dir_all(&dir).unwrap();
+        let payload = dir.join("payload.7z");
+        let mut writer = sevenz_rust::SevenZWriter::create(&payload).unwrap();
+        for name in ["../evil.txt", "ok.txt"] {
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some("x".as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let (path, _) = sfx_archive_of(&dir, &payload);
+
+        let stats = extract_sfx(&path, "", &dir.join("out")).unwrap();
+        assert_eq!((stats.files, stats.skipped), (1, 1));
+        assert!(dir.join("out/ok.txt").exists());
+        assert!(!dir.join("evil.txt").exists());
+    }
+}
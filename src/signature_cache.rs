@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+
+use crate::{detect_archive_format_with, ArchiveFormat};
+
+/// How many files `detect_archive_format_cached` remembers by default.
+pub const DEFAULT_SIGNATURE_CACHE_SIZE: usize = 1024;
+
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_SIGNATURE_CACHE_SIZE);
+static CACHE: LazyLock<Mutex<SignatureCache>> =
+    LazyLock::new(|| Mutex::new(SignatureCache::default()));
+
+/// Sets how many files `detect_archive_format_cached` remembers, from now
+/// on; the least recently used are forgotten first. `read_config` calls
+/// this with `signature_cache_size`. 0 turns the cache off.
+pub fn set_signature_cache_size(size: usize) {
+    CACHE_SIZE.store(size, Ordering::Relaxed);
+    CACHE.lock().unwrap().shrink_to(size);
+}
+
+/// Forgets every format `detect_archive_format_cached` remembers.
+/// `register_extractor` calls this, files seen before may be of the new
+/// format.
+pub fn clear_signature_cache() {
+    CACHE.lock().unwrap().clear();
+}
+
+/// `detect_archive_format_with`, remembering the result for the file as long
+/// as its size and modification time stay the same, so the watcher doesn't
+/// read the head of every file in a busy folder on each poll. Files
+/// without a modification time are read every time.
+pub fn detect_archive_format_cached<P: AsRef<Path>>(
+    path: P,
+    window: usize,
+    enabled: &[ArchiveFormat],
+) -> io::Result<Option<ArchiveFormat>> {
+    let path = path.as_ref();
+    let size = CACHE_SIZE.load(Ordering::Relaxed);
+    let metadata = fs::metadata(path)?;
+    let (true, Ok(modified)) = (size > 0, metadata.modified()) else {
+        return detect_archive_format_with(path, window, enabled);
+    };
+    let key = CacheKey {
+        path: path.to_path_buf(),
+        len: metadata.len(),
+        modified,
+        window,
+        enabled: enabled.to_vec(),
+    };
+    if let Some(format) = CACHE.lock().unwrap().get(&key) {
+        return Ok(format);
+    }
+    // read without holding the lock, other threads may detect meanwhile
+    let format = detect_archive_format_with(path, window, enabled)?;
+    CACHE.lock().unwrap().insert(key, format, size);
+    Ok(format)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+    window: usize,
+    enabled: Vec<ArchiveFormat>,
+}
+
+/// The detected formats with when each was last used. `recency` orders the
+/// keys by that time and `paths` finds the one entry kept per file, so a
+/// lookup, an insert and an eviction each cost a logarithm of the size
+/// rather than a scan of the whole cache on every poll of a busy folder.
+#[derive(Debug, Default)]
+struct SignatureCache {
+    entries: HashMap<CacheKey, (Option<ArchiveFormat>, u64)>,
+    recency: BTreeMap<u64, CacheKey>,
+    paths: HashMap<PathBuf, CacheKey>,
+    clock: u64,
+}
+
+impl SignatureCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Option<ArchiveFormat>> {
+        self.clock += 1;
+        let (format, used) = self.entries.get_mut(key)?;
+        let key = self.recency.remove(used).expect("every entry has a use");
+        *used = self.clock;
+        self.recency.insert(self.clock, key);
+        Some(*format)
+    }
+
+    fn insert(&mut self, key: CacheKey, format: Option<ArchiveFormat>, size: usize) {
+        // an older entry for the same path is stale now
+        if let Some(stale) = self.paths.get(&key.path).cloned() {
+            self.remove(&stale);
+        }
+        self.shrink_to(size.saturating_sub(1));
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.paths.insert(key.path.clone(), key.clone());
+        self.entries.insert(key, (format, self.clock));
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.recency.remove(&used);
+            self.paths.remove(&key.path);
+        }
+    }
+
+    fn shrink_to(&mut self, size: usize) {
+        while self.entries.len() > size {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.paths.remove(&oldest.path);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.paths.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_signature_cache_evicts_least_recently_used() {
+        let key = |name: &str| CacheKey {
+            path: PathBuf::from(name),
+            len: 1,
+            modified: SystemTime::UNIX_EPOCH,
+            window: 16,
+            enabled: ArchiveFormat::ALL.to_vec(),
+        };
+        let mut cache = SignatureCache::default();
+        cache.insert(key("a"), Some(ArchiveFormat::SevenZ), 2);
+        cache.insert(key("b"), None, 2);
+        assert_eq!(cache.get(&key("a")), Some(Some(ArchiveFormat::SevenZ)));
+        cache.insert(key("c"), None, 2);
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")), Some(Some(ArchiveFormat::SevenZ)));
+
+        // a changed file replaces what was known about it
+        let changed = CacheKey { len: 2, ..key("a") };
+        cache.insert(changed.clone(), None, 2);
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(&changed), Some(None));
+        assert_eq!(cache.recency.len(), 2);
+        assert_eq!(cache.paths.len(), 2);
+
+        // the file used longest ago goes first, whatever the insert order
+        assert_eq!(cache.get(&key("c")), Some(None));
+        cache.insert(key("d"), None, 2);
+        assert_eq!(cache.get(&changed), None);
+        assert_eq!(cache.get(&key("c")), Some(None));
+
+        cache.shrink_to(0);
+        assert!(cache.recency.is_empty() && cache.paths.is_empty());
+    }
+
+    #[test]
+    fn test_detect_archive_format_cached() {
+        let dir = test_dir("detect_archive_format_cached");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.7z");
+        fs::copy("tests/sample.7z", &path).unwrap();
+        let enabled = ArchiveFormat::ALL;
+        let detect = || detect_archive_format_cached(&path, 16, &enabled).unwrap();
+        assert_eq!(detect(), Some(ArchiveFormat::SevenZ));
+
+        // same size and time, so the head isn't read again
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        fs::write(&path, vec![0u8; len as usize]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(detect(), Some(ArchiveFormat::SevenZ));
+        assert_eq!(
+            detect_archive_format_with(&path, 16, &enabled).unwrap(),
+            None
+        );
+
+        fs::write(&path, "text").unwrap();
+        assert_eq!(detect(), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use sevenz_rust::SevenZArchiveEntry;
+
+use crate::i18n::log_tr;
+use crate::throttle::{RateLimiter, ThrottledReader};
+use crate::{
+    catch_backend_panic, is_symlink_entry, open_archive_reader, CascadeError, ExtractStats,
+};
+
+/// Where `extract_to_sink` writes the archive's contents. Paths are relative
+/// to the root of the archive and never contain `..` or a root.
+pub trait FileSink {
+    fn create_dir(&mut self, path: &Path) -> io::Result<()>;
+    /// Starts the file at `path`, which the following `write` calls fill.
+    /// Parent folders are not necessarily created first.
+    fn create_file(&mut self, path: &Path) -> io::Result<()>;
+    /// Appends to the file started last.
+    fn write(&mut self, data: &[u8]) -> io::Result<()>;
+    /// Called once the file's data is complete, for entries that store a
+    /// modification time.
+    fn set_mtime(&mut self, path: &Path, mtime: SystemTime) -> io::Result<()>;
+}
+
+/// Writes below `root` on disk, creating parent folders as needed. The
+/// default sink: `extract_with_options` writes every file through one.
+#[derive(Debug)]
+pub struct DiskSink {
+    root: PathBuf,
+    file: Option<File>,
+}
+
+impl DiskSink {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DiskSink {
+            root: root.into(),
+            file: None,
+        }
+    }
+}
+
+impl FileSink for DiskSink {
+    fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(self.root.join(path))
+    }
+
+    fn create_file(&mut self, path: &Path) -> io::Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.file = Some(File::create(path)?);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.write_all(data),
+            None => Err(io::Error::other("write before create_file")),
+        }
+    }
+
+    fn set_mtime(&mut self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        self.file = None;
+        File::options()
+            .write(true)
+            .open(self.root.join(path))?
+            .set_modified(mtime)
+    }
+}
+
+/// Keeps everything in memory, e.g. to look at an archive's contents in a
+/// test without touching the disk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemorySink {
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+    pub dirs: BTreeSet<PathBuf>,
+    pub mtimes: BTreeMap<PathBuf, SystemTime>,
+    current: Option<PathBuf>,
+}
+
+impl FileSink for MemorySink {
+    fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        self.dirs.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn create_file(&mut self, path: &Path) -> io::Result<()> {
+        self.files.insert(path.to_path_buf(), Vec::new());
+        self.current = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self
+            .current
+            .as_ref()
+            .and_then(|path| self.files.get_mut(path))
+        {
+            Some(file) => {
+                file.extend_from_slice(data);
+                Ok(())
+            }
+            None => Err(io::Error::other("write before create_file")),
+        }
+    }
+
+    fn set_mtime(&mut self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        self.mtimes.insert(path.to_path_buf(), mtime);
+        Ok(())
+    }
+}
+
+/// Extracts the archive into `sink` instead of a folder. Symlinks are
+/// skipped, a sink has no way to hold them, and so are entries whose name
+/// would leave the archive root. None of the `ExtractOptions` apply; the
+/// writes keep to `max_bytes_per_sec`.
+pub fn extract_to_sink<P: AsRef<Path>>(
+    path: P,
+    password: &str,
+    sink: &mut dyn FileSink,
+) -> Result<ExtractStats, CascadeError> {
+    let mut reader = open_archive_reader(path.as_ref(), password)?;
+    let mut stats = ExtractStats::default();
+    let mut limiter = RateLimiter::for_rate(None);
+
+    catch_backend_panic(path.as_ref(), || {
+        Ok(reader.for_each_entries(|entry, data| {
+            let relative = match sink_path(entry.name()) {
+                Some(relative) if !is_symlink_entry(entry) => relative,
+                _ => {
+                    log_tr!(info, "跳过条目 {}", "skipping entry {}", entry.name());
+                    io::copy(data, &mut io::sink())?;
+                    stats.skipped += 1;
+                    return Ok(true);
+                }
+            };
+            let written = write_to_sink(sink, entry, data, &relative, true, limiter.as_mut())?;
+            if entry.is_directory() {
+                stats.directories += 1;
+            } else {
+                stats.files += 1;
+                stats.bytes_written += written;
+            }
+            Ok(true)
+        })?)
+    })?;
+    Ok(stats)
+}
+
+/// Writes one entry into `sink` at `relative`: a folder, or a file filled
+/// with `data` and given the entry's modification time when it stores one
+/// and `keep_mtime` says so, no faster than `limiter` allows. Extracting to
+/// a folder goes through here with a `DiskSink` as well, so both write the
+/// same. Returns the bytes written.
+pub(crate) fn write_to_sink(
+    sink: &mut dyn FileSink,
+    entry: &SevenZArchiveEntry,
+    data: &mut dyn Read,
+    relative: &Path,
+    keep_mtime: bool,
+    limiter: Option<&mut RateLimiter>,
+) -> Result<u64, sevenz_rust::Error> {
+    if entry.is_directory() {
+        sink.create_dir(relative).map_err(sevenz_rust::Error::io)?;
+        return Ok(0);
+    }
+    sink.create_file(relative)
+        .map_err(|e| sevenz_rust::Error::FileOpen(e, relative.to_string_lossy().into_owned()))?;
+    let written = match limiter {
+        Some(limiter) => io::copy(
+            &mut ThrottledReader {
+                inner: data,
+                limiter,
+            },
+            &mut SinkWriter(sink),
+        ),
+        None => io::copy(data, &mut SinkWriter(sink)),
+    }
+    .map_err(sevenz_rust::Error::io)?;
+    // a wrong password can decode to a stream that just ends early
+    if written != entry.size() {
+        return Err(sevenz_rust::Error::io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    if keep_mtime && entry.has_last_modified_date {
+        sink.set_mtime(relative, entry.last_modified_date().into())
+            .map_err(sevenz_rust::Error::io)?;
+    }
+    Ok(written)
+}
+
+/// The file a sink is filling, for `io::copy`.
+struct SinkWriter<'a>(&'a mut dyn FileSink);
+
+impl Write for SinkWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The entry name as a relative path, or `None` when it has a root or a
+/// `..` component, or is empty.
+pub(crate) fn sink_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_extract_to_memory_sink() {
+        let mut sink = MemorySink::default();
+        let stats = extract_to_sink("tests/sample.7z", "", &mut sink).unwrap();
+        assert_eq!(stats.files, sink.files.len());
+        assert_eq!(stats.directories, sink.dirs.len());
+        assert_eq!(
+            sink.files[Path::new("7zFormat.txt")],
+            fs::read("tests/7zFormat.txt").unwrap()
+        );
+        assert!(sink.mtimes.contains_key(Path::new("7zFormat.txt")));
+
+        let mut sink = MemorySink::default();
+        assert!(extract_to_sink("tests/7ziplogo_p.7z", "0000", &mut sink).is_err());
+    }
+
+    #[test]
+    fn test_extract_to_disk_sink() {
+        let dest = test_dir("disk_sink");
+        let mut sink = DiskSink::new(&dest);
+        let stats = extract_to_sink("tests/7ziplogo_p.7z", "1151", &mut sink).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(
+            fs::read(dest.join("7ziplogo.png")).unwrap(),
+            fs::read("tests/7ziplogo.png").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_with_options_matches_disk_sink() {
+        let dir = test_dir("extract_with_options_matches_disk_sink");
+        let mut sink = DiskSink::new(dir.join("sink"));
+        extract_to_sink("tests/sample.7z", "", &mut sink).unwrap();
+        let options = crate::ExtractOptions::default();
+        crate::extract_with_options("tests/sample.7z", "", &dir.join("plain"), &options).unwrap();
+
+        for entry in crate::read_7z_contents("tests/sample.7z").unwrap() {
+            let (sunk, plain) = (
+                dir.join("sink").join(entry.name()),
+                dir.join("plain").join(entry.name()),
+            );
+            let (sunk_meta, plain_meta) =
+                (fs::metadata(&sunk).unwrap(), fs::metadata(&plain).unwrap());
+            assert_eq!(sunk_meta.is_dir(), plain_meta.is_dir(), "{}", entry.name());
+            if !entry.is_directory() {
+                assert_eq!(fs::read(&sunk).unwrap(), fs::read(&plain).unwrap());
+                assert_eq!(
+                    sunk_meta.modified().unwrap(),
+                    plain_meta.modified().unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_to_sink_keeps_to_limiter() {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.size = 1500;
+        let mut sink = MemorySink::default();
+        let mut limiter = RateLimiter::new(1000);
+        let start = std::time::Instant::now();
+        let data = vec![1u8; 1500];
+        let written = write_to_sink(
+            &mut sink,
+            &entry,
+            &mut data.as_slice(),
+            Path::new("a.bin"),
+            false,
+            Some(&mut limiter),
+        )
+        .unwrap();
+        assert_eq!(written, 1500);
+        // a second's worth up front, the rest at 1000 bytes a second
+        assert!(start.elapsed() >= std::time::Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_sink_path() {
+        assert_eq!(sink_path("a\\b/./c.txt"), Some(PathBuf::from("a/b/c.txt")));
+        assert_eq!(sink_path("../evil.txt"), None);
+        assert_eq!(sink_path("/etc/passwd"), None);
+    }
+}
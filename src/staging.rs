@@ -0,0 +1,188 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::i18n::log_tr;
+use crate::{
+    clear_attributes, create_symlink, extract_reporting, free_path, ArchiveSource, CascadeError,
+    ExtractOptions, ExtractProgress, ExtractStats, OverwritePolicy, PathStyle, Remap,
+};
+
+/// `extract_reporting` for `ExtractOptions::atomic`: extracts into a fresh
+/// folder next to `dest` and moves the result into `dest` once every entry
+/// is written. The staging folder is removed in any case.
+pub(crate) fn extract_staged(
+    source: ArchiveSource,
+    password: &str,
+    dest: &Path,
+    options: &ExtractOptions,
+    on_progress: Option<&mut dyn FnMut(&ExtractProgress)>,
+    remap: Option<Remap>,
+) -> Result<ExtractStats, CascadeError> {
+    let staging = staging_dir_for(dest)?;
+    let inner = ExtractOptions {
+        atomic: false,
+        // nothing to compare with or resume in an empty folder, and a
+        // failed attempt is thrown away as a whole
+        incremental: false,
+        resume: false,
+        cleanup_on_failure: false,
+        ..options.clone()
+    };
+    let result = extract_reporting(source, password, &staging, &inner, on_progress, remap)
+        .and_then(|mut stats| {
+            if options.record_written_files == Some(PathStyle::Absolute) {
+                let from = std::path::absolute(&staging)?;
+                let to = std::path::absolute(dest)?;
+                for file in &mut stats.written_files {
+                    if let Ok(relative) = file.strip_prefix(&from) {
+                        *file = to.join(relative);
+                    }
+                }
+            }
+            move_into(&staging, dest, options.overwrite_policy)?;
+            Ok(stats)
+        });
+    if fs::symlink_metadata(&staging).is_ok() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    result
+}
+
+/// A new, empty folder beside `dest`, so the final rename stays on one
+/// filesystem. The leading dot keeps it out of most listings meanwhile.
+fn staging_dir_for(dest: &Path) -> io::Result<PathBuf> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let Some(name) = dest.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be extracted into atomically", dest.display()),
+        ));
+    };
+    let parent = match dest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    let staging = parent.join(format!(
+        ".{}.partial-{}-{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir(&staging)?;
+    Ok(staging)
+}
+
+/// Moves `from` to `to`. A folder that exists on both sides is merged entry
+/// by entry, and anything else already at `to` is replaced, kept or
+/// renamed around as `policy` says.
+fn move_into(from: &Path, to: &Path, policy: OverwritePolicy) -> io::Result<()> {
+    let Ok(existing) = fs::symlink_metadata(to) else {
+        return move_path(from, to);
+    };
+    if existing.is_dir() && fs::symlink_metadata(from)?.is_dir() {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_into(&entry.path(), &to.join(entry.file_name()), policy)?;
+        }
+        return Ok(());
+    }
+    match policy {
+        OverwritePolicy::Overwrite => {
+            if existing.is_dir() {
+                fs::remove_dir_all(to)?;
+            } else {
+                let _ = clear_attributes(to);
+            }
+            move_path(from, to)
+        }
+        OverwritePolicy::Skip => {
+            log_tr!(
+                info,
+                "文件已存在，跳过 {}",
+                "file exists, skipping {}",
+                to.display()
+            );
+            Ok(())
+        }
+        OverwritePolicy::Rename => move_path(from, &free_path(to)),
+    }
+}
+
+/// `fs::rename`, or a copy and delete when `to` is on another filesystem,
+/// e.g. because `dest` is a mount point. The copy is made beside `to` and
+/// renamed into place, so `to` still appears complete or not at all.
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            log_tr!(
+                info,
+                "{} 与 {} 不在同一文件系统上，改为复制",
+                "{} and {} are on different filesystems, copying instead",
+                from.display(),
+                to.display()
+            );
+            let mut temp = to.as_os_str().to_owned();
+            temp.push(".partial");
+            let temp = PathBuf::from(temp);
+            if let Err(e) = copy_tree(from, &temp).and_then(|()| fs::rename(&temp, to)) {
+                let _ = fs::remove_dir_all(&temp).or_else(|_| fs::remove_file(&temp));
+                return Err(e);
+            }
+            match fs::symlink_metadata(from)?.is_dir() {
+                true => fs::remove_dir_all(from),
+                false => fs::remove_file(from),
+            }
+        }
+        result => result,
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_symlink() {
+        create_symlink(&fs::read_link(from)?.to_string_lossy(), to)
+    } else if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+
+    #[test]
+    fn test_move_into() {
+        let dir = test_dir("move_into");
+        let from = dir.join("from");
+        fs::create_dir_all(from.join("docs")).unwrap();
+        fs::write(from.join("docs/a.txt"), "new").unwrap();
+        fs::write(from.join("b.txt"), "new").unwrap();
+        fs::write(from.join("c.txt"), "new").unwrap();
+        let to = dir.join("to");
+        fs::create_dir_all(to.join("docs")).unwrap();
+        fs::write(to.join("docs/old.txt"), "old").unwrap();
+        fs::write(to.join("b.txt"), "old").unwrap();
+
+        move_into(&from, &to, OverwritePolicy::Rename).unwrap();
+        assert_eq!(fs::read_to_string(to.join("docs/a.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(to.join("docs/old.txt")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(to.join("b.txt")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(to.join("b (1).txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(to.join("c.txt")).unwrap(), "new");
+
+        let copy = dir.join("copy");
+        copy_tree(&to, &copy).unwrap();
+        assert_eq!(fs::read_to_string(copy.join("docs/a.txt")).unwrap(), "new");
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+
+use crate::{BatchOptions, BatchRun};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchOutcome {
+    Extracted,
+    /// None of the passwords opened the archive.
+    PasswordNotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchItemResult {
+    pub path: PathBuf,
+    pub outcome: BatchOutcome,
+    /// The password that worked, if any.
+    pub password: Option<String>,
+}
+
+/// Cracks the archives one after another in a background task and yields
+/// each result as soon as that archive is done, so a UI can update as it
+/// goes. Dropping the stream stops the batch after the current archive.
+pub fn extract_stream(
+    paths: Vec<PathBuf>,
+    passwords: Vec<Arc<String>>,
+    dest: PathBuf,
+    threads: usize,
+) -> impl Stream<Item = BatchItemResult> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let mut run = BatchRun::new(passwords, dest, threads, BatchOptions::default());
+        for path in paths {
+            let password = run.crack(&path, None, None).await.into_password();
+            let outcome = match password {
+                Some(_) => BatchOutcome::Extracted,
+                None => BatchOutcome::PasswordNotFound,
+            };
+            let item = BatchItemResult {
+                path,
+                outcome,
+                password,
+            };
+            if tx.send(item).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_extract_stream() {
+        let dest = test_dir("extract_stream");
+        let paths = vec![
+            PathBuf::from("tests/7ziplogo_p.7z"),
+            PathBuf::from("tests/7ziplogo.png"),
+        ];
+        let passwords = vec![Arc::new("1151".to_string())];
+
+        let results: Vec<BatchItemResult> = extract_stream(paths, passwords, dest.clone(), 2)
+            .collect()
+            .await;
+        assert_eq!(
+            results,
+            [
+                BatchItemResult {
+                    path: PathBuf::from("tests/7ziplogo_p.7z"),
+                    outcome: BatchOutcome::Extracted,
+                    password: Some("1151".to_string()),
+                },
+                BatchItemResult {
+                    path: PathBuf::from("tests/7ziplogo.png"),
+                    outcome: BatchOutcome::PasswordNotFound,
+                    password: None,
+                },
+            ]
+        );
+        assert!(dest.join("7ziplogo.png").exists());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::i18n::log_tr;
+use crate::throttle::{RateLimiter, ThrottledReader};
+use crate::{
+    CascadeError, CascadeLimit, ExtractOptions, ExtractStats, PathStyle, FULL_SIGNATURE_WINDOW,
+};
+
+/// Extracts a plain `.tar` to `dest`. Tar has no encryption, so `password`
+/// is ignored; it is only there to match the other `try_extract_*`
+/// functions.
+///
+/// Entries that would end up outside `dest`, through an absolute path, a
+/// `..` component or a symlink inside the archive, are skipped with a
+/// warning and counted in `skipped`.
+pub fn try_extract_tar<P: AsRef<Path>>(
+    path: P,
+    _password: &str,
+    dest: &Path,
+) -> Result<ExtractStats, CascadeError> {
+    unpack_tar(BufReader::new(File::open(path)?), dest, None, None)
+}
+
+/// `try_extract_tar` for a gzip compressed `.tar.gz` or `.tgz`. `password`
+/// is ignored here as well.
+pub fn try_extract_tar_gz<P: AsRef<Path>>(
+    path: P,
+    _password: &str,
+    dest: &Path,
+) -> Result<ExtractStats, CascadeError> {
+    unpack_tar(
+        GzDecoder::new(BufReader::new(File::open(path)?)),
+        dest,
+        None,
+        None,
+    )
+}
+
+/// `try_extract_tar`, or `try_extract_tar_gz` for a `gzip` one, keeping to
+/// `ExtractOptions::record_written_files`, `max_total_bytes` and
+/// `max_bytes_per_sec`, which holds down the unpacked tar stream.
+pub(crate) fn extract_tar_recording(
+    path: &Path,
+    gzip: bool,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractStats, CascadeError> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if gzip {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let (record, max_total_bytes) = (options.record_written_files, options.max_total_bytes);
+    match RateLimiter::for_rate(options.max_bytes_per_sec).as_mut() {
+        Some(limiter) => unpack_tar(
+            ThrottledReader {
+                inner: reader,
+                limiter,
+            },
+            dest,
+            record,
+            max_total_bytes,
+        ),
+        None => unpack_tar(reader, dest, record, max_total_bytes),
+    }
+}
+
+/// The entries of a `.tar`, or of a `.tar.gz` with `gzip`, as
+/// `SevenZArchiveEntry`s with only the name, size and folder flag filled
+/// in, so the smart mode checks written for 7z apply to tars as well. Tar
+/// has no index, the whole stream is read to list it.
+pub(crate) fn tar_entries(
+    path: &Path,
+    gzip: bool,
+) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, CascadeError> {
+    let reader = BufReader::new(File::open(path)?);
+    if gzip {
+        list_tar(GzDecoder::new(reader))
+    } else {
+        list_tar(reader)
+    }
+}
+
+fn list_tar<R: Read>(reader: R) -> Result<Vec<sevenz_rust::SevenZArchiveEntry>, CascadeError> {
+    let mut entries = Vec::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = entry?;
+        let mut listed = sevenz_rust::SevenZArchiveEntry::new();
+        listed.name = entry.path()?.to_string_lossy().into_owned();
+        listed.is_directory = entry.header().entry_type().is_dir();
+        listed.size = entry.size();
+        entries.push(listed);
+    }
+    Ok(entries)
+}
+
+/// Whether the gzip stream at `path` holds a tar, judged by the tar magic in
+/// its first decompressed block.
+pub fn is_tar_gz<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let mut buf = Vec::with_capacity(FULL_SIGNATURE_WINDOW);
+    GzDecoder::new(File::open(path)?)
+        .take(FULL_SIGNATURE_WINDOW as u64)
+        .read_to_end(&mut buf)?;
+    Ok(infer::archive::is_tar(&buf))
+}
+
+fn unpack_tar<R: Read>(
+    reader: R,
+    dest: &Path,
+    record: Option<PathStyle>,
+    max_total_bytes: Option<u64>,
+) -> Result<ExtractStats, CascadeError> {
+    // `unpack_in` resolves dest to check where each entry lands
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(reader);
+    let mut stats = ExtractStats::default();
+    let absolute_dest = match record {
+        Some(PathStyle::Absolute) => Some(std::path::absolute(dest)?),
+        _ => None,
+    };
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path: PathBuf = entry.path()?.components().collect();
+        let name = entry_path.display().to_string();
+        let entry_type = entry.header().entry_type();
+        if let Some(max) = max_total_bytes {
+            if entry_type.is_file() && stats.bytes_written + entry.size() > max {
+                return Err(CascadeError::CascadeLimitExceeded {
+                    limit: CascadeLimit::TotalBytes { max },
+                });
+            }
+        }
+        if !entry.unpack_in(dest)? {
+            log_tr!(
+                warn,
+                "跳过解压目标之外的条目 {}",
+                "skipping entry outside the destination: {}",
+                name
+            );
+            stats.skipped += 1;
+            continue;
+        }
+        if entry_type.is_dir() {
+            stats.directories += 1;
+            continue;
+        } else if entry_type.is_symlink() {
+            stats.symlinks += 1;
+        } else {
+            stats.files += 1;
+            stats.bytes_written += entry.size();
+        }
+        if record.is_some() {
+            stats.written_files.push(match &absolute_dest {
+                Some(absolute_dest) => absolute_dest.join(&entry_path),
+                None => entry_path,
+            });
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    /// `a.txt`, `docs/b.txt` and an entry named `../evil.txt`, which the
+    /// tar builder refuses to write, so its name is set by hand.
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in [("a.txt", "a"), ("docs/b.txt", "bb")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..11].copy_from_slice(b"../evil.txt");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn assert_extracted(stats: &ExtractStats, dest: &Path) {
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.bytes_written, 3);
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(fs::read_to_string(dest.join("docs/b.txt")).unwrap(), "bb");
+        assert!(!dest.parent().unwrap().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_try_extract_tar() {
+        let dir = test_dir("try_extract_tar");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("source.tar");
+        fs::write(&path, tar_bytes()).unwrap();
+
+        let dest = dir.join("out");
+        let stats = try_extract_tar(&path, "ignored", &dest).unwrap();
+        assert_extracted(&stats, &dest);
+    }
+
+    #[test]
+    fn test_tar_entries() {
+        let dir = test_dir("tar_entries");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.tar");
+        fs::write(&path, tar_bytes()).unwrap();
+        let entries = tar_entries(&path, false).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name()).collect();
+        assert_eq!(names, ["a.txt", "docs/b.txt", "../evil.txt"]);
+        assert_eq!(entries[1].size(), 2);
+    }
+
+    #[test]
+    fn test_try_extract_tar_gz() {
+        let dir = test_dir("try_extract_tar_gz");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("source.tar.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&tar_bytes()).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        assert!(is_tar_gz(&path).unwrap());
+
+        let dest = dir.join("out");
+        let stats = try_extract_tar_gz(&path, "", &dest).unwrap();
+        assert_extracted(&stats, &dest);
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Datelike, Local};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::CascadeError;
+
+/// Placeholders understood by `expand_dest_template`.
+pub const DEST_PLACEHOLDERS: [&str; 5] = ["archive_stem", "archive_name", "year", "month", "day"];
+
+/// Substitutes `{placeholder}`s in a `dest` setting for one archive, e.g.
+/// `D:/out/{year}/{archive_stem}`. Dates come from the archive's last
+/// modification time, in local time. `{{` and `}}` stand for literal braces.
+pub fn expand_dest_template<P: AsRef<Path>>(
+    template: &str,
+    archive: P,
+) -> Result<PathBuf, CascadeError> {
+    let archive = archive.as_ref();
+    let mut modified: Option<DateTime<Local>> = None;
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..pos]);
+        let brace = &rest[pos..pos + 1];
+        if rest[pos + 1..].starts_with(brace) {
+            expanded.push_str(brace);
+            rest = &rest[pos + 2..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(invalid_template(template, "unmatched '}'"));
+        }
+
+        let end = rest[pos..]
+            .find('}')
+            .ok_or_else(|| invalid_template(template, "unclosed '{'"))?;
+        let name = &rest[pos + 1..pos + end];
+        let value = match name {
+            "archive_stem" => lossy(archive.file_stem()),
+            "archive_name" => lossy(archive.file_name()),
+            "year" | "month" | "day" => {
+                let date = match modified {
+                    Some(date) => date,
+                    None => *modified.insert(fs::metadata(archive)?.modified()?.into()),
+                };
+                match name {
+                    "year" => format!("{:04}", date.year()),
+                    "month" => format!("{:02}", date.month()),
+                    _ => format!("{:02}", date.day()),
+                }
+            }
+            _ => {
+                return Err(invalid_template(
+                    template,
+                    &format!(
+                        "unknown placeholder {{{}}}, expected one of {}",
+                        name,
+                        DEST_PLACEHOLDERS.join(", ")
+                    ),
+                ))
+            }
+        };
+        expanded.push_str(&value);
+        rest = &rest[pos + end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+fn lossy(name: Option<&std::ffi::OsStr>) -> String {
+    name.map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn invalid_template(template: &str, reason: &str) -> CascadeError {
+    CascadeError::InvalidTemplate(format!("{:?}: {}", template, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_dest_template() {
+        let archive = Path::new("tests/sample.7z");
+        let modified: DateTime<Local> = fs::metadata(archive).unwrap().modified().unwrap().into();
+
+        assert_eq!(
+            expand_dest_template("out/{year}-{month}/{archive_stem}", archive).unwrap(),
+            PathBuf::from(format!(
+                "out/{:04}-{:02}/sample",
+                modified.year(),
+                modified.month()
+            ))
+        );
+        assert_eq!(
+            expand_dest_template("{{{archive_name}}}", archive).unwrap(),
+            PathBuf::from("{sample.7z}")
+        );
+        assert_eq!(
+            expand_dest_template("D:/media", archive).unwrap(),
+            PathBuf::from("D:/media")
+        );
+    }
+
+    #[test]
+    fn test_expand_dest_template_errors() {
+        let archive = Path::new("tests/sample.7z");
+        for template in ["out/{stem}", "out/{year", "out/}"] {
+            assert!(matches!(
+                expand_dest_template(template, archive),
+                Err(CascadeError::InvalidTemplate(_))
+            ));
+        }
+        let error = expand_dest_template("{stem}", archive).unwrap_err();
+        assert!(error.to_string().contains("unknown placeholder {stem}"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 0 until `set_max_bytes_per_sec` is called, for no limit.
+static MAX_BYTES_PER_SEC: AtomicU64 = AtomicU64::new(0);
+
+/// Caps how fast the extractions that take no `ExtractOptions` write, from
+/// now on: `extract_to_sink`, `extract_sfx`, the subtree and selection
+/// extractions, and `ExtractOptions` whose own `max_bytes_per_sec` is
+/// `None`. Each extraction keeps to the rate on its own. `read_config`
+/// calls this with `max_bytes_per_sec`. 0 lifts the limit.
+pub fn set_max_bytes_per_sec(rate: u64) {
+    MAX_BYTES_PER_SEC.store(rate, Ordering::Relaxed);
+}
+
+/// The limit set by `set_max_bytes_per_sec`, `None` without one.
+pub fn max_bytes_per_sec() -> Option<u64> {
+    match MAX_BYTES_PER_SEC.load(Ordering::Relaxed) {
+        0 => None,
+        rate => Some(rate),
+    }
+}
+
+/// Token bucket holding up to one second's worth of bytes.
+pub(crate) struct RateLimiter {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64) -> Self {
+        RateLimiter {
+            rate,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// One extraction's limiter for `rate`, or for `max_bytes_per_sec`
+    /// when that is `None`. `None` without a limit.
+    pub(crate) fn for_rate(rate: Option<u64>) -> Option<Self> {
+        rate.or_else(max_bytes_per_sec)
+            .filter(|rate| *rate > 0)
+            .map(RateLimiter::new)
+    }
+
+    pub(crate) fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Takes `bytes` tokens, sleeping off any deficit.
+    pub(crate) fn consume(&mut self, bytes: u64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.tokens -= bytes as f64;
+
+        if self.tokens < 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate as f64));
+        }
+    }
+}
+
+/// Hands out what `inner` reads no faster than `limiter` allows.
+pub(crate) struct ThrottledReader<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) limiter: &'a mut RateLimiter,
+}
+
+impl<R: Read> Read for ThrottledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.limiter.consume(n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        // the first second's worth is available immediately
+        limiter.consume(1000);
+        assert!(start.elapsed() < Duration::from_millis(200));
+        limiter.consume(500);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
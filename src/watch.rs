@@ -0,0 +1,1579 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::i18n::log_tr;
+use crate::{
+    detect_archive_format_with, expand_dest_template, extract_any, is_encrypted, ArchiveFormat,
+    CascadeError, ExtractOptions, ExtractStats, DEFAULT_SIGNATURE_WINDOW, FULL_SIGNATURE_WINDOW,
+};
+#[cfg(feature = "async")]
+use {
+    crate::{
+        archive_fingerprint, delete_extracted_archive, is_complete_archive, planned_dest,
+        run_post_command, start_extraction_with_options, start_extraction_with_wordlists,
+        write_action_log, ActionLog, Config, CrackOptions, Outcome, PathStyle, Shard,
+        SmartModeOptions,
+    },
+    std::collections::{HashSet, VecDeque},
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::{Arc, Mutex},
+    std::time::{Duration, Instant, SystemTime},
+    tokio::task::{self, JoinSet},
+};
+
+/// A folder to watch, written in `settings.toml` either as a plain path or as
+/// a table with its own destination and smart mode:
+///
+/// ```toml
+/// watch_folders = [
+///     "D:/downloads",
+///     { path = "D:/media", dest = "E:/library", smart_mode = false },
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "WatchFolderRepr")]
+pub struct WatchFolder {
+    pub path: String,
+    /// Overrides the global `dest` for archives found in this folder.
+    pub dest: Option<String>,
+    /// Overrides the global `smart_mode` for archives found in this folder.
+    pub smart_mode: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WatchFolderRepr {
+    Path(String),
+    Detailed {
+        path: String,
+        #[serde(default)]
+        dest: Option<String>,
+        #[serde(default)]
+        smart_mode: Option<bool>,
+    },
+}
+
+impl From<WatchFolderRepr> for WatchFolder {
+    fn from(repr: WatchFolderRepr) -> Self {
+        match repr {
+            WatchFolderRepr::Path(path) => WatchFolder {
+                path,
+                dest: None,
+                smart_mode: None,
+            },
+            WatchFolderRepr::Detailed {
+                path,
+                dest,
+                smart_mode,
+            } => WatchFolder {
+                path,
+                dest,
+                smart_mode,
+            },
+        }
+    }
+}
+
+/// Extensions browsers and torrent clients give files still being
+/// downloaded.
+pub const IN_PROGRESS_EXTENSIONS: &[&str] = &["part", "partial", "crdownload", "download", "!ut"];
+
+/// Lists every file under `dir` that is detected as an archive. Hidden files
+/// and unfinished downloads are left out, see `is_ignored`. Subfolders that
+/// can't be read are logged and skipped; only an unreadable `dir` is an
+/// error.
+pub fn find_archives<P: AsRef<Path>>(dir: P, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    find_archives_with_window(dir.as_ref(), recursive, DEFAULT_SIGNATURE_WINDOW)
+}
+
+fn find_archives_with_window(
+    dir: &Path,
+    recursive: bool,
+    window: usize,
+) -> io::Result<Vec<PathBuf>> {
+    let mut archives = Vec::new();
+    let mut unreadable = Vec::new();
+    collect_archives(
+        dir,
+        recursive,
+        true,
+        &ArchiveFormat::ALL,
+        window,
+        &mut archives,
+        &mut unreadable,
+    )?;
+    for (folder, e) in unreadable {
+        log_tr!(
+            warn,
+            "无法读取文件夹 {}，已跳过: {}",
+            "cannot read folder {}, skipped: {}",
+            folder.display(),
+            e
+        );
+    }
+    Ok(archives)
+}
+
+/// Whether `dir` contains at least one archive. Stops at the first one, so
+/// it is much cheaper than `find_archives` on a big tree.
+pub fn dir_has_archives<P: AsRef<Path>>(dir: P, recursive: bool) -> io::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if is_ignored(&entry) {
+            continue;
+        }
+        if file_type.is_dir() {
+            if recursive && dir_has_archives(&path, recursive)? {
+                return Ok(true);
+            }
+        } else if file_type.is_file() {
+            if let Ok(Some(_)) = crate::detect_archive_format_with(
+                &path,
+                DEFAULT_SIGNATURE_WINDOW,
+                &ArchiveFormat::ALL,
+            ) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Extracts the archive in `dir` that was modified last, e.g. the download
+/// that just finished, and returns its path with the stats. Fails with
+/// `NoArchivesFound` when `find_archives` finds none.
+pub fn extract_newest<P: AsRef<Path>>(
+    dir: P,
+    password: &str,
+    dest: &Path,
+    recursive: bool,
+) -> Result<(PathBuf, ExtractStats), CascadeError> {
+    let dir = dir.as_ref();
+    let newest = find_archives(dir, recursive)?
+        .into_iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((modified, path))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path);
+    let Some(path) = newest else {
+        return Err(CascadeError::NoArchivesFound {
+            dir: dir.to_path_buf(),
+        });
+    };
+    log_tr!(
+        info,
+        "解压最新的压缩包: {}",
+        "extracting the newest archive: {}",
+        path.display()
+    );
+    let stats = extract_any(&path, password, dest, &ExtractOptions::default())?;
+    Ok((path, stats))
+}
+
+/// What `scan_stats` found of one format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatStats {
+    pub count: usize,
+    /// The size of the archives themselves on disk.
+    pub total_compressed_bytes: u64,
+    /// Archives that need a password, as far as the header tells without
+    /// one. Always 0 for formats that can't be encrypted.
+    pub encrypted_count: usize,
+}
+
+/// Counts the archives under `dir` by format, skipping the same files as
+/// `find_archives`, to see what a collection holds before processing it.
+/// Only the signature and, for 7z and zip, the header of each archive are
+/// read. Tar archives are recognized too, unlike in `find_archives`.
+pub fn scan_stats<P: AsRef<Path>>(
+    dir: P,
+    recursive: bool,
+) -> Result<HashMap<ArchiveFormat, FormatStats>, CascadeError> {
+    let mut stats: HashMap<ArchiveFormat, FormatStats> = HashMap::new();
+    for path in find_archives_with_window(dir.as_ref(), recursive, FULL_SIGNATURE_WINDOW)? {
+        let Ok(Some(format)) =
+            detect_archive_format_with(&path, FULL_SIGNATURE_WINDOW, &ArchiveFormat::ALL)
+        else {
+            continue;
+        };
+        let format_stats = stats.entry(format).or_default();
+        format_stats.count += 1;
+        format_stats.total_compressed_bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if is_encrypted(&path, format) {
+            format_stats.encrypted_count += 1;
+        }
+    }
+    Ok(stats)
+}
+
+/// Subfolders that can't be read, e.g. for lack of permission, end up in
+/// `unreadable` instead of failing the whole scan.
+fn collect_archives(
+    dir: &Path,
+    recursive: bool,
+    ignore_hidden: bool,
+    enabled: &[ArchiveFormat],
+    window: usize,
+    archives: &mut Vec<PathBuf>,
+    unreadable: &mut Vec<(PathBuf, io::Error)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if ignore_hidden && is_ignored(&entry) {
+            continue;
+        }
+        if file_type.is_dir() {
+            if recursive {
+                if let Err(e) = collect_archives(
+                    &path,
+                    recursive,
+                    ignore_hidden,
+                    enabled,
+                    window,
+                    archives,
+                    unreadable,
+                ) {
+                    unreadable.push((path, e));
+                }
+            }
+        } else if file_type.is_file() {
+            // unreadable files are simply not archives we can handle
+            if let Ok(Some(_)) = crate::detect_archive_format_cached(&path, window, enabled) {
+                archives.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Dotfiles, files with the Windows hidden attribute and downloads still in
+/// progress. Hidden folders are skipped as a whole.
+fn is_ignored(entry: &fs::DirEntry) -> bool {
+    let name = entry.file_name();
+    let name = name.to_string_lossy();
+    if name.starts_with('.') || is_hidden_attribute(entry) {
+        return true;
+    }
+    Path::new(&*name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            IN_PROGRESS_EXTENSIONS
+                .iter()
+                .any(|partial| ext.eq_ignore_ascii_case(partial))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_hidden_attribute(entry: &fs::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    entry
+        .metadata()
+        .map(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(windows))]
+fn is_hidden_attribute(_entry: &fs::DirEntry) -> bool {
+    false
+}
+
+/// The folder an archive is extracted into before smart mode applies. An
+/// empty or `"."` destination means the archive's own folder; anything else
+/// may use the placeholders of `expand_dest_template`.
+pub(crate) fn base_dest_for(
+    archive: &Path,
+    folder_dest: Option<&str>,
+    global_dest: &str,
+) -> Result<PathBuf, CascadeError> {
+    let dest = folder_dest.unwrap_or(global_dest);
+    if dest.is_empty() || dest == "." {
+        Ok(match archive.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+    } else {
+        expand_dest_template(dest, archive)
+    }
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+#[cfg(feature = "async")]
+impl FileStamp {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// How many finished archives `WatchStatus::recent` keeps.
+#[cfg(feature = "async")]
+pub const WATCH_STATUS_RECENT: usize = 20;
+
+/// What the watcher is doing right now, for a status endpoint or a tray
+/// tooltip. `watch_and_extract_with_status` keeps it up to date; lock it
+/// only briefly, the watcher waits for the lock.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchStatus {
+    /// The archives being extracted at the moment.
+    pub processing: Vec<PathBuf>,
+    /// Archives that were found but not extracted yet, because they are
+    /// still being written, the watcher is paused or
+    /// `max_concurrent_archives` are being extracted already.
+    pub pending: usize,
+    /// The last `WATCH_STATUS_RECENT` archives handled, most recent first.
+    pub recent: VecDeque<WatchResult>,
+    /// When the watcher last polled its folders or finished an archive.
+    pub last_activity: Instant,
+    /// Whether the last poll left nothing to do: no archive pending, queued
+    /// or being extracted. False until the first poll.
+    pub idle: bool,
+    /// When the last poll that found the watcher idle started, see
+    /// `wait_until_idle`.
+    pub last_idle_poll: Option<Instant>,
+}
+
+#[cfg(feature = "async")]
+impl Default for WatchStatus {
+    fn default() -> Self {
+        WatchStatus {
+            processing: Vec::new(),
+            pending: 0,
+            recent: VecDeque::new(),
+            last_activity: Instant::now(),
+            idle: false,
+            last_idle_poll: None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchResult {
+    pub path: PathBuf,
+    /// `None` when the archive wasn't tried at all, e.g. because its format
+    /// isn't supported.
+    pub outcome: Option<Outcome>,
+    pub finished: SystemTime,
+}
+
+#[cfg(feature = "async")]
+impl WatchStatus {
+    /// Whether the watcher did something within the last `max_idle`. It
+    /// polls every `watch_interval_ms`, but polls only count while fewer
+    /// than `max_concurrent_archives` archives are being extracted, so
+    /// extractions that wedge make it unhealthy; pick `max_idle` above the
+    /// longest extraction expected, or `max_runtime_secs`, so a supervisor
+    /// only restarts a daemon that is really stuck.
+    pub fn is_healthy(&self, max_idle: Duration) -> bool {
+        self.last_activity.elapsed() <= max_idle
+    }
+
+    fn start(&mut self, path: &Path) {
+        self.processing.push(path.to_path_buf());
+        self.idle = false;
+    }
+
+    /// Records the end of a poll that started at `polled`.
+    fn polled(&mut self, polled: Instant, idle: bool) {
+        if idle && !self.idle {
+            log_tr!(
+                info,
+                "所有压缩包都已处理完毕，继续监视",
+                "all archives are processed, watching for new ones"
+            );
+        }
+        self.idle = idle;
+        if idle {
+            self.last_idle_poll = Some(polled);
+        }
+    }
+
+    fn finish(&mut self, path: &Path, outcome: Option<Outcome>) {
+        self.processing.retain(|p| p != path);
+        self.recent.push_front(WatchResult {
+            path: path.to_path_buf(),
+            outcome,
+            finished: SystemTime::now(),
+        });
+        self.recent.truncate(WATCH_STATUS_RECENT);
+        self.last_activity = Instant::now();
+    }
+}
+
+/// Polls the configured watch folders and extracts archives as they appear,
+/// until `shutdown` is set. A file is only picked up once its size and mtime
+/// are unchanged between two polls and `is_complete_archive` agrees, so
+/// downloads in progress are left alone.
+/// Archives whose `archive_fingerprint` was already seen are skipped.
+///
+/// Up to `max_concurrent_archives` archives are extracted at a time, the
+/// others wait in a queue in the order they were found, so a big batch
+/// arriving at once doesn't have the disk thrash. On shutdown the
+/// extractions underway are finished, the queued ones are left for the
+/// next run.
+#[cfg(feature = "async")]
+pub async fn watch_and_extract(config: &Config, shutdown: Arc<AtomicBool>) {
+    watch_and_extract_with_pause(config, shutdown, Arc::new(AtomicBool::new(false))).await
+}
+
+/// `watch_and_extract` that holds off extracting while `paused` is set, e.g.
+/// from a pause button. The folders are still scanned meanwhile, and
+/// whatever arrived during the pause is extracted once it is cleared.
+#[cfg(feature = "async")]
+pub async fn watch_and_extract_with_pause(
+    config: &Config,
+    shutdown: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+) {
+    let status = Arc::new(Mutex::new(WatchStatus::default()));
+    watch_and_extract_with_status(config, shutdown, paused, status).await
+}
+
+/// `watch_and_extract_with_pause` that reports what it is doing through
+/// `status`, which can be read from another task or thread meanwhile.
+#[cfg(feature = "async")]
+pub async fn watch_and_extract_with_status(
+    config: &Config,
+    shutdown: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    status: Arc<Mutex<WatchStatus>>,
+) {
+    let folders = match &config.user.watch_folders {
+        Some(folders) if !folders.is_empty() => folders.clone(),
+        _ => vec![WatchFolder {
+            path: ".".to_string(),
+            dest: None,
+            smart_mode: None,
+        }],
+    };
+    let mut pending: HashMap<PathBuf, FileStamp> = HashMap::new();
+    let mut handled: HashSet<PathBuf> = HashSet::new();
+    // fingerprints of the archives extracted so far, and of those queued or
+    // running by path; a fingerprint only counts as extracted once its
+    // extraction succeeded
+    let mut extracted: HashSet<u64> = HashSet::new();
+    let mut in_flight: HashMap<PathBuf, u64> = HashMap::new();
+    // folders that failed to scan on the last poll, so each failure and
+    // recovery is only logged once; every poll tries them again
+    let mut unreadable: HashSet<PathBuf> = HashSet::new();
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(config.config.watch_interval_ms));
+    let max_concurrent = config.config.max_concurrent_archives.max(1);
+    // (archive, base destination, smart mode) ready to be extracted
+    let mut queue: VecDeque<(PathBuf, PathBuf, bool)> = VecDeque::new();
+    let mut running: JoinSet<Option<Outcome>> = JoinSet::new();
+    let mut running_paths: HashMap<task::Id, PathBuf> = HashMap::new();
+    let config = Arc::new(config.clone());
+
+    while !shutdown.load(Ordering::Relaxed) {
+        let polled = tokio::select! {
+            _ = interval.tick() => Instant::now(),
+            Some(joined) = running.join_next_with_id(), if !running.is_empty() => {
+                finish_job(joined, &mut running_paths, &mut in_flight, &mut extracted, &status);
+                if !paused.load(Ordering::Relaxed) {
+                    start_jobs(&mut queue, &mut running, &mut running_paths, max_concurrent, &config, &status);
+                }
+                continue;
+            }
+        };
+        // with every slot taken the watcher only waits for extractions,
+        // which `is_healthy` should notice when they wedge
+        if running.len() < max_concurrent {
+            status.lock().unwrap().last_activity = Instant::now();
+        }
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut failed: Vec<(PathBuf, io::Error)> = Vec::new();
+
+        for folder in &folders {
+            let archives = match collect_folder(&folder.path, &config, &mut failed) {
+                Ok(archives) => archives,
+                Err(e) => {
+                    failed.push((PathBuf::from(&folder.path), e));
+                    continue;
+                }
+            };
+
+            for path in archives {
+                seen.insert(path.clone());
+                if handled.contains(&path) {
+                    continue;
+                }
+                let Ok(stamp) = FileStamp::of(&path) else {
+                    continue;
+                };
+                if pending.insert(path.clone(), stamp) != Some(stamp) {
+                    continue;
+                }
+                // stays pending, and is picked up on the first poll after
+                // the pause ends
+                if paused.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // unchanged for a poll, but e.g. a torrent client that
+                // preallocated the file is still filling it in
+                if matches!(is_complete_archive(&path), Ok(false)) {
+                    log_tr!(
+                        debug,
+                        "压缩包尚未下载完成，稍后再试 {}",
+                        "archive is not complete yet, will retry: {}",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                // the same archive showing up again under another name, e.g.
+                // downloaded twice, is only extracted once; while the other
+                // copy is still on its way this one stays pending, and is
+                // extracted after all should that fail
+                let fingerprint = archive_fingerprint(&path).ok();
+                if fingerprint.is_some_and(|f| in_flight.values().any(|&other| other == f)) {
+                    continue;
+                }
+                pending.remove(&path);
+                handled.insert(path.clone());
+                if fingerprint.is_some_and(|f| extracted.contains(&f)) {
+                    log_tr!(
+                        info,
+                        "已处理过相同的压缩包，跳过 {}",
+                        "same archive already processed, skipping {}",
+                        path.display()
+                    );
+                    continue;
+                }
+                match base_dest_for(&path, folder.dest.as_deref(), &config.config.dest) {
+                    Ok(base_dest) => {
+                        let smart_mode = folder.smart_mode.unwrap_or(config.config.smart_mode);
+                        if let Some(fingerprint) = fingerprint {
+                            in_flight.insert(path.clone(), fingerprint);
+                        }
+                        queue.push_back((path, base_dest, smart_mode));
+                    }
+                    Err(e) => log_tr!(
+                        warn,
+                        "无法确定解压目标 {}: {}",
+                        "cannot determine the destination for {}: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+        if !paused.load(Ordering::Relaxed) {
+            start_jobs(
+                &mut queue,
+                &mut running,
+                &mut running_paths,
+                max_concurrent,
+                &config,
+                &status,
+            );
+        }
+
+        let failed_now: HashSet<PathBuf> = failed.iter().map(|(path, _)| path.clone()).collect();
+        for (path, e) in &failed {
+            if !unreadable.contains(path) {
+                log_tr!(
+                    warn,
+                    "无法扫描监视文件夹 {}，稍后重试: {}",
+                    "cannot scan watch folder {}, will retry: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+        for path in unreadable.difference(&failed_now) {
+            log_tr!(
+                info,
+                "监视文件夹已恢复访问 {}",
+                "watch folder is accessible again: {}",
+                path.display()
+            );
+        }
+        unreadable = failed_now;
+
+        // forget archives that went away so a new file with the same name is
+        // picked up again
+        handled.retain(|path| seen.contains(path));
+        pending.retain(|path, _| seen.contains(path));
+        let waiting = pending.len() + queue.len();
+        let mut status = status.lock().unwrap();
+        status.pending = waiting;
+        status.polled(polled, waiting == 0 && running.is_empty());
+    }
+
+    while let Some(joined) = running.join_next_with_id().await {
+        finish_job(
+            joined,
+            &mut running_paths,
+            &mut in_flight,
+            &mut extracted,
+            &status,
+        );
+    }
+}
+
+/// Waits until a poll of the watcher reporting to `status` that started
+/// after this call finds nothing left to do, so archives dropped into a
+/// watch folder beforehand are extracted by then, e.g. for a script to
+/// block until a batch is processed. A watcher that stops first leaves this
+/// waiting forever, so wrap it in `tokio::time::timeout` where that can
+/// happen.
+#[cfg(feature = "async")]
+pub async fn wait_until_idle(status: &Mutex<WatchStatus>) {
+    let called = Instant::now();
+    loop {
+        if status
+            .lock()
+            .unwrap()
+            .last_idle_poll
+            .is_some_and(|polled| polled >= called)
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Moves archives from the front of `queue` to `running` until
+/// `max_concurrent` are being extracted.
+#[cfg(feature = "async")]
+fn start_jobs(
+    queue: &mut VecDeque<(PathBuf, PathBuf, bool)>,
+    running: &mut JoinSet<Option<Outcome>>,
+    running_paths: &mut HashMap<task::Id, PathBuf>,
+    max_concurrent: usize,
+    config: &Arc<Config>,
+    status: &Mutex<WatchStatus>,
+) {
+    while running.len() < max_concurrent {
+        let Some((path, base_dest, smart_mode)) = queue.pop_front() else {
+            break;
+        };
+        let mut status = status.lock().unwrap();
+        status.start(&path);
+        status.pending = status.pending.saturating_sub(1);
+        let config = config.clone();
+        let job_path = path.clone();
+        let handle = running
+            .spawn(async move { process_archive(&job_path, base_dest, smart_mode, &config).await });
+        running_paths.insert(handle.id(), path);
+    }
+}
+
+/// Records how the job ended. The archive's fingerprint is only kept as
+/// extracted when a password was found, so a copy under another name is
+/// still tried after a failure, timeout or cancel.
+#[cfg(feature = "async")]
+fn finish_job(
+    joined: Result<(task::Id, Option<Outcome>), task::JoinError>,
+    running_paths: &mut HashMap<task::Id, PathBuf>,
+    in_flight: &mut HashMap<PathBuf, u64>,
+    extracted: &mut HashSet<u64>,
+    status: &Mutex<WatchStatus>,
+) {
+    let (id, outcome) = match joined {
+        Ok((id, outcome)) => (id, outcome),
+        Err(e) => {
+            let id = e.id();
+            log_tr!(
+                warn,
+                "解压任务异常结束 {}: {}",
+                "extraction task ended abnormally for {}: {}",
+                running_paths
+                    .get(&id)
+                    .map_or(Path::new("?"), |p| p.as_path())
+                    .display(),
+                e
+            );
+            (id, None)
+        }
+    };
+    if let Some(path) = running_paths.remove(&id) {
+        if let Some(fingerprint) = in_flight.remove(&path) {
+            if matches!(outcome, Some(Outcome::Found(_))) {
+                extracted.insert(fingerprint);
+            }
+        }
+        status.lock().unwrap().finish(&path, outcome);
+    }
+}
+
+#[cfg(feature = "async")]
+fn collect_folder(
+    folder: &str,
+    config: &Config,
+    unreadable: &mut Vec<(PathBuf, io::Error)>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut archives = Vec::new();
+    collect_archives(
+        Path::new(folder),
+        config.config.recursive_search,
+        config.config.ignore_hidden,
+        &config.config.enabled_formats,
+        config.config.detection_window(),
+        &mut archives,
+        unreadable,
+    )?;
+    Ok(archives)
+}
+
+/// Returns `None` when the archive wasn't tried because of its format.
+/// `smart_mode` is the folder's setting, which may differ from the global
+/// one.
+#[cfg(feature = "async")]
+async fn process_archive(
+    path: &Path,
+    base_dest: PathBuf,
+    smart_mode: bool,
+    config: &Config,
+) -> Option<Outcome> {
+    let format = crate::detect_archive_format_cached(
+        path,
+        config.config.detection_window(),
+        &config.config.enabled_formats,
+    )
+    .ok()
+    .flatten();
+    if !format.is_some_and(|f| f.is_supported()) {
+        log_tr!(
+            warn,
+            "暂不支持的压缩格式 {}: {}",
+            "unsupported archive format {}: {}",
+            format.map(|f| f.name()).unwrap_or("unknown"),
+            path.display()
+        );
+        return None;
+    }
+
+    let dest = if smart_mode {
+        let smart_mode = SmartModeOptions {
+            enabled: true,
+            ..SmartModeOptions::from_config(&config.config)
+        };
+        match planned_dest(path, &base_dest, &smart_mode) {
+            Ok(dest) => dest,
+            Err(e @ CascadeError::DestinationExists { .. }) => {
+                log_tr!(warn, "跳过 {}: {}", "skipping {}: {}", path.display(), e);
+                return None;
+            }
+            Err(_) => base_dest.clone(),
+        }
+    } else {
+        base_dest.clone()
+    };
+    let action_log = config
+        .config
+        .write_action_log
+        .then(|| ActionLog::start(path, &base_dest, &dest, smart_mode));
+
+    if format != Some(ArchiveFormat::SevenZ) {
+        // tar and tar.gz have no encryption, there is no password to find
+        let options = ExtractOptions {
+            record_written_files: action_log.as_ref().map(|_| PathStyle::Relative),
+            ..ExtractOptions::from_config(&config.config)
+        };
+        let result = extract_any(path, "", &dest, &options);
+        if let Some(mut log) = action_log {
+            match &result {
+                Ok(stats) => {
+                    log.record_stats(stats);
+                    log.outcome = "extracted".to_string();
+                }
+                Err(_) => log.outcome = "failed".to_string(),
+            }
+            write_action_log(log, &base_dest);
+        }
+        return match result {
+            Ok(_) => {
+                after_extraction(path, &dest, config).await;
+                Some(Outcome::Found(String::new()))
+            }
+            Err(e) => {
+                log_tr!(
+                    warn,
+                    "解压失败 {}: {}",
+                    "failed to extract {}: {}",
+                    path.display(),
+                    e
+                );
+                Some(Outcome::NotFound { tried: 1 })
+            }
+        };
+    }
+
+    let shard = config.config.shard().unwrap_or_else(|e| {
+        log_tr!(
+            warn,
+            "分片配置无效，将尝试全部密码: {}",
+            "invalid shard settings, trying all passwords: {}",
+            e
+        );
+        Shard::ALL
+    });
+    // unencrypted archives open with the empty password right away, on every
+    // machine
+    let mut passwords = vec![Arc::new(String::new())];
+    passwords.extend(
+        shard.select(
+            config
+                .user
+                .passwords
+                .iter()
+                .flatten()
+                .map(|p| Arc::new(p.clone())),
+        ),
+    );
+
+    log_tr!(
+        info,
+        "开始处理 {} -> {}",
+        "processing {} -> {}",
+        path.display(),
+        dest.display()
+    );
+    let options = CrackOptions::from_config(&config.config);
+    // for the action log, the list itself goes to the attempts
+    let tried = action_log.as_ref().map(|_| passwords.clone());
+    let mut found = start_extraction_with_options(
+        Arc::from(vec![path.to_path_buf()]),
+        passwords,
+        dest.clone(),
+        config.config.threads as usize,
+        &options,
+    )
+    .await;
+    if matches!(found, Outcome::NotFound { .. }) && !config.config.wordlists.is_empty() {
+        let wordlists: Vec<PathBuf> = config.config.wordlists.iter().map(PathBuf::from).collect();
+        match start_extraction_with_wordlists(
+            Arc::from(vec![path.to_path_buf()]),
+            &wordlists,
+            dest.clone(),
+            config.config.threads as usize,
+            &options,
+            shard,
+        )
+        .await
+        {
+            Ok(Some(hit)) => found = Outcome::Found(hit.password),
+            Ok(None) => {}
+            Err(e) => log_tr!(
+                warn,
+                "尝试密码表失败: {}",
+                "trying the wordlists failed: {}",
+                e
+            ),
+        }
+    }
+
+    if let (Some(mut log), Some(tried)) = (action_log, tried) {
+        log.record_outcome(&found, &tried);
+        if let Outcome::Found(password) = &found {
+            log.record_listing(password);
+        }
+        write_action_log(log, &base_dest);
+    }
+
+    match &found {
+        Outcome::Found(_) => after_extraction(path, &dest, config).await,
+        Outcome::NotFound { .. } => log_tr!(
+            warn,
+            "未找到正确的密码: {}",
+            "no password found for {}",
+            path.display()
+        ),
+        Outcome::TimedOut { tried } => {
+            log_tr!(
+                warn,
+                "破解超时，已尝试 {} 次: {}",
+                "cracking timed out after {} attempts: {}",
+                tried,
+                path.display()
+            )
+        }
+        Outcome::Cancelled { tried } => log_tr!(
+            info,
+            "已取消破解，已尝试 {} 次: {}",
+            "cracking cancelled after {} attempts: {}",
+            tried,
+            path.display()
+        ),
+        Outcome::ExtractFailed { error, .. } => log_tr!(
+            warn,
+            "找到了密码，但解压失败 {}: {}",
+            "found the password, but extracting failed {}: {}",
+            path.display(),
+            error
+        ),
+        Outcome::Skipped { reason } => log_tr!(
+            warn,
+            "跳过 {}: {}",
+            "skipping {}: {}",
+            path.display(),
+            reason
+        ),
+    }
+    Some(found)
+}
+
+/// Runs the `post_command` and deletes the archive if so configured.
+#[cfg(feature = "async")]
+async fn after_extraction(path: &Path, dest: &Path, config: &Config) {
+    if let Some(command) = config.config.post_command.clone() {
+        let (archive, folder) = (path.to_path_buf(), dest.to_path_buf());
+        let result =
+            task::spawn_blocking(move || run_post_command(&command, &archive, &folder)).await;
+        if let Err(e) = result
+            .map_err(|e| CascadeError::from(io::Error::other(e)))
+            .and_then(|result| result)
+        {
+            log_tr!(
+                warn,
+                "解压后命令失败 {}: {}",
+                "post command failed for {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+    if config.config.delete_archive {
+        // the retries sleep, which must not hold up the runtime's workers
+        let (archive, folder) = (path.to_path_buf(), dest.to_path_buf());
+        let result =
+            task::spawn_blocking(move || delete_extracted_archive(&archive, &folder)).await;
+        if let Err(e) = result.map_err(|e| e.into()).and_then(|result| result) {
+            log_tr!(
+                warn,
+                "删除压缩包失败 {}: {}",
+                "failed to delete archive {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use crate::Config;
+
+    #[test]
+    fn test_watch_folder_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = "out"
+            smart_mode = true
+
+            [user]
+            watch_folders = ["a", { path = "b", dest = "b-out" }, { path = "c", smart_mode = false }]
+            "#,
+        )
+        .unwrap();
+        let folders = config.user.watch_folders.unwrap();
+        assert_eq!(folders[0].dest, None);
+        assert_eq!(folders[0].smart_mode, None);
+        assert_eq!(folders[1].path, "b");
+        assert_eq!(folders[1].dest.as_deref(), Some("b-out"));
+        assert_eq!(folders[2].dest, None);
+        assert_eq!(folders[2].smart_mode, Some(false));
+    }
+
+    #[test]
+    fn test_base_dest_for() {
+        let archive = Path::new("in/a.7z");
+        let base_dest =
+            |folder_dest, global_dest| base_dest_for(archive, folder_dest, global_dest).unwrap();
+        assert_eq!(base_dest(None, ""), Path::new("in"));
+        assert_eq!(base_dest(None, "."), Path::new("in"));
+        assert_eq!(base_dest(None, "out"), Path::new("out"));
+        assert_eq!(
+            base_dest(Some("folder-out"), "out"),
+            Path::new("folder-out")
+        );
+        assert_eq!(base_dest(None, "out/{archive_stem}"), Path::new("out/a"));
+        assert!(base_dest_for(archive, None, "out/{nope}").is_err());
+    }
+
+    #[test]
+    fn test_find_archives() {
+        let archives = find_archives("tests", false).unwrap();
+        assert!(archives.contains(&PathBuf::from("tests/sample.7z")));
+        assert!(!archives.contains(&PathBuf::from("tests/7ziplogo.png")));
+    }
+
+    #[test]
+    fn test_find_archives_ignores_hidden() {
+        let dir = test_dir("find_archives_ignores_hidden");
+        fs::create_dir_all(dir.join(".cache")).unwrap();
+        for name in ["a.7z", ".b.7z", "c.7z.part", "d.7z.crdownload", "e.7z.!ut"] {
+            fs::copy("tests/sample.7z", dir.join(name)).unwrap();
+        }
+        fs::copy("tests/sample.7z", dir.join(".cache/f.7z")).unwrap();
+
+        assert_eq!(find_archives(&dir, true).unwrap(), [dir.join("a.7z")]);
+
+        let mut all = Vec::new();
+        collect_archives(
+            &dir,
+            true,
+            false,
+            &ArchiveFormat::ALL,
+            4096,
+            &mut all,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(all.len(), 6);
+    }
+
+    #[test]
+    fn test_extract_newest() {
+        let dir = test_dir("extract_newest");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let err = extract_newest(&dir, "", &dir.join("out"), true).unwrap_err();
+        assert!(matches!(err, CascadeError::NoArchivesFound { .. }));
+
+        fs::copy("tests/sample.7z", dir.join("old.7z")).unwrap();
+        fs::copy("tests/7ziplogo.7z", dir.join("sub/new.7z")).unwrap();
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("old.7z"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let (path, stats) = extract_newest(&dir, "", &dir.join("out"), true).unwrap();
+        assert_eq!(path, dir.join("sub/new.7z"));
+        assert_eq!(stats.files, 1);
+        assert!(dir.join("out/7ziplogo.png").exists());
+
+        let (path, _) = extract_newest(&dir, "", &dir.join("flat"), false).unwrap();
+        assert_eq!(path, dir.join("old.7z"));
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let dir = test_dir("scan_stats");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::copy("tests/sample.7z", dir.join("a.7z")).unwrap();
+        fs::copy("tests/7ziplogo_p.7z", dir.join("sub/b.7z")).unwrap();
+        fs::copy("tests/zipcrypto.zip", dir.join("c.zip")).unwrap();
+        fs::copy("tests/7ziplogo.png", dir.join("d.png")).unwrap();
+
+        let stats = scan_stats(&dir, true).unwrap();
+        assert_eq!(stats.len(), 2);
+        let sevenz = &stats[&ArchiveFormat::SevenZ];
+        assert_eq!(sevenz.count, 2);
+        assert_eq!(sevenz.encrypted_count, 1);
+        assert_eq!(
+            sevenz.total_compressed_bytes,
+            fs::metadata("tests/sample.7z").unwrap().len()
+                + fs::metadata("tests/7ziplogo_p.7z").unwrap().len()
+        );
+        assert_eq!(stats[&ArchiveFormat::Zip].encrypted_count, 1);
+
+        let flat = scan_stats(&dir, false).unwrap();
+        assert_eq!(flat[&ArchiveFormat::SevenZ].count, 1);
+        assert_eq!(flat[&ArchiveFormat::SevenZ].encrypted_count, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_archives_skips_unreadable_folders() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("find_archives_unreadable");
+        let locked = dir.join("locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::copy("tests/sample.7z", dir.join("a.7z")).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let readable_anyway = fs::read_dir(&locked).is_ok();
+
+        let archives = find_archives(&dir, true);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        // root reads everything, the permission check can't be exercised
+        if !readable_anyway {
+            assert_eq!(archives.unwrap(), [dir.join("a.7z")]);
+        }
+    }
+
+    #[test]
+    fn test_dir_has_archives() {
+        assert!(dir_has_archives("tests", false).unwrap());
+
+        let dir = test_dir("dir_has_archives");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::copy("tests/7ziplogo.png", dir.join("7ziplogo.png")).unwrap();
+        assert!(!dir_has_archives(&dir, true).unwrap());
+
+        fs::copy("tests/sample.7z", dir.join("nested/sample.7z")).unwrap();
+        assert!(!dir_has_archives(&dir, false).unwrap());
+        assert!(dir_has_archives(&dir, true).unwrap());
+        assert!(dir_has_archives(dir.join("missing"), true).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_per_folder_dest() {
+        let dir = test_dir("watch_per_folder_dest");
+        let watched = dir.join("watched");
+        let folder_dest = dir.join("folder-dest");
+        fs::create_dir_all(&watched).unwrap();
+        fs::copy("tests/sample.7z", watched.join("sample.7z")).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = true
+            recursive_search = false
+            dest = {:?}
+            smart_mode = true
+            watch_interval_ms = 50
+
+            [user]
+            watch_folders = [{{ path = {:?}, dest = {:?} }}]
+            "#,
+            dir.join("global-dest"),
+            watched,
+            folder_dest,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let expected = folder_dest.join("sample").join("7zFormat.txt");
+        let wait = async {
+            for _ in 0..100 {
+                if expected.exists() && !watched.join("sample.7z").exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(watch_and_extract(&config, shutdown.clone()), wait);
+
+        assert!(expected.exists());
+        assert!(!watched.join("sample.7z").exists());
+        assert!(!dir.join("global-dest").exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_pause() {
+        let dir = test_dir("watch_pause");
+        let watched = dir.join("watched");
+        let dest = dir.join("dest");
+        fs::create_dir_all(&watched).unwrap();
+        fs::copy("tests/sample.7z", watched.join("sample.7z")).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+
+            [user]
+            watch_folders = [{:?}]
+            "#,
+            dest, watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(true));
+        let expected = dest.join("7zFormat.txt");
+        let control = async {
+            // plenty of polls for the file to count as stable
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert!(!expected.exists());
+            paused.store(false, Ordering::Relaxed);
+            for _ in 0..100 {
+                if expected.exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(
+            watch_and_extract_with_pause(&config, shutdown.clone(), paused.clone()),
+            control
+        );
+
+        assert!(expected.exists());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_status() {
+        let dir = test_dir("watch_status");
+        let watched = dir.join("watched");
+        fs::create_dir_all(&watched).unwrap();
+        fs::copy("tests/sample.7z", watched.join("sample.7z")).unwrap();
+        fs::write(watched.join("notes.xz"), [0xfd, b'7', b'z', b'X', b'Z', 0]).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+
+            [user]
+            watch_folders = [{:?}]
+            "#,
+            dir.join("dest"),
+            watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+        let wait = async {
+            for _ in 0..100 {
+                if status.lock().unwrap().recent.len() == 2 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(
+            watch_and_extract_with_status(&config, shutdown.clone(), paused, status.clone()),
+            wait
+        );
+
+        let status = status.lock().unwrap();
+        assert!(status.processing.is_empty());
+        assert_eq!(status.pending, 0);
+        assert!(status.is_healthy(Duration::from_secs(5)));
+        let outcome_of = |name: &str| {
+            status
+                .recent
+                .iter()
+                .find(|result| result.path.ends_with(name))
+                .map(|result| result.outcome.clone())
+        };
+        assert_eq!(
+            outcome_of("sample.7z"),
+            Some(Some(Outcome::Found(String::new())))
+        );
+        assert_eq!(outcome_of("notes.xz"), Some(None));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_retries_copies_of_failed_archives() {
+        let dir = test_dir("watch_retries_copies");
+        let watched = dir.join("watched");
+        fs::create_dir_all(&watched).unwrap();
+        fs::copy("tests/7ziplogo_p.7z", watched.join("a.7z")).unwrap();
+        fs::copy("tests/7ziplogo_p.7z", watched.join("b.7z")).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+
+            [user]
+            passwords = ["wrong"]
+            watch_folders = [{:?}]
+            "#,
+            dir.join("dest"),
+            watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+        let wait = async {
+            for _ in 0..100 {
+                if status.lock().unwrap().recent.len() == 2 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(
+            watch_and_extract_with_status(&config, shutdown.clone(), paused, status.clone()),
+            wait
+        );
+
+        // the failed copy doesn't count as processed for the other one
+        let status = status.lock().unwrap();
+        assert_eq!(status.recent.len(), 2);
+        assert!(status
+            .recent
+            .iter()
+            .all(|result| matches!(result.outcome, Some(Outcome::NotFound { .. }))));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_wait_until_idle() {
+        let dir = test_dir("wait_until_idle");
+        let watched = dir.join("watched");
+        fs::create_dir_all(&watched).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+
+            [user]
+            watch_folders = [{:?}]
+            "#,
+            dir.join("dest"),
+            watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+        let wait = async {
+            let timeout = Duration::from_secs(10);
+            tokio::time::timeout(timeout, wait_until_idle(&status))
+                .await
+                .unwrap();
+            fs::copy("tests/sample.7z", watched.join("sample.7z")).unwrap();
+            tokio::time::timeout(timeout, wait_until_idle(&status))
+                .await
+                .unwrap();
+            assert!(dir.join("dest/7zFormat.txt").exists());
+            assert_eq!(status.lock().unwrap().recent.len(), 1);
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(
+            watch_and_extract_with_status(&config, shutdown.clone(), paused, status.clone()),
+            wait
+        );
+        assert!(status.lock().unwrap().idle);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_watch_status_is_healthy() {
+        let mut status = WatchStatus::default();
+        assert!(status.is_healthy(Duration::from_secs(1)));
+        status.last_activity = Instant::now() - Duration::from_secs(60);
+        assert!(!status.is_healthy(Duration::from_secs(30)));
+        status.finish(Path::new("a.7z"), None);
+        assert!(status.is_healthy(Duration::from_secs(30)));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_tar() {
+        let dir = test_dir("watch_tar");
+        let watched = dir.join("watched");
+        let dest = dir.join("dest");
+        fs::create_dir_all(&watched).unwrap();
+        let mut builder = tar::Builder::new(fs::File::create(watched.join("src.tar")).unwrap());
+        builder
+            .append_path_with_name("tests/7zFormat.txt", "7zFormat.txt")
+            .unwrap();
+        builder
+            .append_path_with_name("tests/7ziplogo.png", "7ziplogo.png")
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = true
+            recursive_search = false
+            dest = {:?}
+            smart_mode = true
+            watch_interval_ms = 20
+            write_action_log = true
+
+            [user]
+            watch_folders = [{:?}]
+            "#,
+            dest, watched,
+        ))
+        .unwrap();
+
+        // the default signature window sees tar too, and smart mode wraps
+        // its two roots like those of a 7z
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let expected = dest.join("src/7zFormat.txt");
+        let wait = async {
+            for _ in 0..100 {
+                if expected.exists() && !watched.join("src.tar").exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(watch_and_extract(&config, shutdown.clone()), wait);
+
+        assert!(expected.exists());
+        assert!(!watched.join("src.tar").exists());
+        let log: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dest.join("src.extract.json")).unwrap())
+                .unwrap();
+        assert_eq!(log["outcome"], "extracted");
+        assert_eq!(log["files"][0]["name"], "7zFormat.txt");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_concurrent() {
+        let dir = test_dir("watch_concurrent");
+        let watched = dir.join("watched");
+        let dest = dir.join("dest");
+        fs::create_dir_all(&watched).unwrap();
+        let names = ["a", "b", "c"];
+        for name in names {
+            let mut builder =
+                tar::Builder::new(fs::File::create(watched.join(format!("{}.tar", name))).unwrap());
+            let content = format!("content of {}", name);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("{}.txt", name), content.as_bytes())
+                .unwrap();
+            builder.finish().unwrap();
+        }
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = false
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+            signature_window = 262
+            max_concurrent_archives = 2
+
+            [user]
+            watch_folders = [{:?}]
+            "#,
+            dest, watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+        let wait = async {
+            for _ in 0..100 {
+                if status.lock().unwrap().recent.len() == names.len() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(
+            watch_and_extract_with_status(&config, shutdown.clone(), paused, status.clone()),
+            wait
+        );
+
+        for name in names {
+            assert_eq!(
+                fs::read_to_string(dest.join(format!("{}.txt", name))).unwrap(),
+                format!("content of {}", name)
+            );
+        }
+        let status = status.lock().unwrap();
+        assert!(status.processing.is_empty());
+        assert_eq!(status.pending, 0);
+        assert!(status
+            .recent
+            .iter()
+            .all(|r| matches!(r.outcome, Some(Outcome::Found(_)))));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_watch_and_extract_skips_missing_folder() {
+        let dir = test_dir("watch_missing_folder");
+        let watched = dir.join("watched");
+        let dest = dir.join("dest");
+        fs::create_dir_all(&watched).unwrap();
+        fs::copy("tests/sample.7z", watched.join("sample.7z")).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [config]
+            delete_archive = false
+            recursive_search = true
+            dest = {:?}
+            smart_mode = false
+            watch_interval_ms = 20
+
+            [user]
+            watch_folders = [{:?}, {:?}]
+            "#,
+            dest,
+            dir.join("missing"),
+            watched,
+        ))
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let expected = dest.join("7zFormat.txt");
+        let wait = async {
+            for _ in 0..100 {
+                if expected.exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            shutdown.store(true, Ordering::Relaxed);
+        };
+        tokio::join!(watch_and_extract(&config, shutdown.clone()), wait);
+
+        assert!(expected.exists());
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::CascadeError;
+
+/// The part of a password list one machine is responsible for: machine
+/// `index` of `count` takes every line where `line_number % count == index`,
+/// so a cluster can split a wordlist without any coordination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    /// The whole list, for running on a single machine.
+    pub const ALL: Shard = Shard { index: 0, count: 1 };
+
+    pub fn new(index: usize, count: usize) -> Result<Self, CascadeError> {
+        if count == 0 || index >= count {
+            return Err(CascadeError::InvalidShard { index, count });
+        }
+        Ok(Shard { index, count })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn contains(&self, line_number: usize) -> bool {
+        line_number % self.count == self.index
+    }
+
+    /// Keeps the passwords whose position in `passwords` falls in this shard.
+    pub fn select<T>(&self, passwords: impl IntoIterator<Item = T>) -> Vec<T> {
+        passwords
+            .into_iter()
+            .enumerate()
+            .filter(|(line_number, _)| self.contains(*line_number))
+            .map(|(_, password)| password)
+            .collect()
+    }
+}
+
+impl Default for Shard {
+    fn default() -> Self {
+        Shard::ALL
+    }
+}
+
+/// Reads one password per line, keeping only the lines in `shard`. Lines
+/// are trimmed, and blank lines and `#` comments are skipped. Line numbers
+/// are counted before anything is skipped, so every machine agrees on them.
+pub fn read_wordlist<P: AsRef<Path>>(
+    path: P,
+    shard: Shard,
+) -> Result<Vec<Arc<String>>, CascadeError> {
+    Ok(read_wordlist_numbered(path, shard)?
+        .into_iter()
+        .map(|(_, password)| password)
+        .collect())
+}
+
+/// `read_wordlist` with the line each password came from, counting from 1
+/// as editors do.
+pub fn read_wordlist_numbered<P: AsRef<Path>>(
+    path: P,
+    shard: Shard,
+) -> Result<Vec<(usize, Arc<String>)>, CascadeError> {
+    let mut passwords = Vec::new();
+    for (line_number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if !shard.contains(line_number) {
+            continue;
+        }
+        if let Some(password) = password_from_line(&line) {
+            passwords.push((line_number + 1, Arc::new(password.to_string())));
+        }
+    }
+    Ok(passwords)
+}
+
+/// Lines longer than this, in bytes, are counted by `validate_wordlist`
+/// without being read into memory; no real password comes close.
+pub const MAX_WORDLIST_LINE: usize = 64 * 1024;
+
+/// What `validate_wordlist` found in a wordlist.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordlistStats {
+    pub total_lines: usize,
+    /// Distinct passwords, trimmed as `read_wordlist` trims them, leaving
+    /// out blank lines, comments and overlong lines.
+    pub unique_lines: usize,
+    /// Empty or whitespace only.
+    pub blank_lines: usize,
+    /// Starting with `#`, skipped by `read_wordlist` as well.
+    pub comment_lines: usize,
+    /// Longer than `MAX_WORDLIST_LINE`.
+    pub long_lines: usize,
+    /// The longest trimmed password, in bytes.
+    pub max_len: usize,
+    /// Some line isn't valid UTF-8, which usually means the file isn't a
+    /// wordlist at all. `read_wordlist` would fail on it.
+    pub has_non_utf8: bool,
+}
+
+/// Reads through a wordlist before a long crack, to catch a binary file or
+/// one that is mostly duplicates. The file is streamed line by line and only
+/// a hash of each line is kept, so its size doesn't matter; lines whose
+/// hashes collide, rare as that is, count as one.
+pub fn validate_wordlist<P: AsRef<Path>>(path: P) -> Result<WordlistStats, CascadeError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut stats = WordlistStats::default();
+    let mut seen = HashSet::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let limit = MAX_WORDLIST_LINE as u64 + 1;
+        if (&mut reader).take(limit).read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        stats.total_lines += 1;
+        if line.len() > MAX_WORDLIST_LINE && !line.ends_with(b"\n") {
+            stats.long_lines += 1;
+            skip_rest_of_line(&mut reader)?;
+            continue;
+        }
+        let password = match std::str::from_utf8(&line) {
+            Ok(text) => match password_from_line(text) {
+                Some(password) => password.as_bytes(),
+                None if text.trim().is_empty() => {
+                    stats.blank_lines += 1;
+                    continue;
+                }
+                None => {
+                    stats.comment_lines += 1;
+                    continue;
+                }
+            },
+            Err(_) => {
+                stats.has_non_utf8 = true;
+                line.trim_ascii()
+            }
+        };
+        stats.max_len = stats.max_len.max(password.len());
+        let mut hasher = DefaultHasher::new();
+        password.hash(&mut hasher);
+        if seen.insert(hasher.finish()) {
+            stats.unique_lines += 1;
+        }
+    }
+    Ok(stats)
+}
+
+fn skip_rest_of_line(reader: &mut impl BufRead) -> io::Result<()> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Where the password that worked was found by `start_extraction_with_wordlists`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordlistHit {
+    pub password: String,
+    pub wordlist: PathBuf,
+    /// Counting from 1, of the password the one that worked is a case
+    /// variant of for `try_case_variants`. `None` when it was the archive's
+    /// own file name, see `use_filename_as_password`.
+    pub line: Option<usize>,
+}
+
+/// The password on a line of a wordlist or of the `passwords` setting, or
+/// `None` for blank lines and `#` comments.
+pub(crate) fn password_from_line(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        None
+    } else {
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_dir;
+    use std::fs;
+
+    #[test]
+    fn test_shard_select() {
+        assert!(Shard::new(0, 0).is_err());
+        assert!(Shard::new(2, 2).is_err());
+
+        let passwords = ["a", "b", "c", "d", "e"];
+        assert_eq!(Shard::new(0, 2).unwrap().select(passwords), ["a", "c", "e"]);
+        assert_eq!(Shard::new(1, 2).unwrap().select(passwords), ["b", "d"]);
+        assert_eq!(Shard::ALL.select(passwords).len(), 5);
+    }
+
+    #[test]
+    fn test_read_wordlist_numbered() {
+        let dir = test_dir("read_wordlist_numbered");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(&path, "# common\n1111\n\n1151\n").unwrap();
+        assert_eq!(
+            read_wordlist_numbered(&path, Shard::ALL).unwrap(),
+            [
+                (2, Arc::new("1111".to_string())),
+                (4, Arc::new("1151".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_wordlist_shard() {
+        let dir = test_dir("read_wordlist_shard");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(&path, "1111\r\n\n1151\r\n2222\n3333\n").unwrap();
+
+        let shard = |index| read_wordlist(&path, Shard::new(index, 3).unwrap()).unwrap();
+        assert_eq!(
+            shard(0),
+            [Arc::new("1111".to_string()), Arc::new("2222".to_string())]
+        );
+        // the blank second line still counts towards shard 1
+        assert_eq!(shard(1), [Arc::new("3333".to_string())]);
+        assert_eq!(shard(2), [Arc::new("1151".to_string())]);
+    }
+
+    #[test]
+    fn test_validate_wordlist() {
+        let dir = test_dir("validate_wordlist");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        let mut content =
+            b"1151\r\n  \n1151 \npassword\n\xff\xfe\n\n# notes\n\xe3\x80\x801151\n".to_vec();
+        content.extend(vec![b'x'; MAX_WORDLIST_LINE * 3]);
+        content.extend(b"\nlast\n");
+        fs::write(&path, content).unwrap();
+        assert_eq!(
+            validate_wordlist(&path).unwrap(),
+            WordlistStats {
+                total_lines: 10,
+                unique_lines: 4,
+                blank_lines: 2,
+                comment_lines: 1,
+                long_lines: 1,
+                max_len: 8,
+                has_non_utf8: true,
+            }
+        );
+        fs::write(&path, "no newline at the end").unwrap();
+        let stats = validate_wordlist(&path).unwrap();
+        assert_eq!((stats.total_lines, stats.has_non_utf8), (1, false));
+    }
+
+    #[test]
+    fn test_read_wordlist_comments() {
+        let dir = test_dir("read_wordlist_comments");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(
+            &path,
+            "# from the forum\n  1151  \n\n   # old\n\tpassword\n",
+        )
+        .unwrap();
+
+        let passwords = read_wordlist(&path, Shard::ALL).unwrap();
+        assert_eq!(
+            passwords,
+            [
+                Arc::new("1151".to_string()),
+                Arc::new("password".to_string())
+            ]
+        );
+    }
+}